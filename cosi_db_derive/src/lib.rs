@@ -0,0 +1,64 @@
+// Derive macros for cosi_db models.
+//
+// A model is written once as its ORM struct. References to other models and dates are kept as
+// their rich types. The derive generates everything else:
+//
+//   #[derive(Clone, Debug, Deserialize, Serialize, CosiModel)]
+//   #[cosi(natural_key = "house_name, address")]
+//   pub struct Household {
+//       pub house_name: String,
+//       #[cosi(reference)]
+//       pub address: Address,
+//       ...
+//   }
+//
+// * `HouseholdImpl`: storage struct. References are stored as OID, dates as strings.
+// * `HouseholdOptional`: query struct. Every field is wrapped in an Option.
+// * `From` conversions between the three structs.
+// * `COSIForm` for the storage and query structs.
+// * `COSIModel` with the table name, fields, natural key, reference hints and field formats.
+// * `COSISchema` for the storage and query structs.
+// * `COSICollection` unless the model resolves its references by hand.
+// * Route handlers and `Household::routes()` to mount them.
+//
+// Struct attributes, all optional:
+// * `table = "name"`: table name. Defaults to the lower case struct name.
+// * `natural_key = "a, b"`: fields used to upsert.
+// * `validate = "path"`: `fn(&Optional) -> COSIResult<()>` run before inserts and updates.
+// * `manual_collection`: `COSICollection` is implemented by hand.
+//
+// Field attributes:
+// * `reference`: field holds another model, stored as its OID.
+// * `format = "%Y-%m-%d"`: chrono field stored as a string with the given format.
+//
+// `CosiSchema` describes the enums and plain structs used inside models for the OpenAPI document.
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+mod model;
+mod routes;
+mod schema;
+
+#[proc_macro_derive(CosiModel, attributes(cosi))]
+pub fn derive_cosi_model(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let result = model::CosiModel::parse(&input).map(|m| {
+        let mut tokens = m.expand();
+        tokens.extend(routes::expand(&m));
+        tokens
+    });
+    match result {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+#[proc_macro_derive(CosiSchema)]
+pub fn derive_cosi_schema(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match schema::expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
@@ -0,0 +1,449 @@
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::{
+    Attribute, Data, DeriveInput, Error, Fields, GenericArgument, Ident, Lit, LitStr, Meta,
+    NestedMeta, Path, PathArguments, Result, Type, Visibility,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Wrap {
+    Plain,
+    Option,
+    Vec,
+}
+
+pub enum Kind {
+    Value,
+    Reference,
+    Format(LitStr),
+}
+
+pub struct CosiField {
+    pub ident: Ident,
+    pub vis: Visibility,
+    pub ty: Type,
+    pub wrap: Wrap,
+    pub inner: Type,
+    pub kind: Kind,
+}
+
+pub struct CosiModel {
+    pub ident: Ident,
+    pub vis: Visibility,
+    pub table: String,
+    pub natural_key: Option<Vec<String>>,
+    pub validate: Option<Path>,
+    pub manual_collection: bool,
+    pub fields: Vec<CosiField>,
+}
+
+// Returns the nested metas of every #[cosi(...)] attribute.
+fn cosi_metas(attrs: &[Attribute]) -> Result<Vec<NestedMeta>> {
+    let mut result = vec![];
+    for attr in attrs.iter().filter(|a| a.path.is_ident("cosi")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => result.extend(list.nested),
+            meta => return Err(Error::new_spanned(meta, "Expected #[cosi(...)].")),
+        }
+    }
+    Ok(result)
+}
+
+fn lit_str(lit: &Lit) -> Result<LitStr> {
+    match lit {
+        Lit::Str(s) => Ok(s.clone()),
+        _ => Err(Error::new_spanned(lit, "Expected a string.")),
+    }
+}
+
+// Splits Option<T> and Vec<T> into the wrapper and T.
+pub fn split_wrap(ty: &Type) -> (Wrap, Type) {
+    if let Type::Path(tp) = ty {
+        if let Some(seg) = tp.path.segments.last() {
+            let wrap = match seg.ident.to_string().as_str() {
+                "Option" => Wrap::Option,
+                "Vec" => Wrap::Vec,
+                _ => return (Wrap::Plain, ty.clone()),
+            };
+            if let PathArguments::AngleBracketed(args) = &seg.arguments {
+                if let Some(GenericArgument::Type(inner)) = args.args.first() {
+                    return (wrap, inner.clone());
+                }
+            }
+        }
+    }
+    (Wrap::Plain, ty.clone())
+}
+
+fn wrap_type(wrap: Wrap, inner: TokenStream) -> TokenStream {
+    match wrap {
+        Wrap::Plain => inner,
+        Wrap::Option => quote!(::std::option::Option<#inner>),
+        Wrap::Vec => quote!(::std::vec::Vec<#inner>),
+    }
+}
+
+// Applies a conversion to the value, looking through Option and Vec.
+fn map_wrap(wrap: Wrap, value: TokenStream, f: TokenStream) -> TokenStream {
+    match wrap {
+        Wrap::Plain => quote!({ let x = #value; #f }),
+        Wrap::Option => quote!(#value.map(|x| #f)),
+        Wrap::Vec => quote!(#value.into_iter().map(|x| #f).collect()),
+    }
+}
+
+impl CosiField {
+    fn parse(field: &syn::Field) -> Result<CosiField> {
+        let ident = field.ident.clone().unwrap();
+        let (wrap, inner) = split_wrap(&field.ty);
+        let mut kind = Kind::Value;
+        for meta in cosi_metas(&field.attrs)? {
+            match meta {
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident("reference") => {
+                    kind = Kind::Reference;
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("format") => {
+                    kind = Kind::Format(lit_str(&nv.lit)?);
+                }
+                meta => return Err(Error::new_spanned(meta, "Unknown cosi field attribute.")),
+            }
+        }
+        Ok(CosiField {
+            ident,
+            vis: field.vis.clone(),
+            ty: field.ty.clone(),
+            wrap,
+            inner,
+            kind,
+        })
+    }
+
+    // Type stored in the database.
+    pub fn impl_type(&self) -> TokenStream {
+        match &self.kind {
+            Kind::Value => {
+                let ty = &self.ty;
+                quote!(#ty)
+            }
+            Kind::Reference => wrap_type(self.wrap, quote!(crate::cosi_db::model::common::OID)),
+            Kind::Format(_) => wrap_type(self.wrap, quote!(::std::string::String)),
+        }
+    }
+
+    pub fn optional_type(&self) -> TokenStream {
+        let ty = self.impl_type();
+        quote!(::std::option::Option<#ty>)
+    }
+
+    // Expression building the schema of the stored value.
+    pub fn schema(&self, ty: TokenStream) -> TokenStream {
+        let base = quote!(<#ty as crate::cosi_db::model::common::COSISchema>::schema());
+        let inner = &self.inner;
+        match &self.kind {
+            Kind::Value => base,
+            Kind::Reference => quote! {
+                crate::cosi_db::model::common::describe_schema(
+                    #base,
+                    format!(
+                        "Object id of a datum in {}.",
+                        <#inner as crate::cosi_db::model::common::COSIModel>::table_name()
+                    ),
+                )
+            },
+            Kind::Format(fmt) => quote! {
+                crate::cosi_db::model::common::describe_schema(#base, format!("Formatted as {}.", #fmt))
+            },
+        }
+    }
+
+    fn to_impl(&self, value: TokenStream) -> TokenStream {
+        let name = &self.ident;
+        match &self.kind {
+            Kind::Value => quote!(#value.#name),
+            // Placeholder, references are resolved by COSICollection::to_impl.
+            Kind::Reference => quote!(::std::default::Default::default()),
+            Kind::Format(fmt) => map_wrap(
+                self.wrap,
+                quote!(#value.#name),
+                quote!(x.format(#fmt).to_string()),
+            ),
+        }
+    }
+
+    fn to_orm(&self, value: TokenStream) -> TokenStream {
+        let name = &self.ident;
+        let inner = &self.inner;
+        match &self.kind {
+            Kind::Value => quote!(#value.#name),
+            // Placeholder, references are resolved by COSICollection::to_orm.
+            Kind::Reference => quote!(::std::default::Default::default()),
+            Kind::Format(fmt) => map_wrap(
+                self.wrap,
+                quote!(#value.#name),
+                quote!(<#inner>::parse_from_str(&x, #fmt).unwrap()),
+            ),
+        }
+    }
+}
+
+impl CosiModel {
+    pub fn parse(input: &DeriveInput) -> Result<CosiModel> {
+        let fields = match &input.data {
+            Data::Struct(s) => match &s.fields {
+                Fields::Named(named) => named
+                    .named
+                    .iter()
+                    .map(CosiField::parse)
+                    .collect::<Result<Vec<CosiField>>>()?,
+                _ => return Err(Error::new_spanned(&input.ident, "Expected named fields.")),
+            },
+            _ => return Err(Error::new_spanned(&input.ident, "Expected a struct.")),
+        };
+
+        let mut model = CosiModel {
+            ident: input.ident.clone(),
+            vis: input.vis.clone(),
+            table: input.ident.to_string().to_lowercase(),
+            natural_key: None,
+            validate: None,
+            manual_collection: false,
+            fields,
+        };
+        for meta in cosi_metas(&input.attrs)? {
+            match meta {
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("table") => {
+                    model.table = lit_str(&nv.lit)?.value();
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("natural_key") => {
+                    let keys: Vec<String> = lit_str(&nv.lit)?
+                        .value()
+                        .split(",")
+                        .map(|k| k.trim().to_string())
+                        .collect();
+                    for k in &keys {
+                        if !model.fields.iter().any(|f| f.ident == k) {
+                            return Err(Error::new_spanned(
+                                nv.lit,
+                                format!("Unknown field {}.", k),
+                            ));
+                        }
+                    }
+                    model.natural_key = Some(keys);
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("validate") => {
+                    model.validate = Some(lit_str(&nv.lit)?.parse()?);
+                }
+                NestedMeta::Meta(Meta::Path(p)) if p.is_ident("manual_collection") => {
+                    model.manual_collection = true;
+                }
+                meta => return Err(Error::new_spanned(meta, "Unknown cosi attribute.")),
+            }
+        }
+        Ok(model)
+    }
+
+    pub fn impl_ident(&self) -> Ident {
+        format_ident!("{}Impl", self.ident)
+    }
+
+    pub fn optional_ident(&self) -> Ident {
+        format_ident!("{}Optional", self.ident)
+    }
+
+    pub fn expand(&self) -> TokenStream {
+        let ident = &self.ident;
+        let vis = &self.vis;
+        let impl_ident = self.impl_ident();
+        let optional_ident = self.optional_ident();
+        let table = LitStr::new(&self.table, Span::call_site());
+
+        let names: Vec<&Ident> = self.fields.iter().map(|f| &f.ident).collect();
+        let field_vis: Vec<&Visibility> = self.fields.iter().map(|f| &f.vis).collect();
+        let impl_types: Vec<TokenStream> = self.fields.iter().map(|f| f.impl_type()).collect();
+        let optional_types: Vec<TokenStream> =
+            self.fields.iter().map(|f| f.optional_type()).collect();
+        // Arrays and nullable values may be left out of insert bodies.
+        let impl_attrs: Vec<TokenStream> = self
+            .fields
+            .iter()
+            .map(|f| match f.wrap {
+                Wrap::Plain => quote!(),
+                _ => quote!(#[serde(default)]),
+            })
+            .collect();
+        let to_impl: Vec<TokenStream> = self.fields.iter().map(|f| f.to_impl(quote!(v))).collect();
+        let to_orm: Vec<TokenStream> = self.fields.iter().map(|f| f.to_orm(quote!(v))).collect();
+
+        let field_names: Vec<LitStr> = self
+            .fields
+            .iter()
+            .map(|f| LitStr::new(&f.ident.to_string(), Span::call_site()))
+            .collect();
+        let impl_schemas: Vec<TokenStream> = self
+            .fields
+            .iter()
+            .map(|f| f.schema(f.impl_type()))
+            .collect();
+        let optional_schemas: Vec<TokenStream> = self
+            .fields
+            .iter()
+            .map(|f| f.schema(f.optional_type()))
+            .collect();
+        let required: Vec<LitStr> = self
+            .fields
+            .iter()
+            .filter(|f| f.wrap == Wrap::Plain)
+            .map(|f| LitStr::new(&f.ident.to_string(), Span::call_site()))
+            .collect();
+
+        let natural_key = match &self.natural_key {
+            Some(keys) => quote!(Some(vec![#(#keys.to_string()),*])),
+            None => quote!(None),
+        };
+        let references: Vec<TokenStream> = self
+            .fields
+            .iter()
+            .filter(|f| matches!(f.kind, Kind::Reference))
+            .map(|f| {
+                let name = LitStr::new(&f.ident.to_string(), Span::call_site());
+                let inner = &f.inner;
+                quote! {
+                    crate::cosi_db::model::common::Reference {
+                        field: #name,
+                        table: <#inner as crate::cosi_db::model::common::COSIModel>::table_name,
+                        natural_key: <#inner as crate::cosi_db::model::common::COSIModel>::natural_key,
+                        fields: <#inner as crate::cosi_db::model::common::COSIModel>::fields,
+                    }
+                }
+            })
+            .collect();
+        let formats: Vec<TokenStream> = self
+            .fields
+            .iter()
+            .filter_map(|f| match &f.kind {
+                Kind::Format(fmt) => {
+                    let name = LitStr::new(&f.ident.to_string(), Span::call_site());
+                    Some(quote!((#name, #fmt)))
+                }
+                _ => None,
+            })
+            .collect();
+
+        let form_impls = match &self.validate {
+            Some(validate) => quote! {
+                impl crate::cosi_db::model::common::COSIForm for #impl_ident {
+                    fn sanitize_insert(&self) -> crate::cosi_db::errors::COSIResult<::mongodb::bson::Document>
+                    where
+                        Self: ::serde::Serialize,
+                    {
+                        #validate(&#optional_ident::from(self.clone()))?;
+                        return self.convert_to_document(true);
+                    }
+                }
+
+                impl crate::cosi_db::model::common::COSIForm for #optional_ident {
+                    fn sanitize_insert(&self) -> crate::cosi_db::errors::COSIResult<::mongodb::bson::Document>
+                    where
+                        Self: ::serde::Serialize,
+                    {
+                        #validate(self)?;
+                        return self.convert_to_document(false);
+                    }
+                }
+            },
+            None => quote! {
+                impl crate::cosi_db::model::common::COSIForm for #impl_ident {}
+                impl crate::cosi_db::model::common::COSIForm for #optional_ident {}
+            },
+        };
+
+        let collection_impl = if self.manual_collection {
+            quote!()
+        } else {
+            quote! {
+                #[::rocket::async_trait]
+                impl crate::cosi_db::model::common::COSICollection<'_, #ident, #impl_ident, #optional_ident> for #ident {}
+            }
+        };
+
+        quote! {
+            #[derive(Clone, Debug, ::serde::Deserialize, ::rocket::form::FromForm, ::serde::Serialize)]
+            #vis struct #impl_ident {
+                #(#impl_attrs #field_vis #names: #impl_types,)*
+            }
+
+            #[derive(Clone, Debug, Default, ::serde::Deserialize, ::rocket::form::FromForm, ::serde::Serialize)]
+            #vis struct #optional_ident {
+                #(#field_vis #names: #optional_types,)*
+            }
+
+            impl ::std::convert::From<#ident> for #impl_ident {
+                fn from(v: #ident) -> #impl_ident {
+                    #impl_ident {
+                        #(#names: #to_impl,)*
+                    }
+                }
+            }
+
+            impl ::std::convert::From<#impl_ident> for #ident {
+                fn from(v: #impl_ident) -> #ident {
+                    #ident {
+                        #(#names: #to_orm,)*
+                    }
+                }
+            }
+
+            impl ::std::convert::From<#impl_ident> for #optional_ident {
+                fn from(v: #impl_ident) -> #optional_ident {
+                    #optional_ident {
+                        #(#names: Some(v.#names),)*
+                    }
+                }
+            }
+
+            #form_impls
+
+            impl crate::cosi_db::model::common::COSISchema for #impl_ident {
+                fn schema() -> ::serde_json::Value {
+                    crate::cosi_db::model::common::object_schema(
+                        vec![#((#field_names, #impl_schemas)),*],
+                        vec![#(#required),*],
+                    )
+                }
+            }
+
+            impl crate::cosi_db::model::common::COSISchema for #optional_ident {
+                fn schema() -> ::serde_json::Value {
+                    crate::cosi_db::model::common::object_schema(
+                        vec![#((#field_names, #optional_schemas)),*],
+                        vec![],
+                    )
+                }
+            }
+
+            impl crate::cosi_db::model::common::COSIModel for #ident {
+                fn table_name() -> &'static str {
+                    #table
+                }
+
+                fn fields() -> ::std::vec::Vec<&'static str> {
+                    vec![#(#field_names),*]
+                }
+
+                fn natural_key() -> ::std::option::Option<::std::vec::Vec<::std::string::String>> {
+                    #natural_key
+                }
+
+                fn references() -> ::std::vec::Vec<crate::cosi_db::model::common::Reference> {
+                    vec![#(#references),*]
+                }
+
+                fn formats() -> ::std::vec::Vec<(&'static str, &'static str)> {
+                    vec![#(#formats),*]
+                }
+            }
+
+            #collection_impl
+        }
+    }
+}
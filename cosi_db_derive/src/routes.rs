@@ -0,0 +1,260 @@
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::LitStr;
+
+use crate::model::CosiModel;
+
+// Route handlers are thin wrappers around the generic endpoints in controller::api.
+// Only the paths and concrete types differ between tables.
+pub fn expand(model: &CosiModel) -> TokenStream {
+    let ident = &model.ident;
+    let impl_ident = model.impl_ident();
+    let optional_ident = model.optional_ident();
+    let name = model.table.clone();
+    let module = format_ident!("__cosi_routes_{}", name);
+    let types = quote!(<super::#ident, super::#impl_ident, super::#optional_ident>);
+
+    let path = |p: &str| LitStr::new(&p.replace("{}", &name), Span::call_site());
+    let fn_name = |p: &str| format_ident!("{}", p.replace("{}", &name));
+
+    let gen = fn_name("gen_{}");
+    let get = fn_name("get_{}");
+    let get_by_id = fn_name("get_{}_by_id");
+    let insert = fn_name("insert_{}");
+    let insert_json = fn_name("insert_{}_json");
+    let drop = fn_name("drop_{}");
+    let update = fn_name("update_{}");
+    let update_json = fn_name("update_{}_json");
+    let patch = fn_name("patch_{}");
+    let upsert = fn_name("upsert_{}");
+    let upsert_json = fn_name("upsert_{}_json");
+    let bulk_insert = fn_name("bulk_insert_{}");
+    let bulk_upsert = fn_name("bulk_upsert_{}");
+    let bulk_update = fn_name("bulk_update_{}");
+    let bulk_delete = fn_name("bulk_delete_{}");
+    let v1_list = fn_name("v1_list_{}");
+    let v1_get = fn_name("v1_get_{}");
+    let v1_create = fn_name("v1_create_{}");
+    let v1_put = fn_name("v1_put_{}");
+    let v1_replace = fn_name("v1_replace_{}");
+    let v1_modify = fn_name("v1_modify_{}");
+    let v1_remove = fn_name("v1_remove_{}");
+    let v1_bulk_insert = fn_name("v1_bulk_insert_{}");
+    let v1_bulk_upsert = fn_name("v1_bulk_upsert_{}");
+    let v1_bulk_update = fn_name("v1_bulk_update_{}");
+    let v1_bulk_delete = fn_name("v1_bulk_delete_{}");
+    let v1_export = fn_name("v1_export_{}");
+
+    let gen_path = path("/gen_{}/<total>");
+    let get_path =
+        path("/get_{}?<page>&<page_size>&<cursor>&<sort>&<fields>&<expand>&<search_query..>");
+    let get_by_id_path = path("/get_{}/<oid>?<expand>");
+    let insert_path = path("/insert_{}");
+    let drop_path = path("/drop_{}");
+    let update_path = path("/update_{}?<oid>");
+    let upsert_path = path("/upsert_{}");
+    let bulk_insert_path = path("/bulk_insert_{}");
+    let bulk_upsert_path = path("/bulk_upsert_{}");
+    let bulk_update_path = path("/bulk_update_{}");
+    let bulk_delete_path = path("/bulk_delete_{}");
+    let successor = path("/api/v1/{}");
+    let v1_list_path =
+        path("/api/v1/{}?<page>&<page_size>&<cursor>&<sort>&<fields>&<expand>&<search_query..>");
+    let v1_path = path("/api/v1/{}");
+    let v1_item_path = path("/api/v1/{}/<oid>");
+    let v1_get_path = path("/api/v1/{}/<oid>?<expand>");
+    let v1_bulk_path = path("/api/v1/{}/bulk");
+    let v1_export_path = path("/api/v1/{}/export?<format>&<sort>&<search_query..>");
+
+    quote! {
+        #[doc(hidden)]
+        pub mod #module {
+            use crate::cosi_db::connection::COSIMongo;
+            use crate::cosi_db::controller::api;
+            use crate::cosi_db::controller::api::ApiResult;
+            use crate::cosi_db::controller::common::{BulkUpdate, Deprecated, Download, Located};
+            use crate::cosi_db::model::auth::User;
+
+            use ::rocket::form::Form;
+            use ::rocket::response::content::RawJson;
+            use ::rocket::response::status::{Custom, NoContent};
+            use ::rocket::serde::json::{Error as JsonError, Json};
+            use ::rocket_db_pools::Connection;
+            use ::serde_json::{Map, Value};
+
+            #[::rocket::get(#gen_path)]
+            pub async fn #gen(_user: User, connect: Connection<COSIMongo>, total: u8) -> RawJson<String> {
+                api::generate::#types(&*connect, total).await
+            }
+
+            #[::rocket::get(#get_path)]
+            #[allow(clippy::too_many_arguments)]
+            pub async fn #get(_user: User, connect: Connection<COSIMongo>, page: Option<u64>, page_size: Option<i64>, cursor: Option<String>, sort: Option<String>, fields: Option<String>, expand: Option<bool>, search_query: super::#optional_ident) -> Deprecated<Custom<RawJson<String>>> {
+                Deprecated::new(api::get_page::#types(&*connect, page, page_size, cursor, sort, fields, expand, search_query).await, #successor)
+            }
+
+            #[::rocket::get(#get_by_id_path)]
+            pub async fn #get_by_id(_user: User, connect: Connection<COSIMongo>, oid: &str, expand: Option<bool>) -> Deprecated<Custom<RawJson<String>>> {
+                Deprecated::new(api::get_by_id::#types(&*connect, oid, expand).await, #successor)
+            }
+
+            // Forms and JSON bodies share the same route. JSON is matched first through the content type.
+            #[::rocket::post(#insert_path, data = "<insert_query>", rank = 2)]
+            pub async fn #insert(_user: User, connect: Connection<COSIMongo>, insert_query: Form<super::#impl_ident>) -> Deprecated<Custom<RawJson<String>>> {
+                Deprecated::new(api::insert::#types(&*connect, Ok(insert_query.into_inner())).await, #successor)
+            }
+
+            #[::rocket::post(#insert_path, format = "json", data = "<insert_query>")]
+            pub async fn #insert_json(_user: User, connect: Connection<COSIMongo>, insert_query: Result<Json<super::#impl_ident>, JsonError<'_>>) -> Deprecated<Custom<RawJson<String>>> {
+                Deprecated::new(api::insert::#types(&*connect, insert_query.map(|q| q.into_inner())).await, #successor)
+            }
+
+            #[::rocket::get(#drop_path)]
+            pub async fn #drop(_user: User, connect: Connection<COSIMongo>) -> Deprecated<RawJson<String>> {
+                Deprecated::new(api::drop_table::#types(&*connect).await, #successor)
+            }
+
+            #[::rocket::post(#update_path, data = "<update_query>", rank = 2)]
+            pub async fn #update(_user: User, connect: Connection<COSIMongo>, oid: String, update_query: Form<super::#impl_ident>) -> Deprecated<Custom<RawJson<String>>> {
+                Deprecated::new(api::update::#types(&*connect, &oid, Ok(update_query.into_inner())).await, #successor)
+            }
+
+            #[::rocket::post(#update_path, format = "json", data = "<update_query>")]
+            pub async fn #update_json(_user: User, connect: Connection<COSIMongo>, oid: String, update_query: Result<Json<super::#impl_ident>, JsonError<'_>>) -> Deprecated<Custom<RawJson<String>>> {
+                Deprecated::new(api::update::#types(&*connect, &oid, update_query.map(|q| q.into_inner())).await, #successor)
+            }
+
+            #[::rocket::patch(#update_path, format = "json", data = "<patch_query>")]
+            pub async fn #patch(_user: User, connect: Connection<COSIMongo>, oid: String, patch_query: Result<Json<Map<String, Value>>, JsonError<'_>>) -> Deprecated<Custom<RawJson<String>>> {
+                Deprecated::new(api::patch::#types(&*connect, &oid, patch_query.map(|q| q.into_inner())).await, #successor)
+            }
+
+            #[::rocket::post(#upsert_path, data = "<upsert_query>", rank = 2)]
+            pub async fn #upsert(_user: User, connect: Connection<COSIMongo>, upsert_query: Form<super::#impl_ident>) -> Deprecated<Custom<RawJson<String>>> {
+                Deprecated::new(api::upsert::#types(&*connect, Ok(upsert_query.into_inner())).await, #successor)
+            }
+
+            #[::rocket::post(#upsert_path, format = "json", data = "<upsert_query>")]
+            pub async fn #upsert_json(_user: User, connect: Connection<COSIMongo>, upsert_query: Result<Json<super::#impl_ident>, JsonError<'_>>) -> Deprecated<Custom<RawJson<String>>> {
+                Deprecated::new(api::upsert::#types(&*connect, upsert_query.map(|q| q.into_inner())).await, #successor)
+            }
+
+            #[::rocket::post(#bulk_insert_path, format = "json", data = "<insert_query>")]
+            pub async fn #bulk_insert(_user: User, connect: Connection<COSIMongo>, insert_query: Result<Json<Vec<Value>>, JsonError<'_>>) -> Deprecated<Custom<RawJson<String>>> {
+                Deprecated::new(api::bulk_insert::#types(&*connect, insert_query.map(|q| q.into_inner())).await, #successor)
+            }
+
+            #[::rocket::post(#bulk_upsert_path, format = "json", data = "<upsert_query>")]
+            pub async fn #bulk_upsert(_user: User, connect: Connection<COSIMongo>, upsert_query: Result<Json<Vec<Value>>, JsonError<'_>>) -> Deprecated<Custom<RawJson<String>>> {
+                Deprecated::new(api::bulk_upsert::#types(&*connect, upsert_query.map(|q| q.into_inner())).await, #successor)
+            }
+
+            #[::rocket::post(#bulk_update_path, format = "json", data = "<update_query>")]
+            pub async fn #bulk_update(_user: User, connect: Connection<COSIMongo>, update_query: Result<Json<Vec<BulkUpdate>>, JsonError<'_>>) -> Deprecated<Custom<RawJson<String>>> {
+                Deprecated::new(api::bulk_update::#types(&*connect, update_query.map(|q| q.into_inner())).await, #successor)
+            }
+
+            #[::rocket::post(#bulk_delete_path, format = "json", data = "<delete_query>")]
+            pub async fn #bulk_delete(_user: User, connect: Connection<COSIMongo>, delete_query: Result<Json<Vec<String>>, JsonError<'_>>) -> Deprecated<Custom<RawJson<String>>> {
+                Deprecated::new(api::bulk_delete::#types(&*connect, delete_query.map(|q| q.into_inner())).await, #successor)
+            }
+
+            // Resource style routes. The verb named routes above are kept for older clients.
+            #[::rocket::get(#v1_list_path)]
+            #[allow(clippy::too_many_arguments)]
+            pub async fn #v1_list(_user: User, connect: Connection<COSIMongo>, page: Option<u64>, page_size: Option<i64>, cursor: Option<String>, sort: Option<String>, fields: Option<String>, expand: Option<bool>, search_query: super::#optional_ident) -> Custom<RawJson<String>> {
+                api::get_page::#types(&*connect, page, page_size, cursor, sort, fields, expand, search_query).await
+            }
+
+            #[::rocket::get(#v1_export_path)]
+            pub async fn #v1_export(_user: User, connect: Connection<COSIMongo>, format: Option<String>, sort: Option<String>, search_query: super::#optional_ident) -> ApiResult<Download> {
+                api::export::#types(&*connect, format, sort, search_query).await
+            }
+
+            #[::rocket::get(#v1_get_path)]
+            pub async fn #v1_get(_user: User, connect: Connection<COSIMongo>, oid: &str, expand: Option<bool>) -> Custom<RawJson<String>> {
+                api::get_by_id::#types(&*connect, oid, expand).await
+            }
+
+            #[::rocket::post(#v1_path, format = "json", data = "<create_query>")]
+            pub async fn #v1_create(_user: User, connect: Connection<COSIMongo>, create_query: Result<Json<super::#impl_ident>, JsonError<'_>>) -> ApiResult<Located> {
+                api::create::#types(&*connect, create_query.map(|q| q.into_inner())).await
+            }
+
+            #[::rocket::put(#v1_path, format = "json", data = "<upsert_query>")]
+            pub async fn #v1_put(_user: User, connect: Connection<COSIMongo>, upsert_query: Result<Json<super::#impl_ident>, JsonError<'_>>) -> ApiResult<Located> {
+                api::put::#types(&*connect, upsert_query.map(|q| q.into_inner())).await
+            }
+
+            #[::rocket::put(#v1_item_path, format = "json", data = "<replace_query>")]
+            pub async fn #v1_replace(_user: User, connect: Connection<COSIMongo>, oid: &str, replace_query: Result<Json<super::#impl_ident>, JsonError<'_>>) -> ApiResult<Custom<RawJson<String>>> {
+                api::replace::#types(&*connect, oid, replace_query.map(|q| q.into_inner())).await
+            }
+
+            #[::rocket::patch(#v1_item_path, format = "json", data = "<patch_query>")]
+            pub async fn #v1_modify(_user: User, connect: Connection<COSIMongo>, oid: &str, patch_query: Result<Json<Map<String, Value>>, JsonError<'_>>) -> ApiResult<Custom<RawJson<String>>> {
+                api::modify::#types(&*connect, oid, patch_query.map(|q| q.into_inner())).await
+            }
+
+            #[::rocket::delete(#v1_item_path)]
+            pub async fn #v1_remove(_user: User, connect: Connection<COSIMongo>, oid: &str) -> ApiResult<NoContent> {
+                api::remove::#types(&*connect, oid).await
+            }
+
+            #[::rocket::post(#v1_bulk_path, format = "json", data = "<insert_query>")]
+            pub async fn #v1_bulk_insert(_user: User, connect: Connection<COSIMongo>, insert_query: Result<Json<Vec<Value>>, JsonError<'_>>) -> Custom<RawJson<String>> {
+                api::bulk_insert::#types(&*connect, insert_query.map(|q| q.into_inner())).await
+            }
+
+            #[::rocket::put(#v1_bulk_path, format = "json", data = "<upsert_query>")]
+            pub async fn #v1_bulk_upsert(_user: User, connect: Connection<COSIMongo>, upsert_query: Result<Json<Vec<Value>>, JsonError<'_>>) -> Custom<RawJson<String>> {
+                api::bulk_upsert::#types(&*connect, upsert_query.map(|q| q.into_inner())).await
+            }
+
+            #[::rocket::patch(#v1_bulk_path, format = "json", data = "<update_query>")]
+            pub async fn #v1_bulk_update(_user: User, connect: Connection<COSIMongo>, update_query: Result<Json<Vec<BulkUpdate>>, JsonError<'_>>) -> Custom<RawJson<String>> {
+                api::bulk_update::#types(&*connect, update_query.map(|q| q.into_inner())).await
+            }
+
+            #[::rocket::delete(#v1_bulk_path, format = "json", data = "<delete_query>")]
+            pub async fn #v1_bulk_delete(_user: User, connect: Connection<COSIMongo>, delete_query: Result<Json<Vec<String>>, JsonError<'_>>) -> Custom<RawJson<String>> {
+                api::bulk_delete::#types(&*connect, delete_query.map(|q| q.into_inner())).await
+            }
+        }
+
+        impl #ident {
+            // All endpoints of the table, ready to be mounted.
+            pub fn routes() -> ::std::vec::Vec<::rocket::Route> {
+                ::rocket::routes![
+                    #module::#gen,
+                    #module::#get,
+                    #module::#get_by_id,
+                    #module::#insert,
+                    #module::#insert_json,
+                    #module::#drop,
+                    #module::#update,
+                    #module::#update_json,
+                    #module::#patch,
+                    #module::#upsert,
+                    #module::#upsert_json,
+                    #module::#bulk_insert,
+                    #module::#bulk_upsert,
+                    #module::#bulk_update,
+                    #module::#bulk_delete,
+                    #module::#v1_list,
+                    #module::#v1_get,
+                    #module::#v1_create,
+                    #module::#v1_put,
+                    #module::#v1_replace,
+                    #module::#v1_modify,
+                    #module::#v1_remove,
+                    #module::#v1_bulk_insert,
+                    #module::#v1_bulk_upsert,
+                    #module::#v1_bulk_update,
+                    #module::#v1_bulk_delete,
+                    #module::#v1_export
+                ]
+            }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, LitStr, Result};
+
+use crate::model::{split_wrap, Wrap};
+
+// Schema of a plain struct or of an enum made of unit variants.
+// Enums are serialized by name, structs follow the same rules as the model storage structs.
+pub fn expand(input: &DeriveInput) -> Result<TokenStream> {
+    let ident = &input.ident;
+    let schema = match &input.data {
+        Data::Enum(e) => {
+            let mut variants = vec![];
+            for v in &e.variants {
+                if !matches!(v.fields, Fields::Unit) {
+                    return Err(Error::new_spanned(v, "Expected a unit variant."));
+                }
+                variants.push(LitStr::new(&v.ident.to_string(), Span::call_site()));
+            }
+            quote!(::serde_json::json!({"type": "string", "enum": [#(#variants),*]}))
+        }
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(named) => {
+                let names: Vec<LitStr> = named
+                    .named
+                    .iter()
+                    .map(|f| LitStr::new(&f.ident.as_ref().unwrap().to_string(), Span::call_site()))
+                    .collect();
+                let types = named.named.iter().map(|f| &f.ty);
+                let required: Vec<&LitStr> = named
+                    .named
+                    .iter()
+                    .zip(names.iter())
+                    .filter(|(f, _)| split_wrap(&f.ty).0 == Wrap::Plain)
+                    .map(|(_, n)| n)
+                    .collect();
+                quote! {
+                    crate::cosi_db::model::common::object_schema(
+                        vec![#((#names, <#types as crate::cosi_db::model::common::COSISchema>::schema())),*],
+                        vec![#(#required),*],
+                    )
+                }
+            }
+            _ => return Err(Error::new_spanned(ident, "Expected named fields.")),
+        },
+        _ => return Err(Error::new_spanned(ident, "Expected a struct or an enum.")),
+    };
+
+    Ok(quote! {
+        impl crate::cosi_db::model::common::COSISchema for #ident {
+            fn schema() -> ::serde_json::Value {
+                #schema
+            }
+        }
+    })
+}
@@ -0,0 +1,86 @@
+// Month view of event occurrences, weeks start on Monday.
+const MONTHS = ["January", "February", "March", "April", "May", "June", "July",
+                "August", "September", "October", "November", "December"];
+
+let shown = new Date();
+shown.setDate(1);
+
+function pad(n) {
+    return String(n).padStart(2, "0");
+}
+
+function dateString(d) {
+    return `${d.getFullYear()}-${pad(d.getMonth() + 1)}-${pad(d.getDate())}`;
+}
+
+function render(first, days, occurrences) {
+    let byDay = {};
+    for (let o of occurrences) {
+        let day = o.start_datetime.slice(0, 10);
+        (byDay[day] = byDay[day] || []).push(o);
+    }
+
+    let body = $("#calendar tbody").empty();
+    let row = null;
+    for (let i = 0; i < days.length; i++) {
+        if (i % 7 == 0) {
+            row = $("<tr>").appendTo(body);
+        }
+        let day = days[i];
+        let cell = $("<td>").appendTo(row);
+        if (day.getMonth() != first.getMonth()) {
+            cell.addClass("other-month");
+        }
+        cell.append($("<div class='day'>").text(day.getDate()));
+        for (let o of byDay[dateString(day)] || []) {
+            let item = $("<div class='occurrence'>")
+                .text(`${o.start_datetime.slice(11, 16)} ${o.name}`)
+                .attr("title", o.end_datetime ? `Until ${o.end_datetime}` : "");
+            if (o.cancelled) {
+                item.addClass("cancelled");
+            }
+            if (o.moved) {
+                item.addClass("moved").attr("title", `Moved from ${o.occurrence}`);
+            }
+            cell.append(item);
+        }
+    }
+}
+
+function load() {
+    let first = new Date(shown.getFullYear(), shown.getMonth(), 1);
+    let last = new Date(shown.getFullYear(), shown.getMonth() + 1, 0);
+    $("#calendar-title").text(`${MONTHS[first.getMonth()]} ${first.getFullYear()}`);
+
+    // Whole weeks around the month.
+    let start = new Date(first);
+    start.setDate(start.getDate() - (start.getDay() + 6) % 7);
+    let end = new Date(last);
+    end.setDate(end.getDate() + (7 - end.getDay()) % 7);
+    let days = [];
+    for (let d = new Date(start); d <= end; d.setDate(d.getDate() + 1)) {
+        days.push(new Date(d));
+    }
+
+    $.getJSON(`/api/v1/occurrences?from=${dateString(start)}&to=${dateString(end)}&cancelled=true`)
+        .done((result) => {
+            $("#calendar-status").text("");
+            render(first, days, result.data);
+        })
+        .fail((xhr) => {
+            let err = xhr.responseJSON ? xhr.responseJSON.err : "Could not load events.";
+            $("#calendar-status").text(err);
+        });
+}
+
+$(document).ready(() => {
+    $("#calendar-prev").click(() => {
+        shown.setMonth(shown.getMonth() - 1);
+        load();
+    });
+    $("#calendar-next").click(() => {
+        shown.setMonth(shown.getMonth() + 1);
+        load();
+    });
+    load();
+});
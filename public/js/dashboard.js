@@ -100,9 +100,11 @@ $(document).ready(() => {
     let table = new Table(actionToolbar, $("#data-table"));
 
     // Logic to rerender the table by fetching data from endpoint.
+    let currentFilter = "";
     let updateTable = function(appendFilter = "", page=0) {
+        currentFilter = appendFilter;
         // Update table.
-        let fetchEndpoint = "/" + GET_ENDPOINT_LOOKUP[tableTrack] + `?page=${page}` + appendFilter;
+        let fetchEndpoint = "/" + GET_ENDPOINT_LOOKUP[tableTrack] + `?page=${page}` + table.sortQuery() + appendFilter;
         table.tableDiv.hide();
 
         let tName = ENDPOINT[tableTrack];
@@ -135,6 +137,10 @@ $(document).ready(() => {
     };
 
     miniBoard.setUpdateTable(updateTable);
+    table.setSortCallback(() => {
+        CURRENT_PAGE = 0;
+        updateTable();
+    });
 
     let getStateClass = (name) => {
         if (name == "Address") { return AddressState}
@@ -154,6 +160,7 @@ $(document).ready(() => {
         let tableName = $(this).children(".search-suggestion-table").attr("table");
 
         tableTrack = R_ENDPOINT[tableName.toLowerCase()];
+        table.resetSort();
 
         // update action toolbar
         actionToolbar.setState(getStateClass(tableName));
@@ -167,6 +174,7 @@ $(document).ready(() => {
     $("#address-select").on("click", () => {
         tableTrack = ADDRESS_TABLE_IDX;
         CURRENT_PAGE = 0;
+        table.resetSort();
         actionToolbar.setState(getStateClass("Address"));
         updateTable();
     });
@@ -174,6 +182,7 @@ $(document).ready(() => {
     $("#household-select").on("click", () => {
         tableTrack = HOUSEHOLD_TABLE_IDX;
         CURRENT_PAGE = 0;
+        table.resetSort();
         actionToolbar.setState(null);
         updateTable();
     });
@@ -181,6 +190,7 @@ $(document).ready(() => {
     $("#people-select").on("click", () => {
         tableTrack = PEOPLE_TABLE_IDX;
         CURRENT_PAGE = 0;
+        table.resetSort();
         actionToolbar.setState(getStateClass("Person"));
         updateTable();
     });
@@ -188,6 +198,7 @@ $(document).ready(() => {
     $("#group-select").on("click", () => {
         tableTrack = GROUP_TABLE_IDX;
         CURRENT_PAGE = 0;
+        table.resetSort();
         actionToolbar.setState(getStateClass("Group"));
         updateTable();
     });
@@ -195,10 +206,34 @@ $(document).ready(() => {
     $("#event-select").on("click", () => {
         tableTrack = EVENT_TABLE_IDX;
         CURRENT_PAGE = 0;
+        table.resetSort();
         actionToolbar.setState(getStateClass("Event"));
         updateTable();
     });
 
+    // Downloads every row matching the current filter and sort.
+    for (let format of ["csv", "xlsx"]) {
+        $(`#export-${format}`).on("click", () => {
+            window.location = `/api/v1/${ENDPOINT[tableTrack]}/export?format=${format}` + table.sortQuery() + currentFilter;
+        });
+    }
+
+    // Reload the displayed table when it changes elsewhere.
+    let changeTimer = null;
+    let refreshTable = () => {
+        clearTimeout(changeTimer);
+        changeTimer = setTimeout(() => updateTable(currentFilter, CURRENT_PAGE), 500);
+    };
+    let changes = new EventSource("/api/v1/changes");
+    for (let op of ["insert", "update", "delete", "drop"]) {
+        changes.addEventListener(op, (e) => {
+            if (JSON.parse(e.data)["table"] == ENDPOINT[tableTrack]) {
+                refreshTable();
+            }
+        });
+    }
+    changes.addEventListener("lagged", refreshTable);
+
     // Loading bar hooks.
     let loading =  $("#loading").hide();
 
@@ -0,0 +1,123 @@
+// Child check-in kiosk. Occurrences of today are offered, labels are printed after check-in.
+function pad(n) {
+    return String(n).padStart(2, "0");
+}
+
+function today() {
+    let d = new Date();
+    return `${d.getFullYear()}-${pad(d.getMonth() + 1)}-${pad(d.getDate())}`;
+}
+
+function status(text, error) {
+    $("#kiosk-status").text(text).toggleClass("error", !!error);
+}
+
+function failed(xhr) {
+    status(xhr.responseJSON ? xhr.responseJSON.err : "Request failed.", true);
+}
+
+function selected() {
+    let value = $("#kiosk-occurrence").val();
+    return value ? JSON.parse(value) : null;
+}
+
+function postJSON(url, data) {
+    return $.ajax({url: url, type: "POST", contentType: "application/json", data: JSON.stringify(data)});
+}
+
+function loadOccurrences() {
+    $.getJSON(`/api/v1/occurrences?from=${today()}&to=${today()}`)
+        .done((result) => {
+            let select = $("#kiosk-occurrence").empty();
+            for (let o of result.data) {
+                let value = JSON.stringify({event: o.event, occurrence: o.occurrence});
+                select.append($("<option>").val(value).text(`${o.start_datetime.slice(11, 16)} ${o.name}`));
+            }
+            if (result.data.length == 0) {
+                status("No events today.", true);
+            }
+        })
+        .fail(failed);
+}
+
+function renderLabels(labels) {
+    let area = $("#kiosk-labels").empty();
+    for (let child of labels.children) {
+        let label = $("<div class='label'>").appendTo(area);
+        label.append($("<div class='label-name'>").text(child.name));
+        label.append($("<div class='label-code'>").text(child.code));
+        if (child.notes) {
+            label.append($("<div class='label-notes'>").text(child.notes));
+        }
+    }
+    let guardian = $("<div class='label guardian'>").appendTo(area);
+    guardian.append($("<div class='label-name'>").text(labels.guardian.house_name));
+    guardian.append($("<div>").text(labels.guardian.children.join(", ")));
+    guardian.append($("<div class='label-code'>").text(labels.guardian.code));
+    window.print();
+}
+
+function renderHouseholds(households) {
+    let area = $("#kiosk-households").empty();
+    if (households.length == 0) {
+        area.text("No household found.");
+    }
+    for (let h of households) {
+        let box = $("<div class='household'>").appendTo(area);
+        box.append($("<h3>").text(h.house_name));
+        for (let m of h.members) {
+            let row = $("<label class='member'>").appendTo(box);
+            $("<input type='checkbox'>")
+                .val(m._id)
+                .prop("checked", m.child && !m.checked_in)
+                .prop("disabled", m.checked_in)
+                .appendTo(row);
+            row.append($("<span>").text(` ${m.first_name} ${m.last_name}`));
+            if (m.checked_in) {
+                row.append($("<span class='checked-in'>").text(" checked in"));
+            }
+            if (m.notes) {
+                row.append($("<div class='notes'>").html("<i class='fa fa-exclamation-triangle'></i> ").append(document.createTextNode(m.notes)));
+            }
+        }
+        $("<button>").text("Check In").appendTo(box).click(() => {
+            let persons = box.find("input:checked").map((_, e) => e.value).get();
+            let occurrence = selected();
+            postJSON("/api/v1/kiosk/checkin", Object.assign({household: h._id, persons: persons}, occurrence))
+                .done((labels) => {
+                    status(`Checked in, pickup code ${labels.code}.`);
+                    area.empty();
+                    $("#kiosk-query").val("");
+                    renderLabels(labels);
+                })
+                .fail(failed);
+        });
+    }
+}
+
+$(document).ready(() => {
+    loadOccurrences();
+
+    $("#kiosk-search").submit((e) => {
+        e.preventDefault();
+        let occurrence = selected() || {};
+        let query = {query: $("#kiosk-query").val()};
+        $.getJSON("/api/v1/kiosk/households", Object.assign(query, occurrence))
+            .done((households) => {
+                status("");
+                renderHouseholds(households);
+            })
+            .fail(failed);
+    });
+
+    $("#kiosk-checkout").submit((e) => {
+        e.preventDefault();
+        let occurrence = selected();
+        postJSON("/api/v1/kiosk/checkout", Object.assign({code: $("#kiosk-code").val()}, occurrence))
+            .done((result) => {
+                status(`Checked out ${result.checked_out.length} children.`);
+                $("#kiosk-code").val("");
+            })
+            .fail(failed);
+    });
+});
@@ -0,0 +1,154 @@
+// Renders /openapi.json without any external dependency.
+// Schemas are printed as a compact type tree with $ref resolved.
+
+const MAX_DEPTH = 6;
+
+function resolveRef(spec, schema) {
+    if (schema && schema["$ref"]) {
+        let name = schema["$ref"].split("/").pop();
+        return [name, spec["components"]["schemas"][name]];
+    }
+    return [null, schema];
+}
+
+function describeSchema(spec, schema, indent, depth) {
+    let [name, s] = resolveRef(spec, schema);
+    if (s === undefined || s === null) {
+        return "any";
+    }
+    // Object ids are common enough to keep short.
+    if (name === "ObjectId") {
+        return "ObjectId {\"$oid\": hex}";
+    }
+    if (depth > MAX_DEPTH) {
+        return name || "...";
+    }
+
+    let result;
+    if (s["allOf"]) {
+        result = describeSchema(spec, s["allOf"][0], indent, depth + 1);
+    } else if (s["enum"]) {
+        result = s["enum"].map((v) => JSON.stringify(v)).join(" | ");
+    } else if (s["type"] === "array") {
+        result = "[" + describeSchema(spec, s["items"], indent, depth + 1) + "]";
+    } else if (s["type"] === "object" && s["properties"]) {
+        let required = s["required"] || [];
+        let pad = "  ".repeat(indent + 1);
+        let lines = Object.entries(s["properties"]).map(([k, v]) => {
+            let mark = required.includes(k) ? "" : "?";
+            let line = pad + k + mark + ": " + describeSchema(spec, v, indent + 1, depth + 1);
+            if (v["description"]) {
+                line += "  // " + v["description"];
+            }
+            return line;
+        });
+        result = (name ? name + " " : "") + "{\n" + lines.join("\n") + "\n" + "  ".repeat(indent) + "}";
+    } else {
+        result = s["type"] || "any";
+        if (s["pattern"]) {
+            result += " /" + s["pattern"] + "/";
+        }
+    }
+    if (s["nullable"]) {
+        result += " | null";
+    }
+    return result;
+}
+
+function renderParameters(op) {
+    let params = op["parameters"] || [];
+    if (params.length == 0) {
+        return "";
+    }
+    let table = $("<table>").append(
+        $("<tr>").append($("<th>").text("name"), $("<th>").text("in"), $("<th>").text("type"), $("<th>").text("description"))
+    );
+    for (let p of params) {
+        let schema = p["schema"] || {};
+        let type = schema["type"] || "";
+        if (schema["enum"]) {
+            type = schema["enum"].join(" | ");
+        } else if (schema["type"] === "array") {
+            type = "[" + (schema["items"]["type"] || "") + "]";
+        }
+        let description = [p["description"], schema["description"]].filter((x) => x).join(" ");
+        table.append($("<tr>").append(
+            $("<td>").text(p["name"] + (p["required"] ? "" : "?")),
+            $("<td>").text(p["in"]),
+            $("<td>").text(type),
+            $("<td>").text(description)
+        ));
+    }
+    return $("<div>").append($("<h4>").text("Parameters"), table);
+}
+
+function renderBody(spec, op) {
+    let body = op["requestBody"];
+    if (!body) {
+        return "";
+    }
+    let div = $("<div>").append($("<h4>").text("Body"));
+    for (let [type, content] of Object.entries(body["content"])) {
+        div.append($("<p>").text(type));
+        div.append($("<div class='api-schema'>").text(describeSchema(spec, content["schema"], 0, 0)));
+    }
+    return div;
+}
+
+function renderResponses(spec, op) {
+    let div = $("<div>").append($("<h4>").text("Responses"));
+    for (let [status, response] of Object.entries(op["responses"])) {
+        div.append($("<p>").text(status + ": " + response["description"]));
+        let content = response["content"] || {};
+        if (content["application/json"]) {
+            let schema = content["application/json"]["schema"];
+            div.append($("<div class='api-schema'>").text(describeSchema(spec, schema, 0, 0)));
+        }
+    }
+    return div;
+}
+
+function renderOperation(spec, path, method, op) {
+    let header = $("<div class='api-op-header'>").append(
+        $("<span class='api-method'>").addClass("api-method-" + method).text(method),
+        $("<span class='api-path'>").text(path),
+        $("<span>").text(op["summary"] || "")
+    );
+    let body = $("<div class='api-op-body'>");
+    if (op["description"]) {
+        body.append($("<p>").text(op["description"]));
+    }
+    if (op["deprecated"]) {
+        body.append($("<p>").text("Deprecated, use the matching /api/v1 route."));
+    }
+    if (op["security"] && op["security"].length == 0) {
+        body.append($("<p>").text("No login required."));
+    }
+    body.append(renderParameters(op), renderBody(spec, op), renderResponses(spec, op));
+    header.on("click", () => body.toggle());
+    return $("<div class='api-op'>").toggleClass("api-deprecated", !!op["deprecated"]).append(header, body);
+}
+
+function renderSpec(spec) {
+    $("#api-title").text(spec["info"]["title"] + " " + spec["info"]["version"]);
+    $("#api-description").text(spec["info"]["description"]);
+
+    // Group operations by their first tag, keeping document order.
+    let tags = {};
+    for (let [path, methods] of Object.entries(spec["paths"])) {
+        for (let [method, op] of Object.entries(methods)) {
+            let tag = (op["tags"] || ["other"])[0];
+            if (!(tag in tags)) {
+                tags[tag] = $("<div class='api-tag'>").append($("<h2>").text(tag));
+            }
+            tags[tag].append(renderOperation(spec, path, method, op));
+        }
+    }
+    $("#api-tags").append(Object.values(tags));
+}
+
+$(document).ready(() => {
+    $.getJSON("/openapi.json")
+        .done(renderSpec)
+        .fail(() => $("#api-status").text("Unable to load /openapi.json."));
+});
@@ -22,6 +22,23 @@ class Table {
         this.tableDiv = tableDiv
         this.actionToolbar = actionToolbar;
 
+        // Sorting is done server-side. Clicking a header toggles ascending then descending.
+        this.sortKey = null;
+        this.sortDesc = false;
+        this.sortCallback = null;
+        $("#data-table").on("click", "th", (e) => {
+            let key = $(e.currentTarget).attr("sort-key");
+            if (this.sortKey == key) {
+                this.sortDesc = !this.sortDesc;
+            } else {
+                this.sortKey = key;
+                this.sortDesc = false;
+            }
+            if (this.sortCallback != null) {
+                this.sortCallback();
+            }
+        });
+
         let prevRef = undefined;
         $("#data-table").on("click", "tr", function() {
             if (prevRef !== undefined) {
@@ -35,6 +52,20 @@ class Table {
         });
     }
 
+    setSortCallback(sortCallback) {
+        this.sortCallback = sortCallback;
+    }
+
+    resetSort() {
+        this.sortKey = null;
+        this.sortDesc = false;
+    }
+
+    sortQuery() {
+        if (this.sortKey == null) { return ""; }
+        return `&sort=${this.sortDesc ? "-" : ""}${this.sortKey}`;
+    }
+
     render(tableName, data) {
         const displaySpeed = 200;
         this.tableDiv.hide().empty();
@@ -51,7 +82,10 @@ class Table {
         for (let h = 0; h < keys.length; ++h) {
             if (keys[h] == "_id") { continue; }
             let rename = keys[h] in RENAME ? RENAME[keys[h]] : keys[h];
-            headerRow.append($("<th>").html(rename));
+            if (keys[h] == this.sortKey) {
+                rename += this.sortDesc ? " &#9660;" : " &#9650;";
+            }
+            headerRow.append($("<th>").html(rename).attr("sort-key", keys[h]));
         }
 
         // Body generate.
@@ -1,5 +1,6 @@
 # Importer to import data from external database.
 import os
+import sys
 import json
 import requests
 import pandas
@@ -20,8 +21,9 @@ def cosi_get(endpoint, session, params=None):
     response = session.get(os.path.join(API_URL, endpoint), params=params)
     return response.json()
 
-def cosi_post(endpoint, session, params=None):
-    response = session.post(os.path.join(API_URL, endpoint), data=params)
+def cosi_post(endpoint, session, params=None, json_body=None):
+    # Nested data such as lists of ids is easier to send as JSON.
+    response = session.post(os.path.join(API_URL, endpoint), data=params, json=json_body)
     parsed = response.json()
     assert "err" not in parsed, parsed["err"]
     return parsed
@@ -46,9 +48,17 @@ def nan_to_none(v):
         return None
     return v
 
+def get_external_id(person):
+    # Prefer the id assigned by the legacy system. Fall back to the name and address otherwise.
+    if "Individual ID" in person and str(person["Individual ID"]) != "nan":
+        return str(person["Individual ID"])
+    return "|".join([str(person["FirstName"]), str(person["LastName"]), str(person["Address1"])])
+
 def import_person(people_df, session):
-    # Import people
+    # Import people in a single batch. Re-running updates people matched by external_id.
     person_track = {}
+    people = []
+    keys = []
     for _, person in people_df.iterrows():
         f_date = person["Birth Date"]
         if str(f_date) != "nan":
@@ -67,17 +77,25 @@ def import_person(people_df, session):
             "work_phone": nan_to_none(person["WorkPhone"]),
             "sex": get_gender(person["Gender"]),
             "notes": nan_to_empty(person["Allergy/child notes"]),
-            "emergency_contact": nan_to_empty(person["Emergency Contact"])
+            "emergency_contact": nan_to_empty(person["Emergency Contact"]),
+            "external_id": get_external_id(person)
         }
 
-        result = cosi_post("insert_person", session=session, params=p)
         key = (p["first_name"], p["last_name"], person["Address1"])
-        assert key not in person_track, f"Duplicate name: {key}"
-        assert "$oid" in result
-        person_track[key] = result["$oid"]
+        assert key not in keys, f"Duplicate name: {key}"
+        keys.append(key)
+        people.append(p)
+
+    result = cosi_post("bulk_upsert_person", session=session, json_body=people)
+    failed = [r for r in result["results"] if not r["ok"]]
+    for r in failed:
+        print(f"Unable to import {keys[r['index']]}: {r['err']}")
+    assert len(failed) == 0, f"{len(failed)} people failed to import."
+
+    for r in result["results"]:
+        person_track[keys[r["index"]]] = r["_id"]["$oid"]
     return person_track
 
-
 def import_address(people_df, person_oid_track, session):
     household_track = defaultdict(list)
     for _, person in people_df.iterrows():
@@ -94,15 +112,9 @@ def import_address(people_df, person_oid_track, session):
         if a["line_one"] == "":
             continue
 
-        # Check if db already exists this value
-        res = cosi_get("get_address", session=session, params=a)
-        address_oid = None
-        if len(res["data"]) == 0:
-            res = cosi_post("insert_address", session=session, params=a)
-            address_oid = res["$oid"]
-        else:
-            assert len(res["data"]) == 1
-            address_oid = res["data"][0]["_id"]["$oid"]
+        # Shared addresses resolve to the same entry.
+        res = cosi_post("upsert_address", session=session, params=a)
+        address_oid = res["_id"]["$oid"]
 
         # Don't generate households until the very end.
         # Grab household if it doesn't exist
@@ -118,10 +130,11 @@ def import_address(people_df, person_oid_track, session):
             last_names.append(p_keys[p_values.index(poid)][1])
 
         household_name = max(set(last_names), key=last_names.count)
-        res = cosi_post("insert_household", session=session, params={
+        res = cosi_post("upsert_household", session=session, json_body={
             "house_name": household_name + " Household",
             "address": address_oid,
-            "persons": people
+            "persons": people,
+            "relations": []
         })
 
         assert "err" not in res, res
@@ -140,8 +153,8 @@ def import_group(group_df, person_oid_track, session):
             "group_desc": group_desc[group_name]
         }
 
-        res = cosi_post("insert_group", session, g)
-        oid = res["$oid"]
+        res = cosi_post("upsert_group", session, g)
+        oid = res["_id"]["$oid"]
         for people in tups:
             pg = {
                 "person": person_oid_track[people[0]],
@@ -149,21 +162,22 @@ def import_group(group_df, person_oid_track, session):
                 "role": people[1]
             }
 
-            cosi_post("insert_grouprelation", session, pg)
+            cosi_post("upsert_grouprelation", session, pg)
 
 def main():
     with requests.sessions.Session() as session:
         login("admin@projectcosi.org", "admin", session)
 
-        # Drop data
-        print("**Dropping Tables**")
-        for k in [t.lower() for t in TABLE_NAMES]:
-            cosi_get(f"drop_{k}", session=session)
-            # Double check all data is wiped
-            result = cosi_get(f"get_{k}", session=session, params={"page": 0})
-            assert len(result["data"]) == 0
-            print(f"Dropped: {k}")
-        print()
+        # Imports are idempotent. Only drop data when explicitly asked.
+        if "--drop" in sys.argv:
+            print("**Dropping Tables**")
+            for k in [t.lower() for t in TABLE_NAMES]:
+                cosi_get(f"drop_{k}", session=session)
+                # Double check all data is wiped
+                result = cosi_get(f"get_{k}", session=session, params={"page": 0})
+                assert len(result["data"]) == 0
+                print(f"Dropped: {k}")
+            print()
 
     print("Reading File")
     people_file = "people.csv"
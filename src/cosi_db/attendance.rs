@@ -0,0 +1,310 @@
+// Attendance of event occurrences.
+// Occurrences are named by their scheduled start, as in event exceptions, so moved occurrences
+// keep their attendance. The attendance of an occurrence is its present and late attendees plus
+// the headcount of those not recorded by name.
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime};
+use mongodb::bson::{doc, from_document, oid::ObjectId, to_bson, Bson, Document};
+use mongodb::options::{FindOptions, UpdateOptions};
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::cosi_db::errors::{COSIError, COSIResult};
+use crate::cosi_db::model::attendance::{Attendance, AttendanceStatus, Headcount};
+use crate::cosi_db::model::common::{COSICollection, COSIForm, OID};
+use crate::cosi_db::model::event::Event;
+use crate::cosi_db::model::group::{Group, GroupRelation};
+use crate::cosi_db::model::household::Household;
+use crate::cosi_db::model::person::Person;
+use crate::cosi_db::recurrence::{is_scheduled, DATETIME_FORMAT};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Attendee {
+    pub person: Option<String>,
+    pub group: Option<String>,
+    pub household: Option<String>,
+    pub status: AttendanceStatus,
+}
+
+// Attendance of one occurrence, recorded at once.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AttendanceSheet {
+    pub occurrence: String,
+    // Replaces the previous headcount when given.
+    pub headcount: Option<u32>,
+    #[serde(default)]
+    pub attendees: Vec<Attendee>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Summary {
+    pub present: u64,
+    pub absent: u64,
+    pub late: u64,
+}
+
+impl Summary {
+    fn add(&mut self, status: AttendanceStatus) {
+        match status {
+            AttendanceStatus::Present => self.present += 1,
+            AttendanceStatus::Absent => self.absent += 1,
+            AttendanceStatus::Late => self.late += 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct OccurrenceAttendance {
+    pub occurrence: String,
+    #[serde(flatten)]
+    pub summary: Summary,
+    pub headcount: u32,
+    pub attendance: u64,
+    pub attendees: Vec<Document>,
+}
+
+// The occurrence must be scheduled and not cancelled.
+pub fn check_occurrence(event: &Event, occurrence: &str) -> COSIResult<()> {
+    let time = NaiveDateTime::parse_from_str(occurrence, DATETIME_FORMAT).map_err(|_| {
+        COSIError::msg(format!(
+            "Occurrence {} should be <year>-<month>-<day> <hour>:<minute>:<second>.",
+            occurrence
+        ))
+    })?;
+    if !is_scheduled(event, time) {
+        return Err(COSIError::msg(format!(
+            "{} is not an occurrence of the event.",
+            occurrence
+        )));
+    }
+    if event
+        .exceptions
+        .iter()
+        .any(|e| e.cancelled && e.occurrence == occurrence)
+    {
+        return Err(COSIError::msg(format!(
+            "Occurrence {} is cancelled.",
+            occurrence
+        )));
+    }
+    Ok(())
+}
+
+// Occurrences between two days, both included.
+pub fn between(from: Option<NaiveDate>, to: Option<NaiveDate>) -> Document {
+    let mut filter = Document::new();
+    if let Some(from) = from {
+        filter.insert(
+            "$gte",
+            from.and_hms(0, 0, 0).format(DATETIME_FORMAT).to_string(),
+        );
+    }
+    if let Some(to) = to {
+        let end = (to + Duration::days(1)).and_hms(0, 0, 0);
+        filter.insert("$lt", end.format(DATETIME_FORMAT).to_string());
+    }
+    filter
+}
+
+fn parse_key(value: &Option<String>) -> COSIResult<Option<OID>> {
+    value
+        .as_deref()
+        .map(|v| {
+            ObjectId::parse_str(v)
+                .map(OID::from)
+                .map_err(|_| COSIError::msg(format!("Invalid object id {}.", v)))
+        })
+        .transpose()
+}
+
+async fn record_attendee(
+    client: &Client,
+    event: ObjectId,
+    occurrence: &str,
+    attendee: &Attendee,
+) -> COSIResult<Bson> {
+    let entry = Attendance {
+        event: event.into(),
+        occurrence: occurrence.to_string(),
+        person: parse_key(&attendee.person)?,
+        group: parse_key(&attendee.group)?,
+        household: parse_key(&attendee.household)?,
+        status: attendee.status,
+        timestamp: Local::now().format(DATETIME_FORMAT).to_string(),
+    };
+    entry.validate()?;
+    let (table, col, id) = match (&entry.person, &entry.group, &entry.household) {
+        (Some(p), _, _) => ("person", Person::get_raw_document(client).await, p.0),
+        (_, Some(g), _) => ("group", Group::get_raw_document(client).await, g.0),
+        (_, _, Some(h)) => ("household", Household::get_raw_document(client).await, h.0),
+        _ => unreachable!(),
+    };
+    if col.count_documents(doc! {"_id": id}, None).await? == 0 {
+        return Err(COSIError::msg(format!("No {} found.", table)));
+    }
+
+    // One entry per attendee of the occurrence.
+    let filter = doc! {
+        "event": event,
+        "occurrence": occurrence,
+        "person": to_bson(&entry.person)?,
+        "group": to_bson(&entry.group)?,
+        "household": to_bson(&entry.household)?,
+    };
+    let options = UpdateOptions::builder().upsert(true).build();
+    Attendance::update_datum(
+        client,
+        &filter,
+        &doc! {"$set": entry.sanitize_insert()?},
+        Some(options),
+    )
+    .await?;
+    let stored = Attendance::find_raw_document(client, Some(filter), None)
+        .await?
+        .pop()
+        .ok_or(COSIError::msg("Unable to find recorded attendance."))?;
+    Ok(stored.get("_id").unwrap().clone())
+}
+
+// Records the sheet, returning the result of every attendee.
+pub async fn record(
+    client: &Client,
+    event: ObjectId,
+    sheet: &AttendanceSheet,
+) -> COSIResult<Vec<COSIResult<Bson>>> {
+    if let Some(count) = sheet.headcount {
+        let headcount = Headcount {
+            event: event.into(),
+            occurrence: sheet.occurrence.clone(),
+            count,
+            timestamp: Local::now().format(DATETIME_FORMAT).to_string(),
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+        Headcount::update_datum(
+            client,
+            &doc! {"event": event, "occurrence": &sheet.occurrence},
+            &doc! {"$set": headcount.sanitize_insert()?},
+            Some(options),
+        )
+        .await?;
+    }
+
+    let mut results = vec![];
+    for attendee in &sheet.attendees {
+        results.push(record_attendee(client, event, &sheet.occurrence, attendee).await);
+    }
+    Ok(results)
+}
+
+fn status(data: &Document) -> COSIResult<AttendanceStatus> {
+    Ok(from_document::<Attendance>(data.clone())?.status)
+}
+
+fn entry<'a>(
+    result: &'a mut BTreeMap<String, OccurrenceAttendance>,
+    occurrence: &str,
+) -> &'a mut OccurrenceAttendance {
+    result
+        .entry(occurrence.to_string())
+        .or_insert_with(|| OccurrenceAttendance {
+            occurrence: occurrence.to_string(),
+            ..Default::default()
+        })
+}
+
+// Attendance of the occurrences of an event with any recorded, by occurrence.
+pub async fn event_history(
+    client: &Client,
+    event: ObjectId,
+    range: Document,
+) -> COSIResult<Vec<OccurrenceAttendance>> {
+    let mut filter = doc! {"event": event};
+    if !range.is_empty() {
+        filter.insert("occurrence", range);
+    }
+    let mut result: BTreeMap<String, OccurrenceAttendance> = BTreeMap::new();
+
+    let options = FindOptions::builder().sort(doc! {"_id": 1}).build();
+    for data in Attendance::find_raw_document(client, Some(filter.clone()), Some(options)).await? {
+        let occurrence = entry(&mut result, data.get_str("occurrence")?);
+        occurrence.summary.add(status(&data)?);
+        occurrence.attendees.push(data);
+    }
+    for data in Headcount::find_raw_document(client, Some(filter), None).await? {
+        let headcount: Headcount = from_document(data)?;
+        entry(&mut result, &headcount.occurrence).headcount = headcount.count;
+    }
+
+    let mut result: Vec<OccurrenceAttendance> = result.into_values().collect();
+    for o in result.iter_mut() {
+        o.attendance = o.summary.present + o.summary.late + u64::from(o.headcount);
+    }
+    Ok(result)
+}
+
+// Attendance entries matching the filter, with the name of their event, by occurrence.
+async fn history(
+    client: &Client,
+    mut filter: Document,
+    range: Document,
+) -> COSIResult<(Summary, Vec<Document>)> {
+    if !range.is_empty() {
+        filter.insert("occurrence", range);
+    }
+    let options = FindOptions::builder()
+        .sort(doc! {"occurrence": 1, "_id": 1})
+        .build();
+    let mut data = Attendance::find_raw_document(client, Some(filter), Some(options)).await?;
+
+    let ids: Vec<Bson> = data
+        .iter()
+        .filter_map(|d| d.get("event").cloned())
+        .collect();
+    let names: HashMap<ObjectId, String> =
+        Event::find_raw_document(client, Some(doc! {"_id": {"$in": ids}}), None)
+            .await?
+            .into_iter()
+            .filter_map(|e| {
+                Some((
+                    e.get_object_id("_id").ok()?,
+                    e.get_str("name").ok()?.to_string(),
+                ))
+            })
+            .collect();
+
+    let mut summary = Summary::default();
+    for d in data.iter_mut() {
+        summary.add(status(d)?);
+        let name = d
+            .get_object_id("event")
+            .ok()
+            .and_then(|id| names.get(&id).cloned());
+        d.insert("name", name);
+    }
+    Ok((summary, data))
+}
+
+pub async fn person_history(
+    client: &Client,
+    person: ObjectId,
+    range: Document,
+) -> COSIResult<(Summary, Vec<Document>)> {
+    history(client, doc! {"person": person}, range).await
+}
+
+// Entries of the group and of its members.
+pub async fn group_history(
+    client: &Client,
+    group: ObjectId,
+    range: Document,
+) -> COSIResult<(Summary, Vec<Document>)> {
+    let members: Vec<Bson> =
+        GroupRelation::find_raw_document(client, Some(doc! {"group": group}), None)
+            .await?
+            .into_iter()
+            .filter_map(|r| r.get("person").cloned())
+            .collect();
+    let filter = doc! {"$or": [{"group": group}, {"person": {"$in": members}}]};
+    history(client, filter, range).await
+}
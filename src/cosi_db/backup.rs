@@ -0,0 +1,417 @@
+// Backup archives of the whole database.
+// An archive is a gzipped tar holding manifest.json plus <table>.jsonl for every table, one
+// document per line in canonical extended JSON so that dates and object ids survive a restore.
+// Restores are checked against the current models before anything is written and only go into
+// an empty database, either the live one or a tenant database named cosi_db_<tenant>. Logins are
+// merged instead: users already stored are kept, so the administrator restoring the archive
+// stays logged in.
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::stream::TryStreamExt;
+use mongodb::bson::{doc, from_document, oid::ObjectId, Bson, Document};
+use mongodb::options::FindOptions;
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::cosi_db::errors::{COSIError, COSIResult};
+use crate::cosi_db::model::address::{Address, AddressImpl};
+use crate::cosi_db::model::attendance::{Attendance, Headcount};
+use crate::cosi_db::model::auth::{User, UserLogin};
+use crate::cosi_db::model::checkin::CheckIn;
+use crate::cosi_db::model::common::{COSIData, COSIForm, COSIModel};
+use crate::cosi_db::model::event::{Event, EventImpl, EventRegistration, EventRegistrationImpl};
+use crate::cosi_db::model::group::{Group, GroupImpl, GroupRelation, GroupRelationImpl};
+use crate::cosi_db::model::household::{Household, HouseholdImpl};
+use crate::cosi_db::model::person::{Person, PersonImpl};
+use crate::cosi_db::model::room::{Room, RoomImpl};
+use crate::cosi_db::model::webhook::Webhook;
+
+pub const DATABASE: &str = "cosi_db";
+pub const ARCHIVE_FORMAT: &str = "cosi-db-backup";
+// Layout of the archive itself.
+pub const ARCHIVE_VERSION: u32 = 1;
+// Bump when stored models change in a way older archives no longer fit.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const RESTORE_BATCH: usize = 500;
+// Reported invalid documents. Validation stops after that many.
+const MAX_ERRORS: usize = 100;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    pub schema_version: u32,
+    pub app_version: String,
+    pub database: String,
+    pub created: String,
+    // Table to number of documents.
+    pub collections: BTreeMap<String, u64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DocumentError {
+    pub table: String,
+    // Line in <table>.jsonl, starting at 1.
+    pub line: u64,
+    pub err: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RestoreReport {
+    pub database: String,
+    pub dry_run: bool,
+    pub created: String,
+    pub schema_version: u32,
+    pub collections: BTreeMap<String, u64>,
+    pub errors: Vec<DocumentError>,
+}
+
+// Documents must fit the storage struct of their table.
+fn check<I: COSIData + COSIForm>(data: Document) -> COSIResult<()> {
+    let imp: I = from_document(data)?;
+    imp.sanitize_insert()?;
+    Ok(())
+}
+
+type Check = fn(Document) -> COSIResult<()>;
+// Documents to restore per table.
+pub type TableData = Vec<(&'static str, Vec<Document>)>;
+
+// Tables in a backup. Webhook deliveries are a log and are left out.
+fn tables() -> Vec<(&'static str, Check)> {
+    vec![
+        (Person::table_name(), check::<PersonImpl>),
+        (Address::table_name(), check::<AddressImpl>),
+        (Household::table_name(), check::<HouseholdImpl>),
+        (Group::table_name(), check::<GroupImpl>),
+        (GroupRelation::table_name(), check::<GroupRelationImpl>),
+        (Room::table_name(), check::<RoomImpl>),
+        (Event::table_name(), check::<EventImpl>),
+        (
+            EventRegistration::table_name(),
+            check::<EventRegistrationImpl>,
+        ),
+        (Attendance::table_name(), check::<Attendance>),
+        (Headcount::table_name(), check::<Headcount>),
+        (CheckIn::table_name(), check::<CheckIn>),
+        (User::table_name(), check::<User>),
+        (UserLogin::table_name(), check::<UserLogin>),
+        (Webhook::table_name(), check::<Webhook>),
+    ]
+}
+
+// The live database without a tenant.
+pub fn database_name(tenant: Option<&str>) -> COSIResult<String> {
+    match tenant {
+        None => Ok(DATABASE.to_string()),
+        Some(t)
+            if !t.is_empty()
+                && t.len() <= 32
+                && t.chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') =>
+        {
+            Ok(format!("{}_{}", DATABASE, t))
+        }
+        Some(_) => Err(COSIError::msg(
+            "Tenant names hold up to 32 lowercase letters, digits or _.",
+        )),
+    }
+}
+
+// Tables merged into the stored data rather than expected to be empty.
+fn is_merged(table: &str) -> bool {
+    table == User::table_name() || table == UserLogin::table_name()
+}
+
+fn append<W: Write>(builder: &mut tar::Builder<W>, path: &str, data: &[u8]) -> COSIResult<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+pub async fn backup(client: &Client, database: &str) -> COSIResult<Vec<u8>> {
+    let db = client.database(database);
+    let mut collections = BTreeMap::new();
+    let mut files = vec![];
+    for (table, _) in tables() {
+        let options = FindOptions::builder().sort(doc! {"_id": 1}).build();
+        let mut cursor = db.collection::<Document>(table).find(None, options).await?;
+        let mut body: Vec<u8> = vec![];
+        let mut count = 0;
+        while let Some(data) = cursor.try_next().await? {
+            serde_json::to_writer(&mut body, &Bson::Document(data).into_canonical_extjson())?;
+            body.push(b'\n');
+            count += 1;
+        }
+        collections.insert(table.to_string(), count);
+        files.push((format!("{}.jsonl", table), body));
+    }
+
+    let manifest = Manifest {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        schema_version: SCHEMA_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        database: database.to_string(),
+        created: chrono::Utc::now().to_rfc3339(),
+        collections,
+    };
+    let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+    append(
+        &mut builder,
+        MANIFEST,
+        &serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    for (path, body) in files {
+        append(&mut builder, &path, &body)?;
+    }
+    Ok(builder.into_inner()?.finish()?)
+}
+
+fn read_archive(bytes: &[u8]) -> COSIResult<(Manifest, HashMap<String, String>)> {
+    let invalid = |_| COSIError::msg("Not a gzipped tar archive.");
+    let mut archive = tar::Archive::new(GzDecoder::new(bytes));
+    let mut files = HashMap::new();
+    for entry in archive.entries().map_err(invalid)? {
+        let mut entry = entry.map_err(invalid)?;
+        let path = entry.path()?.to_string_lossy().to_string();
+        let mut text = String::new();
+        entry.read_to_string(&mut text)?;
+        files.insert(path, text);
+    }
+
+    let manifest: Manifest = match files.remove(MANIFEST) {
+        Some(text) => serde_json::from_str(&text)
+            .map_err(|err| COSIError::msg(format!("Invalid manifest: {}", err)))?,
+        None => return Err(COSIError::msg("Archive has no manifest.")),
+    };
+    if manifest.format != ARCHIVE_FORMAT {
+        return Err(COSIError::msg("Archive is not a cosi-db backup."));
+    }
+    if manifest.version > ARCHIVE_VERSION {
+        return Err(COSIError::msg(format!(
+            "Archive version {} is newer than the supported version {}.",
+            manifest.version, ARCHIVE_VERSION
+        )));
+    }
+    if manifest.schema_version != SCHEMA_VERSION {
+        return Err(COSIError::msg(format!(
+            "Archive schema version {} does not match the current schema version {}.",
+            manifest.schema_version, SCHEMA_VERSION
+        )));
+    }
+    Ok((manifest, files))
+}
+
+fn parse_document(line: &str, check: Check) -> COSIResult<Document> {
+    let value: Value = serde_json::from_str(line)?;
+    let data = match Bson::try_from(value)? {
+        Bson::Document(d) => d,
+        _ => return Err(COSIError::msg("Expected a document.")),
+    };
+    if data.get_object_id("_id").is_err() {
+        return Err(COSIError::msg("Missing object id."));
+    }
+    check(data.clone())?;
+    Ok(data)
+}
+
+// Fails on archives that cannot be read. Invalid documents are listed in the report,
+// the documents are only returned when there are none.
+pub fn validate(bytes: &[u8]) -> COSIResult<(RestoreReport, TableData)> {
+    let (manifest, mut files) = read_archive(bytes)?;
+    let tables = tables();
+    if let Some(path) = files
+        .keys()
+        .find(|p| !tables.iter().any(|(t, _)| **p == format!("{}.jsonl", t)))
+    {
+        return Err(COSIError::msg(format!("Unknown archive entry {}.", path)));
+    }
+
+    let mut report = RestoreReport {
+        created: manifest.created.clone(),
+        schema_version: manifest.schema_version,
+        ..Default::default()
+    };
+    let mut result = vec![];
+    for (table, check) in tables {
+        let expected = manifest.collections.get(table).copied();
+        let text = files.remove(&format!("{}.jsonl", table));
+        let text = match (expected, text) {
+            (None, None) => continue,
+            (Some(_), Some(text)) => text,
+            _ => {
+                return Err(COSIError::msg(format!(
+                    "Manifest and archive disagree on table {}.",
+                    table
+                )))
+            }
+        };
+
+        let mut data = vec![];
+        for (idx, line) in text.lines().enumerate() {
+            if report.errors.len() >= MAX_ERRORS {
+                break;
+            }
+            match parse_document(line, check) {
+                Ok(d) => data.push(d),
+                Err(err) => report.errors.push(DocumentError {
+                    table: table.to_string(),
+                    line: idx as u64 + 1,
+                    err: err.to_string(),
+                }),
+            }
+        }
+        let count = text.lines().count() as u64;
+        if expected != Some(count) {
+            return Err(COSIError::msg(format!(
+                "Table {} holds {} documents, the manifest lists {}.",
+                table,
+                count,
+                expected.unwrap_or(0)
+            )));
+        }
+        report.collections.insert(table.to_string(), count);
+        result.push((table, data));
+    }
+
+    if !report.errors.is_empty() {
+        result.clear();
+    }
+    Ok((report, result))
+}
+
+pub async fn is_empty(client: &Client, database: &str) -> COSIResult<bool> {
+    let db = client.database(database);
+    for (table, _) in tables().into_iter().filter(|(t, _)| !is_merged(t)) {
+        if db
+            .collection::<Document>(table)
+            .estimated_document_count(None)
+            .await?
+            > 0
+        {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// Leaves out users already stored, matched by id, username or email, along with their logins.
+async fn merge_logins(client: &Client, database: &str, data: &mut TableData) -> COSIResult<()> {
+    let db = client.database(database);
+    let stored = db
+        .collection::<Document>(User::table_name())
+        .find(None, None)
+        .await?
+        .try_collect::<Vec<Document>>()
+        .await?;
+    let taken = |d: &Document, field: &str| {
+        let value = d.get(field);
+        value.is_some() && stored.iter().any(|s| s.get(field) == value)
+    };
+
+    let mut skipped: Vec<ObjectId> = vec![];
+    for (table, docs) in data.iter_mut() {
+        if *table == User::table_name() {
+            docs.retain(|d| {
+                let keep = !["_id", "username", "email"].iter().any(|f| taken(d, f));
+                if !keep {
+                    skipped.extend(d.get_object_id("_id").ok());
+                }
+                keep
+            });
+        } else if *table == UserLogin::table_name() {
+            docs.retain(|d| {
+                d.get_object_id("user_id")
+                    .map_or(true, |id| !skipped.contains(&id))
+            });
+        }
+    }
+    Ok(())
+}
+
+// Expects an empty database but for the logins. Restored documents are removed again when a
+// write fails.
+pub async fn restore(client: &Client, database: &str, mut data: TableData) -> COSIResult<()> {
+    merge_logins(client, database, &mut data).await?;
+    let db = client.database(database);
+    for (table, docs) in &data {
+        let collection = db.collection::<Document>(table);
+        for chunk in docs.chunks(RESTORE_BATCH) {
+            if let Err(err) = collection.insert_many(chunk, None).await {
+                for (t, docs) in &data {
+                    let ids: Vec<Bson> =
+                        docs.iter().filter_map(|d| d.get("_id").cloned()).collect();
+                    db.collection::<Document>(t)
+                        .delete_many(doc! {"_id": {"$in": ids}}, None)
+                        .await?;
+                }
+                return Err(err.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+const USAGE: &str = "Usage:
+    cosi-db backup <file> [--tenant <name>]
+    cosi-db restore <file> [--tenant <name>] [--dry-run]";
+
+// Command line entry point. The database is the one configured in Rocket.toml.
+pub async fn cli(args: &[String]) -> COSIResult<()> {
+    let (command, path) = match args {
+        [command, path, ..] if !path.starts_with("--") => (command.as_str(), path),
+        _ => return Err(COSIError::msg(USAGE)),
+    };
+    let mut tenant: Option<&str> = None;
+    let mut dry_run = false;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--tenant" => tenant = Some(options.next().ok_or(COSIError::msg(USAGE))?),
+            "--dry-run" if command == "restore" => dry_run = true,
+            _ => return Err(COSIError::msg(USAGE)),
+        }
+    }
+    let database = database_name(tenant)?;
+
+    let url: String = rocket::Config::figment().extract_inner("databases.mongodb.url")?;
+    let client = Client::with_uri_str(&url).await?;
+    match command {
+        "backup" => {
+            std::fs::write(path, backup(&client, &database).await?)?;
+            println!("Wrote {} to {}.", database, path);
+        }
+        "restore" => {
+            if !dry_run && !is_empty(&client, &database).await? {
+                return Err(COSIError::msg(format!(
+                    "Database {} is not empty.",
+                    database
+                )));
+            }
+            let (mut report, docs) = validate(&std::fs::read(path)?)?;
+            report.database = database.clone();
+            report.dry_run = dry_run;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            if !report.errors.is_empty() {
+                return Err(COSIError::msg("Archive holds invalid documents."));
+            }
+            if !dry_run {
+                restore(&client, &database, docs).await?;
+                println!("Restored {}.", database);
+            }
+        }
+        _ => return Err(COSIError::msg(USAGE)),
+    }
+    Ok(())
+}
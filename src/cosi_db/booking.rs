@@ -0,0 +1,255 @@
+// Rooms reserved by events.
+// An event holds its rooms over each of its occurrences, cancelled ones excepted, and two events
+// cannot hold a room at overlapping times. Occurrences without an end hold the room for an hour.
+// Two events are compared over the two years after both have started, so that events repeating
+// without end are checked against those starting later. Repeating events that would only clash
+// later than that are not refused.
+use std::collections::HashMap;
+
+use chrono::{Duration, NaiveDateTime};
+use mongodb::bson::{doc, from_document, oid::ObjectId, Document};
+use mongodb::Client;
+
+use crate::cosi_db::errors::{COSIError, COSIResult, Refused};
+use crate::cosi_db::ical::find_events;
+use crate::cosi_db::model::common::{COSICollection, OID};
+use crate::cosi_db::model::event::{Event, EventImpl};
+use crate::cosi_db::model::room::Room;
+use crate::cosi_db::recurrence::{occurrences, Occurrence, DATETIME_FORMAT};
+
+pub const HORIZON_DAYS: i64 = 731;
+const DEFAULT_HOURS: i64 = 1;
+// Changes to other fields of an event keep its bookings.
+const SCHEDULE_FIELDS: [&str; 7] = [
+    "start_datetime",
+    "end_datetime",
+    "meeting_days",
+    "freq",
+    "reoccuring",
+    "exceptions",
+    "rooms",
+];
+
+type Span = (NaiveDateTime, NaiveDateTime, Occurrence);
+
+fn parse(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, DATETIME_FORMAT).ok()
+}
+
+fn format(value: NaiveDateTime) -> String {
+    value.format(DATETIME_FORMAT).to_string()
+}
+
+fn parse_event(data: &Document) -> COSIResult<Event> {
+    let event: EventImpl = from_document(data.clone())?;
+    let datetimes = std::iter::once(&event.start_datetime).chain(event.end_datetime.iter());
+    for d in datetimes {
+        parse(d).ok_or(COSIError::msg(format!(
+            "Datetime {} should be <year>-<month>-<day> <hour>:<minute>:<second>.",
+            d
+        )))?;
+    }
+    Ok(event.into())
+}
+
+// Occurrences over [from, to) with the times they hold their rooms.
+fn spans(id: &ObjectId, event: &Event, from: NaiveDateTime, to: NaiveDateTime) -> Vec<Span> {
+    occurrences(id, event, from, to, false)
+        .into_iter()
+        .filter_map(|o| {
+            let start = parse(&o.start_datetime)?;
+            let end = match o.end_datetime.as_deref().and_then(parse) {
+                Some(end) => end,
+                None => start + Duration::hours(DEFAULT_HOURS),
+            };
+            Some((start, end, o))
+        })
+        .collect()
+}
+
+// First pair of overlapping spans, both lists sorted by start.
+fn overlap<'a>(a: &'a [Span], b: &'a [Span]) -> Option<(&'a Span, &'a Span)> {
+    for x in a {
+        for y in b.iter().take_while(|y| y.0 < x.1) {
+            if x.0 < y.1 {
+                return Some((x, y));
+            }
+        }
+    }
+    None
+}
+
+// Refuses the event when one of its rooms is held by another event at the same time.
+// Events of the batch are checked along with the stored ones. The stored event with the id or
+// natural key is the one being written.
+async fn check(
+    client: &Client,
+    id: Option<ObjectId>,
+    event: &Event,
+    natural_key: Option<Document>,
+    batch: &[(ObjectId, Event)],
+) -> COSIResult<()> {
+    if event.rooms.is_empty() {
+        return Ok(());
+    }
+    let rooms = OID::vec_to_object_id(&event.rooms);
+    let mut names: HashMap<ObjectId, String> = HashMap::new();
+    for r in Room::find_raw_document(client, Some(doc! {"_id": {"$in": &rooms}}), None).await? {
+        let name = format!("{} {}", r.get_str("facility")?, r.get_str("name")?);
+        names.insert(r.get_object_id("_id")?, name);
+    }
+    if let Some(missing) = rooms.iter().find(|r| !names.contains_key(r)) {
+        return Err(Refused(format!("Room {} does not exist.", missing.to_hex())).into());
+    }
+
+    let mut filter = doc! {"rooms": {"$in": &rooms}};
+    if let Some(id) = id {
+        filter.insert("_id", doc! {"$ne": id});
+    }
+    if let Some(key) = natural_key {
+        filter.insert("$nor", vec![key]);
+    }
+    let mut others = find_events(client, Some(filter)).await?;
+    others.extend(batch.iter().cloned());
+
+    for (other_id, other) in &others {
+        let shared = match other.rooms.iter().find(|r| names.contains_key(&r.0)) {
+            Some(r) => &names[&r.0],
+            None => continue,
+        };
+        let from = event.start_datetime.max(other.start_datetime);
+        let to = from + Duration::days(HORIZON_DAYS);
+        let own = spans(&id.unwrap_or_default(), event, from, to);
+        let theirs = spans(other_id, other, from, to);
+        if let Some((_, (start, end, _))) = overlap(&own, &theirs) {
+            return Err(Refused(format!(
+                "{} is booked by {} from {} to {}.",
+                shared,
+                other.name,
+                format(*start),
+                format(*end)
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+async fn check_one(
+    client: &Client,
+    data: &Document,
+    batch: &[(ObjectId, Event)],
+) -> COSIResult<Event> {
+    let event = parse_event(data)?;
+    let natural_key = Event::natural_key_query(data).ok();
+    check(
+        client,
+        data.get_object_id("_id").ok(),
+        &event,
+        natural_key,
+        batch,
+    )
+    .await?;
+    Ok(event)
+}
+
+// Checks events about to be inserted, each against the ones of the batch before it.
+pub async fn check_all(client: &Client, data: &[Document]) -> Vec<COSIResult<()>> {
+    let mut batch: Vec<(ObjectId, Event)> = vec![];
+    let mut results = vec![];
+    for d in data {
+        match check_one(client, d, &batch).await {
+            Ok(event) => {
+                batch.push((d.get_object_id("_id").unwrap_or_default(), event));
+                results.push(Ok(()));
+            }
+            Err(err) => results.push(Err(err)),
+        }
+    }
+    results
+}
+
+// Applies the $set, $unset, $addToSet and $pull of a patch, see controller::common::build_patch.
+// Returns whether a field of the schedule changed.
+fn apply(current: &mut Document, update: &Document) -> COSIResult<bool> {
+    let mut touched = false;
+    for (op, fields) in update {
+        let fields = match fields.as_document() {
+            Some(fields) => fields,
+            None => continue,
+        };
+        for (field, value) in fields {
+            touched |= SCHEDULE_FIELDS.contains(&field.as_str());
+            if op == "$set" {
+                current.insert(field, value.clone());
+                continue;
+            }
+            if op == "$unset" {
+                current.remove(field);
+                continue;
+            }
+            let mut items = current.get_array(field).cloned().unwrap_or_default();
+            let operand = |key: &str| {
+                value
+                    .as_document()
+                    .and_then(|d| d.get_array(key).ok())
+                    .ok_or(COSIError::msg(format!("Unexpected update of {}.", field)))
+            };
+            match op.as_str() {
+                "$addToSet" => {
+                    for item in operand("$each")? {
+                        if !items.contains(item) {
+                            items.push(item.clone());
+                        }
+                    }
+                }
+                "$pull" => {
+                    let removed = operand("$in")?;
+                    items.retain(|v| !removed.contains(v));
+                }
+                _ => return Err(COSIError::msg(format!("Unexpected update {}.", op))),
+            }
+            current.insert(field, items);
+        }
+    }
+    Ok(touched)
+}
+
+// Checks the event matching the query as it is once the update is applied.
+pub async fn check_update(client: &Client, query: &Document, update: &Document) -> COSIResult<()> {
+    let mut current = match Event::find_raw_document(client, Some(query.clone()), None)
+        .await?
+        .pop()
+    {
+        Some(current) => current,
+        None => return Ok(()),
+    };
+    if !apply(&mut current, update)? {
+        return Ok(());
+    }
+    let event = parse_event(&current)?;
+    check(
+        client,
+        Some(current.get_object_id("_id")?),
+        &event,
+        None,
+        &[],
+    )
+    .await
+}
+
+// Occurrences holding the room over [from, to), by start.
+pub async fn bookings(
+    client: &Client,
+    room: ObjectId,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> COSIResult<Vec<Occurrence>> {
+    let filter = doc! {"rooms": room, "start_datetime": {"$lt": format(to)}};
+    let mut result = vec![];
+    for (id, event) in find_events(client, Some(filter)).await? {
+        result.extend(occurrences(&id, &event, from, to, false));
+    }
+    result.sort_by(|a, b| a.start_datetime.cmp(&b.start_datetime));
+    Ok(result)
+}
@@ -0,0 +1,135 @@
+// Live change notifications for the tables.
+// Mongo change streams are used when the deployment supports them (replica sets),
+// otherwise COSICollection writes publish their changes in process.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use futures::stream::StreamExt;
+use mongodb::bson::{oid::ObjectId, Bson};
+use mongodb::change_stream::event::OperationType;
+use mongodb::Client;
+use rocket::fairing::AdHoc;
+use rocket::tokio::sync::broadcast;
+use rocket_db_pools::Database;
+use serde::Serialize;
+
+use crate::cosi_db::connection::COSIMongo;
+
+// Events kept for slow subscribers before they start lagging.
+const FEED_CAPACITY: usize = 1024;
+
+static FEED: OnceLock<broadcast::Sender<ChangeEvent>> = OnceLock::new();
+static CHANGE_STREAMS: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeOp {
+    Insert,
+    Update,
+    Delete,
+    Drop,
+}
+
+impl ChangeOp {
+    pub fn parse(op: &str) -> Option<ChangeOp> {
+        match op {
+            "insert" => Some(ChangeOp::Insert),
+            "update" => Some(ChangeOp::Update),
+            "delete" => Some(ChangeOp::Delete),
+            "drop" => Some(ChangeOp::Drop),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeOp::Insert => "insert",
+            ChangeOp::Update => "update",
+            ChangeOp::Delete => "delete",
+            ChangeOp::Drop => "drop",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ChangeEvent {
+    pub table: String,
+    pub op: ChangeOp,
+    // Missing when the change does not concern a single datum (e.g. drop).
+    pub _id: Option<String>,
+}
+
+impl ChangeEvent {
+    pub fn oid(&self) -> Option<ObjectId> {
+        self._id
+            .as_deref()
+            .and_then(|id| ObjectId::parse_str(id).ok())
+    }
+}
+
+fn feed() -> &'static broadcast::Sender<ChangeEvent> {
+    FEED.get_or_init(|| broadcast::channel(FEED_CAPACITY).0)
+}
+
+pub fn subscribe() -> broadcast::Receiver<ChangeEvent> {
+    feed().subscribe()
+}
+
+fn send(table: &str, op: ChangeOp, id: Option<&Bson>) {
+    let _id = id.map(|id| match id {
+        Bson::ObjectId(oid) => oid.to_hex(),
+        Bson::String(s) => s.clone(),
+        other => other.to_string(),
+    });
+    // Fails only when nobody is listening.
+    let _ = feed().send(ChangeEvent {
+        table: table.to_string(),
+        op,
+        _id,
+    });
+}
+
+// Called on writes. Ignored while the change stream already reports them.
+pub fn publish(table: &str, op: ChangeOp, id: Option<&Bson>) {
+    if !CHANGE_STREAMS.load(Ordering::Relaxed) {
+        send(table, op, id);
+    }
+}
+
+async fn watch(client: Client) {
+    let mut stream = match client.database("cosi_db").watch(None, None).await {
+        Ok(stream) => stream,
+        // Standalone servers do not support change streams.
+        Err(_) => return,
+    };
+    CHANGE_STREAMS.store(true, Ordering::Relaxed);
+
+    while let Some(Ok(event)) = stream.next().await {
+        let op = match event.operation_type {
+            OperationType::Insert => ChangeOp::Insert,
+            OperationType::Update | OperationType::Replace => ChangeOp::Update,
+            OperationType::Delete => ChangeOp::Delete,
+            OperationType::Drop | OperationType::Rename => ChangeOp::Drop,
+            _ => continue,
+        };
+        let table = match event.ns.and_then(|ns| ns.coll) {
+            Some(table) => table,
+            None => continue,
+        };
+        let id = event.document_key.as_ref().and_then(|k| k.get("_id"));
+        send(&table, op, id);
+    }
+
+    // Fall back to in-process notifications if the stream ends.
+    CHANGE_STREAMS.store(false, Ordering::Relaxed);
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Change Feed", |rocket| {
+        Box::pin(async move {
+            if let Some(db) = COSIMongo::fetch(rocket) {
+                rocket::tokio::spawn(watch(Client::clone(db)));
+            }
+        })
+    })
+}
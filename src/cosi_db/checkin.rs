@@ -0,0 +1,504 @@
+// Child check-in at the kiosk.
+// Households are looked up by phone or name and their children are checked into an occurrence
+// with a random pickup code, printed on the labels of the children and of the guardian. Check-out
+// requires that code. Checked in children are marked present, see cosi_db::attendance.
+// A unique index keeps a child from being checked in twice, even from two kiosks at once.
+use std::collections::HashMap;
+
+use chrono::{Datelike, Local, NaiveDate};
+use mongodb::bson::{doc, from_document, oid::ObjectId, Bson, Document};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::{FindOptions, IndexOptions};
+use mongodb::{Client, IndexModel};
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::cosi_db::attendance::{self, AttendanceSheet, Attendee};
+use crate::cosi_db::changes::{self, ChangeOp};
+use crate::cosi_db::errors::{COSIError, COSIResult};
+use crate::cosi_db::model::attendance::AttendanceStatus;
+use crate::cosi_db::model::checkin::CheckIn;
+use crate::cosi_db::model::common::{COSICollection, COSIModel};
+use crate::cosi_db::model::household::{HouseRelationStatus, Household, HouseholdImpl};
+use crate::cosi_db::model::person::Person;
+use crate::cosi_db::recurrence::DATETIME_FORMAT;
+
+// Letters and digits that cannot be mistaken for one another.
+const CODE_CHARS: &[u8] = b"ACDEFHJKLMNPRTUVWXY34679";
+// Long enough that guessing the code of some child checked in takes millions of attempts.
+const CODE_LENGTH: usize = 6;
+pub const MAX_HOUSEHOLDS: i64 = 20;
+// Phone lookups need at least that many digits.
+const MIN_PHONE_DIGITS: usize = 4;
+const ADULT_AGE: i32 = 18;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Member {
+    pub _id: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub dob: Option<NaiveDate>,
+    // A child in the household relations, or younger than 18.
+    pub child: bool,
+    // Allergies and other care notes of the person.
+    pub notes: String,
+    pub checked_in: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct KioskHousehold {
+    pub _id: String,
+    pub house_name: String,
+    pub members: Vec<Member>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CheckInRequest {
+    pub event: String,
+    pub occurrence: String,
+    pub household: String,
+    pub persons: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CheckOutRequest {
+    pub event: String,
+    pub occurrence: String,
+    pub code: String,
+    // Every child checked in with the code by default.
+    pub persons: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ChildLabel {
+    pub person: String,
+    pub name: String,
+    pub notes: String,
+    pub code: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct GuardianLabel {
+    pub house_name: String,
+    pub children: Vec<String>,
+    pub code: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Labels {
+    pub code: String,
+    pub children: Vec<ChildLabel>,
+    pub guardian: GuardianLabel,
+}
+
+fn parse_oid(value: &str) -> COSIResult<ObjectId> {
+    ObjectId::parse_str(value).map_err(|_| COSIError::msg(format!("Invalid object id {}.", value)))
+}
+
+fn escape_regex(value: &str) -> String {
+    let mut result = String::new();
+    for c in value.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+// Matches the digits of a phone number whatever its punctuation.
+fn phone_regex(digits: &str) -> String {
+    let parts: Vec<String> = digits.chars().map(|c| c.to_string()).collect();
+    parts.join("\\D*")
+}
+
+fn is_child(household: &HouseholdImpl, id: ObjectId, dob: Option<NaiveDate>) -> bool {
+    let related = household
+        .relations
+        .iter()
+        .any(|r| matches!(r.relation, HouseRelationStatus::Child) && r.person_a.0 == id);
+    let today = Local::today().naive_local();
+    let minor = dob.is_some_and(|d| {
+        let mut age = today.year() - d.year();
+        if (today.month(), today.day()) < (d.month(), d.day()) {
+            age -= 1;
+        }
+        age < ADULT_AGE
+    });
+    related || minor
+}
+
+fn name(person: &Person) -> String {
+    format!("{} {}", person.first_name, person.last_name)
+        .trim()
+        .to_string()
+}
+
+// Children of the occurrence that are still checked in.
+async fn checked_in(
+    client: &Client,
+    event: ObjectId,
+    occurrence: &str,
+    persons: &[ObjectId],
+) -> COSIResult<Vec<Document>> {
+    CheckIn::find_raw_document(
+        client,
+        Some(doc! {
+            "event": event,
+            "occurrence": occurrence,
+            "person": {"$in": persons},
+            "checked_out": Bson::Null,
+        }),
+        None,
+    )
+    .await
+}
+
+// Only one entry per child of an occurrence may lack a check-out time. Creating an existing
+// index is a no-op, so this also covers dropped or restored collections.
+async fn create_index(client: &Client) -> COSIResult<()> {
+    let options = IndexOptions::builder()
+        .name("checked_in".to_string())
+        .unique(true)
+        .partial_filter_expression(doc! {"checked_out": {"$type": "null"}})
+        .build();
+    let index = IndexModel::builder()
+        .keys(doc! {"event": 1, "occurrence": 1, "person": 1})
+        .options(options)
+        .build();
+    CheckIn::get_raw_document(client)
+        .await
+        .create_index(index, None)
+        .await?;
+    Ok(())
+}
+
+fn is_duplicate(err: &COSIError) -> bool {
+    match err
+        .downcast_ref::<mongodb::error::Error>()
+        .map(|e| e.kind.as_ref())
+    {
+        Some(ErrorKind::Write(WriteFailure::WriteError(e))) => e.code == 11000,
+        _ => false,
+    }
+}
+
+// Households with a member whose phone holds the digits of the query, or named like it.
+// Members are marked checked in when an occurrence is given.
+pub async fn find_households(
+    client: &Client,
+    query: &str,
+    occurrence: Option<(ObjectId, &str)>,
+) -> COSIResult<Vec<KioskHousehold>> {
+    let query = query.trim();
+    let digits: String = query.chars().filter(|c| c.is_ascii_digit()).collect();
+    let is_phone = query
+        .chars()
+        .all(|c| c.is_ascii_digit() || " -+().".contains(c));
+    let person_filter = if is_phone {
+        if digits.len() < MIN_PHONE_DIGITS {
+            return Err(COSIError::msg(format!(
+                "Phone lookups need at least {} digits.",
+                MIN_PHONE_DIGITS
+            )));
+        }
+        let regex = phone_regex(&digits);
+        doc! {"$or": [
+            {"home_phone": {"$regex": &regex}},
+            {"work_phone": {"$regex": &regex}},
+            {"mobile_phone": {"$regex": &regex}},
+        ]}
+    } else {
+        if query.is_empty() {
+            return Err(COSIError::msg("Search for a phone number or a name."));
+        }
+        let regex = format!("(?i){}", escape_regex(query));
+        doc! {"$or": [
+            {"first_name": {"$regex": &regex}},
+            {"last_name": {"$regex": &regex}},
+        ]}
+    };
+    let matches: Vec<Bson> = Person::find_raw_document(client, Some(person_filter), None)
+        .await?
+        .into_iter()
+        .filter_map(|p| p.get("_id").cloned())
+        .collect();
+    let mut household_filter = vec![doc! {"persons": {"$in": matches}}];
+    if !is_phone {
+        let regex = format!("(?i){}", escape_regex(query));
+        household_filter.push(doc! {"house_name": {"$regex": regex}});
+    }
+    let options = FindOptions::builder()
+        .sort(doc! {"house_name": 1})
+        .limit(MAX_HOUSEHOLDS)
+        .build();
+    let households: Vec<(ObjectId, HouseholdImpl)> =
+        Household::find_raw_document(client, Some(doc! {"$or": household_filter}), Some(options))
+            .await?
+            .into_iter()
+            .map(|h| Ok((h.get_object_id("_id")?, from_document(h)?)))
+            .collect::<COSIResult<_>>()?;
+
+    let ids: Vec<ObjectId> = households
+        .iter()
+        .flat_map(|(_, h)| h.persons.iter().map(|p| p.0))
+        .collect();
+    let mut people: HashMap<ObjectId, Person> = HashMap::new();
+    for p in Person::find_raw_document(client, Some(doc! {"_id": {"$in": &ids}}), None).await? {
+        people.insert(p.get_object_id("_id")?, from_document(p)?);
+    }
+    let present: Vec<ObjectId> = match occurrence {
+        Some((event, occurrence)) => checked_in(client, event, occurrence, &ids)
+            .await?
+            .iter()
+            .filter_map(|c| c.get_object_id("person").ok())
+            .collect(),
+        None => vec![],
+    };
+
+    let mut result = vec![];
+    for (id, household) in &households {
+        let mut members = vec![];
+        for p in &household.persons {
+            let person = match people.get(&p.0) {
+                Some(person) => person,
+                None => continue,
+            };
+            members.push(Member {
+                _id: p.0.to_hex(),
+                first_name: person.first_name.clone(),
+                last_name: person.last_name.clone(),
+                dob: person.dob,
+                child: is_child(household, p.0, person.dob),
+                notes: person.notes.clone(),
+                checked_in: present.contains(&p.0),
+            });
+        }
+        result.push(KioskHousehold {
+            _id: id.to_hex(),
+            house_name: household.house_name.clone(),
+            members,
+        });
+    }
+    Ok(result)
+}
+
+// A code not held by children still checked into the occurrence.
+async fn new_code(client: &Client, event: ObjectId, occurrence: &str) -> COSIResult<String> {
+    loop {
+        let code: String = (0..CODE_LENGTH)
+            .map(|_| CODE_CHARS[thread_rng().gen_range(0, CODE_CHARS.len())] as char)
+            .collect();
+        let filter = doc! {
+            "event": event,
+            "occurrence": occurrence,
+            "code": &code,
+            "checked_out": Bson::Null,
+        };
+        if CheckIn::find_raw_document(client, Some(filter), None)
+            .await?
+            .is_empty()
+        {
+            return Ok(code);
+        }
+    }
+}
+
+// Checks the children in, returning their labels. The occurrence must be checked beforehand.
+pub async fn check_in(client: &Client, request: &CheckInRequest) -> COSIResult<Labels> {
+    let event = parse_oid(&request.event)?;
+    let household_id = parse_oid(&request.household)?;
+    if request.persons.is_empty() {
+        return Err(COSIError::msg("Select at least one child."));
+    }
+    let persons: Vec<ObjectId> = request
+        .persons
+        .iter()
+        .map(|p| parse_oid(p))
+        .collect::<COSIResult<_>>()?;
+
+    let household: HouseholdImpl =
+        Household::find_raw_document(client, Some(doc! {"_id": household_id}), None)
+            .await?
+            .pop()
+            .map(from_document)
+            .transpose()?
+            .ok_or(COSIError::msg("No household found."))?;
+    if let Some(p) = persons
+        .iter()
+        .find(|p| !household.persons.iter().any(|h| h.0 == **p))
+    {
+        return Err(COSIError::msg(format!(
+            "Person {} is not in the household.",
+            p.to_hex()
+        )));
+    }
+    let mut people = vec![];
+    for id in &persons {
+        let person: Person = Person::find_raw_document(client, Some(doc! {"_id": id}), None)
+            .await?
+            .pop()
+            .map(from_document)
+            .transpose()?
+            .ok_or(COSIError::msg("No person found."))?;
+        if !is_child(&household, *id, person.dob) {
+            return Err(COSIError::msg(format!(
+                "{} is not a child of the household.",
+                name(&person)
+            )));
+        }
+        people.push((id, person));
+    }
+    if !checked_in(client, event, &request.occurrence, &persons)
+        .await?
+        .is_empty()
+    {
+        return Err(COSIError::msg("Some children are already checked in."));
+    }
+
+    create_index(client).await?;
+    let code = new_code(client, event, &request.occurrence).await?;
+    let now = Local::now().format(DATETIME_FORMAT).to_string();
+    let mut inserted = vec![];
+    let mut children = vec![];
+    for (id, person) in people {
+        let entry = CheckIn {
+            event: event.into(),
+            occurrence: request.occurrence.clone(),
+            household: household_id.into(),
+            person: (*id).into(),
+            code: code.clone(),
+            checked_in: now.clone(),
+            checked_out: None,
+        };
+        match CheckIn::insert_datum(client, &entry, None).await {
+            Ok(id) => inserted.push(id),
+            Err(err) => {
+                // The children checked in so far are checked in again with the next attempt.
+                CheckIn::get_raw_document(client)
+                    .await
+                    .delete_many(doc! {"_id": {"$in": &inserted}}, None)
+                    .await?;
+                for id in &inserted {
+                    changes::publish(CheckIn::table_name(), ChangeOp::Delete, Some(id));
+                }
+                if is_duplicate(&err) {
+                    return Err(COSIError::msg("Some children are already checked in."));
+                }
+                return Err(err);
+            }
+        }
+        children.push(ChildLabel {
+            person: id.to_hex(),
+            name: name(&person),
+            notes: person.notes.clone(),
+            code: code.clone(),
+        });
+    }
+
+    let sheet = AttendanceSheet {
+        occurrence: request.occurrence.clone(),
+        headcount: None,
+        attendees: persons
+            .iter()
+            .map(|p| Attendee {
+                person: Some(p.to_hex()),
+                group: None,
+                household: None,
+                status: AttendanceStatus::Present,
+            })
+            .collect(),
+    };
+    for result in attendance::record(client, event, &sheet).await? {
+        result?;
+    }
+
+    let guardian = GuardianLabel {
+        house_name: household.house_name.clone(),
+        children: children.iter().map(|c| c.name.clone()).collect(),
+        code: code.clone(),
+    };
+    Ok(Labels {
+        code,
+        children,
+        guardian,
+    })
+}
+
+// Checks out the children holding the code, returning their ids. None when the code matches no
+// child still checked in.
+pub async fn check_out(
+    client: &Client,
+    request: &CheckOutRequest,
+) -> COSIResult<Option<Vec<String>>> {
+    let event = parse_oid(&request.event)?;
+    let mut filter = doc! {
+        "event": event,
+        "occurrence": &request.occurrence,
+        "code": request.code.trim().to_uppercase(),
+        "checked_out": Bson::Null,
+    };
+    if let Some(persons) = &request.persons {
+        let persons: Vec<ObjectId> = persons
+            .iter()
+            .map(|p| parse_oid(p))
+            .collect::<COSIResult<_>>()?;
+        filter.insert("person", doc! {"$in": persons});
+    }
+    let found = CheckIn::find_raw_document(client, Some(filter.clone()), None).await?;
+    if found.is_empty() {
+        return Ok(None);
+    }
+
+    let now = Local::now().format(DATETIME_FORMAT).to_string();
+    CheckIn::get_raw_document(client)
+        .await
+        .update_many(filter, doc! {"$set": {"checked_out": now}}, None)
+        .await?;
+    for c in &found {
+        changes::publish(CheckIn::table_name(), ChangeOp::Update, c.get("_id"));
+    }
+    let persons = found
+        .iter()
+        .filter_map(|c| c.get_object_id("person").ok())
+        .map(|p| p.to_hex())
+        .collect();
+    Ok(Some(persons))
+}
+
+// Children checked into the occurrence, with their names. Pickup codes are left out.
+pub async fn roster(
+    client: &Client,
+    event: ObjectId,
+    occurrence: &str,
+) -> COSIResult<Vec<Document>> {
+    let options = FindOptions::builder()
+        .sort(doc! {"checked_in": 1, "_id": 1})
+        .build();
+    let mut data = CheckIn::find_raw_document(
+        client,
+        Some(doc! {"event": event, "occurrence": occurrence}),
+        Some(options),
+    )
+    .await?;
+    let ids: Vec<Bson> = data
+        .iter()
+        .filter_map(|d| d.get("person").cloned())
+        .collect();
+    let mut names: HashMap<ObjectId, (String, String)> = HashMap::new();
+    for p in Person::find_raw_document(client, Some(doc! {"_id": {"$in": ids}}), None).await? {
+        let person: Person = from_document(p.clone())?;
+        names.insert(p.get_object_id("_id")?, (name(&person), person.notes));
+    }
+    for d in data.iter_mut() {
+        d.remove("code");
+        let (name, notes) = d
+            .get_object_id("person")
+            .ok()
+            .and_then(|id| names.get(&id).cloned())
+            .unwrap_or_default();
+        d.insert("name", name);
+        d.insert("notes", notes);
+    }
+    Ok(data)
+}
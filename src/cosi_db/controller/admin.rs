@@ -0,0 +1,83 @@
+// Database maintenance, see cosi_db::backup.
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::{ContentType, Status};
+use rocket::response::content::RawJson;
+use rocket::response::status::Custom;
+use rocket_db_pools::Connection;
+
+use mongodb::Client;
+
+use crate::cosi_db::backup::{backup, database_name, is_empty, restore, validate, DATABASE};
+use crate::cosi_db::connection::COSIMongo;
+use crate::cosi_db::controller::api::ApiResult;
+use crate::cosi_db::controller::common::{attachment, render_err, Download};
+use crate::cosi_db::model::auth::Admin;
+
+pub const MAX_RESTORE_MIB: usize = 512;
+
+fn server_err<E: std::fmt::Display>(err: E) -> Custom<RawJson<String>> {
+    Custom(Status::InternalServerError, RawJson(render_err(err)))
+}
+
+#[get("/api/v1/admin/backup")]
+pub async fn admin_backup(_admin: Admin, connect: Connection<COSIMongo>) -> ApiResult<Download> {
+    let client: &Client = &connect;
+    let archive = backup(client, DATABASE).await.map_err(server_err)?;
+    let filename = format!(
+        "cosi-db-{}.tar.gz",
+        chrono::Utc::now().format("%Y%m%dT%H%M%SZ")
+    );
+    Ok(Download::File(
+        archive,
+        ContentType::GZIP,
+        attachment(&filename),
+    ))
+}
+
+// The body is an archive from the backup endpoint. Without a tenant the live database is
+// restored. The database must be empty but for the logins, unless the archive is only checked.
+#[post("/api/v1/admin/restore?<tenant>&<dry_run>", data = "<data>")]
+pub async fn admin_restore(
+    _admin: Admin,
+    connect: Connection<COSIMongo>,
+    tenant: Option<&str>,
+    dry_run: Option<bool>,
+    data: Data<'_>,
+) -> ApiResult<Custom<RawJson<String>>> {
+    let client: &Client = &connect;
+    let database = database_name(tenant)
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+    let dry_run = dry_run.unwrap_or(false);
+    let bytes = data
+        .open(MAX_RESTORE_MIB.mebibytes())
+        .into_bytes()
+        .await
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+    if !bytes.is_complete() {
+        let err = format!("Archives are limited to {} MiB.", MAX_RESTORE_MIB);
+        return Err(Custom(Status::PayloadTooLarge, RawJson(render_err(err))));
+    }
+    if !dry_run && !is_empty(client, &database).await.map_err(server_err)? {
+        let err = format!("Database {} is not empty.", database);
+        return Err(Custom(Status::Conflict, RawJson(render_err(err))));
+    }
+
+    let (mut report, docs) =
+        validate(&bytes).map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+    report.database = database.clone();
+    report.dry_run = dry_run;
+
+    // Nothing is written unless every document is valid.
+    let status = if report.errors.is_empty() {
+        Status::Ok
+    } else {
+        Status::UnprocessableEntity
+    };
+    if status == Status::Ok && !report.dry_run {
+        restore(client, &database, docs).await.map_err(server_err)?;
+    }
+    Ok(Custom(
+        status,
+        RawJson(serde_json::to_string(&report).unwrap()),
+    ))
+}
@@ -1,86 +1,813 @@
-//std
-use std::collections::HashMap;
-use std::str::FromStr;
+// Generic endpoints shared by every table.
+// Route handlers are generated by #[derive(CosiModel)] and call into these with the model types.
 
 // serde
 use serde_json;
+use serde_json::{Map, Value};
 
 // rocket
-use rocket::form::Form;
-use rocket::http::Status;
+use rocket::http::{ContentType, Status};
 use rocket::response::content::RawJson;
-use rocket::response::status::Custom;
-use rocket_db_pools::Connection;
+use rocket::response::status::{Custom, NoContent};
+use rocket::response::stream::ByteStream;
+use rocket::serde::json::Error as JsonError;
+
+// futures
+use futures::stream::{self, StreamExt};
 
 // mongo
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::{doc, from_document, to_bson, Bson, Document};
+use mongodb::bson::{doc, from_document, Bson, Document};
 use mongodb::options::FindOptions;
 use mongodb::Client;
 
 // cosi_db
-use crate::cosi_db::connection::COSIMongo;
-use crate::cosi_db::controller::common::PaginateData;
-use crate::cosi_db::errors::COSIResult;
-use crate::cosi_db::model::auth::User;
-use crate::cosi_db::model::common::{COSICollection, Generator};
-
-use crate::{
-    generate_dropper, generate_generators, generate_pageable_getter, generate_pageable_inserter,
-    generate_pageable_update,
+use crate::cosi_db::changes::{self, ChangeOp};
+use crate::cosi_db::controller::common::{
+    attachment, build_patch, decode_cursor, encode_cursor, page_skip, parse_page_size,
+    parse_projection, parse_sort, project_documents, render_err, BulkItemResult, BulkResult,
+    BulkUpdate, Download, Located, PaginateData, UpsertResult, MAX_BULK_SIZE,
 };
+use crate::cosi_db::errors::{COSIError, Missing, Refused};
+use crate::cosi_db::export::{self, ExportFormat, EXPORT_BATCH};
+use crate::cosi_db::model::common::{COSICollection, COSIData, COSIForm, Generator};
 
-// Address
-use crate::cosi_db::model::address::{Address, AddressImpl, AddressOptional};
-generate_generators! { Address }
-generate_pageable_getter! { Address }
-generate_pageable_inserter! { Address }
-generate_dropper! { Address }
-generate_pageable_update! { Address }
-
-// Person
-use crate::cosi_db::model::person::{Person, PersonImpl, PersonOptional};
-generate_generators! { Person }
-generate_pageable_getter! { Person }
-generate_pageable_inserter! { Person }
-generate_dropper! { Person }
-generate_pageable_update! { Person }
-
-// Household
-use crate::cosi_db::model::household::{Household, HouseholdImpl, HouseholdOptional};
-generate_generators! { Household }
-generate_pageable_getter! { Household }
-generate_pageable_inserter! { Household }
-generate_dropper! { Household }
-
-// Event
-use crate::cosi_db::model::event::{Event, EventImpl, EventOptional};
-generate_generators! { Event }
-generate_pageable_getter! { Event }
-generate_pageable_inserter! { Event }
-generate_dropper! { Event }
-generate_pageable_update! { Event }
-
-// Event Registration
-use crate::cosi_db::model::event::{
-    EventRegistration, EventRegistrationImpl, EventRegistrationOptional,
-};
-generate_generators! { EventRegistration }
-generate_pageable_getter! { EventRegistration }
-generate_pageable_inserter! { EventRegistration }
-generate_dropper! { EventRegistration }
-
-// Group
-use crate::cosi_db::model::group::{Group, GroupImpl, GroupOptional};
-generate_generators! { Group }
-generate_pageable_getter! { Group }
-generate_pageable_inserter! { Group }
-generate_dropper! { Group }
-generate_pageable_update! { Group }
-
-// Group Relation
-use crate::cosi_db::model::group::{GroupRelation, GroupRelationImpl, GroupRelationOptional};
-generate_generators! { GroupRelation }
-generate_pageable_getter! { GroupRelation }
-generate_pageable_inserter! { GroupRelation }
-generate_dropper! { GroupRelation }
+// GENERATORS
+pub async fn generate<T, I, F>(client: &Client, total: u8) -> RawJson<String>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I> + Generator<T>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    #[cfg(debug_assertions)]
+    {
+        let data = T::generate(client, total as u32).await.unwrap();
+
+        let col = T::get_collection(client).await;
+        col.drop(None).await.unwrap();
+        col.insert_many(T::to_impl(client, data).await.unwrap(), None)
+            .await
+            .unwrap();
+        changes::publish(T::table_name(), ChangeOp::Drop, None);
+
+        let total = col.estimated_document_count(None).await.unwrap();
+        RawJson(format!("{{\"total\": {}}}", total))
+    }
+    #[cfg(not(debug_assertions))]
+    {
+        let _ = (client, total);
+        RawJson("{}".to_string())
+    }
+}
+
+// GETTERS
+// Takes the query parameters of the list routes one by one.
+#[allow(clippy::too_many_arguments)]
+pub async fn get_page<T, I, F>(
+    client: &Client,
+    page: Option<u64>,
+    page_size: Option<i64>,
+    cursor: Option<String>,
+    sort: Option<String>,
+    fields: Option<String>,
+    expand: Option<bool>,
+    search_query: F,
+) -> Custom<RawJson<String>>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let page = page.unwrap_or(0);
+
+    let col = T::get_collection(client).await;
+
+    // Validate sorting and projection against the model.
+    let valid_fields = T::get_field_names();
+    let sort_doc = match parse_sort(sort.as_deref().unwrap_or(""), &valid_fields) {
+        Ok(v) => v,
+        Err(err) => return Custom(Status::BadRequest, RawJson(render_err(err))),
+    };
+    let projection = match fields
+        .as_deref()
+        .map(|f| parse_projection(f, &valid_fields))
+        .transpose()
+    {
+        Ok(v) => v,
+        Err(err) => return Custom(Status::BadRequest, RawJson(render_err(err))),
+    };
+    let limit_size: i64 = match parse_page_size(page_size) {
+        Ok(v) => v,
+        Err(err) => return Custom(Status::BadRequest, RawJson(render_err(err))),
+    };
+    let cursor_doc = match cursor
+        .as_deref()
+        .map(|c| decode_cursor(c, &sort_doc))
+        .transpose()
+    {
+        Ok(v) => v,
+        Err(err) => return Custom(Status::BadRequest, RawJson(render_err(err))),
+    };
+
+    // Page calculate.
+    let search_doc = match T::convert_form_query(search_query) {
+        Ok(v) => v,
+        Err(err) => return Custom(Status::BadRequest, RawJson(render_err(err))),
+    };
+    let skip = match page_skip(page, limit_size) {
+        Ok(v) => v,
+        Err(err) => return Custom(Status::BadRequest, RawJson(render_err(err))),
+    };
+    let total_result = if !search_doc.is_empty() {
+        col.count_documents(Some(search_doc.clone()), None).await
+    } else {
+        col.estimated_document_count(None).await
+    };
+    let total_result: u64 = match total_result {
+        Ok(v) => v,
+        Err(err) => return Custom(Status::InternalServerError, RawJson(render_err(err))),
+    };
+
+    let total_pages: u64 = (total_result as f64 / limit_size as f64).ceil() as u64;
+
+    // Cursors replace skipping as they stay correct while data changes.
+    let (find_doc, skip) = match cursor_doc {
+        Some(c) => (doc! {"$and": [search_doc, c]}, 0),
+        None => (search_doc, skip),
+    };
+    let find_options = FindOptions::builder()
+        .limit(limit_size)
+        .skip(skip)
+        .sort(sort_doc.clone())
+        .build();
+
+    // Query any search_queries
+    let mut data: Vec<Document> =
+        match T::find_raw_document(client, Some(find_doc), Some(find_options)).await {
+            Ok(v) => v,
+            Err(err) => return Custom(Status::InternalServerError, RawJson(render_err(err))),
+        };
+    let next = if data.len() as i64 == limit_size {
+        data.last().map(|last| encode_cursor(&sort_doc, last))
+    } else {
+        None
+    };
+
+    // Inline referenced documents unless the client asks for raw ids.
+    if expand.unwrap_or(true) {
+        T::process_foreign_keys(client, &mut data).await;
+    }
+    if let Some(p) = projection {
+        project_documents(&mut data, &p);
+    }
+    Custom(
+        Status::Ok,
+        RawJson(
+            serde_json::to_string(&PaginateData {
+                page,
+                page_size: limit_size as u64,
+                total_pages,
+                total_result,
+                next,
+                data,
+            })
+            .unwrap(),
+        ),
+    )
+}
+
+pub async fn get_by_id<T, I, F>(
+    client: &Client,
+    oid: &str,
+    expand: Option<bool>,
+) -> Custom<RawJson<String>>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let oid = match ObjectId::parse_str(oid) {
+        Ok(v) => v,
+        Err(_) => {
+            return Custom(
+                Status::BadRequest,
+                RawJson(render_err("Invalid object id.")),
+            )
+        }
+    };
+
+    let mut data = T::find_raw_document(client, Some(doc! {"_id": oid}), None)
+        .await
+        .unwrap();
+    if data.is_empty() {
+        return Custom(Status::NotFound, RawJson(render_err("No data found.")));
+    }
+    if expand.unwrap_or(true) {
+        T::process_foreign_keys(client, &mut data).await;
+    }
+    Custom(
+        Status::Ok,
+        RawJson(serde_json::to_string(&data[0]).unwrap()),
+    )
+}
+
+// EXPORT
+// Every datum matching the filters of get_page, as a spreadsheet. CSV is streamed in batches.
+pub async fn export<T, I, F>(
+    client: &Client,
+    format: Option<String>,
+    sort: Option<String>,
+    search_query: F,
+) -> ApiResult<Download>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let bad_request = |err: COSIError| Custom(Status::BadRequest, RawJson(render_err(err)));
+    let format = ExportFormat::parse(format.as_deref()).map_err(bad_request)?;
+    let sort_doc =
+        parse_sort(sort.as_deref().unwrap_or(""), &T::get_field_names()).map_err(bad_request)?;
+    let search_doc = T::convert_form_query(search_query).map_err(bad_request)?;
+
+    let options = FindOptions::builder().sort(sort_doc).build();
+    let cursor = T::get_raw_document(client)
+        .await
+        .find(search_doc, options)
+        .await
+        .map_err(|err| Custom(Status::InternalServerError, RawJson(render_err(err))))?;
+
+    let columns = export::columns::<T>();
+    let header: Vec<String> = columns.iter().map(|c| c.name.clone()).collect();
+    let client = client.clone();
+    let batches = cursor
+        .filter_map(|d| async move { d.ok() })
+        .chunks(EXPORT_BATCH)
+        .then(move |mut batch| {
+            let client = client.clone();
+            let columns = columns.clone();
+            async move {
+                T::process_foreign_keys(&client, &mut batch).await;
+                batch
+                    .iter()
+                    .map(|d| export::row(d, &columns))
+                    .collect::<Vec<Vec<String>>>()
+            }
+        });
+
+    let filename = format!("{}.{}", T::get_table_name(), format.extension());
+    match format {
+        ExportFormat::Csv => {
+            let body = stream::once(async move { vec![header] })
+                .chain(batches)
+                .map(|rows| export::render_csv(&rows).unwrap_or_default());
+            let body = stream::once(async { export::CSV_BOM.to_vec() }).chain(body);
+            Ok(Download::Stream(
+                ByteStream(body.boxed()),
+                ContentType::CSV,
+                attachment(&filename),
+            ))
+        }
+        ExportFormat::Xlsx => {
+            let mut rows = vec![header];
+            rows.extend(batches.concat().await);
+            let file = export::render_xlsx(T::table_name(), &rows)
+                .map_err(|err| Custom(Status::InternalServerError, RawJson(render_err(err))))?;
+            Ok(Download::File(
+                file,
+                ContentType::new(
+                    "application",
+                    "vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+                ),
+                attachment(&filename),
+            ))
+        }
+    }
+}
+
+// INSERT
+pub async fn insert<T, I, F>(
+    client: &Client,
+    insert_query: Result<I, JsonError<'_>>,
+) -> Custom<RawJson<String>>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let insert_query_obj = match insert_query {
+        Ok(q) => q,
+        Err(err) => return Custom(Status::BadRequest, RawJson(render_err(err))),
+    };
+    match T::convert_form_insert(insert_query_obj) {
+        Ok(search_obj) => {
+            // Query any search_queries
+            match T::insert_datum(client, &from_document(search_obj).unwrap(), None).await {
+                Ok(bson_id) => Custom(
+                    Status::Ok,
+                    RawJson(serde_json::to_string(&bson_id).unwrap()),
+                ),
+                Err(err) if err.is::<Refused>() => {
+                    Custom(Status::Conflict, RawJson(render_err(err)))
+                }
+                Err(err) => Custom(Status::InternalServerError, RawJson(render_err(err))),
+            }
+        }
+        Err(err) => Custom(Status::BadRequest, RawJson(render_err(err))),
+    }
+}
+
+// UPDATE
+pub async fn update<T, I, F>(
+    client: &Client,
+    oid: &str,
+    update_query: Result<I, JsonError<'_>>,
+) -> Custom<RawJson<String>>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let data_obj = match update_query {
+        Ok(q) => q,
+        Err(err) => return Custom(Status::BadRequest, RawJson(render_err(err))),
+    };
+    let oid = match ObjectId::parse_str(oid) {
+        Ok(v) => v,
+        Err(_) => {
+            return Custom(
+                Status::BadRequest,
+                RawJson(render_err("Invalid object id.")),
+            )
+        }
+    };
+
+    // We make the following assumption: absence -> null. We do not store empty strings.
+    // This has to do with HashMap limitations and Rust autocasting behavior.
+    match T::convert_form_insert(data_obj) {
+        Ok(update_obj) => {
+            // Query any update_queries
+            match T::update_datum(client, &doc! {"_id": oid}, &doc! {"$set": update_obj}, None)
+                .await
+            {
+                Ok(result) => Custom(Status::Ok, RawJson(serde_json::to_string(&result).unwrap())),
+                Err(err) => failed_write(err),
+            }
+        }
+        Err(err) => Custom(Status::BadRequest, RawJson(render_err(err))),
+    }
+}
+
+// PATCH
+pub async fn patch<T, I, F>(
+    client: &Client,
+    oid: &str,
+    patch_query: Result<Map<String, Value>, JsonError<'_>>,
+) -> Custom<RawJson<String>>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let patch_obj = match patch_query {
+        Ok(q) => q,
+        Err(err) => return Custom(Status::BadRequest, RawJson(render_err(err))),
+    };
+    let oid = match ObjectId::parse_str(oid) {
+        Ok(v) => v,
+        Err(_) => {
+            return Custom(
+                Status::BadRequest,
+                RawJson(render_err("Invalid object id.")),
+            )
+        }
+    };
+
+    let current = match T::find_raw_document(client, Some(doc! {"_id": oid}), None)
+        .await
+        .unwrap()
+        .pop()
+    {
+        Some(v) => v,
+        None => return Custom(Status::NotFound, RawJson(render_err("No data found."))),
+    };
+    let update = match build_patch::<I>(&current, patch_obj, &T::get_field_names()) {
+        Ok(v) => v,
+        Err(err) => return Custom(Status::BadRequest, RawJson(render_err(err))),
+    };
+
+    match T::update_datum(client, &doc! {"_id": oid}, &update, None).await {
+        Ok(result) => Custom(Status::Ok, RawJson(serde_json::to_string(&result).unwrap())),
+        Err(err) => failed_write(err),
+    }
+}
+
+// UPSERT
+// Matches on the natural key of the table. Re-sending the same datum updates it in place.
+pub async fn upsert<T, I, F>(
+    client: &Client,
+    upsert_query: Result<I, JsonError<'_>>,
+) -> Custom<RawJson<String>>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let data = match upsert_query
+        .map_err(|err| COSIError::msg(err.to_string()))
+        .and_then(|q| T::convert_form_insert(q))
+    {
+        Ok(v) => v,
+        Err(err) => return Custom(Status::BadRequest, RawJson(render_err(err))),
+    };
+    if let Err(err) = T::natural_key_query(&data) {
+        return Custom(Status::BadRequest, RawJson(render_err(err)));
+    }
+
+    match T::upsert_datum(client, data).await {
+        Ok((id, upserted)) => Custom(
+            Status::Ok,
+            RawJson(serde_json::to_string(&UpsertResult { _id: id, upserted }).unwrap()),
+        ),
+        Err(err) => refused(err),
+    }
+}
+
+// BULK
+// Each item is validated on its own so one bad row does not fail the batch.
+fn check_bulk<V>(query: Result<Vec<V>, JsonError<'_>>) -> Result<Vec<V>, Custom<RawJson<String>>> {
+    let items = match query {
+        Ok(q) => q,
+        Err(err) => return Err(Custom(Status::BadRequest, RawJson(render_err(err)))),
+    };
+    if items.len() > MAX_BULK_SIZE {
+        return Err(Custom(
+            Status::PayloadTooLarge,
+            RawJson(render_err(format!(
+                "At most {} items per request.",
+                MAX_BULK_SIZE
+            ))),
+        ));
+    }
+    Ok(items)
+}
+
+fn render_bulk(results: Vec<BulkItemResult>) -> Custom<RawJson<String>> {
+    let bulk: BulkResult = results.into();
+    Custom(Status::Ok, RawJson(serde_json::to_string(&bulk).unwrap()))
+}
+
+pub async fn bulk_insert<T, I, F>(
+    client: &Client,
+    insert_query: Result<Vec<Value>, JsonError<'_>>,
+) -> Custom<RawJson<String>>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let items = match check_bulk(insert_query) {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let mut results: Vec<Option<BulkItemResult>> = vec![None; items.len()];
+    let mut valid_idx: Vec<usize> = vec![];
+    let mut valid_docs: Vec<Document> = vec![];
+    for (idx, item) in items.into_iter().enumerate() {
+        let converted = serde_json::from_value::<I>(item)
+            .map_err(COSIError::from)
+            .and_then(|v| T::convert_form_insert(v));
+        match converted {
+            Ok(d) => {
+                valid_idx.push(idx);
+                valid_docs.push(d);
+            }
+            Err(err) => results[idx] = Some(BulkItemResult::from_result(idx, Err(err))),
+        }
+    }
+
+    let inserted = match T::insert_data(client, valid_docs).await {
+        Ok(v) => v,
+        Err(err) => return Custom(Status::InternalServerError, RawJson(render_err(err))),
+    };
+    for (idx, r) in valid_idx.into_iter().zip(inserted) {
+        results[idx] = Some(BulkItemResult::from_result(idx, r));
+    }
+
+    render_bulk(results.into_iter().map(|r| r.unwrap()).collect())
+}
+
+pub async fn bulk_update<T, I, F>(
+    client: &Client,
+    update_query: Result<Vec<BulkUpdate>, JsonError<'_>>,
+) -> Custom<RawJson<String>>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let items = match check_bulk(update_query) {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    // Run in order through update_datum so that models check each item against the ones before it.
+    let mut results: Vec<BulkItemResult> = vec![];
+    for (idx, item) in items.into_iter().enumerate() {
+        let result = async {
+            let oid =
+                ObjectId::parse_str(&item.oid).map_err(|_| COSIError::msg("Invalid object id."))?;
+            let data_obj: I = serde_json::from_value(item.data)?;
+            let update_obj = T::convert_form_insert(data_obj)?;
+            T::update_datum(client, &doc! {"_id": oid}, &doc! {"$set": update_obj}, None).await?;
+            Ok(Bson::ObjectId(oid))
+        };
+        results.push(BulkItemResult::from_result(idx, result.await));
+    }
+    render_bulk(results)
+}
+
+pub async fn bulk_upsert<T, I, F>(
+    client: &Client,
+    upsert_query: Result<Vec<Value>, JsonError<'_>>,
+) -> Custom<RawJson<String>>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let items = match check_bulk(upsert_query) {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    // Run in order so that repeated keys within one request resolve to the same datum.
+    let mut results: Vec<BulkItemResult> = vec![];
+    for (idx, item) in items.into_iter().enumerate() {
+        let data = serde_json::from_value::<I>(item)
+            .map_err(COSIError::from)
+            .and_then(|v| T::convert_form_insert(v));
+        let result = match data {
+            Ok(d) => T::upsert_datum(client, d).await,
+            Err(err) => Err(err),
+        };
+        results.push(BulkItemResult::from_upsert(idx, result));
+    }
+    render_bulk(results)
+}
+
+pub async fn bulk_delete<T, I, F>(
+    client: &Client,
+    delete_query: Result<Vec<String>, JsonError<'_>>,
+) -> Custom<RawJson<String>>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let items = match check_bulk(delete_query) {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    let deletes = items.into_iter().map(|item| async move {
+        let oid = ObjectId::parse_str(&item).map_err(|_| COSIError::msg("Invalid object id."))?;
+        T::delete_datum(client, &doc! {"_id": oid}).await?;
+        Ok(Bson::ObjectId(oid))
+    });
+    render_bulk(
+        futures::future::join_all(deletes)
+            .await
+            .into_iter()
+            .enumerate()
+            .map(|(idx, r)| BulkItemResult::from_result(idx, r))
+            .collect(),
+    )
+}
+
+// V1
+// Resource style endpoints mounted under /api/v1. Listing, fetching and bulk requests share
+// the functions above, the rest answer with the stored datum and REST status codes.
+pub type ApiResult<R> = Result<R, Custom<RawJson<String>>>;
+
+pub fn resource_path(table: &str, oid: &ObjectId) -> String {
+    format!("/api/v1/{}/{}", table, oid.to_hex())
+}
+
+pub fn parse_oid(oid: &str) -> ApiResult<ObjectId> {
+    ObjectId::parse_str(oid).map_err(|_| {
+        Custom(
+            Status::BadRequest,
+            RawJson(render_err("Invalid object id.")),
+        )
+    })
+}
+
+async fn find_stored<T, I, F>(client: &Client, oid: ObjectId) -> ApiResult<Document>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    match T::find_raw_document(client, Some(doc! {"_id": oid}), None).await {
+        Ok(mut data) => data
+            .pop()
+            .ok_or_else(|| Custom(Status::NotFound, RawJson(render_err("No data found.")))),
+        Err(err) => Err(Custom(
+            Status::InternalServerError,
+            RawJson(render_err(err)),
+        )),
+    }
+}
+
+fn render_stored(status: Status, data: &Document) -> Custom<RawJson<String>> {
+    Custom(status, RawJson(serde_json::to_string(data).unwrap()))
+}
+
+// Data refused by the model conflicts with the data stored. Database failures are ours,
+// anything else is a bad request.
+fn refused(err: COSIError) -> Custom<RawJson<String>> {
+    if err.is::<Refused>() {
+        return Custom(Status::Conflict, RawJson(render_err(err)));
+    }
+    if err.is::<mongodb::error::Error>() {
+        return Custom(Status::InternalServerError, RawJson(render_err(err)));
+    }
+    Custom(Status::BadRequest, RawJson(render_err(err)))
+}
+
+// Writes matching no datum are not found, other failures as in refused.
+fn failed_write(err: COSIError) -> Custom<RawJson<String>> {
+    if err.is::<Missing>() {
+        return Custom(Status::NotFound, RawJson(render_err(err)));
+    }
+    refused(err)
+}
+
+pub async fn create<T, I, F>(
+    client: &Client,
+    create_query: Result<I, JsonError<'_>>,
+) -> ApiResult<Located>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let data = create_query
+        .map_err(|err| COSIError::msg(err.to_string()))
+        .and_then(|q| T::convert_form_insert(q))
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+    let datum: I =
+        from_document(data).map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+    let oid = match T::insert_datum(client, &datum, None).await {
+        Ok(id) => id.as_object_id().unwrap(),
+        Err(err) if err.is::<Refused>() => {
+            return Err(Custom(Status::Conflict, RawJson(render_err(err))))
+        }
+        Err(err) => {
+            return Err(Custom(
+                Status::InternalServerError,
+                RawJson(render_err(err)),
+            ))
+        }
+    };
+
+    let stored = find_stored::<T, I, F>(client, oid).await?;
+    Ok(Located::new(
+        render_stored(Status::Created, &stored),
+        resource_path(&T::get_table_name(), &oid),
+    ))
+}
+
+pub async fn replace<T, I, F>(
+    client: &Client,
+    oid: &str,
+    replace_query: Result<I, JsonError<'_>>,
+) -> ApiResult<Custom<RawJson<String>>>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let oid = parse_oid(oid)?;
+    let data = replace_query
+        .map_err(|err| COSIError::msg(err.to_string()))
+        .and_then(|q| T::convert_form_insert(q))
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+
+    let mut update = doc! {"$set": data};
+    let _lock = T::write_lock().await;
+    T::before_update(client, &doc! {"_id": oid}, &mut update)
+        .await
+        .map_err(refused)?;
+    let result = T::get_collection(client)
+        .await
+        .update_one(doc! {"_id": oid}, update, None)
+        .await
+        .map_err(|err| Custom(Status::InternalServerError, RawJson(render_err(err))))?;
+    if result.matched_count == 0 {
+        return Err(Custom(
+            Status::NotFound,
+            RawJson(render_err("No data found.")),
+        ));
+    }
+    changes::publish(
+        T::table_name(),
+        ChangeOp::Update,
+        Some(&Bson::ObjectId(oid)),
+    );
+
+    let stored = find_stored::<T, I, F>(client, oid).await?;
+    Ok(render_stored(Status::Ok, &stored))
+}
+
+pub async fn modify<T, I, F>(
+    client: &Client,
+    oid: &str,
+    patch_query: Result<Map<String, Value>, JsonError<'_>>,
+) -> ApiResult<Custom<RawJson<String>>>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let oid = parse_oid(oid)?;
+    let patch_obj =
+        patch_query.map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+
+    let current = find_stored::<T, I, F>(client, oid).await?;
+    let update = build_patch::<I>(&current, patch_obj, &T::get_field_names())
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+    T::update_datum(client, &doc! {"_id": oid}, &update, None)
+        .await
+        .map_err(failed_write)?;
+
+    let stored = find_stored::<T, I, F>(client, oid).await?;
+    Ok(render_stored(Status::Ok, &stored))
+}
+
+pub async fn remove<T, I, F>(client: &Client, oid: &str) -> ApiResult<NoContent>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let oid = parse_oid(oid)?;
+    match T::delete_datum(client, &doc! {"_id": oid}).await {
+        Ok(_) => Ok(NoContent),
+        Err(err) => Err(failed_write(err)),
+    }
+}
+
+// Creates the datum when no datum has the same natural key, replaces it otherwise.
+pub async fn put<T, I, F>(
+    client: &Client,
+    upsert_query: Result<I, JsonError<'_>>,
+) -> ApiResult<Located>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let data = upsert_query
+        .map_err(|err| COSIError::msg(err.to_string()))
+        .and_then(|q| T::convert_form_insert(q))
+        .and_then(|d| T::natural_key_query(&d).map(|_| d))
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+
+    let (id, upserted) = T::upsert_datum(client, data).await.map_err(refused)?;
+    let oid = id.as_object_id().unwrap();
+    let stored = find_stored::<T, I, F>(client, oid).await?;
+    let status = if upserted {
+        Status::Created
+    } else {
+        Status::Ok
+    };
+    Ok(Located::new(
+        render_stored(status, &stored),
+        resource_path(&T::get_table_name(), &oid),
+    ))
+}
+
+// DROP
+pub async fn drop_table<T, I, F>(client: &Client) -> RawJson<String>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    #[cfg(debug_assertions)]
+    {
+        let col = T::get_collection(client).await;
+        col.drop(None).await.unwrap();
+        T::create_collection(client).await.unwrap();
+        changes::publish(T::table_name(), ChangeOp::Drop, None);
+        RawJson("{\"dropped\": true}".to_string())
+    }
+    #[cfg(not(debug_assertions))]
+    {
+        let _ = client;
+        RawJson("{}".to_string())
+    }
+}
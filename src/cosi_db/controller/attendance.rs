@@ -0,0 +1,154 @@
+// Attendance of event occurrences, see cosi_db::attendance.
+use rocket::http::Status;
+use rocket::response::content::RawJson;
+use rocket::response::status::Custom;
+use rocket::serde::json::{Error as JsonError, Json};
+use rocket_db_pools::Connection;
+
+use mongodb::bson::{doc, from_document, oid::ObjectId, Document};
+use mongodb::Client;
+
+use crate::cosi_db::attendance::{
+    between, check_occurrence, event_history, group_history, person_history, record,
+    AttendanceSheet, Summary,
+};
+use crate::cosi_db::connection::COSIMongo;
+use crate::cosi_db::controller::api::{parse_oid, ApiResult};
+use crate::cosi_db::controller::common::{render_err, BulkItemResult, BulkResult, MAX_BULK_SIZE};
+use crate::cosi_db::controller::occurrence::parse_date;
+use crate::cosi_db::model::auth::User;
+use crate::cosi_db::model::common::COSICollection;
+use crate::cosi_db::model::event::{Event, EventImpl};
+use crate::cosi_db::model::group::Group;
+use crate::cosi_db::model::person::Person;
+
+fn server_err<E: std::fmt::Display>(err: E) -> Custom<RawJson<String>> {
+    Custom(Status::InternalServerError, RawJson(render_err(err)))
+}
+
+fn not_found(table: &str) -> Custom<RawJson<String>> {
+    Custom(
+        Status::NotFound,
+        RawJson(render_err(format!("No {} found.", table))),
+    )
+}
+
+// Days to report, every one by default.
+fn range(from: Option<&str>, to: Option<&str>) -> ApiResult<Document> {
+    let from = from.map(parse_date).transpose()?;
+    let to = to.map(parse_date).transpose()?;
+    Ok(between(from, to))
+}
+
+pub async fn find_event(client: &Client, id: ObjectId) -> ApiResult<Event> {
+    let data = Event::find_raw_document(client, Some(doc! {"_id": id}), None)
+        .await
+        .map_err(server_err)?
+        .pop()
+        .ok_or_else(|| not_found("event"))?;
+    let event: EventImpl = from_document(data).map_err(server_err)?;
+    Ok(event.into())
+}
+
+fn render_history(summary: Summary, data: Vec<Document>) -> RawJson<String> {
+    let mut result = serde_json::to_value(&summary).unwrap();
+    result["data"] = serde_json::to_value(&data).unwrap();
+    RawJson(result.to_string())
+}
+
+// Records who attended an occurrence. Attendees already recorded for it are updated.
+#[post("/api/v1/event/<oid>/attendance", format = "json", data = "<sheet>")]
+pub async fn record_attendance(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+    sheet: Result<Json<AttendanceSheet>, JsonError<'_>>,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    let id = parse_oid(oid)?;
+    let sheet = sheet
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?
+        .into_inner();
+    if sheet.attendees.len() > MAX_BULK_SIZE {
+        return Err(Custom(
+            Status::PayloadTooLarge,
+            RawJson(render_err(format!(
+                "At most {} items per request.",
+                MAX_BULK_SIZE
+            ))),
+        ));
+    }
+    let event = find_event(client, id).await?;
+    check_occurrence(&event, &sheet.occurrence)
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+
+    let results: Vec<BulkItemResult> = record(client, id, &sheet)
+        .await
+        .map_err(server_err)?
+        .into_iter()
+        .enumerate()
+        .map(|(i, r)| BulkItemResult::from_result(i, r))
+        .collect();
+    let bulk: BulkResult = results.into();
+    Ok(RawJson(serde_json::to_string(&bulk).unwrap()))
+}
+
+// Attendance by occurrence, for occurrences with any recorded.
+#[get("/api/v1/event/<oid>/attendance?<from>&<to>")]
+pub async fn event_attendance(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    let id = parse_oid(oid)?;
+    let range = range(from, to)?;
+    find_event(client, id).await?;
+    let data = event_history(client, id, range).await.map_err(server_err)?;
+    Ok(RawJson(serde_json::to_string(&data).unwrap()))
+}
+
+#[get("/api/v1/person/<oid>/attendance?<from>&<to>")]
+pub async fn person_attendance(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    let id = parse_oid(oid)?;
+    let range = range(from, to)?;
+    Person::find_raw_document(client, Some(doc! {"_id": id}), None)
+        .await
+        .map_err(server_err)?
+        .pop()
+        .ok_or_else(|| not_found("person"))?;
+    let (summary, data) = person_history(client, id, range)
+        .await
+        .map_err(server_err)?;
+    Ok(render_history(summary, data))
+}
+
+// Attendance of the group and of its members.
+#[get("/api/v1/group/<oid>/attendance?<from>&<to>")]
+pub async fn group_attendance(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    let id = parse_oid(oid)?;
+    let range = range(from, to)?;
+    Group::find_raw_document(client, Some(doc! {"_id": id}), None)
+        .await
+        .map_err(server_err)?
+        .pop()
+        .ok_or_else(|| not_found("group"))?;
+    let (summary, data) = group_history(client, id, range).await.map_err(server_err)?;
+    Ok(render_history(summary, data))
+}
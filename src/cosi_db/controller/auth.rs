@@ -35,7 +35,7 @@ pub fn hash_password(pass: &str, salt: &str, calc_password: &mut Credential) {
 }
 
 pub fn render_result_json(key: &str, value: &str) -> RawJson<String> {
-    return RawJson(format!("{{\"{}\": \"{}\"}}", key, value));
+    RawJson(format!("{{\"{}\": \"{}\"}}", key, value))
 }
 
 #[get("/login", rank = 2)]
@@ -53,7 +53,7 @@ pub async fn gen_login(points: u32, connect: Connection<COSIMongo>) -> RawJson<S
     #[cfg(debug_assertions)]
     {
         // TODO: Ignores points for now.
-        let client: &Client = &*connect;
+        let client: &Client = &connect;
 
         // Delete prior data.
         User::get_collection(client).await.drop(None).await.unwrap();
@@ -70,6 +70,8 @@ pub async fn gen_login(points: u32, connect: Connection<COSIMongo>) -> RawJson<S
                 username: "admin".to_string(),
                 email: "admin@projectcosi.org".to_string(),
                 token: String::new(),
+                admin: true,
+                calendar_token: String::new(),
             },
             None,
         )
@@ -93,11 +95,11 @@ pub async fn gen_login(points: u32, connect: Connection<COSIMongo>) -> RawJson<S
         .await
         .unwrap();
 
-        return RawJson(format!("{{\"{}\": {}}}", "total", points));
+        RawJson(format!("{{\"{}\": {}}}", "total", points))
     }
     #[cfg(not(debug_assertions))]
     {
-        return RawJson("{}".to_string());
+        RawJson("{}".to_string())
     }
 }
 
@@ -112,18 +114,16 @@ pub async fn login_submit(
         return render_result_json("err", "Password not entered.");
     }
 
-    let client: &Client = &*connect;
+    let client: &Client = &connect;
     let user_form_obj: UserForm = user_form.into_inner();
     let mut find_doc = User::convert_form_query(user_form_obj.clone()).unwrap();
     find_doc.remove("token");
     let user_doc_opt = User::find_document(client, Some(find_doc), None).await;
 
     match user_doc_opt {
-        Err(e) => {
-            return render_result_json("err", &e.to_string());
-        }
+        Err(e) => render_result_json("err", &e.to_string()),
         Ok(d_vec) => {
-            if d_vec.len() == 0 {
+            if d_vec.is_empty() {
                 return render_result_json("err", "Invalid user or password.");
             } else if d_vec.len() > 1 {
                 return render_result_json("err", "Internal server error.");
@@ -132,12 +132,12 @@ pub async fn login_submit(
             let oid = d_vec[0].get("_id").unwrap().as_object_id().unwrap();
             // TODO: Error check here.
             let u_login_doc =
-                UserLogin::find_data(client, Some(doc! {"user_id": oid.clone() }), None).await;
-            if let Err(_) = u_login_doc {
+                UserLogin::find_data(client, Some(doc! {"user_id": oid }), None).await;
+            if u_login_doc.is_err() {
                 return render_result_json("err", "Internal server error.");
             }
             let u_logins: Vec<UserLogin> = u_login_doc.unwrap();
-            if u_logins.len() > 1 || u_logins.len() == 0 {
+            if u_logins.len() > 1 || u_logins.is_empty() {
                 return render_result_json("err", "Internal server error.");
             }
 
@@ -157,26 +157,26 @@ pub async fn login_submit(
             // Update user token on DB to persist.
             let update_result = User::update_datum(
                 client,
-                &doc! {"_id": oid.clone()},
+                &doc! {"_id": oid},
                 &doc! {"$set": {"token": uuid_str.clone()}},
                 None,
             )
             .await;
-            if let Err(_) = update_result {
+            if update_result.is_err() {
                 return render_result_json("err", "Internal server error.");
             }
 
             // Update cookies
             cookies.add_private(Cookie::new("user_id", db_oid.clone())); // Store should be hex only.
             cookies.add_private(Cookie::new("user_token", uuid_str.clone()));
-            return render_result_json("success", "User logged in.");
+            render_result_json("success", "User logged in.")
         }
     }
 }
 
 #[get("/logout")]
 pub fn logout(cookies: &CookieJar<'_>) -> Flash<Redirect> {
-    cookies.remove_private(Cookie::named("user_id"));
-    cookies.remove_private(Cookie::named("user_token"));
+    cookies.remove_private(Cookie::from("user_id"));
+    cookies.remove_private(Cookie::from("user_token"));
     Flash::success(Redirect::to("/login"), "Logging out.")
 }
@@ -0,0 +1,58 @@
+// Availability of rooms, see cosi_db::booking.
+use chrono::Duration;
+use rocket::http::Status;
+use rocket::response::content::RawJson;
+use rocket::response::status::Custom;
+use rocket_db_pools::Connection;
+
+use mongodb::bson::doc;
+use mongodb::Client;
+
+use crate::cosi_db::booking::bookings;
+use crate::cosi_db::connection::COSIMongo;
+use crate::cosi_db::controller::api::{parse_oid, ApiResult};
+use crate::cosi_db::controller::common::render_err;
+use crate::cosi_db::controller::occurrence::{range, DATE_FORMAT};
+use crate::cosi_db::model::auth::User;
+use crate::cosi_db::model::common::COSICollection;
+use crate::cosi_db::model::room::Room;
+
+fn server_err<E: std::fmt::Display>(err: E) -> Custom<RawJson<String>> {
+    Custom(Status::InternalServerError, RawJson(render_err(err)))
+}
+
+// Occurrences holding the room over a range of days, the coming week by default.
+#[get("/api/v1/room/<oid>/availability?<from>&<to>")]
+pub async fn room_availability(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    let id = parse_oid(oid)?;
+    let found = Room::find_raw_document(client, Some(doc! {"_id": id}), None)
+        .await
+        .map_err(server_err)?;
+    if found.is_empty() {
+        return Err(Custom(
+            Status::NotFound,
+            RawJson(render_err("No room found.")),
+        ));
+    }
+
+    let (first, last) = range(from, to)?;
+    let start = first.and_hms(0, 0, 0);
+    let end = (last + Duration::days(1)).and_hms(0, 0, 0);
+    let data = bookings(client, id, start, end).await.map_err(server_err)?;
+    Ok(RawJson(
+        serde_json::json!({
+            "from": first.format(DATE_FORMAT).to_string(),
+            "to": last.format(DATE_FORMAT).to_string(),
+            "available": data.is_empty(),
+            "bookings": data,
+        })
+        .to_string(),
+    ))
+}
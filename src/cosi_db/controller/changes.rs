@@ -0,0 +1,42 @@
+// Server-sent events for table changes, see cosi_db::changes.
+use rocket::response::stream::{Event, EventStream};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::Shutdown;
+
+use crate::cosi_db::changes::subscribe;
+use crate::cosi_db::model::auth::User;
+
+// Streams every change the user is allowed to read.
+// `tables` is an optional comma separated list restricting the feed.
+#[get("/api/v1/changes?<tables>")]
+pub fn changes(user: User, tables: Option<String>, mut end: Shutdown) -> EventStream![] {
+    let tables: Option<Vec<String>> =
+        tables.map(|t| t.split(',').map(|s| s.trim().to_string()).collect());
+    let mut feed = subscribe();
+    EventStream! {
+        loop {
+            let change = select! {
+                msg = feed.recv() => match msg {
+                    Ok(change) => change,
+                    Err(RecvError::Closed) => break,
+                    // Missed events, subscribers should reload what they display.
+                    Err(RecvError::Lagged(_)) => {
+                        yield Event::data("").event("lagged");
+                        continue;
+                    }
+                },
+                _ = &mut end => break,
+            };
+            if !user.can_read(&change.table) {
+                continue;
+            }
+            if let Some(t) = &tables {
+                if !t.contains(&change.table) {
+                    continue;
+                }
+            }
+            yield Event::json(&change).event(change.op.as_str());
+        }
+    }
+}
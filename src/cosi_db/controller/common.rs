@@ -1,182 +1,473 @@
-use serde::{Deserialize, Serialize};
+use mongodb::bson::{doc, from_document, to_document, Bson, Document};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use futures::stream::BoxStream;
+use rocket::http::{ContentType, Header};
+use rocket::request::Request;
+use rocket::response::content::RawJson;
+use rocket::response::status::Custom;
+use rocket::response::stream::ByteStream;
+use rocket::response::{self, Responder};
+
+use crate::cosi_db::errors::{COSIError, COSIResult};
+use crate::cosi_db::model::common::COSIForm;
+
+// Per-item outcome of a bulk request. Results keep the order of the request.
+#[derive(Clone, Debug, Serialize)]
+pub struct BulkItemResult {
+    pub index: usize,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub _id: Option<Bson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub err: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upserted: Option<bool>,
+}
+
+impl BulkItemResult {
+    pub fn from_result(index: usize, result: COSIResult<Bson>) -> BulkItemResult {
+        match result {
+            Ok(id) => BulkItemResult {
+                index,
+                ok: true,
+                _id: Some(id),
+                err: None,
+                upserted: None,
+            },
+            Err(err) => BulkItemResult {
+                index,
+                ok: false,
+                _id: None,
+                err: Some(err.to_string()),
+                upserted: None,
+            },
+        }
+    }
+
+    pub fn from_upsert(index: usize, result: COSIResult<(Bson, bool)>) -> BulkItemResult {
+        match result {
+            Ok((id, upserted)) => BulkItemResult {
+                upserted: Some(upserted),
+                ..BulkItemResult::from_result(index, Ok(id))
+            },
+            Err(err) => BulkItemResult::from_result(index, Err(err)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BulkResult {
+    pub total_ok: u64,
+    pub total_err: u64,
+    pub results: Vec<BulkItemResult>,
+}
+
+impl From<Vec<BulkItemResult>> for BulkResult {
+    fn from(results: Vec<BulkItemResult>) -> BulkResult {
+        let total_ok = results.iter().filter(|r| r.ok).count() as u64;
+        BulkResult {
+            total_ok,
+            total_err: results.len() as u64 - total_ok,
+            results,
+        }
+    }
+}
+
+// Update spec used by the bulk update endpoints.
+#[derive(Clone, Debug, Deserialize)]
+pub struct BulkUpdate {
+    pub oid: String,
+    pub data: Value,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct UpsertResult {
+    pub _id: Bson,
+    pub upserted: bool,
+}
+
+// Wraps responses of the verb named routes that predate /api/v1.
+// Clients are pointed at the replacement through the Link header.
+pub struct Deprecated<R> {
+    pub inner: R,
+    pub successor: &'static str,
+}
+
+impl<R> Deprecated<R> {
+    pub fn new(inner: R, successor: &'static str) -> Deprecated<R> {
+        Deprecated { inner, successor }
+    }
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Deprecated<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let mut response = self.inner.respond_to(request)?;
+        response.set_raw_header("Deprecation", "true");
+        response.set_raw_header(
+            "Link",
+            format!("<{}>; rel=\"successor-version\"", self.successor),
+        );
+        Ok(response)
+    }
+}
+
+// Response pointing at a datum, such as the one created by a request.
+#[derive(Responder)]
+pub struct Located {
+    pub inner: Custom<RawJson<String>>,
+    pub location: Header<'static>,
+}
+
+impl Located {
+    pub fn new(inner: Custom<RawJson<String>>, location: String) -> Located {
+        Located {
+            inner,
+            location: Header::new("Location", location),
+        }
+    }
+}
+
+// File download. Streams are sent as they are produced.
+pub enum Download {
+    Stream(
+        ByteStream<BoxStream<'static, Vec<u8>>>,
+        ContentType,
+        Header<'static>,
+    ),
+    File(Vec<u8>, ContentType, Header<'static>),
+}
+
+impl<'r> Responder<'r, 'r> for Download {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'r> {
+        let (mut response, content_type, disposition) = match self {
+            Download::Stream(body, ct, d) => (body.respond_to(request)?, ct, d),
+            Download::File(body, ct, d) => (body.respond_to(request)?, ct, d),
+        };
+        response.set_header(content_type);
+        response.set_header(disposition);
+        Ok(response)
+    }
+}
+
+pub fn attachment(filename: &str) -> Header<'static> {
+    Header::new(
+        "Content-Disposition",
+        format!("attachment; filename=\"{}\"", filename),
+    )
+}
+
+// Keeps names such as "Smith Family" usable as file names.
+pub fn filename(name: &str, fallback: &str, extension: &str) -> String {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    match name.trim_matches('-') {
+        "" => format!("{}.{}", fallback, extension),
+        n => format!("{}.{}", n, extension),
+    }
+}
+
+pub const MAX_BULK_SIZE: usize = 5000;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 100;
+pub const MAX_PAGE_SIZE: i64 = 500;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct PaginateData<T> {
     pub page: u64,
+    pub page_size: u64,
     pub total_pages: u64,
     pub total_result: u64,
+    // Opaque cursor for fetching the page after this one. None when there is no more data.
+    pub next: Option<String>,
     pub data: Vec<T>,
 }
 
-// Helper macros to generate endpoints.
-// Use paste to auto-generate a helper macro.
-// GENERATORS
-#[macro_export]
-macro_rules! generate_generators {
-    ($T:ident) => {
-        $crate::paste::paste! {
-            $crate::with_builtin_macros::with_builtin!{
-                let $v_path = concat!("/gen_", stringify!([<$T: lower>]),  "/<total>") in {
-                    #[get($v_path)]
-                    pub async fn [<gen_ $T:lower>](_user: User, connect: Connection<COSIMongo>, total: u8) -> RawJson<String> {
-                        #[cfg(debug_assertions)]
-                        {
-                            let client: &Client = &*connect;
-                            let data = $T::generate(client, total as u32).await.unwrap();
-
-                            let col = $T::get_collection(client).await;
-                            col.drop(None).await.unwrap();
-                            col.insert_many($T::to_impl(client, data).await.unwrap(), None).await.unwrap();
-
-                            let total = col.estimated_document_count(None).await.unwrap();
-                            return RawJson(format!("{{\"total\": {}}}", total));
-                        }
-                        #[cfg(not(debug_assertions))]
-                        {
-                            return RawJson("{}".to_string());
-                        }
-                    }
-                }
-            }
+// Requested page sizes above the maximum are clamped.
+pub fn parse_page_size(page_size: Option<i64>) -> COSIResult<i64> {
+    match page_size {
+        None => Ok(DEFAULT_PAGE_SIZE),
+        Some(v) if v < 1 => Err(COSIError::msg("Page size must be at least 1.")),
+        Some(v) => Ok(std::cmp::min(v, MAX_PAGE_SIZE)),
+    }
+}
+
+// Data skipped to reach the page. Pages too far out to address are refused.
+pub fn page_skip(page: u64, page_size: i64) -> COSIResult<u64> {
+    page.checked_mul(page_size as u64)
+        .filter(|skip| *skip <= i64::MAX as u64)
+        .ok_or(COSIError::msg("Page is out of range."))
+}
+
+// Splits comma separated query values such as "first_name,last_name".
+fn split_query_list(query: &str) -> Vec<&str> {
+    query
+        .split(",")
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+fn check_field(field: &str, valid_fields: &[String]) -> COSIResult<()> {
+    if field != "_id" && !valid_fields.iter().any(|f| f == field) {
+        return Err(COSIError::msg(format!("Unknown field: {}.", field)));
+    }
+    Ok(())
+}
+
+// Converts "last_name,-dob" into a sort document. A leading "-" means descending.
+// "_id" is always appended as a tie-breaker so that pages stay stable.
+pub fn parse_sort(sort: &str, valid_fields: &[String]) -> COSIResult<Document> {
+    let mut result = Document::new();
+    for entry in split_query_list(sort) {
+        let (field, order) = match entry.strip_prefix("-") {
+            Some(f) => (f, -1),
+            None => (entry.strip_prefix("+").unwrap_or(entry), 1),
+        };
+        check_field(field, valid_fields)?;
+        if result.contains_key(field) {
+            return Err(COSIError::msg(format!("Duplicate sort field: {}.", field)));
         }
-    };
+        result.insert(field, order);
+    }
+
+    if !result.contains_key("_id") {
+        result.insert("_id", 1);
+    }
+    Ok(result)
+}
+
+// Converts "first_name,last_name" into the list of keys to return.
+pub fn parse_projection(fields: &str, valid_fields: &[String]) -> COSIResult<Vec<String>> {
+    let mut result = vec![];
+    for field in split_query_list(fields) {
+        check_field(field, valid_fields)?;
+        result.push(field.to_string());
+    }
+    Ok(result)
 }
 
-// GETTERS
-#[macro_export]
-macro_rules! generate_pageable_getter {
-    ($T:ident) => {
-        $crate::paste::paste! {
-            $crate::with_builtin_macros::with_builtin!{
-                let $v_path = concat!("/get_", stringify!([<$T: lower>]), "?<page>&<search_query..>") in {
-                    #[get($v_path)]
-                    pub async fn [<get_ $T:lower>](_user: User, connect: Connection<COSIMongo>, page: Option<u64>, search_query: [<$T Optional>]) -> RawJson<String> {
-                        let client: &Client = &*connect;
-                        let page = page.unwrap_or(0);
-
-                        let col = $T::get_collection(client).await;
-
-                        // Page calculate.
-                        let search_doc = $T::convert_form_query(search_query).unwrap();
-                        let total_result:u64 = if search_doc.len() != 0 {
-                            col.count_documents(Some(search_doc.clone()), None).await.unwrap()
-                        } else {
-                            col.estimated_document_count(None).await.unwrap()
-                        };
-
-                        let limit_size: i64 = 100;
-                        let total_pages: u64 = (total_result as f64 / limit_size as f64).ceil() as u64;
-
-                        let find_options = FindOptions::builder()
-                            .limit(limit_size)
-                            .skip(limit_size as u64 * page)
-                            .build();
-
-                        // Query any search_queries
-                        let data: Vec<Document> = $T::find_document(client, Some(search_doc), Some(find_options)).await.unwrap();
-                        RawJson(
-                            serde_json::to_string(&PaginateData {
-                                page: page,
-                                total_pages: total_pages,
-                                total_result: total_result,
-                                data: data
-                            }).unwrap()
-                        )
-                    }
-                }
+// Projection is applied after foreign keys are resolved as resolving requires the full document.
+// "_id" is always kept so rows can still be referenced.
+pub fn project_documents(docs: &mut [Document], fields: &[String]) {
+    for d in docs.iter_mut() {
+        let keys: Vec<String> = d.keys().map(|k| k.to_string()).collect();
+        for k in keys {
+            if k != "_id" && !fields.contains(&k) {
+                d.remove(&k);
             }
         }
     }
 }
 
-// INSERT
-#[macro_export]
-macro_rules! generate_pageable_inserter {
-    ($T:ident) => {
-        $crate::paste::paste! {
-            $crate::with_builtin_macros::with_builtin!{
-                let $v_path = concat!("/insert_", stringify!([<$T: lower>])) in {
-                    #[post($v_path, data="<insert_query>")]
-                    pub async fn [<insert_ $T:lower>](_user: User, connect: Connection<COSIMongo>, insert_query: Form<[<$T Impl>]>) -> Custom<RawJson<String>> {
-                        let client: &Client = &*connect;
-                        let insert_query_obj = insert_query.into_inner();
-                        let search_convert = $T::convert_form_insert(insert_query_obj);
-                        return match search_convert {
-                            Ok(search_obj) => {
-                                // Query any search_queries
-                                let bson_id: Bson = $T::insert_datum(client, &from_document(search_obj).unwrap(), None).await.unwrap();
-                                Custom(Status::Ok, RawJson(
-                                    serde_json::to_string(&bson_id).unwrap()
-                                ))
-                            },
-                            Err(err) => {
-                                Custom(Status::BadRequest, RawJson(format!("{{\"err\": \"{}\"}}", err)))
-                            }
-                        }
-                    }
-                }
-            }
+// Cursors store the sort they were created with plus the sort values of the last row.
+// They are encoded as hex BSON so clients treat them as opaque strings.
+pub fn encode_cursor(sort: &Document, last: &Document) -> String {
+    let mut values = Document::new();
+    for k in sort.keys() {
+        values.insert(k, last.get(k).cloned().unwrap_or(Bson::Null));
+    }
+
+    let mut bytes: Vec<u8> = vec![];
+    doc! {"sort": sort.clone(), "values": values}
+        .to_writer(&mut bytes)
+        .unwrap();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Converts a cursor back into a filter matching every row after the cursor under the given sort.
+pub fn decode_cursor(cursor: &str, sort: &Document) -> COSIResult<Document> {
+    let invalid = || COSIError::msg("Invalid cursor.");
+    if cursor.len() % 2 == 1 || !cursor.is_ascii() {
+        return Err(invalid());
+    }
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| invalid())?;
+    let decoded = Document::from_reader(&mut bytes.as_slice()).map_err(|_| invalid())?;
+    let values = decoded.get_document("values").map_err(|_| invalid())?;
+    if decoded.get_document("sort").map_err(|_| invalid())? != sort {
+        return Err(COSIError::msg("Cursor does not match the requested sort."));
+    }
+
+    // Keyset pagination: (k0 > v0) or (k0 == v0 and k1 > v1) or ...
+    // Mongo sorts null before every other value which needs special casing
+    // as comparison operators never match across types.
+    let mut clauses: Vec<Bson> = vec![];
+    let mut prefix = Document::new();
+    for (k, order) in sort {
+        let v = values.get(k).ok_or_else(invalid)?;
+        let ascending = order.as_i32() != Some(-1);
+        let clause = match (v, ascending) {
+            (Bson::Null, true) => Some(doc! {k: {"$ne": Bson::Null}}),
+            (Bson::Null, false) => None,
+            (_, true) => Some(doc! {k: {"$gt": v.clone()}}),
+            (_, false) => Some(doc! {"$or": [{k: {"$lt": v.clone()}}, {k: Bson::Null}]}),
+        };
+        if let Some(c) = clause {
+            let mut full = prefix.clone();
+            full.extend(c);
+            clauses.push(Bson::Document(full));
         }
+        prefix.insert(k, v.clone());
+    }
+
+    if clauses.is_empty() {
+        // Nothing can come after the cursor.
+        return Ok(doc! {"_id": {"$exists": false}});
     }
+    Ok(doc! {"$or": clauses})
 }
 
-#[macro_export]
-macro_rules! generate_pageable_update {
-    ($T:ident) => {
-        $crate::paste::paste! {
-            $crate::with_builtin_macros::with_builtin!{
-                let $v_path = concat!("/update_", stringify!([<$T: lower>]), "?<oid>") in {
-                    #[post($v_path, data="<update_query>")]
-                    pub async fn [<update_ $T:lower>](_user: User, connect: Connection<COSIMongo>, oid: String, update_query: Form<[<$T Impl>]>) -> Custom<RawJson<String>> {
-                        let client: &Client = &*connect;
-                        // We make the following assumption: absence -> null. We do not store empty strings.
-                        // This has to do with HashMap limitations and Rust autocasting behavior.
-                        let data_obj = update_query.into_inner();
-                        let update_convert = $T::convert_form_insert(data_obj);
-                        return match update_convert {
-                            Ok(update_obj) => {
-                                // Query any update_queries
-                                let result = $T::update_datum(client, &doc!{"_id": ObjectId::from_str(&oid).unwrap()}, &doc!{"$set": update_obj}, None).await.unwrap();
-                                Custom(Status::Ok, RawJson(
-                                    serde_json::to_string(&result).unwrap()
-                                ))
-
-                            },
-                            Err(err) => {
-                                Custom(Status::BadRequest, RawJson(format!("{{\"err\": \"{}\"}}", err)))
-                            }
-                        };
-                    }
-                }
-            }
+// Runs a value through the model type so that, for example, hex strings become ObjectIds.
+// Also rejects values the model cannot hold such as null on a required field.
+fn normalize_field<I>(base: &Document, field: &str, value: Bson) -> COSIResult<Bson>
+where
+    I: Serialize + DeserializeOwned,
+{
+    let mut probe = base.clone();
+    probe.insert(field, value);
+    let imp: I = from_document(probe)
+        .map_err(|e| COSIError::msg(format!("Invalid value for {}: {}", field, e)))?;
+    Ok(to_document(&imp)?.get(field).cloned().unwrap_or(Bson::Null))
+}
+
+fn patch_array_ops<I>(
+    base: &Document,
+    ops: Option<Value>,
+    op_name: &str,
+    valid_fields: &[String],
+) -> COSIResult<Vec<(String, Vec<Bson>)>>
+where
+    I: Serialize + DeserializeOwned,
+{
+    let ops = match ops {
+        None => return Ok(vec![]),
+        Some(Value::Object(o)) => o,
+        Some(_) => return Err(COSIError::msg(format!("{} must be an object.", op_name))),
+    };
+
+    let mut result = vec![];
+    for (field, items) in ops {
+        check_field(&field, valid_fields)?;
+        if !items.is_array() {
+            return Err(COSIError::msg(format!(
+                "{} values for {} must be an array.",
+                op_name, field
+            )));
+        }
+        let items = Bson::try_from(items)?;
+        match normalize_field::<I>(base, &field, items)? {
+            Bson::Array(v) => result.push((field, v)),
+            _ => return Err(COSIError::msg(format!("{} is not an array field.", field))),
         }
     }
+    Ok(result)
 }
 
-// DROP
-#[macro_export]
-macro_rules! generate_dropper {
-    ($T:ident) => {
-        $crate::paste::paste! {
-            $crate::with_builtin_macros::with_builtin!{
-                let $v_path = concat!("/drop_", stringify!([<$T: lower>])) in {
-                    #[get($v_path)]
-                    pub async fn [<drop_ $T:lower>](_user:User, connect: Connection<COSIMongo>) -> RawJson<String> {
-                        #[cfg(debug_assertions)]
-                        {
-                            let client: &Client = &*connect;
-                            let col = $T::get_collection(client).await;
-                            col.drop(None).await.unwrap();
-                            $T::create_collection(client).await.unwrap();
-                            return RawJson(format!("{{\"dropped\": true}}"));
-                        }
-                        #[cfg(not(debug_assertions))]
-                        {
-                            return RawJson("{}".to_string());
-                        }
-                    }
-                }
+// Converts a JSON patch into a mongo update for the given document.
+// Omitted fields are untouched, null removes a field from the document with "$unset"
+// and "$add" / "$remove" add or remove items of array fields:
+//   {"middle_name": null, "$add": {"nicks": ["Bob"]}, "$remove": {"nicks": ["Rob"]}}
+// The patched document must still be a valid model or the patch is rejected.
+pub fn build_patch<I>(
+    current: &Document,
+    mut patch: Map<String, Value>,
+    valid_fields: &[String],
+) -> COSIResult<Document>
+where
+    I: Serialize + DeserializeOwned + COSIForm,
+{
+    let mut base = current.clone();
+    base.remove("_id");
+
+    let adds = patch_array_ops::<I>(&base, patch.remove("$add"), "$add", valid_fields)?;
+    let removes = patch_array_ops::<I>(&base, patch.remove("$remove"), "$remove", valid_fields)?;
+
+    let mut sets = Document::new();
+    let mut unsets = Document::new();
+    for (field, value) in patch {
+        check_field(&field, valid_fields)?;
+        if field == "_id" {
+            return Err(COSIError::msg("_id cannot be updated."));
+        }
+        // Null clears an optional field rather than storing null.
+        let value = normalize_field::<I>(&base, &field, Bson::try_from(value)?)?;
+        if value == Bson::Null {
+            unsets.insert(field, "");
+        } else {
+            sets.insert(field, value);
+        }
+    }
+
+    // Mongo refuses multiple operators on the same field.
+    let mut touched: Vec<&String> = sets.keys().chain(unsets.keys()).collect();
+    for (field, _) in adds.iter().chain(removes.iter()) {
+        if touched.contains(&field) {
+            return Err(COSIError::msg(format!(
+                "{} can only be changed once per patch.",
+                field
+            )));
+        }
+        touched.push(field);
+    }
+    if touched.is_empty() {
+        return Err(COSIError::msg("Nothing to update."));
+    }
+
+    // Validate the final document before writing.
+    let mut patched = base.clone();
+    patched.extend(sets.clone());
+    for field in unsets.keys() {
+        patched.remove(field);
+    }
+    for (field, items) in &adds {
+        let mut arr = patched.get_array(field).cloned().unwrap_or_default();
+        for item in items {
+            if !arr.contains(item) {
+                arr.push(item.clone());
             }
         }
+        patched.insert(field, arr);
+    }
+    for (field, items) in &removes {
+        let mut arr = patched.get_array(field).cloned().unwrap_or_default();
+        arr.retain(|v| !items.contains(v));
+        patched.insert(field, arr);
+    }
+    let imp: I = from_document(patched)?;
+    imp.sanitize_insert()?;
+
+    let mut update = Document::new();
+    if !sets.is_empty() {
+        update.insert("$set", sets);
+    }
+    if !unsets.is_empty() {
+        update.insert("$unset", unsets);
+    }
+    if !adds.is_empty() {
+        let mut d = Document::new();
+        for (field, items) in adds {
+            d.insert(field, doc! {"$each": items});
+        }
+        update.insert("$addToSet", d);
+    }
+    if !removes.is_empty() {
+        let mut d = Document::new();
+        for (field, items) in removes {
+            d.insert(field, doc! {"$in": items});
+        }
+        update.insert("$pull", d);
     }
+    Ok(update)
+}
+
+pub fn render_err(err: impl std::fmt::Display) -> String {
+    serde_json::json!({ "err": err.to_string() }).to_string()
 }
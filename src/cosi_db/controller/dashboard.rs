@@ -44,9 +44,31 @@ pub fn person_redirect() -> Flash<Redirect> {
     Flash::success(Redirect::to("/person"), "User needs to be logged in.")
 }
 
+// Month view of event occurrences.
+#[get("/calendar", rank = 2)]
+pub fn calendar(_user: User) -> RawHtml<Template> {
+    RawHtml(Template::render("calendar", context! {}))
+}
+
+#[get("/calendar", rank = 3)]
+pub fn calendar_redirect() -> Flash<Redirect> {
+    Flash::success(Redirect::to("/login"), "User needs to be logged in.")
+}
+
+// Child check-in, see controller::kiosk.
+#[get("/kiosk", rank = 2)]
+pub fn kiosk(_user: User) -> RawHtml<Template> {
+    RawHtml(Template::render("kiosk", context! {}))
+}
+
+#[get("/kiosk", rank = 3)]
+pub fn kiosk_redirect() -> Flash<Redirect> {
+    Flash::success(Redirect::to("/login"), "User needs to be logged in.")
+}
+
 #[get("/search?<query>")]
 pub async fn search(_user: User, connect: Connection<COSIMongo>, query: &str) -> RawJson<String> {
-    let client: &Client = &*connect;
+    let client: &Client = &connect;
 
     // TODO add tables parameter.
     let rstring = format!("(?i).*{}.*", query.to_lowercase());
@@ -0,0 +1,101 @@
+// GEDCOM downloads and imports, see cosi_db::gedcom.
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::{ContentType, Status};
+use rocket::response::content::RawJson;
+use rocket::response::status::Custom;
+use rocket_db_pools::Connection;
+
+use mongodb::bson::doc;
+use mongodb::Client;
+
+use crate::cosi_db::connection::COSIMongo;
+use crate::cosi_db::controller::api::{parse_oid, ApiResult};
+use crate::cosi_db::controller::common::{attachment, render_err, Download};
+use crate::cosi_db::controller::import::MAX_IMPORT_MIB;
+use crate::cosi_db::gedcom::{export, import};
+use crate::cosi_db::import::decode;
+use crate::cosi_db::model::auth::User;
+use crate::cosi_db::model::common::COSICollection;
+use crate::cosi_db::model::household::Household;
+
+fn server_err<E: std::fmt::Display>(err: E) -> Custom<RawJson<String>> {
+    Custom(Status::InternalServerError, RawJson(render_err(err)))
+}
+
+fn download(data: String, filename: &str) -> Download {
+    Download::File(
+        data.into_bytes(),
+        ContentType::new("application", "x-gedcom"),
+        attachment(filename),
+    )
+}
+
+// Every person and family.
+#[get("/api/v1/gedcom")]
+pub async fn gedcom(_user: User, connect: Connection<COSIMongo>) -> ApiResult<Download> {
+    let client: &Client = &connect;
+    let data = export(client, None).await.map_err(server_err)?;
+    Ok(download(data, "cosi-db.ged"))
+}
+
+#[get("/api/v1/household/<oid>/gedcom")]
+pub async fn household_gedcom(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+) -> ApiResult<Download> {
+    let client: &Client = &connect;
+    let id = parse_oid(oid)?;
+    let found = Household::find_raw_document(client, Some(doc! {"_id": id}), None)
+        .await
+        .map_err(server_err)?;
+    if found.is_empty() {
+        return Err(Custom(
+            Status::NotFound,
+            RawJson(render_err("No household found.")),
+        ));
+    }
+    let data = export(client, Some(id)).await.map_err(server_err)?;
+    Ok(download(data, &format!("household-{}.ged", oid)))
+}
+
+// The body is a GEDCOM 5.5.1 file in UTF-8.
+#[post("/api/v1/import/gedcom?<dry_run>", data = "<data>")]
+pub async fn import_gedcom(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    dry_run: Option<bool>,
+    data: Data<'_>,
+) -> ApiResult<Custom<RawJson<String>>> {
+    let client: &Client = &connect;
+    let bytes = data
+        .open(MAX_IMPORT_MIB.mebibytes())
+        .into_bytes()
+        .await
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+    if !bytes.is_complete() {
+        let err = format!("Imports are limited to {} MiB.", MAX_IMPORT_MIB);
+        return Err(Custom(Status::PayloadTooLarge, RawJson(render_err(err))));
+    }
+    let text = decode(bytes.into_inner());
+
+    let report = import(client, &text, dry_run.unwrap_or(false))
+        .await
+        .map_err(server_err)?;
+    if report.individuals == 0 && report.errors.is_empty() {
+        return Err(Custom(
+            Status::BadRequest,
+            RawJson(render_err("File holds no individuals.")),
+        ));
+    }
+    // Nothing is written when a line is invalid.
+    let status = if report.errors.is_empty() {
+        Status::Ok
+    } else {
+        Status::UnprocessableEntity
+    };
+    Ok(Custom(
+        status,
+        RawJson(serde_json::to_string(&report).unwrap()),
+    ))
+}
@@ -0,0 +1,638 @@
+// GraphQL schema built from the model metadata, so new fields and references show up without
+// touching this file. Every table gets:
+// * An object type with its fields. References resolve to the referenced object, and each table
+//   referencing it adds a list field, such as Person.households or Group.grouprelations.
+// * `<table>(id)` and `<tables>(filter, page, page_size, sort)` on the query root.
+// References are loaded per list rather than per item, see Loader.
+
+// async-graphql
+use async_graphql::dynamic::{
+    Field, FieldFuture, FieldValue, InputObject, InputValue, Object, ResolverContext, Scalar,
+    Schema, TypeRef,
+};
+use async_graphql::{Name, Value as GraphValue};
+
+// serde
+use serde_json::Value;
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+
+// rocket
+use rocket::http::Status;
+use rocket::response::content::RawJson;
+use rocket::response::status::Custom;
+use rocket::serde::json::{Error as JsonError, Json};
+use rocket::tokio::sync::Mutex;
+use rocket::tokio::task::yield_now;
+use rocket::State;
+use rocket_db_pools::Connection;
+
+// mongo
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Client, Collection};
+
+use futures::stream::TryStreamExt;
+
+// cosi_db
+use crate::cosi_db::connection::COSIMongo;
+use crate::cosi_db::controller::common::{
+    page_skip, parse_page_size, parse_sort, render_err, PaginateData,
+};
+use crate::cosi_db::controller::openapi::short_name;
+use crate::cosi_db::model::address::{Address, AddressImpl, AddressOptional};
+use crate::cosi_db::model::auth::User;
+use crate::cosi_db::model::common::{COSIModel, COSISchema};
+use crate::cosi_db::model::event::{
+    Event, EventImpl, EventOptional, EventRegistration, EventRegistrationImpl,
+    EventRegistrationOptional,
+};
+use crate::cosi_db::model::group::{
+    Group, GroupImpl, GroupOptional, GroupRelation, GroupRelationImpl, GroupRelationOptional,
+};
+use crate::cosi_db::model::household::{Household, HouseholdImpl, HouseholdOptional};
+use crate::cosi_db::model::person::{Person, PersonImpl, PersonOptional};
+use crate::cosi_db::model::room::{Room, RoomImpl, RoomOptional};
+
+const JSON_SCALAR: &str = "JSON";
+const MAX_DEPTH: usize = 12;
+const MAX_COMPLEXITY: usize = 1000;
+
+#[derive(Clone)]
+struct GraphTable {
+    table: &'static str,
+    type_name: &'static str,
+    // Schema of the stored datum and of the query form.
+    schema: Value,
+    filter: Value,
+    // Field and referenced table.
+    references: Vec<(&'static str, &'static str)>,
+}
+
+impl GraphTable {
+    fn new<T, I, F>() -> GraphTable
+    where
+        T: COSIModel,
+        I: COSISchema,
+        F: COSISchema,
+    {
+        GraphTable {
+            table: T::table_name(),
+            type_name: short_name::<T>(),
+            schema: I::schema(),
+            filter: F::schema(),
+            references: T::references()
+                .iter()
+                .map(|r| (r.field, (r.table)()))
+                .collect(),
+        }
+    }
+
+    fn reference(&self, field: &str) -> Option<&'static str> {
+        self.references
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, t)| *t)
+    }
+
+    fn field_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.schema["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        names.push("_id".to_string());
+        names
+    }
+}
+
+fn tables() -> Vec<GraphTable> {
+    vec![
+        GraphTable::new::<Person, PersonImpl, PersonOptional>(),
+        GraphTable::new::<Address, AddressImpl, AddressOptional>(),
+        GraphTable::new::<Household, HouseholdImpl, HouseholdOptional>(),
+        GraphTable::new::<Event, EventImpl, EventOptional>(),
+        GraphTable::new::<EventRegistration, EventRegistrationImpl, EventRegistrationOptional>(),
+        GraphTable::new::<Group, GroupImpl, GroupOptional>(),
+        GraphTable::new::<GroupRelation, GroupRelationImpl, GroupRelationOptional>(),
+        GraphTable::new::<Room, RoomImpl, RoomOptional>(),
+    ]
+}
+
+fn plural(name: &str) -> String {
+    match name.ends_with('s') {
+        true => format!("{}es", name),
+        false => format!("{}s", name),
+    }
+}
+
+fn collection(client: &Client, table: &str) -> Collection<Document> {
+    client.database("cosi_db").collection::<Document>(table)
+}
+
+// Looks through nullable wrappers.
+fn unwrap_schema(schema: &Value) -> &Value {
+    match schema.get("allOf") {
+        Some(all) => &all[0],
+        None => schema,
+    }
+}
+
+fn is_object_id(schema: &Value) -> bool {
+    unwrap_schema(schema)["$ref"] == "#/components/schemas/ObjectId"
+}
+
+// GraphQL scalar of a schema, None for arrays and objects.
+fn scalar_name(schema: &Value) -> Option<&'static str> {
+    let schema = unwrap_schema(schema);
+    if is_object_id(schema) {
+        return Some(TypeRef::ID);
+    }
+    match schema["type"].as_str() {
+        Some("string") => Some(TypeRef::STRING),
+        Some("integer") => Some(TypeRef::INT),
+        Some("number") => Some(TypeRef::FLOAT),
+        Some("boolean") => Some(TypeRef::BOOLEAN),
+        _ => None,
+    }
+}
+
+fn field_type(schema: &Value, required: bool) -> TypeRef {
+    let inner = unwrap_schema(schema);
+    match (inner["type"].as_str(), scalar_name(inner)) {
+        (Some("array"), _) => match scalar_name(&inner["items"]) {
+            Some(item) if required => TypeRef::named_nn_list_nn(item),
+            Some(item) => TypeRef::named_nn_list(item),
+            None => TypeRef::named(JSON_SCALAR),
+        },
+        (_, Some(scalar)) if required => TypeRef::named_nn(scalar),
+        (_, Some(scalar)) => TypeRef::named(scalar),
+        _ => TypeRef::named(JSON_SCALAR),
+    }
+}
+
+// Object ids are exposed as their hex string.
+fn to_graph_value(value: &Bson) -> GraphValue {
+    match value {
+        Bson::ObjectId(oid) => GraphValue::String(oid.to_hex()),
+        Bson::Array(a) => GraphValue::List(a.iter().map(to_graph_value).collect()),
+        Bson::Document(d) => GraphValue::Object(
+            d.iter()
+                .map(|(k, v)| (Name::new(k), to_graph_value(v)))
+                .collect(),
+        ),
+        v => GraphValue::from_json(v.clone().into_relaxed_extjson()).unwrap_or(GraphValue::Null),
+    }
+}
+
+fn parse_id(id: &str) -> async_graphql::Result<ObjectId> {
+    ObjectId::parse_str(id).map_err(|_| "Invalid object id.".into())
+}
+
+fn parent<'a>(ctx: &ResolverContext<'a>) -> async_graphql::Result<&'a Document> {
+    ctx.parent_value.try_downcast_ref::<Document>()
+}
+
+fn page_args(field: Field) -> Field {
+    field
+        .argument(InputValue::new("page", TypeRef::named(TypeRef::INT)))
+        .argument(InputValue::new("page_size", TypeRef::named(TypeRef::INT)))
+}
+
+fn read_page(ctx: &ResolverContext<'_>) -> async_graphql::Result<(u64, i64)> {
+    let page = match ctx.args.get("page") {
+        Some(v) if !v.is_null() => v.u64()?,
+        _ => 0,
+    };
+    let page_size = match ctx.args.get("page_size") {
+        Some(v) if !v.is_null() => Some(v.i64()?),
+        _ => None,
+    };
+    Ok((page, parse_page_size(page_size)?))
+}
+
+async fn find_page(
+    client: &Client,
+    table: &str,
+    filter: Document,
+    sort: Document,
+    page: u64,
+    page_size: i64,
+) -> async_graphql::Result<Vec<Document>> {
+    let options = FindOptions::builder()
+        .limit(page_size)
+        .skip(page_skip(page, page_size)?)
+        .sort(sort)
+        .build();
+    Ok(collection(client, table)
+        .find(filter, options)
+        .await?
+        .try_collect()
+        .await?)
+}
+
+fn value_field(name: String, ty: TypeRef) -> Field {
+    let key = name.clone();
+    Field::new(name, ty, move |ctx| {
+        let key = key.clone();
+        FieldFuture::new(async move {
+            Ok(parent(&ctx)?
+                .get(&key)
+                .filter(|v| **v != Bson::Null)
+                .map(|v| FieldValue::value(to_graph_value(v))))
+        })
+    })
+}
+
+// Lookups of one request. The items of a list resolve together: each queues the ids it needs
+// and yields, then the first to resume fetches every queued id of the batch in one query.
+#[derive(Default)]
+struct Loader {
+    state: Mutex<LoaderState>,
+}
+
+#[derive(Default)]
+struct LoaderState {
+    queued: HashMap<String, HashSet<ObjectId>>,
+    found: HashMap<(String, ObjectId), Vec<Document>>,
+}
+
+impl Loader {
+    // Data of the ids in order. Fetch returns the data with the id it was found for.
+    async fn load<F, Fut>(
+        &self,
+        batch: String,
+        ids: &[ObjectId],
+        fetch: F,
+    ) -> async_graphql::Result<Vec<Document>>
+    where
+        F: FnOnce(Vec<ObjectId>) -> Fut,
+        Fut: Future<Output = async_graphql::Result<Vec<(ObjectId, Document)>>>,
+    {
+        {
+            let mut state = self.state.lock().await;
+            let missing: Vec<ObjectId> = ids
+                .iter()
+                .filter(|id| !state.found.contains_key(&(batch.clone(), **id)))
+                .cloned()
+                .collect();
+            state
+                .queued
+                .entry(batch.clone())
+                .or_default()
+                .extend(missing);
+        }
+        yield_now().await;
+
+        let mut state = self.state.lock().await;
+        let queued: Vec<ObjectId> = state
+            .queued
+            .remove(&batch)
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        if !queued.is_empty() {
+            let data = fetch(queued.clone()).await?;
+            for id in queued {
+                state.found.insert((batch.clone(), id), vec![]);
+            }
+            for (id, d) in data {
+                state.found.entry((batch.clone(), id)).or_default().push(d);
+            }
+        }
+        Ok(ids
+            .iter()
+            .flat_map(|id| {
+                state
+                    .found
+                    .get(&(batch.clone(), *id))
+                    .cloned()
+                    .unwrap_or_default()
+            })
+            .collect())
+    }
+}
+
+// Resolves a field holding one or many object ids into the referenced data.
+fn reference_field(name: String, target: &GraphTable, many: bool) -> Field {
+    let key = name.clone();
+    let table = target.table;
+    let ty = if many {
+        TypeRef::named_nn_list_nn(target.type_name)
+    } else {
+        TypeRef::named(target.type_name)
+    };
+    Field::new(name, ty, move |ctx| {
+        let key = key.clone();
+        FieldFuture::new(async move {
+            let client = ctx.data::<Client>()?;
+            let ids: Vec<ObjectId> = match parent(&ctx)?.get(&key) {
+                Some(Bson::ObjectId(oid)) => vec![*oid],
+                Some(Bson::Array(a)) => a.iter().filter_map(|v| v.as_object_id()).collect(),
+                _ => vec![],
+            };
+            let mut found = ctx
+                .data::<Loader>()?
+                .load(table.to_string(), &ids, |queued| async move {
+                    let data: Vec<Document> = collection(client, table)
+                        .find(doc! {"_id": {"$in": queued}}, None)
+                        .await?
+                        .try_collect()
+                        .await?;
+                    Ok(data
+                        .into_iter()
+                        .filter_map(|d| Some((d.get_object_id("_id").ok()?, d)))
+                        .collect())
+                })
+                .await?;
+            if many {
+                Ok(Some(FieldValue::list(
+                    found.into_iter().map(FieldValue::owned_any),
+                )))
+            } else {
+                Ok(found.pop().map(FieldValue::owned_any))
+            }
+        })
+    })
+}
+
+// Lists the data of another table referencing the parent.
+fn referenced_by_field(name: String, source: &GraphTable, key: &'static str) -> Field {
+    let table = source.table;
+    let field = Field::new(
+        name,
+        TypeRef::named_nn_list_nn(source.type_name),
+        move |ctx| {
+            FieldFuture::new(async move {
+                let client = ctx.data::<Client>()?;
+                let id = parent(&ctx)?.get_object_id("_id")?;
+                let (page, page_size) = read_page(&ctx)?;
+                let batch = format!("{}.{}:{}:{}", table, key, page, page_size);
+                let data = ctx
+                    .data::<Loader>()?
+                    .load(batch, &[id], |queued| {
+                        find_referencing(client, table, key, queued, page, page_size)
+                    })
+                    .await?;
+                Ok(Some(FieldValue::list(
+                    data.into_iter().map(FieldValue::owned_any),
+                )))
+            })
+        },
+    );
+    page_args(field)
+}
+
+// The page of data referencing each of the ids through the key, with the id referenced.
+async fn find_referencing(
+    client: &Client,
+    table: &str,
+    key: &str,
+    ids: Vec<ObjectId>,
+    page: u64,
+    page_size: i64,
+) -> async_graphql::Result<Vec<(ObjectId, Document)>> {
+    let skip = page_skip(page, page_size)? as i64;
+    // Keys holding many ids are unwound so each referenced id gets its own group.
+    let pipeline = vec![
+        doc! {"$match": {key: {"$in": &ids}}},
+        doc! {"$sort": {"_id": 1}},
+        doc! {"$addFields": {"_referenced": format!("${}", key)}},
+        doc! {"$unwind": "$_referenced"},
+        doc! {"$match": {"_referenced": {"$in": &ids}}},
+        doc! {"$group": {"_id": "$_referenced", "data": {"$push": "$$ROOT"}}},
+        doc! {"$project": {"data": {"$slice": ["$data", skip, page_size]}}},
+    ];
+    let groups: Vec<Document> = collection(client, table)
+        .aggregate(pipeline, None)
+        .await?
+        .try_collect()
+        .await?;
+    let mut result = vec![];
+    for group in groups {
+        let id = group.get_object_id("_id")?;
+        for d in group.get_array("data")? {
+            if let Some(d) = d.as_document() {
+                let mut d = d.clone();
+                d.remove("_referenced");
+                result.push((id, d));
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn build_object(table: &GraphTable, all: &Vec<GraphTable>) -> Object {
+    let mut object = Object::new(table.type_name).field(value_field(
+        "_id".to_string(),
+        TypeRef::named_nn(TypeRef::ID),
+    ));
+
+    let required: Vec<&str> = table.schema["required"]
+        .as_array()
+        .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or(vec![]);
+    for (name, schema) in table.schema["properties"].as_object().unwrap() {
+        let field = match table.reference(name) {
+            Some(target) => {
+                let target = all.iter().find(|t| t.table == target).unwrap();
+                let many = unwrap_schema(schema)["type"] == "array";
+                reference_field(name.clone(), target, many)
+            }
+            None => value_field(
+                name.clone(),
+                field_type(schema, required.contains(&name.as_str())),
+            ),
+        };
+        object = object.field(field);
+    }
+
+    // Reverse references. Tables referencing this one more than once name the field as well.
+    for source in all {
+        let keys: Vec<&'static str> = source
+            .references
+            .iter()
+            .filter(|(_, t)| *t == table.table)
+            .map(|(f, _)| *f)
+            .collect();
+        for key in &keys {
+            let name = if keys.len() == 1 {
+                plural(source.table)
+            } else {
+                format!("{}_by_{}", plural(source.table), key)
+            };
+            object = object.field(referenced_by_field(name, source, key));
+        }
+    }
+    object
+}
+
+fn build_filter(table: &GraphTable) -> InputObject {
+    let mut filter = InputObject::new(format!("{}Filter", table.type_name));
+    for (name, schema) in table.filter["properties"].as_object().unwrap() {
+        if let Some(scalar) = scalar_name(schema) {
+            filter = filter.field(InputValue::new(name.clone(), TypeRef::named(scalar)));
+        }
+    }
+    filter
+}
+
+// Converts the filter argument into a mongo query. Ids are matched as object ids.
+fn read_filter(ctx: &ResolverContext<'_>, table: &GraphTable) -> async_graphql::Result<Document> {
+    let mut query = Document::new();
+    let filter = match ctx.args.get("filter") {
+        Some(v) if !v.is_null() => v.object()?,
+        _ => return Ok(query),
+    };
+    for (name, value) in filter.iter() {
+        if value.is_null() {
+            continue;
+        }
+        let schema = &table.filter["properties"][name.as_str()];
+        let bson = if is_object_id(schema) {
+            Bson::ObjectId(parse_id(value.string()?)?)
+        } else {
+            Bson::try_from(value.as_value().clone().into_json()?)?
+        };
+        query.insert(name.as_str(), bson);
+    }
+    Ok(query)
+}
+
+fn build_page(table: &GraphTable) -> Object {
+    let page_field = |name: &'static str| {
+        Field::new(name, TypeRef::named_nn(TypeRef::INT), move |ctx| {
+            FieldFuture::new(async move {
+                let page = ctx
+                    .parent_value
+                    .try_downcast_ref::<PaginateData<Document>>()?;
+                let value = match name {
+                    "page" => page.page,
+                    "page_size" => page.page_size,
+                    "total_pages" => page.total_pages,
+                    _ => page.total_result,
+                };
+                Ok(Some(FieldValue::value(value)))
+            })
+        })
+    };
+
+    Object::new(format!("{}Page", table.type_name))
+        .field(page_field("page"))
+        .field(page_field("page_size"))
+        .field(page_field("total_pages"))
+        .field(page_field("total_result"))
+        .field(Field::new(
+            "data",
+            TypeRef::named_nn_list_nn(table.type_name),
+            |ctx| {
+                FieldFuture::new(async move {
+                    let page = ctx
+                        .parent_value
+                        .try_downcast_ref::<PaginateData<Document>>()?;
+                    Ok(Some(FieldValue::list(
+                        page.data.iter().map(|d| FieldValue::borrowed_any(d)),
+                    )))
+                })
+            },
+        ))
+}
+
+fn query_fields(query: Object, table: &GraphTable) -> Object {
+    let name = table.table;
+    let by_id = Field::new(name, TypeRef::named(table.type_name), move |ctx| {
+        FieldFuture::new(async move {
+            let client = ctx.data::<Client>()?;
+            let id = parse_id(ctx.args.try_get("id")?.string()?)?;
+            Ok(collection(client, name)
+                .find_one(doc! {"_id": id}, None)
+                .await?
+                .map(FieldValue::owned_any))
+        })
+    })
+    .argument(InputValue::new("id", TypeRef::named_nn(TypeRef::ID)));
+
+    let graph_table = table.clone();
+    let list = Field::new(
+        plural(name),
+        TypeRef::named_nn(format!("{}Page", table.type_name)),
+        move |ctx| {
+            let table = graph_table.clone();
+            FieldFuture::new(async move {
+                let client = ctx.data::<Client>()?;
+                let filter = read_filter(&ctx, &table)?;
+                let sort = match ctx.args.get("sort") {
+                    Some(v) if !v.is_null() => v.string()?,
+                    _ => "",
+                };
+                let sort = parse_sort(sort, &table.field_names())?;
+                let (page, page_size) = read_page(&ctx)?;
+
+                let total_result = collection(client, table.table)
+                    .count_documents(filter.clone(), None)
+                    .await?;
+                let data = find_page(client, table.table, filter, sort, page, page_size).await?;
+                Ok(Some(FieldValue::owned_any(PaginateData {
+                    page,
+                    page_size: page_size as u64,
+                    total_pages: (total_result as f64 / page_size as f64).ceil() as u64,
+                    total_result,
+                    next: None,
+                    data,
+                })))
+            })
+        },
+    )
+    .argument(InputValue::new(
+        "filter",
+        TypeRef::named(format!("{}Filter", table.type_name)),
+    ))
+    .argument(InputValue::new("sort", TypeRef::named(TypeRef::STRING)));
+
+    query.field(by_id).field(page_args(list))
+}
+
+pub fn build_schema() -> Schema {
+    let all = tables();
+    let mut query = Object::new("Query");
+    let mut builder = Schema::build("Query", None, None)
+        .register(Scalar::new(JSON_SCALAR))
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY);
+    for table in &all {
+        query = query_fields(query, table);
+        builder = builder
+            .register(build_object(table, &all))
+            .register(build_filter(table))
+            .register(build_page(table));
+    }
+    builder.register(query).finish().unwrap()
+}
+
+#[post("/graphql", format = "json", data = "<request>")]
+pub async fn graphql(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    schema: &State<Schema>,
+    request: Result<Json<async_graphql::Request>, JsonError<'_>>,
+) -> Custom<RawJson<String>> {
+    let request = match request {
+        Ok(r) => r.into_inner(),
+        Err(err) => return Custom(Status::BadRequest, RawJson(render_err(err))),
+    };
+    let client: Client = (*connect).clone();
+    let response = schema
+        .execute(request.data(client).data(Loader::default()))
+        .await;
+    Custom(
+        Status::Ok,
+        RawJson(serde_json::to_string(&response).unwrap()),
+    )
+}
+
+#[get("/graphql/schema")]
+pub fn graphql_schema(_user: User, schema: &State<Schema>) -> String {
+    schema.sdl()
+}
@@ -0,0 +1,145 @@
+// iCalendar feeds and imports, see cosi_db::ical.
+// Calendar apps subscribe to feeds with the calendar token of a user, e.g. /api/v1/ical?token=...
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::{ContentType, Status};
+use rocket::response::content::RawJson;
+use rocket::response::status::Custom;
+use rocket_db_pools::Connection;
+
+use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::Client;
+use uuid::Uuid;
+
+use crate::cosi_db::connection::COSIMongo;
+use crate::cosi_db::controller::api::{parse_oid, ApiResult};
+use crate::cosi_db::controller::common::{attachment, filename, render_err, Download};
+use crate::cosi_db::controller::import::MAX_IMPORT_MIB;
+use crate::cosi_db::ical::{find_events, group_events, import, render};
+use crate::cosi_db::import::decode;
+use crate::cosi_db::model::auth::{CalendarUser, User};
+use crate::cosi_db::model::common::COSICollection;
+use crate::cosi_db::model::event::Event;
+use crate::cosi_db::model::group::Group;
+
+fn server_err<E: std::fmt::Display>(err: E) -> Custom<RawJson<String>> {
+    Custom(Status::InternalServerError, RawJson(render_err(err)))
+}
+
+fn not_found(table: &str) -> Custom<RawJson<String>> {
+    Custom(
+        Status::NotFound,
+        RawJson(render_err(format!("No {} found.", table))),
+    )
+}
+
+fn download(name: &str, events: &[(ObjectId, Event)]) -> Download {
+    Download::File(
+        render(name, events).into_bytes(),
+        ContentType::new("text", "calendar").with_params(("charset", "utf-8")),
+        attachment(&filename(name, "events", "ics")),
+    )
+}
+
+// Every event of the church.
+#[get("/api/v1/ical")]
+pub async fn ical(_user: CalendarUser, connect: Connection<COSIMongo>) -> ApiResult<Download> {
+    let client: &Client = &connect;
+    let events = find_events(client, None).await.map_err(server_err)?;
+    Ok(download("Events", &events))
+}
+
+#[get("/api/v1/event/<oid>/ical")]
+pub async fn event_ical(
+    _user: CalendarUser,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+) -> ApiResult<Download> {
+    let client: &Client = &connect;
+    let id = parse_oid(oid)?;
+    let events = find_events(client, Some(doc! {"_id": id}))
+        .await
+        .map_err(server_err)?;
+    let name = match events.first() {
+        Some((_, event)) => event.name.clone(),
+        None => return Err(not_found("event")),
+    };
+    Ok(download(&name, &events))
+}
+
+// Events the group is registered to.
+#[get("/api/v1/group/<oid>/ical")]
+pub async fn group_ical(
+    _user: CalendarUser,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+) -> ApiResult<Download> {
+    let client: &Client = &connect;
+    let id = parse_oid(oid)?;
+    let group = Group::find_raw_document(client, Some(doc! {"_id": id}), None)
+        .await
+        .map_err(server_err)?
+        .pop()
+        .ok_or_else(|| not_found("group"))?;
+    let events = group_events(client, id).await.map_err(server_err)?;
+    Ok(download(group.get_str("group_name").unwrap_or(""), &events))
+}
+
+// Makes a new calendar token for the feed links of the user. Links with the previous token stop working.
+#[post("/api/v1/ical/token")]
+pub async fn calendar_token(
+    user: User,
+    connect: Connection<COSIMongo>,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    let token = Uuid::new_v4().simple().to_string();
+    User::update_datum(
+        client,
+        &doc! {"username": &user.username, "token": &user.token},
+        &doc! {"$set": {"calendar_token": &token}},
+        None,
+    )
+    .await
+    .map_err(server_err)?;
+    Ok(RawJson(serde_json::json!({ "token": token }).to_string()))
+}
+
+// The body is an .ics file with one or more events.
+#[post("/api/v1/import/ical?<dry_run>", data = "<data>")]
+pub async fn import_ical(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    dry_run: Option<bool>,
+    data: Data<'_>,
+) -> ApiResult<Custom<RawJson<String>>> {
+    let client: &Client = &connect;
+    let bytes = data
+        .open(MAX_IMPORT_MIB.mebibytes())
+        .into_bytes()
+        .await
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+    if !bytes.is_complete() {
+        let err = format!("Imports are limited to {} MiB.", MAX_IMPORT_MIB);
+        return Err(Custom(Status::PayloadTooLarge, RawJson(render_err(err))));
+    }
+    let text = decode(bytes.into_inner());
+
+    let report = import(client, &text, dry_run.unwrap_or(false))
+        .await
+        .map_err(server_err)?;
+    if report.total == 0 {
+        return Err(Custom(
+            Status::BadRequest,
+            RawJson(render_err("File holds no events.")),
+        ));
+    }
+    // Nothing is written unless every event is valid.
+    let status = if report.invalid > 0 {
+        Status::UnprocessableEntity
+    } else {
+        Status::Ok
+    };
+    Ok(Custom(
+        status,
+        RawJson(serde_json::to_string(&report).unwrap()),
+    ))
+}
@@ -0,0 +1,126 @@
+// CSV upload, see cosi_db::import.
+use std::collections::HashMap;
+
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::Status;
+use rocket::response::content::RawJson;
+use rocket::response::status::Custom;
+use rocket_db_pools::Connection;
+
+use mongodb::Client;
+
+use crate::cosi_db::connection::COSIMongo;
+use crate::cosi_db::controller::api::ApiResult;
+use crate::cosi_db::controller::common::render_err;
+use crate::cosi_db::errors::Refused;
+use crate::cosi_db::import::{commit, decode, prepare};
+use crate::cosi_db::model::address::{Address, AddressImpl, AddressOptional};
+use crate::cosi_db::model::auth::User;
+use crate::cosi_db::model::common::{COSICollection, COSIData, COSIForm, COSISchema};
+use crate::cosi_db::model::group::{GroupRelation, GroupRelationImpl, GroupRelationOptional};
+use crate::cosi_db::model::household::{Household, HouseholdImpl, HouseholdOptional};
+use crate::cosi_db::model::person::{Person, PersonImpl, PersonOptional};
+
+pub const MAX_IMPORT_MIB: usize = 16;
+
+async fn import_table<T, I, F>(
+    client: &Client,
+    text: &str,
+    mapping: &HashMap<String, String>,
+    dry_run: bool,
+    atomic: bool,
+) -> ApiResult<Custom<RawJson<String>>>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm + COSISchema,
+    F: COSIData + Default + COSIForm,
+{
+    let (mut report, rows) = prepare::<T, I, F>(client, text, mapping)
+        .await
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+    report.dry_run = dry_run;
+
+    // Nothing is written unless every row is valid.
+    let status = if !report.errors.is_empty() && !dry_run {
+        Status::UnprocessableEntity
+    } else {
+        Status::Ok
+    };
+    if status == Status::Ok && !dry_run {
+        let (inserted, updated) = commit::<T, I, F>(client, rows, atomic)
+            .await
+            .map_err(|err| {
+                let status = if err.is::<Refused>() {
+                    Status::Conflict
+                } else {
+                    Status::InternalServerError
+                };
+                Custom(status, RawJson(render_err(err)))
+            })?;
+        report.inserted = inserted;
+        report.updated = updated;
+    }
+    Ok(Custom(
+        status,
+        RawJson(serde_json::to_string(&report).unwrap()),
+    ))
+}
+
+// The body is the CSV file. Columns are mapped with mapping[<column>]=<field>.
+// atomic=false writes the rows on databases without transactions.
+#[post("/api/v1/import/<table>?<dry_run>&<atomic>&<mapping>", data = "<data>")]
+pub async fn import_csv(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    table: &str,
+    dry_run: Option<bool>,
+    atomic: Option<bool>,
+    mapping: Option<HashMap<String, String>>,
+    data: Data<'_>,
+) -> ApiResult<Custom<RawJson<String>>> {
+    let client: &Client = &connect;
+    let bytes = data
+        .open(MAX_IMPORT_MIB.mebibytes())
+        .into_bytes()
+        .await
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+    if !bytes.is_complete() {
+        let err = format!("Imports are limited to {} MiB.", MAX_IMPORT_MIB);
+        return Err(Custom(Status::PayloadTooLarge, RawJson(render_err(err))));
+    }
+    let text = decode(bytes.into_inner());
+    let mapping = mapping.unwrap_or_default();
+    let dry_run = dry_run.unwrap_or(false);
+    let atomic = atomic.unwrap_or(true);
+
+    match table {
+        "person" => {
+            import_table::<Person, PersonImpl, PersonOptional>(
+                client, &text, &mapping, dry_run, atomic,
+            )
+            .await
+        }
+        "address" => {
+            import_table::<Address, AddressImpl, AddressOptional>(
+                client, &text, &mapping, dry_run, atomic,
+            )
+            .await
+        }
+        "household" => {
+            import_table::<Household, HouseholdImpl, HouseholdOptional>(
+                client, &text, &mapping, dry_run, atomic,
+            )
+            .await
+        }
+        "grouprelation" => {
+            import_table::<GroupRelation, GroupRelationImpl, GroupRelationOptional>(
+                client, &text, &mapping, dry_run, atomic,
+            )
+            .await
+        }
+        _ => Err(Custom(
+            Status::NotFound,
+            RawJson(render_err(format!("Table {} cannot be imported.", table))),
+        )),
+    }
+}
@@ -0,0 +1,102 @@
+// Child check-in kiosk, see cosi_db::checkin.
+// Kiosks are devices logged in as a user, showing /kiosk.
+use rocket::http::Status;
+use rocket::response::content::RawJson;
+use rocket::response::status::Custom;
+use rocket::serde::json::{Error as JsonError, Json};
+use rocket_db_pools::Connection;
+
+use mongodb::Client;
+
+use crate::cosi_db::attendance::check_occurrence;
+use crate::cosi_db::checkin::{
+    check_in, check_out, find_households, roster, CheckInRequest, CheckOutRequest,
+};
+use crate::cosi_db::connection::COSIMongo;
+use crate::cosi_db::controller::api::{parse_oid, ApiResult};
+use crate::cosi_db::controller::attendance::find_event;
+use crate::cosi_db::controller::common::render_err;
+use crate::cosi_db::model::auth::User;
+
+fn server_err<E: std::fmt::Display>(err: E) -> Custom<RawJson<String>> {
+    Custom(Status::InternalServerError, RawJson(render_err(err)))
+}
+
+fn bad_request<E: std::fmt::Display>(err: E) -> Custom<RawJson<String>> {
+    Custom(Status::BadRequest, RawJson(render_err(err)))
+}
+
+// Looks up households by phone or name. With an occurrence, children already checked in are marked.
+#[get("/api/v1/kiosk/households?<query>&<event>&<occurrence>")]
+pub async fn kiosk_households(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    query: &str,
+    event: Option<&str>,
+    occurrence: Option<&str>,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    let occurrence = match (event, occurrence) {
+        (Some(e), Some(o)) => Some((parse_oid(e)?, o)),
+        _ => None,
+    };
+    let data = find_households(client, query, occurrence)
+        .await
+        .map_err(bad_request)?;
+    Ok(RawJson(serde_json::to_string(&data).unwrap()))
+}
+
+// Checks children of a household into an occurrence and returns their labels.
+#[post("/api/v1/kiosk/checkin", format = "json", data = "<request>")]
+pub async fn kiosk_check_in(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    request: Result<Json<CheckInRequest>, JsonError<'_>>,
+) -> ApiResult<Custom<RawJson<String>>> {
+    let client: &Client = &connect;
+    let request = request.map_err(bad_request)?.into_inner();
+    let event = find_event(client, parse_oid(&request.event)?).await?;
+    check_occurrence(&event, &request.occurrence).map_err(bad_request)?;
+
+    let labels = check_in(client, &request).await.map_err(bad_request)?;
+    Ok(Custom(
+        Status::Created,
+        RawJson(serde_json::to_string(&labels).unwrap()),
+    ))
+}
+
+// Children are only checked out with their pickup code.
+#[post("/api/v1/kiosk/checkout", format = "json", data = "<request>")]
+pub async fn kiosk_check_out(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    request: Result<Json<CheckOutRequest>, JsonError<'_>>,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    let request = request.map_err(bad_request)?.into_inner();
+    match check_out(client, &request).await.map_err(bad_request)? {
+        Some(persons) => Ok(RawJson(
+            serde_json::json!({ "checked_out": persons }).to_string(),
+        )),
+        None => Err(Custom(
+            Status::Forbidden,
+            RawJson(render_err("Wrong pickup code.")),
+        )),
+    }
+}
+
+// Children checked into an occurrence, for the staff of the room.
+#[get("/api/v1/kiosk/checkins?<event>&<occurrence>")]
+pub async fn kiosk_checkins(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    event: &str,
+    occurrence: &str,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    let event = parse_oid(event)?;
+    let data = roster(client, event, occurrence)
+        .await
+        .map_err(server_err)?;
+    Ok(RawJson(serde_json::to_string(&data).unwrap()))
+}
@@ -0,0 +1,40 @@
+// Printable label sheets, see cosi_db::labels.
+use rocket::http::{ContentType, Status};
+use rocket::response::content::RawJson;
+use rocket::response::status::Custom;
+use rocket::serde::json::{Error as JsonError, Json};
+use rocket_db_pools::Connection;
+
+use mongodb::Client;
+
+use crate::cosi_db::connection::COSIMongo;
+use crate::cosi_db::controller::api::ApiResult;
+use crate::cosi_db::controller::common::{attachment, render_err, Download};
+use crate::cosi_db::labels::{presets, render, LabelRequest};
+use crate::cosi_db::model::auth::User;
+
+fn bad_request<E: std::fmt::Display>(err: E) -> Custom<RawJson<String>> {
+    Custom(Status::BadRequest, RawJson(render_err(err)))
+}
+
+// Preset layouts, in points.
+#[get("/api/v1/labels/layouts")]
+pub fn label_layouts(_user: User) -> RawJson<String> {
+    RawJson(serde_json::to_string(&presets()).unwrap())
+}
+
+#[post("/api/v1/labels", format = "json", data = "<request>")]
+pub async fn labels(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    request: Result<Json<LabelRequest>, JsonError<'_>>,
+) -> ApiResult<Download> {
+    let client: &Client = &connect;
+    let request = request.map_err(bad_request)?.into_inner();
+    let pdf = render(client, &request).await.map_err(bad_request)?;
+    Ok(Download::File(
+        pdf,
+        ContentType::PDF,
+        attachment("labels.pdf"),
+    ))
+}
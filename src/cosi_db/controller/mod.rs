@@ -1,4 +1,19 @@
+pub mod admin;
 pub mod api;
+pub mod attendance;
 pub mod auth;
+pub mod booking;
+pub mod changes;
 pub mod common;
 pub mod dashboard;
+pub mod gedcom;
+pub mod graphql;
+pub mod ical;
+pub mod import;
+pub mod kiosk;
+pub mod labels;
+pub mod occurrence;
+pub mod openapi;
+pub mod registration;
+pub mod vcard;
+pub mod webhook;
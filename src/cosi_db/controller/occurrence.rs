@@ -0,0 +1,135 @@
+// Occurrences of events over a range of days, see cosi_db::recurrence.
+use chrono::{Duration, Local, NaiveDate};
+use rocket::http::Status;
+use rocket::response::content::RawJson;
+use rocket::response::status::Custom;
+use rocket_db_pools::Connection;
+
+use mongodb::bson::{doc, Document};
+use mongodb::Client;
+
+use crate::cosi_db::connection::COSIMongo;
+use crate::cosi_db::controller::api::{parse_oid, ApiResult};
+use crate::cosi_db::controller::common::render_err;
+use crate::cosi_db::ical::find_events;
+use crate::cosi_db::model::auth::User;
+use crate::cosi_db::model::common::COSICollection;
+use crate::cosi_db::model::event::Event;
+use crate::cosi_db::recurrence::{occurrences, Occurrence, DATETIME_FORMAT};
+
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+const DEFAULT_DAYS: i64 = 7;
+pub const MAX_DAYS: i64 = 366;
+
+fn server_err<E: std::fmt::Display>(err: E) -> Custom<RawJson<String>> {
+    Custom(Status::InternalServerError, RawJson(render_err(err)))
+}
+
+fn bad_request(err: String) -> Custom<RawJson<String>> {
+    Custom(Status::BadRequest, RawJson(render_err(err)))
+}
+
+pub fn parse_date(value: &str) -> ApiResult<NaiveDate> {
+    NaiveDate::parse_from_str(value, DATE_FORMAT)
+        .map_err(|_| bad_request(format!("Date {} should be <year>-<month>-<day>.", value)))
+}
+
+// Days from the first to the last one, both included. The coming week by default.
+pub fn range(from: Option<&str>, to: Option<&str>) -> ApiResult<(NaiveDate, NaiveDate)> {
+    let from = match from {
+        Some(f) => parse_date(f)?,
+        None => Local::today().naive_local(),
+    };
+    let to = match to {
+        Some(t) => parse_date(t)?,
+        None => from + Duration::days(DEFAULT_DAYS - 1),
+    };
+    if to < from {
+        return Err(bad_request("to is before from.".to_string()));
+    }
+    if (to - from).num_days() >= MAX_DAYS {
+        return Err(bad_request(format!(
+            "Ranges are limited to {} days.",
+            MAX_DAYS
+        )));
+    }
+    Ok((from, to))
+}
+
+async fn list(
+    client: &Client,
+    filter: Document,
+    from: Option<&str>,
+    to: Option<&str>,
+    cancelled: Option<bool>,
+) -> ApiResult<RawJson<String>> {
+    let (first, last) = range(from, to)?;
+    let start = first.and_hms(0, 0, 0);
+    let end = (last + Duration::days(1)).and_hms(0, 0, 0);
+
+    // Events starting later cannot occur in the range.
+    let mut filter = filter;
+    filter.insert(
+        "start_datetime",
+        doc! {"$lt": end.format(DATETIME_FORMAT).to_string()},
+    );
+    let events = find_events(client, Some(filter))
+        .await
+        .map_err(server_err)?;
+    let mut data: Vec<Occurrence> = vec![];
+    for (id, event) in &events {
+        data.extend(occurrences(
+            id,
+            event,
+            start,
+            end,
+            cancelled.unwrap_or(false),
+        ));
+    }
+    data.sort_by(|a, b| a.start_datetime.cmp(&b.start_datetime));
+
+    Ok(RawJson(
+        serde_json::json!({
+            "from": first.format(DATE_FORMAT).to_string(),
+            "to": last.format(DATE_FORMAT).to_string(),
+            "data": data,
+        })
+        .to_string(),
+    ))
+}
+
+// Cancelled occurrences are left out unless cancelled=true.
+#[get("/api/v1/occurrences?<from>&<to>&<cancelled>")]
+pub async fn list_occurrences(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    from: Option<&str>,
+    to: Option<&str>,
+    cancelled: Option<bool>,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    list(client, doc! {}, from, to, cancelled).await
+}
+
+#[get("/api/v1/event/<oid>/occurrences?<from>&<to>&<cancelled>")]
+pub async fn event_occurrences(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+    cancelled: Option<bool>,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    let id = parse_oid(oid)?;
+    let found = Event::find_raw_document(client, Some(doc! {"_id": id}), None)
+        .await
+        .map_err(server_err)?;
+    if found.is_empty() {
+        return Err(Custom(
+            Status::NotFound,
+            RawJson(render_err("No event found.")),
+        ));
+    }
+    list(client, doc! {"_id": id}, from, to, cancelled).await
+}
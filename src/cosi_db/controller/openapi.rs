@@ -0,0 +1,1800 @@
+// OpenAPI document of every route, built from the model schemas.
+// Table paths mirror the handlers generated by #[derive(CosiModel)].
+
+// serde
+use serde_json::{json, Value};
+
+// rocket
+use rocket::response::content::RawJson;
+
+// cosi_db
+use crate::cosi_db::controller::common::{MAX_BULK_SIZE, MAX_PAGE_SIZE};
+use crate::cosi_db::model::address::{Address, AddressImpl, AddressOptional};
+use crate::cosi_db::model::common::{object_schema, COSIModel, COSISchema};
+use crate::cosi_db::model::event::{
+    Event, EventImpl, EventOptional, EventRegistration, EventRegistrationImpl,
+    EventRegistrationOptional,
+};
+use crate::cosi_db::model::group::{
+    Group, GroupImpl, GroupOptional, GroupRelation, GroupRelationImpl, GroupRelationOptional,
+};
+use crate::cosi_db::model::household::{Household, HouseholdImpl, HouseholdOptional};
+use crate::cosi_db::model::person::{Person, PersonImpl, PersonOptional};
+use crate::cosi_db::model::room::{Room, RoomImpl, RoomOptional};
+
+const OID_PATTERN: &str = "^[0-9a-fA-F]{24}$";
+
+#[get("/openapi.json")]
+pub fn openapi() -> RawJson<String> {
+    RawJson(build_spec().to_string())
+}
+
+pub fn build_spec() -> Value {
+    let mut spec = json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "COSI DB",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Church database API. Log in through POST /login, the session cookie \
+                            authenticates every other request. Requests without a valid session \
+                            are answered with 404. Resources live under /api/v1, the verb named \
+                            routes are kept for older clients and answer with a Deprecation header."
+        },
+        "security": [{"session": []}],
+        "paths": {},
+        "components": {
+            "securitySchemes": {
+                "session": {"type": "apiKey", "in": "cookie", "name": "user_token"}
+            },
+            "schemas": {
+                "ObjectId": {
+                    "type": "object",
+                    "properties": {"$oid": {"type": "string", "pattern": OID_PATTERN}},
+                    "required": ["$oid"]
+                },
+                "Error": {
+                    "type": "object",
+                    "properties": {"err": {"type": "string"}},
+                    "required": ["err"]
+                },
+                "UpsertResult": {
+                    "type": "object",
+                    "properties": {
+                        "_id": {"$ref": "#/components/schemas/ObjectId"},
+                        "upserted": {"type": "boolean", "description": "False when an existing datum was updated."}
+                    },
+                    "required": ["_id", "upserted"]
+                },
+                "BulkResult": {
+                    "type": "object",
+                    "properties": {
+                        "total_ok": {"type": "integer"},
+                        "total_err": {"type": "integer"},
+                        "results": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "index": {"type": "integer", "description": "Position of the item in the request."},
+                                    "ok": {"type": "boolean"},
+                                    "_id": {"$ref": "#/components/schemas/ObjectId"},
+                                    "err": {"type": "string"},
+                                    "upserted": {"type": "boolean"}
+                                },
+                                "required": ["index", "ok"]
+                            }
+                        }
+                    },
+                    "required": ["total_ok", "total_err", "results"]
+                }
+            }
+        }
+    });
+
+    add_table::<Person, PersonImpl, PersonOptional>(&mut spec);
+    add_table::<Address, AddressImpl, AddressOptional>(&mut spec);
+    add_table::<Household, HouseholdImpl, HouseholdOptional>(&mut spec);
+    add_table::<Event, EventImpl, EventOptional>(&mut spec);
+    add_table::<EventRegistration, EventRegistrationImpl, EventRegistrationOptional>(&mut spec);
+    add_table::<Group, GroupImpl, GroupOptional>(&mut spec);
+    add_table::<GroupRelation, GroupRelationImpl, GroupRelationOptional>(&mut spec);
+    add_table::<Room, RoomImpl, RoomOptional>(&mut spec);
+    add_common(&mut spec);
+    add_webhooks(&mut spec);
+    add_import(&mut spec);
+    add_vcard(&mut spec);
+    add_gedcom(&mut spec);
+    add_ical(&mut spec);
+    add_occurrences(&mut spec);
+    add_attendance(&mut spec);
+    add_registration(&mut spec);
+    add_rooms(&mut spec);
+    add_kiosk(&mut spec);
+    add_labels(&mut spec);
+    add_admin(&mut spec);
+    spec
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn json_body(schema: Value) -> Value {
+    json!({"required": true, "content": {"application/json": {"schema": schema}}})
+}
+
+fn json_response(description: &str, schema: Value) -> Value {
+    json!({"description": description, "content": {"application/json": {"schema": schema}}})
+}
+
+fn error_response(description: &str) -> Value {
+    json_response(description, schema_ref("Error"))
+}
+
+fn query_param(name: &str, description: &str, schema: Value) -> Value {
+    json!({"name": name, "in": "query", "description": description, "schema": schema})
+}
+
+fn oid_param(location: &str) -> Value {
+    json!({
+        "name": "oid",
+        "in": location,
+        "required": true,
+        "schema": {"type": "string", "pattern": OID_PATTERN}
+    })
+}
+
+fn add_operation(spec: &mut Value, path: &str, method: &str, operation: Value) {
+    let paths = spec["paths"].as_object_mut().unwrap();
+    let entry = paths.entry(path.to_string()).or_insert(json!({}));
+    entry[method] = operation;
+}
+
+// Verb named routes that predate /api/v1.
+fn add_deprecated(spec: &mut Value, path: &str, method: &str, mut operation: Value) {
+    operation["deprecated"] = json!(true);
+    add_operation(spec, path, method, operation);
+}
+
+// Query strings carry object ids as plain hex. Nested objects cannot be filtered on.
+fn query_schema(schema: &Value) -> Option<Value> {
+    let inner = match schema.get("allOf") {
+        Some(all) => &all[0],
+        None => schema,
+    };
+    let mut result = if inner["$ref"] == "#/components/schemas/ObjectId" {
+        json!({"type": "string", "pattern": OID_PATTERN})
+    } else if inner["type"] == "object" {
+        return None;
+    } else if inner["type"] == "array" {
+        json!({"type": "array", "items": query_schema(&inner["items"])?})
+    } else {
+        inner.clone()
+    };
+    result.as_object_mut().unwrap().remove("nullable");
+    if let Some(description) = schema.get("description") {
+        result["description"] = description.clone();
+    }
+    Some(result)
+}
+
+pub fn short_name<T>() -> &'static str {
+    std::any::type_name::<T>().rsplit("::").next().unwrap()
+}
+
+fn add_table<T, I, F>(spec: &mut Value)
+where
+    T: COSIModel,
+    I: COSISchema,
+    F: COSISchema,
+{
+    let table = T::table_name();
+    let impl_name = short_name::<I>();
+    let stored_name = short_name::<T>();
+    let page_name = format!("{}Page", stored_name);
+    let tags = json!([table]);
+
+    // Components.
+    let impl_schema = I::schema();
+    let mut patch_schema = impl_schema.clone();
+    patch_schema.as_object_mut().unwrap().remove("required");
+    let mut stored_schema = impl_schema.clone();
+    stored_schema["properties"]["_id"] = schema_ref("ObjectId");
+    {
+        let schemas = spec["components"]["schemas"].as_object_mut().unwrap();
+        schemas.insert(impl_name.to_string(), impl_schema.clone());
+        schemas.insert(stored_name.to_string(), stored_schema);
+        schemas.insert(
+            page_name.clone(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "page": {"type": "integer"},
+                    "page_size": {"type": "integer"},
+                    "total_pages": {"type": "integer"},
+                    "total_result": {"type": "integer"},
+                    "next": {"type": "string", "nullable": true, "description": "Cursor of the next page. Null on the last page."},
+                    "data": {"type": "array", "items": schema_ref(stored_name)}
+                },
+                "required": ["page", "page_size", "total_pages", "total_result", "data"]
+            }),
+        );
+    }
+
+    // Filters come from the query struct.
+    let sort_param = query_param(
+        "sort",
+        "Comma separated fields, prefixed with `-` for descending order.",
+        json!({"type": "string"}),
+    );
+    let mut get_params = vec![
+        query_param(
+            "page",
+            "Page to fetch, starting at 0.",
+            json!({"type": "integer", "minimum": 0}),
+        ),
+        query_param(
+            "page_size",
+            &format!("Data per page, at most {}.", MAX_PAGE_SIZE),
+            json!({"type": "integer", "minimum": 1}),
+        ),
+        query_param(
+            "cursor",
+            "Value of `next` from the previous page. Replaces `page`.",
+            json!({"type": "string"}),
+        ),
+        sort_param.clone(),
+        query_param(
+            "fields",
+            "Comma separated fields to return.",
+            json!({"type": "string"}),
+        ),
+        query_param(
+            "expand",
+            "Replace references with the referenced data. Defaults to true.",
+            json!({"type": "boolean"}),
+        ),
+    ];
+    let mut export_params = vec![
+        query_param(
+            "format",
+            "Defaults to csv.",
+            json!({"type": "string", "enum": ["csv", "xlsx"]}),
+        ),
+        sort_param,
+    ];
+    let filters = F::schema();
+    for (name, schema) in filters["properties"].as_object().unwrap() {
+        if let Some(schema) = query_schema(schema) {
+            get_params.push(query_param(name, "Filter on equality.", schema.clone()));
+            export_params.push(query_param(name, "Filter on equality.", schema));
+        }
+    }
+    let natural_key = match T::natural_key() {
+        Some(keys) => format!("Matches on the natural key: {}.", keys.join(", ")),
+        None => "The table has no natural key, every request fails.".to_string(),
+    };
+    let bulk_description = format!(
+        "At most {} items. Items succeed or fail on their own.",
+        MAX_BULK_SIZE
+    );
+    let bulk_update_schema = json!({
+        "type": "array",
+        "items": {
+            "type": "object",
+            "properties": {
+                "oid": {"type": "string", "pattern": OID_PATTERN},
+                "data": schema_ref(impl_name)
+            },
+            "required": ["oid", "data"]
+        }
+    });
+    let bulk_delete_schema =
+        json!({"type": "array", "items": {"type": "string", "pattern": OID_PATTERN}});
+    let bulk_responses = json!({
+        "200": json_response("Result of every item.", schema_ref("BulkResult")),
+        "400": error_response("Malformed body."),
+        "413": error_response("Too many items.")
+    });
+
+    let v1_path = format!("/api/v1/{}", table);
+    let v1_item_path = format!("/api/v1/{}/{{oid}}", table);
+    let v1_bulk_path = format!("/api/v1/{}/bulk", table);
+    let location = json!({
+        "Location": {"description": "Path of the datum.", "schema": {"type": "string"}}
+    });
+    add_operation(
+        spec,
+        &v1_path,
+        "get",
+        json!({
+            "tags": tags,
+            "summary": format!("List {}.", table),
+            "parameters": get_params,
+            "responses": {
+                "200": json_response("Page of data.", schema_ref(&page_name)),
+                "400": error_response("Invalid sort, fields, page size or cursor.")
+            }
+        }),
+    );
+    add_operation(
+        spec,
+        &format!("/api/v1/{}/export", table),
+        "get",
+        json!({
+            "tags": tags,
+            "summary": format!("Export every matching {} as a spreadsheet.", table),
+            "description": "References are flattened into a column per referenced field, such as address.city. Lists are joined with \"; \".",
+            "parameters": export_params,
+            "responses": {
+                "200": {
+                    "description": "File download.",
+                    "content": {
+                        "text/csv": {},
+                        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet": {}
+                    }
+                },
+                "400": error_response("Invalid format or sort.")
+            }
+        }),
+    );
+    add_operation(
+        spec,
+        &v1_path,
+        "post",
+        json!({
+            "tags": tags,
+            "summary": format!("Create a {}.", table),
+            "requestBody": json_body(schema_ref(impl_name)),
+            "responses": {
+                "201": {
+                    "description": "The new datum.",
+                    "headers": location,
+                    "content": {"application/json": {"schema": schema_ref(stored_name)}}
+                },
+                "400": error_response("Invalid datum.")
+            }
+        }),
+    );
+    add_operation(
+        spec,
+        &v1_path,
+        "put",
+        json!({
+            "tags": tags,
+            "summary": format!("Create or replace a {}.", table),
+            "description": natural_key,
+            "requestBody": json_body(schema_ref(impl_name)),
+            "responses": {
+                "200": {
+                    "description": "The replaced datum.",
+                    "headers": location,
+                    "content": {"application/json": {"schema": schema_ref(stored_name)}}
+                },
+                "201": {
+                    "description": "The new datum.",
+                    "headers": location,
+                    "content": {"application/json": {"schema": schema_ref(stored_name)}}
+                },
+                "400": error_response("Invalid datum or missing natural key.")
+            }
+        }),
+    );
+    add_operation(
+        spec,
+        &v1_item_path,
+        "get",
+        json!({
+            "tags": tags,
+            "summary": format!("Get a {}.", table),
+            "parameters": [
+                oid_param("path"),
+                query_param("expand", "Replace references with the referenced data. Defaults to true.", json!({"type": "boolean"}))
+            ],
+            "responses": {
+                "200": json_response("The datum.", schema_ref(stored_name)),
+                "400": error_response("Invalid object id."),
+                "404": error_response("No data found.")
+            }
+        }),
+    );
+    add_operation(
+        spec,
+        &v1_item_path,
+        "put",
+        json!({
+            "tags": tags,
+            "summary": format!("Replace a {}.", table),
+            "parameters": [oid_param("path")],
+            "requestBody": json_body(schema_ref(impl_name)),
+            "responses": {
+                "200": json_response("The replaced datum.", schema_ref(stored_name)),
+                "400": error_response("Invalid datum or object id."),
+                "404": error_response("No data found.")
+            }
+        }),
+    );
+    add_operation(
+        spec,
+        &v1_item_path,
+        "patch",
+        json!({
+            "tags": tags,
+            "summary": format!("Update some fields of a {}.", table),
+            "description": "Omitted fields are untouched and null clears a field. \
+                            {\"$add\": {field: [...]}} and {\"$remove\": {field: [...]}} edit array fields.",
+            "parameters": [oid_param("path")],
+            "requestBody": json_body(patch_schema.clone()),
+            "responses": {
+                "200": json_response("The updated datum.", schema_ref(stored_name)),
+                "400": error_response("Invalid field or object id."),
+                "404": error_response("No data found.")
+            }
+        }),
+    );
+    add_operation(
+        spec,
+        &v1_item_path,
+        "delete",
+        json!({
+            "tags": tags,
+            "summary": format!("Delete a {}.", table),
+            "parameters": [oid_param("path")],
+            "responses": {
+                "204": {"description": "Deleted."},
+                "400": error_response("Invalid object id."),
+                "404": error_response("No data was deleted.")
+            }
+        }),
+    );
+    let bulk_operations = vec![
+        (
+            "post",
+            "Create many",
+            json!({"type": "array", "items": schema_ref(impl_name)}),
+        ),
+        (
+            "put",
+            "Create or replace many",
+            json!({"type": "array", "items": schema_ref(impl_name)}),
+        ),
+        ("patch", "Replace many", bulk_update_schema.clone()),
+        ("delete", "Delete many", bulk_delete_schema.clone()),
+    ];
+    for (method, summary, body) in bulk_operations {
+        add_operation(
+            spec,
+            &v1_bulk_path,
+            method,
+            json!({
+                "tags": tags,
+                "summary": format!("{} {}.", summary, table),
+                "description": bulk_description,
+                "requestBody": json_body(body),
+                "responses": bulk_responses
+            }),
+        );
+    }
+
+    add_deprecated(
+        spec,
+        &format!("/get_{}", table),
+        "get",
+        json!({
+            "tags": tags,
+            "summary": format!("List {}.", table),
+            "parameters": get_params,
+            "responses": {
+                "200": json_response("Page of data.", schema_ref(&page_name)),
+                "400": error_response("Invalid sort, fields, page size or cursor.")
+            }
+        }),
+    );
+    add_deprecated(
+        spec,
+        &format!("/get_{}/{{oid}}", table),
+        "get",
+        json!({
+            "tags": tags,
+            "summary": format!("Get one {} by id.", table),
+            "parameters": [
+                oid_param("path"),
+                query_param("expand", "Replace references with the referenced data. Defaults to true.", json!({"type": "boolean"}))
+            ],
+            "responses": {
+                "200": json_response("The datum.", schema_ref(stored_name)),
+                "400": error_response("Invalid object id."),
+                "404": error_response("No data found.")
+            }
+        }),
+    );
+    add_deprecated(
+        spec,
+        &format!("/insert_{}", table),
+        "post",
+        json!({
+            "tags": tags,
+            "summary": format!("Insert a {}.", table),
+            "requestBody": {
+                "required": true,
+                "content": {
+                    "application/json": {"schema": schema_ref(impl_name)},
+                    "application/x-www-form-urlencoded": {"schema": schema_ref(impl_name)}
+                }
+            },
+            "responses": {
+                "200": json_response("Id of the new datum.", schema_ref("ObjectId")),
+                "400": error_response("Invalid datum.")
+            }
+        }),
+    );
+    add_deprecated(
+        spec,
+        &format!("/update_{}", table),
+        "post",
+        json!({
+            "tags": tags,
+            "summary": format!("Replace a {}.", table),
+            "parameters": [oid_param("query")],
+            "requestBody": {
+                "required": true,
+                "content": {
+                    "application/json": {"schema": schema_ref(impl_name)},
+                    "application/x-www-form-urlencoded": {"schema": schema_ref(impl_name)}
+                }
+            },
+            "responses": {
+                "200": json_response("Number of modified data.", json!({"type": "integer"})),
+                "400": error_response("Invalid datum or object id."),
+                "404": error_response("No data was updated.")
+            }
+        }),
+    );
+    add_deprecated(
+        spec,
+        &format!("/update_{}", table),
+        "patch",
+        json!({
+            "tags": tags,
+            "summary": format!("Update some fields of a {}.", table),
+            "description": "Omitted fields are untouched and null clears a field. \
+                            {\"$add\": {field: [...]}} and {\"$remove\": {field: [...]}} edit array fields.",
+            "parameters": [oid_param("query")],
+            "requestBody": json_body(patch_schema),
+            "responses": {
+                "200": json_response("Number of modified data.", json!({"type": "integer"})),
+                "400": error_response("Invalid field or object id."),
+                "404": error_response("No data found.")
+            }
+        }),
+    );
+    add_deprecated(
+        spec,
+        &format!("/upsert_{}", table),
+        "post",
+        json!({
+            "tags": tags,
+            "summary": format!("Insert or update a {}.", table),
+            "description": natural_key,
+            "requestBody": {
+                "required": true,
+                "content": {
+                    "application/json": {"schema": schema_ref(impl_name)},
+                    "application/x-www-form-urlencoded": {"schema": schema_ref(impl_name)}
+                }
+            },
+            "responses": {
+                "200": json_response("Id of the datum.", schema_ref("UpsertResult")),
+                "400": error_response("Invalid datum or missing natural key.")
+            }
+        }),
+    );
+    add_deprecated(
+        spec,
+        &format!("/bulk_insert_{}", table),
+        "post",
+        json!({
+            "tags": tags,
+            "summary": format!("Insert many {}.", table),
+            "description": bulk_description,
+            "requestBody": json_body(json!({"type": "array", "items": schema_ref(impl_name)})),
+            "responses": bulk_responses
+        }),
+    );
+    add_deprecated(
+        spec,
+        &format!("/bulk_upsert_{}", table),
+        "post",
+        json!({
+            "tags": tags,
+            "summary": format!("Insert or update many {}.", table),
+            "description": format!("{} {}", natural_key, bulk_description),
+            "requestBody": json_body(json!({"type": "array", "items": schema_ref(impl_name)})),
+            "responses": bulk_responses
+        }),
+    );
+    add_deprecated(
+        spec,
+        &format!("/bulk_update_{}", table),
+        "post",
+        json!({
+            "tags": tags,
+            "summary": format!("Replace many {}.", table),
+            "description": bulk_description,
+            "requestBody": json_body(bulk_update_schema),
+            "responses": bulk_responses
+        }),
+    );
+    add_deprecated(
+        spec,
+        &format!("/bulk_delete_{}", table),
+        "post",
+        json!({
+            "tags": tags,
+            "summary": format!("Delete many {}.", table),
+            "description": bulk_description,
+            "requestBody": json_body(bulk_delete_schema),
+            "responses": bulk_responses
+        }),
+    );
+    add_operation(
+        spec,
+        &format!("/gen_{}/{{total}}", table),
+        "get",
+        json!({
+            "tags": tags,
+            "summary": format!("Replace {} with generated data.", table),
+            "description": "Debug builds only.",
+            "parameters": [{"name": "total", "in": "path", "required": true, "schema": u8::schema()}],
+            "responses": {"200": json_response("Total data.", json!({"type": "object", "properties": {"total": {"type": "integer"}}}))}
+        }),
+    );
+    add_deprecated(
+        spec,
+        &format!("/drop_{}", table),
+        "get",
+        json!({
+            "tags": tags,
+            "summary": format!("Remove every {}.", table),
+            "description": "Debug builds only.",
+            "responses": {"200": json_response("Table dropped.", json!({"type": "object", "properties": {"dropped": {"type": "boolean"}}}))}
+        }),
+    );
+}
+
+fn add_common(spec: &mut Value) {
+    let html =
+        |description: &str| json!({"description": description, "content": {"text/html": {}}});
+    let redirect = json!({"description": "Redirect."});
+
+    add_operation(
+        spec,
+        "/",
+        "get",
+        json!({
+            "tags": ["dashboard"],
+            "summary": "Dashboard page.",
+            "responses": {"200": html("Dashboard."), "303": redirect}
+        }),
+    );
+    add_operation(
+        spec,
+        "/person",
+        "get",
+        json!({
+            "tags": ["dashboard"],
+            "summary": "Person page.",
+            "responses": {"200": html("Person page."), "303": redirect}
+        }),
+    );
+    add_operation(
+        spec,
+        "/search",
+        "get",
+        json!({
+            "tags": ["dashboard"],
+            "summary": "Search addresses and households.",
+            "parameters": [{"name": "query", "in": "query", "required": true, "schema": {"type": "string"}}],
+            "responses": {"200": json_response("Matching data and the field that matched.", json!({"type": "object"}))}
+        }),
+    );
+    add_operation(
+        spec,
+        "/login",
+        "get",
+        json!({
+            "tags": ["auth"],
+            "summary": "Login page.",
+            "security": [],
+            "responses": {"200": html("Login page."), "303": redirect}
+        }),
+    );
+    add_operation(
+        spec,
+        "/login",
+        "post",
+        json!({
+            "tags": ["auth"],
+            "summary": "Start a session.",
+            "security": [],
+            "requestBody": {
+                "required": true,
+                "content": {"application/x-www-form-urlencoded": {"schema": {
+                    "type": "object",
+                    "properties": {
+                        "username": {"type": "string"},
+                        "email": {"type": "string"},
+                        "token": {"type": "string", "description": "Password."}
+                    },
+                    "required": ["token"]
+                }}}
+            },
+            "responses": {"200": json_response(
+                "Either {\"success\": ...} with the session cookies set, or {\"err\": ...}.",
+                json!({"type": "object", "properties": {"success": {"type": "string"}, "err": {"type": "string"}}})
+            )}
+        }),
+    );
+    add_operation(
+        spec,
+        "/logout",
+        "get",
+        json!({
+            "tags": ["auth"],
+            "summary": "End the session.",
+            "responses": {"303": redirect}
+        }),
+    );
+    add_operation(
+        spec,
+        "/gen_login/{points}",
+        "get",
+        json!({
+            "tags": ["auth"],
+            "summary": "Create the default user.",
+            "description": "Debug builds only.",
+            "security": [],
+            "parameters": [{"name": "points", "in": "path", "required": true, "schema": u32::schema()}],
+            "responses": {"200": json_response("Created user.", json!({"type": "object"}))}
+        }),
+    );
+    add_operation(
+        spec,
+        "/openapi.json",
+        "get",
+        json!({
+            "tags": ["docs"],
+            "summary": "This document.",
+            "security": [],
+            "responses": {"200": json_response("OpenAPI document.", json!({"type": "object"}))}
+        }),
+    );
+    add_operation(
+        spec,
+        "/graphql",
+        "post",
+        json!({
+            "tags": ["graphql"],
+            "summary": "Run a GraphQL query.",
+            "description": "The schema is listed by GET /graphql/schema.",
+            "requestBody": json_body(json!({
+                "type": "object",
+                "properties": {
+                    "query": {"type": "string"},
+                    "operationName": {"type": "string"},
+                    "variables": {"type": "object"}
+                },
+                "required": ["query"]
+            })),
+            "responses": {
+                "200": json_response("GraphQL response.", json!({
+                    "type": "object",
+                    "properties": {"data": {"type": "object"}, "errors": {"type": "array", "items": {"type": "object"}}}
+                })),
+                "400": error_response("Malformed body.")
+            }
+        }),
+    );
+    add_operation(
+        spec,
+        "/graphql/schema",
+        "get",
+        json!({
+            "tags": ["graphql"],
+            "summary": "GraphQL schema.",
+            "responses": {"200": {"description": "Schema definition language.", "content": {"text/plain": {}}}}
+        }),
+    );
+    add_operation(
+        spec,
+        "/api/v1/changes",
+        "get",
+        json!({
+            "tags": ["changes"],
+            "summary": "Server-sent events for table changes.",
+            "description": "Events are named insert, update, delete or drop, with data such as {\"table\": \"person\", \"op\": \"insert\", \"_id\": \"...\"}. A lagged event means some changes were missed.",
+            "parameters": [{
+                "name": "tables",
+                "in": "query",
+                "description": "Comma separated tables to follow, all readable tables by default.",
+                "schema": {"type": "string"}
+            }],
+            "responses": {"200": {"description": "Event stream.", "content": {"text/event-stream": {}}}}
+        }),
+    );
+}
+
+fn add_webhooks(spec: &mut Value) {
+    let schemas = spec["components"]["schemas"].as_object_mut().unwrap();
+    schemas.insert(
+        "Webhook".to_string(),
+        json!({
+            "type": "object",
+            "properties": {
+                "url": {"type": "string"},
+                "events": {
+                    "type": "array",
+                    "items": {"type": "string"},
+                    "description": "Changes to deliver, such as person.insert, event.* or *. Only the model tables can be subscribed to."
+                },
+                "secret": {"type": "string", "writeOnly": true, "description": "Key of the X-COSI-Signature HMAC-SHA256."},
+                "owner": {"type": "string", "readOnly": true, "description": "Administrator who created the subscription."}
+            },
+            "required": ["url", "events", "secret"]
+        }),
+    );
+    schemas.insert(
+        "WebhookDelivery".to_string(),
+        json!({
+            "type": "object",
+            "properties": {
+                "_id": schema_ref("ObjectId"),
+                "webhook": schema_ref("ObjectId"),
+                "delivery": {"type": "string", "description": "Shared by the retries of one delivery."},
+                "event": {"type": "string"},
+                "attempt": {"type": "integer"},
+                "status": {"type": "integer", "nullable": true},
+                "error": {"type": "string", "nullable": true},
+                "delivered": {"type": "boolean"},
+                "time": {"type": "string", "format": "date-time"}
+            }
+        }),
+    );
+
+    add_operation(
+        spec,
+        "/api/v1/webhooks",
+        "get",
+        json!({
+            "tags": ["webhooks"],
+            "summary": "List webhook subscriptions.",
+            "responses": {"200": json_response("Subscriptions without their secret.", json!({"type": "array", "items": schema_ref("Webhook")}))}
+        }),
+    );
+    add_operation(
+        spec,
+        "/api/v1/webhooks",
+        "post",
+        json!({
+            "tags": ["webhooks"],
+            "summary": "Subscribe to changes.",
+            "description": "Matching changes are POSTed as {event, table, _id, data, time}. Failed deliveries are retried with exponential backoff.",
+            "requestBody": json_body(schema_ref("Webhook")),
+            "responses": {
+                "201": json_response("Created subscription.", schema_ref("Webhook")),
+                "400": error_response("Invalid subscription."),
+                "403": {"description": "Not an administrator."}
+            }
+        }),
+    );
+    add_operation(
+        spec,
+        "/api/v1/webhooks/{oid}",
+        "delete",
+        json!({
+            "tags": ["webhooks"],
+            "summary": "Remove a webhook subscription.",
+            "parameters": [oid_param("path")],
+            "responses": {
+                "204": {"description": "Removed."},
+                "403": {"description": "Not an administrator."},
+                "404": error_response("No subscription found.")
+            }
+        }),
+    );
+    add_operation(
+        spec,
+        "/api/v1/webhooks/{oid}/deliveries",
+        "get",
+        json!({
+            "tags": ["webhooks"],
+            "summary": "Delivery attempts of a webhook, newest first.",
+            "parameters": [
+                oid_param("path"),
+                query_param("page", "Page number, starting at 0.", json!({"type": "integer", "minimum": 0})),
+                query_param("page_size", "Attempts per page.", json!({"type": "integer", "minimum": 1}))
+            ],
+            "responses": {"200": json_response("Page of attempts.", json!({
+                "type": "object",
+                "properties": {"data": {"type": "array", "items": schema_ref("WebhookDelivery")}}
+            }))}
+        }),
+    );
+}
+
+fn add_import(spec: &mut Value) {
+    let report = json!({
+        "type": "object",
+        "properties": {
+            "table": {"type": "string"},
+            "dry_run": {"type": "boolean"},
+            "columns": {"type": "array", "items": {"type": "string"}},
+            "fields": {"type": "array", "items": {"type": "string"}},
+            "mapping": {"type": "object", "additionalProperties": {"type": "string"}, "description": "Column to field."},
+            "total_rows": {"type": "integer"},
+            "valid_rows": {"type": "integer"},
+            "errors": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {"row": {"type": "integer", "description": "Line in the file."}, "err": {"type": "string"}}
+                }
+            },
+            "preview": {"type": "array", "items": {"type": "object"}},
+            "inserted": {"type": "integer"},
+            "updated": {"type": "integer"}
+        }
+    });
+    add_operation(
+        spec,
+        "/api/v1/import/{table}",
+        "post",
+        json!({
+            "tags": ["import"],
+            "summary": "Import a CSV file.",
+            "description": "Rows are written only when every row is valid. Rows matching the natural key of the table update the existing datum.",
+            "parameters": [
+                {"name": "table", "in": "path", "required": true, "schema": {"type": "string", "enum": ["person", "address", "household", "grouprelation"]}},
+                query_param("dry_run", "Only validate and preview the rows.", json!({"type": "boolean"})),
+                query_param("atomic", "False writes the rows one by one on databases without transactions.", json!({"type": "boolean"})),
+                {
+                    "name": "mapping",
+                    "in": "query",
+                    "style": "deepObject",
+                    "description": "Field of each column, such as mapping[Birth Date]=dob. Columns named like a field are mapped by default, an empty field ignores the column.",
+                    "schema": {"type": "object", "additionalProperties": {"type": "string"}}
+                }
+            ],
+            "requestBody": {"required": true, "content": {"text/csv": {"schema": {"type": "string"}}}},
+            "responses": {
+                "200": json_response("Import report.", report.clone()),
+                "400": error_response("Unreadable file or invalid mapping."),
+                "404": error_response("Table cannot be imported."),
+                "409": error_response("Rows refused by the model, or no transactions for an atomic import."),
+                "413": error_response("File too large."),
+                "422": json_response("Some rows are invalid, nothing was written.", report)
+            }
+        }),
+    );
+}
+
+fn add_vcard(spec: &mut Value) {
+    for (table, summary) in [
+        ("person", "Download the vCard of a person."),
+        (
+            "household",
+            "Download the vCards of every member of a household.",
+        ),
+        ("group", "Download the vCards of every member of a group."),
+    ] {
+        add_operation(
+            spec,
+            &format!("/api/v1/{}/{{oid}}/vcard", table),
+            "get",
+            json!({
+                "tags": ["vcard"],
+                "summary": summary,
+                "description": "vCard 4.0 with the name, nicks, phones, birthday and household addresses of each person.",
+                "parameters": [oid_param("path")],
+                "responses": {
+                    "200": {"description": "vCard file.", "content": {"text/vcard": {"schema": {"type": "string"}}}},
+                    "400": error_response("Invalid object id."),
+                    "404": error_response("Datum not found.")
+                }
+            }),
+        );
+    }
+
+    let report = json!({
+        "type": "object",
+        "properties": {
+            "dry_run": {"type": "boolean"},
+            "total": {"type": "integer"},
+            "created": {"type": "integer"},
+            "duplicates": {"type": "integer"},
+            "invalid": {"type": "integer"},
+            "cards": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "card": {"type": "integer", "description": "Position in the file."},
+                        "name": {"type": "string"},
+                        "status": {"type": "string", "enum": ["created", "duplicate", "invalid"]},
+                        "_id": {"type": "string", "description": "Created person, or the existing one for duplicates."},
+                        "err": {"type": "string"}
+                    }
+                }
+            }
+        }
+    });
+    add_operation(
+        spec,
+        "/api/v1/import/vcard",
+        "post",
+        json!({
+            "tags": ["vcard", "import"],
+            "summary": "Import a vCard file.",
+            "description": "Creates a person per card and adds them to the household at each of their addresses. People that already exist, by UID or by name plus birthday or phone, are skipped.",
+            "parameters": [query_param("dry_run", "Only validate the cards.", json!({"type": "boolean"}))],
+            "requestBody": {"required": true, "content": {"text/vcard": {"schema": {"type": "string"}}}},
+            "responses": {
+                "200": json_response("Import report.", report.clone()),
+                "400": error_response("File holds no vCards."),
+                "413": error_response("File too large."),
+                "422": json_response("Some cards are invalid, nothing was written.", report)
+            }
+        }),
+    );
+}
+
+fn add_gedcom(spec: &mut Value) {
+    let gedcom = json!({"description": "GEDCOM 5.5.1 file.", "content": {"application/x-gedcom": {"schema": {"type": "string"}}}});
+    add_operation(
+        spec,
+        "/api/v1/gedcom",
+        "get",
+        json!({
+            "tags": ["gedcom"],
+            "summary": "Download every person and family as GEDCOM.",
+            "description": "Families come from household relations. Individuals carry their object id as REFN.",
+            "responses": {"200": gedcom.clone()}
+        }),
+    );
+    add_operation(
+        spec,
+        "/api/v1/household/{oid}/gedcom",
+        "get",
+        json!({
+            "tags": ["gedcom"],
+            "summary": "Download the members and families of a household as GEDCOM.",
+            "parameters": [oid_param("path")],
+            "responses": {
+                "200": gedcom,
+                "400": error_response("Invalid object id."),
+                "404": error_response("Household not found.")
+            }
+        }),
+    );
+
+    let lines = json!({
+        "type": "array",
+        "items": {
+            "type": "object",
+            "properties": {"line": {"type": "integer"}, "err": {"type": "string"}}
+        }
+    });
+    let report = json!({
+        "type": "object",
+        "properties": {
+            "dry_run": {"type": "boolean"},
+            "individuals": {"type": "integer"},
+            "families": {"type": "integer"},
+            "persons_created": {"type": "integer"},
+            "persons_existing": {"type": "integer"},
+            "households_created": {"type": "integer"},
+            "households_existing": {"type": "integer"},
+            "relations": {"type": "integer"},
+            "warnings": lines.clone(),
+            "errors": lines
+        }
+    });
+    add_operation(
+        spec,
+        "/api/v1/import/gedcom",
+        "post",
+        json!({
+            "tags": ["gedcom", "import"],
+            "summary": "Import a GEDCOM file.",
+            "description": "Creates a person per individual and a household per family, related as husband, wife and child. Individuals matching an existing person by REFN or _UID are not created again.",
+            "parameters": [query_param("dry_run", "Only validate the file.", json!({"type": "boolean"}))],
+            "requestBody": {"required": true, "content": {"application/x-gedcom": {"schema": {"type": "string"}}}},
+            "responses": {
+                "200": json_response("Import report.", report.clone()),
+                "400": error_response("File holds no individuals."),
+                "413": error_response("File too large."),
+                "422": json_response("Some lines are invalid, nothing was written.", report)
+            }
+        }),
+    );
+}
+
+fn add_ical(spec: &mut Value) {
+    spec["components"]["securitySchemes"]["calendar"] =
+        json!({"type": "apiKey", "in": "query", "name": "token"});
+    let calendar = json!({"description": "iCalendar file.", "content": {"text/calendar": {"schema": {"type": "string"}}}});
+    let feed = json!([{"session": []}, {"calendar": []}]);
+    add_operation(
+        spec,
+        "/api/v1/ical",
+        "get",
+        json!({
+            "tags": ["ical"],
+            "summary": "Subscribe to every event.",
+            "description": "Events with their RRULE. Calendar apps give the calendar token of a user as ?token=.",
+            "security": feed.clone(),
+            "responses": {"200": calendar.clone()}
+        }),
+    );
+    for (table, summary) in [
+        ("event", "Subscribe to an event."),
+        ("group", "Subscribe to the events a group is registered to."),
+    ] {
+        add_operation(
+            spec,
+            &format!("/api/v1/{}/{{oid}}/ical", table),
+            "get",
+            json!({
+                "tags": ["ical"],
+                "summary": summary,
+                "parameters": [oid_param("path")],
+                "security": feed.clone(),
+                "responses": {
+                    "200": calendar.clone(),
+                    "400": error_response("Invalid object id."),
+                    "404": error_response("Datum not found.")
+                }
+            }),
+        );
+    }
+    add_operation(
+        spec,
+        "/api/v1/ical/token",
+        "post",
+        json!({
+            "tags": ["ical"],
+            "summary": "Make a new calendar token for feed links.",
+            "description": "Feed links with the previous token of the user stop working.",
+            "responses": {
+                "200": json_response("The token.", json!({"type": "object", "properties": {"token": {"type": "string"}}}))
+            }
+        }),
+    );
+
+    let report = json!({
+        "type": "object",
+        "properties": {
+            "dry_run": {"type": "boolean"},
+            "total": {"type": "integer"},
+            "created": {"type": "integer"},
+            "duplicates": {"type": "integer"},
+            "invalid": {"type": "integer"},
+            "events": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "event": {"type": "integer", "description": "Position in the file."},
+                        "name": {"type": "string"},
+                        "status": {"type": "string", "enum": ["created", "duplicate", "invalid"]},
+                        "_id": {"type": "string", "description": "Created event, or the existing one for duplicates."},
+                        "err": {"type": "string"}
+                    }
+                }
+            }
+        }
+    });
+    add_operation(
+        spec,
+        "/api/v1/import/ical",
+        "post",
+        json!({
+            "tags": ["ical", "import"],
+            "summary": "Import an iCalendar file.",
+            "description": "Creates an event per VEVENT. Daily, weekly and monthly RRULEs are kept. Events that already exist, by UID or by name and start, are skipped.",
+            "parameters": [query_param("dry_run", "Only validate the events.", json!({"type": "boolean"}))],
+            "requestBody": {"required": true, "content": {"text/calendar": {"schema": {"type": "string"}}}},
+            "responses": {
+                "200": json_response("Import report.", report.clone()),
+                "400": error_response("File holds no events."),
+                "413": error_response("File too large."),
+                "422": json_response("Some events are invalid, nothing was written.", report)
+            }
+        }),
+    );
+}
+
+fn add_occurrences(spec: &mut Value) {
+    let list = json!({
+        "type": "object",
+        "properties": {
+            "from": {"type": "string", "format": "date"},
+            "to": {"type": "string", "format": "date"},
+            "data": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "event": {"type": "string"},
+                        "name": {"type": "string"},
+                        "occurrence": {"type": "string", "description": "Start as scheduled, used by exceptions."},
+                        "start_datetime": {"type": "string"},
+                        "end_datetime": {"type": "string"},
+                        "moved": {"type": "boolean"},
+                        "cancelled": {"type": "boolean"}
+                    }
+                }
+            }
+        }
+    });
+    let params = vec![
+        query_param(
+            "from",
+            "First day, today by default.",
+            json!({"type": "string", "format": "date"}),
+        ),
+        query_param(
+            "to",
+            "Last day, a week after from by default.",
+            json!({"type": "string", "format": "date"}),
+        ),
+        query_param(
+            "cancelled",
+            "Include cancelled occurrences.",
+            json!({"type": "boolean"}),
+        ),
+    ];
+    add_operation(
+        spec,
+        "/api/v1/occurrences",
+        "get",
+        json!({
+            "tags": ["event"],
+            "summary": "List occurrences of every event over a range of days.",
+            "description": "Repeating events are expanded, with their exceptions applied. Ranges are limited to 366 days.",
+            "parameters": params.clone(),
+            "responses": {
+                "200": json_response("Occurrences by start.", list.clone()),
+                "400": error_response("Invalid range.")
+            }
+        }),
+    );
+    let mut params = params;
+    params.insert(0, oid_param("path"));
+    add_operation(
+        spec,
+        "/api/v1/event/{oid}/occurrences",
+        "get",
+        json!({
+            "tags": ["event"],
+            "summary": "List occurrences of an event over a range of days.",
+            "parameters": params,
+            "responses": {
+                "200": json_response("Occurrences by start.", list),
+                "400": error_response("Invalid object id or range."),
+                "404": error_response("Datum not found.")
+            }
+        }),
+    );
+}
+
+fn add_attendance(spec: &mut Value) {
+    let status = json!({"type": "string", "enum": ["Present", "Absent", "Late"]});
+    let sheet = json!({
+        "type": "object",
+        "properties": {
+            "occurrence": {"type": "string", "description": "Scheduled start of the occurrence."},
+            "headcount": {"type": "integer", "minimum": 0, "description": "Attendees not recorded by name."},
+            "attendees": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "description": "Exactly one of person, group or household.",
+                    "properties": {
+                        "person": {"type": "string", "pattern": OID_PATTERN},
+                        "group": {"type": "string", "pattern": OID_PATTERN},
+                        "household": {"type": "string", "pattern": OID_PATTERN},
+                        "status": status.clone()
+                    },
+                    "required": ["status"]
+                }
+            }
+        },
+        "required": ["occurrence"]
+    });
+    let entry = json!({
+        "type": "object",
+        "properties": {
+            "event": schema_ref("ObjectId"),
+            "occurrence": {"type": "string"},
+            "person": schema_ref("ObjectId"),
+            "group": schema_ref("ObjectId"),
+            "household": schema_ref("ObjectId"),
+            "status": status,
+            "timestamp": {"type": "string"}
+        }
+    });
+    let summary = vec![
+        ("present", json!({"type": "integer"})),
+        ("absent", json!({"type": "integer"})),
+        ("late", json!({"type": "integer"})),
+    ];
+    let mut occurrence = summary.clone();
+    occurrence.extend([
+        ("occurrence", json!({"type": "string"})),
+        ("headcount", json!({"type": "integer"})),
+        ("attendance", json!({"type": "integer", "description": "Present and late attendees plus the headcount."})),
+        ("attendees", json!({"type": "array", "items": entry.clone()})),
+    ]);
+    let mut history = summary;
+    let mut named = entry;
+    named["properties"]["name"] = json!({"type": "string", "description": "Name of the event."});
+    history.push(("data", json!({"type": "array", "items": named})));
+    let params = vec![
+        oid_param("path"),
+        query_param(
+            "from",
+            "First day, every one by default.",
+            json!({"type": "string", "format": "date"}),
+        ),
+        query_param(
+            "to",
+            "Last day, every one by default.",
+            json!({"type": "string", "format": "date"}),
+        ),
+    ];
+
+    add_operation(
+        spec,
+        "/api/v1/event/{oid}/attendance",
+        "post",
+        json!({
+            "tags": ["attendance"],
+            "summary": "Record the attendance of an occurrence.",
+            "description": "Attendees already recorded for the occurrence are updated.",
+            "parameters": [oid_param("path")],
+            "requestBody": {"required": true, "content": {"application/json": {"schema": sheet}}},
+            "responses": {
+                "200": json_response("Result of every attendee.", schema_ref("BulkResult")),
+                "400": error_response("Malformed body or not an occurrence of the event."),
+                "404": error_response("Datum not found."),
+                "413": error_response("Too many attendees.")
+            }
+        }),
+    );
+    add_operation(
+        spec,
+        "/api/v1/event/{oid}/attendance",
+        "get",
+        json!({
+            "tags": ["attendance"],
+            "summary": "Attendance of an event by occurrence.",
+            "parameters": params.clone(),
+            "responses": {
+                "200": json_response("Occurrences with attendance recorded.", json!({"type": "array", "items": object_schema(occurrence, vec![])})),
+                "400": error_response("Invalid object id or date."),
+                "404": error_response("Datum not found.")
+            }
+        }),
+    );
+    for (table, summary) in [
+        ("person", "Attendance history of a person."),
+        ("group", "Attendance history of a group and its members."),
+    ] {
+        add_operation(
+            spec,
+            &format!("/api/v1/{}/{{oid}}/attendance", table),
+            "get",
+            json!({
+                "tags": ["attendance"],
+                "summary": summary,
+                "parameters": params.clone(),
+                "responses": {
+                    "200": json_response("Attendance by occurrence.", object_schema(history.clone(), vec![])),
+                    "400": error_response("Invalid object id or date."),
+                    "404": error_response("Datum not found.")
+                }
+            }),
+        );
+    }
+}
+
+fn add_registration(spec: &mut Value) {
+    let table = EventRegistration::table_name();
+    spec["paths"][format!("/api/v1/{}", table)]["post"]["responses"]["409"] =
+        error_response("The event is full, or the registration is over its capacity.");
+
+    let seats = object_schema(
+        vec![
+            (
+                "capacity",
+                json!({"type": "integer", "nullable": true, "description": "No limit when null."}),
+            ),
+            ("waitlist", json!({"type": "boolean"})),
+            (
+                "taken",
+                json!({"type": "integer", "description": "Persons of the registered registrations."}),
+            ),
+            (
+                "waitlisted",
+                json!({"type": "array", "items": schema_ref("ObjectId"), "description": "In the order they are promoted."}),
+            ),
+        ],
+        vec![],
+    );
+    add_operation(
+        spec,
+        "/api/v1/event/{oid}/seats",
+        "get",
+        json!({
+            "tags": ["registration"],
+            "summary": "Seats taken at an event.",
+            "description": "Persons and households take a seat per person, groups a seat per member.",
+            "parameters": [oid_param("path")],
+            "responses": {
+                "200": json_response("Capacity and waitlist of the event.", seats),
+                "400": error_response("Invalid object id."),
+                "404": error_response("Datum not found.")
+            }
+        }),
+    );
+    add_operation(
+        spec,
+        "/api/v1/event/{oid}/waitlist/promote",
+        "post",
+        json!({
+            "tags": ["registration"],
+            "summary": "Register the waitlisted registrations that fit.",
+            "description": "Runs when a registration is deleted, and by hand once the capacity is raised.",
+            "parameters": [oid_param("path")],
+            "responses": {
+                "200": json_response("Promoted registrations.", object_schema(vec![("promoted", json!({"type": "array", "items": schema_ref("ObjectId")}))], vec!["promoted"])),
+                "400": error_response("Invalid object id."),
+                "404": error_response("Datum not found.")
+            }
+        }),
+    );
+}
+
+fn add_rooms(spec: &mut Value) {
+    let table = Event::table_name();
+    let conflict = error_response(
+        "A room of the event is booked at the same time, within two years after both events start.",
+    );
+    spec["paths"][format!("/api/v1/{}", table)]["post"]["responses"]["409"] = conflict.clone();
+    for method in ["put", "patch"] {
+        spec["paths"][format!("/api/v1/{}/{{oid}}", table)][method]["responses"]["409"] =
+            conflict.clone();
+    }
+
+    let mut params = vec![oid_param("path")];
+    params.push(query_param(
+        "from",
+        "First day, today by default.",
+        json!({"type": "string", "format": "date"}),
+    ));
+    params.push(query_param(
+        "to",
+        "Last day, a week after from by default.",
+        json!({"type": "string", "format": "date"}),
+    ));
+    // Same as the occurrences listed by add_occurrences.
+    let occurrence = spec["paths"]["/api/v1/occurrences"]["get"]["responses"]["200"]["content"]
+        ["application/json"]["schema"]["properties"]["data"]["items"]
+        .clone();
+    let availability = object_schema(
+        vec![
+            ("from", json!({"type": "string", "format": "date"})),
+            ("to", json!({"type": "string", "format": "date"})),
+            (
+                "available",
+                json!({"type": "boolean", "description": "No occurrence holds the room."}),
+            ),
+            ("bookings", json!({"type": "array", "items": occurrence})),
+        ],
+        vec!["from", "to", "available", "bookings"],
+    );
+    add_operation(
+        spec,
+        "/api/v1/room/{oid}/availability",
+        "get",
+        json!({
+            "tags": ["rooms"],
+            "summary": "Occurrences holding a room over a range of days.",
+            "description": "Events hold their rooms over every occurrence that is not cancelled, an hour when the occurrence has no end.",
+            "parameters": params,
+            "responses": {
+                "200": json_response("Bookings of the room.", availability),
+                "400": error_response("Invalid object id or date, or too long a range."),
+                "404": error_response("Datum not found.")
+            }
+        }),
+    );
+}
+
+fn add_kiosk(spec: &mut Value) {
+    let oid = json!({"type": "string", "pattern": OID_PATTERN});
+    let occurrence = vec![
+        query_param("event", "Event of the occurrence.", oid.clone()),
+        query_param(
+            "occurrence",
+            "Scheduled start of the occurrence.",
+            json!({"type": "string"}),
+        ),
+    ];
+    let member = json!({
+        "type": "object",
+        "properties": {
+            "_id": {"type": "string"},
+            "first_name": {"type": "string"},
+            "last_name": {"type": "string"},
+            "dob": {"type": "string", "format": "date", "nullable": true},
+            "child": {"type": "boolean", "description": "A child in the household relations, or younger than 18."},
+            "notes": {"type": "string", "description": "Allergies and other care notes."},
+            "checked_in": {"type": "boolean"}
+        }
+    });
+    let household = json!({
+        "type": "object",
+        "properties": {
+            "_id": {"type": "string"},
+            "house_name": {"type": "string"},
+            "members": {"type": "array", "items": member}
+        }
+    });
+    let mut params = vec![query_param(
+        "query",
+        "Phone number or name.",
+        json!({"type": "string"}),
+    )];
+    params.extend(occurrence.clone());
+    params[0]["required"] = json!(true);
+    add_operation(
+        spec,
+        "/api/v1/kiosk/households",
+        "get",
+        json!({
+            "tags": ["kiosk"],
+            "summary": "Look up households by phone or name.",
+            "description": "Members checked into the given occurrence are marked.",
+            "parameters": params,
+            "responses": {
+                "200": json_response("Matching households.", json!({"type": "array", "items": household})),
+                "400": error_response("Query too short.")
+            }
+        }),
+    );
+
+    let check_in = json!({
+        "type": "object",
+        "properties": {
+            "event": oid.clone(),
+            "occurrence": {"type": "string"},
+            "household": oid.clone(),
+            "persons": {"type": "array", "items": oid.clone()}
+        },
+        "required": ["event", "occurrence", "household", "persons"]
+    });
+    let labels = json!({
+        "type": "object",
+        "properties": {
+            "code": {"type": "string", "description": "Pickup code."},
+            "children": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "person": {"type": "string"},
+                        "name": {"type": "string"},
+                        "notes": {"type": "string"},
+                        "code": {"type": "string"}
+                    }
+                }
+            },
+            "guardian": {
+                "type": "object",
+                "properties": {
+                    "house_name": {"type": "string"},
+                    "children": {"type": "array", "items": {"type": "string"}},
+                    "code": {"type": "string"}
+                }
+            }
+        }
+    });
+    add_operation(
+        spec,
+        "/api/v1/kiosk/checkin",
+        "post",
+        json!({
+            "tags": ["kiosk"],
+            "summary": "Check children of a household into an occurrence.",
+            "description": "Children share a new pickup code and are marked present.",
+            "requestBody": {"required": true, "content": {"application/json": {"schema": check_in}}},
+            "responses": {
+                "201": json_response("Labels of the children and of the guardian.", labels),
+                "400": error_response("Not an occurrence, not in the household or already checked in."),
+                "404": error_response("Datum not found.")
+            }
+        }),
+    );
+
+    let check_out = json!({
+        "type": "object",
+        "properties": {
+            "event": oid.clone(),
+            "occurrence": {"type": "string"},
+            "code": {"type": "string"},
+            "persons": {"type": "array", "items": oid, "description": "Every child with the code by default."}
+        },
+        "required": ["event", "occurrence", "code"]
+    });
+    add_operation(
+        spec,
+        "/api/v1/kiosk/checkout",
+        "post",
+        json!({
+            "tags": ["kiosk"],
+            "summary": "Check children out with their pickup code.",
+            "requestBody": {"required": true, "content": {"application/json": {"schema": check_out}}},
+            "responses": {
+                "200": json_response("Children checked out.", json!({"type": "object", "properties": {"checked_out": {"type": "array", "items": {"type": "string"}}}})),
+                "400": error_response("Malformed body."),
+                "403": error_response("Wrong pickup code.")
+            }
+        }),
+    );
+
+    let mut params = occurrence;
+    for p in params.iter_mut() {
+        p["required"] = json!(true);
+    }
+    add_operation(
+        spec,
+        "/api/v1/kiosk/checkins",
+        "get",
+        json!({
+            "tags": ["kiosk"],
+            "summary": "Children checked into an occurrence.",
+            "description": "Pickup codes are left out.",
+            "parameters": params,
+            "responses": {
+                "200": json_response("Check-ins with the name and notes of the child.", json!({"type": "array", "items": {"type": "object"}})),
+                "400": error_response("Invalid object id.")
+            }
+        }),
+    );
+}
+
+fn add_labels(spec: &mut Value) {
+    let number = json!({"type": "number"});
+    let layout = json!({
+        "type": "object",
+        "description": "Sizes are in points, 1/72 inch.",
+        "properties": {
+            "name": {"type": "string"},
+            "page_width": number.clone(),
+            "page_height": number.clone(),
+            "columns": {"type": "integer", "minimum": 1},
+            "rows": {"type": "integer", "minimum": 1},
+            "label_width": number.clone(),
+            "label_height": number.clone(),
+            "top": {"type": "number", "description": "Margin to the first label."},
+            "left": {"type": "number", "description": "Margin to the first label."},
+            "h_pitch": {"type": "number", "description": "Distance from a label to the next one."},
+            "v_pitch": number
+        }
+    });
+    add_operation(
+        spec,
+        "/api/v1/labels/layouts",
+        "get",
+        json!({
+            "tags": ["labels"],
+            "summary": "List preset label layouts.",
+            "responses": {"200": json_response("Avery layouts.", json!({"type": "array", "items": layout.clone()}))}
+        }),
+    );
+
+    let request = json!({
+        "type": "object",
+        "properties": {
+            "kind": {
+                "type": "string",
+                "enum": ["NameTag", "Mailing", "Allergy"],
+                "description": "Name tags and allergy labels are made for people, mailing labels for people, households or addresses."
+            },
+            "source": {"type": "string", "enum": ["Person", "Household", "Address"]},
+            "ids": {"type": "array", "items": {"type": "string", "pattern": OID_PATTERN}, "description": "Every datum of the source by default."},
+            "layout": {"oneOf": [{"type": "string", "description": "Preset name."}, layout]},
+            "skip": {"type": "integer", "minimum": 0, "description": "Labels already used on the first sheet."},
+            "outline": {"type": "boolean", "description": "Draw the edges of the labels."}
+        },
+        "required": ["kind", "source"]
+    });
+    add_operation(
+        spec,
+        "/api/v1/labels",
+        "post",
+        json!({
+            "tags": ["labels"],
+            "summary": "Print a sheet of labels.",
+            "description": "People are mailed at the address of their household. Allergy labels are made for people with notes.",
+            "requestBody": {"required": true, "content": {"application/json": {"schema": request}}},
+            "responses": {
+                "200": {"description": "PDF of the sheets.", "content": {"application/pdf": {"schema": {"type": "string", "format": "binary"}}}},
+                "400": error_response("Invalid layout, kind or source, or no labels to print.")
+            }
+        }),
+    );
+}
+
+fn add_admin(spec: &mut Value) {
+    let report = json!({
+        "type": "object",
+        "properties": {
+            "database": {"type": "string"},
+            "dry_run": {"type": "boolean"},
+            "created": {"type": "string", "format": "date-time", "description": "Time of the backup."},
+            "schema_version": {"type": "integer"},
+            "collections": {"type": "object", "additionalProperties": {"type": "integer"}, "description": "Documents per table."},
+            "errors": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "table": {"type": "string"},
+                        "line": {"type": "integer", "description": "Line in <table>.jsonl."},
+                        "err": {"type": "string"}
+                    }
+                }
+            }
+        }
+    });
+    add_operation(
+        spec,
+        "/api/v1/admin/backup",
+        "get",
+        json!({
+            "tags": ["admin"],
+            "summary": "Download a backup of the database.",
+            "description": "A gzipped tar holding manifest.json and <table>.jsonl per table in canonical extended JSON. Administrators only.",
+            "responses": {
+                "200": {"description": "Backup archive.", "content": {"application/gzip": {"schema": {"type": "string", "format": "binary"}}}},
+                "403": {"description": "Not an administrator."}
+            }
+        }),
+    );
+    add_operation(
+        spec,
+        "/api/v1/admin/restore",
+        "post",
+        json!({
+            "tags": ["admin"],
+            "summary": "Restore a backup into an empty database.",
+            "description": "Every document is checked against the current models, nothing is written unless all are valid. Users already stored are kept and the others are added. Administrators only.",
+            "parameters": [
+                query_param("tenant", "Restore into the cosi_db_<tenant> database.", json!({"type": "string", "pattern": "^[a-z0-9_]{1,32}$"})),
+                query_param("dry_run", "Only check the archive, the database may hold data.", json!({"type": "boolean"}))
+            ],
+            "requestBody": {"required": true, "content": {"application/gzip": {"schema": {"type": "string", "format": "binary"}}}},
+            "responses": {
+                "200": json_response("Restore report.", report.clone()),
+                "400": error_response("Unreadable archive, unsupported version or invalid tenant."),
+                "403": {"description": "Not an administrator."},
+                "409": error_response("Database holds data other than logins."),
+                "413": error_response("Archive too large."),
+                "422": json_response("Some documents are invalid, nothing was written.", report)
+            }
+        }),
+    );
+}
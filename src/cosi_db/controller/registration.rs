@@ -0,0 +1,48 @@
+// Capacity and waitlist of events, see cosi_db::registration.
+use rocket::http::Status;
+use rocket::response::content::RawJson;
+use rocket::response::status::Custom;
+use rocket_db_pools::Connection;
+
+use mongodb::Client;
+
+use crate::cosi_db::connection::COSIMongo;
+use crate::cosi_db::controller::api::{parse_oid, ApiResult};
+use crate::cosi_db::controller::attendance::find_event;
+use crate::cosi_db::controller::common::render_err;
+use crate::cosi_db::model::auth::User;
+use crate::cosi_db::registration::{lock, promote, seats};
+
+fn server_err<E: std::fmt::Display>(err: E) -> Custom<RawJson<String>> {
+    Custom(Status::InternalServerError, RawJson(render_err(err)))
+}
+
+#[get("/api/v1/event/<oid>/seats")]
+pub async fn event_seats(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    let id = parse_oid(oid)?;
+    find_event(client, id).await?;
+    let data = seats(client, id).await.map_err(server_err)?;
+    Ok(RawJson(serde_json::to_string(&data).unwrap()))
+}
+
+// Promotes the waitlist by hand, after the capacity of the event is raised.
+#[post("/api/v1/event/<oid>/waitlist/promote")]
+pub async fn promote_waitlist(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    let id = parse_oid(oid)?;
+    find_event(client, id).await?;
+    let _lock = lock().await;
+    let promoted = promote(client, id, None).await.map_err(server_err)?;
+    Ok(RawJson(
+        serde_json::json!({ "promoted": promoted }).to_string(),
+    ))
+}
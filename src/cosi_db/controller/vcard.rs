@@ -0,0 +1,154 @@
+// vCard downloads and imports, see cosi_db::vcard.
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::{ContentType, Status};
+use rocket::response::content::RawJson;
+use rocket::response::status::Custom;
+use rocket_db_pools::Connection;
+
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::Client;
+
+use crate::cosi_db::connection::COSIMongo;
+use crate::cosi_db::controller::api::{parse_oid, ApiResult};
+use crate::cosi_db::controller::common::{attachment, filename, render_err, Download};
+use crate::cosi_db::controller::import::MAX_IMPORT_MIB;
+use crate::cosi_db::import::decode;
+use crate::cosi_db::model::auth::User;
+use crate::cosi_db::model::common::COSICollection;
+use crate::cosi_db::model::group::{Group, GroupRelation};
+use crate::cosi_db::model::household::Household;
+use crate::cosi_db::model::person::Person;
+use crate::cosi_db::vcard::{import, render_people};
+
+fn server_err<E: std::fmt::Display>(err: E) -> Custom<RawJson<String>> {
+    Custom(Status::InternalServerError, RawJson(render_err(err)))
+}
+
+fn not_found(table: &str) -> Custom<RawJson<String>> {
+    Custom(
+        Status::NotFound,
+        RawJson(render_err(format!("No {} found.", table))),
+    )
+}
+
+async fn download(client: &Client, ids: &[ObjectId], name: &str) -> ApiResult<Download> {
+    let cards = render_people(client, ids).await.map_err(server_err)?;
+    Ok(Download::File(
+        cards.into_bytes(),
+        ContentType::new("text", "vcard").with_params(("charset", "utf-8")),
+        attachment(&filename(name, "contacts", "vcf")),
+    ))
+}
+
+fn first(mut data: Vec<Document>, table: &str) -> ApiResult<Document> {
+    data.pop().ok_or_else(|| not_found(table))
+}
+
+#[get("/api/v1/person/<oid>/vcard")]
+pub async fn person_vcard(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+) -> ApiResult<Download> {
+    let client: &Client = &connect;
+    let id = parse_oid(oid)?;
+    let person = Person::find_raw_document(client, Some(doc! {"_id": id}), None)
+        .await
+        .map_err(server_err)?;
+    let person = first(person, "person")?;
+    let name = format!(
+        "{} {}",
+        person.get_str("first_name").unwrap_or(""),
+        person.get_str("last_name").unwrap_or("")
+    );
+    download(client, &[id], &name).await
+}
+
+// Every member of the household.
+#[get("/api/v1/household/<oid>/vcard")]
+pub async fn household_vcard(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+) -> ApiResult<Download> {
+    let client: &Client = &connect;
+    let id = parse_oid(oid)?;
+    let household = Household::find_raw_document(client, Some(doc! {"_id": id}), None)
+        .await
+        .map_err(server_err)?;
+    let household = first(household, "household")?;
+    let persons: Vec<ObjectId> = household
+        .get_array("persons")
+        .map(|ps| ps.iter().filter_map(|p| p.as_object_id()).collect())
+        .unwrap_or_default();
+    download(
+        client,
+        &persons,
+        household.get_str("house_name").unwrap_or(""),
+    )
+    .await
+}
+
+// Every member of the group.
+#[get("/api/v1/group/<oid>/vcard")]
+pub async fn group_vcard(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+) -> ApiResult<Download> {
+    let client: &Client = &connect;
+    let id = parse_oid(oid)?;
+    let group = Group::find_raw_document(client, Some(doc! {"_id": id}), None)
+        .await
+        .map_err(server_err)?;
+    let group = first(group, "group")?;
+    let persons: Vec<ObjectId> =
+        GroupRelation::find_raw_document(client, Some(doc! {"group": id}), None)
+            .await
+            .map_err(server_err)?
+            .iter()
+            .filter_map(|r| r.get_object_id("person").ok())
+            .collect();
+    download(client, &persons, group.get_str("group_name").unwrap_or("")).await
+}
+
+// The body is a .vcf file with one or more cards.
+#[post("/api/v1/import/vcard?<dry_run>", data = "<data>")]
+pub async fn import_vcard(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    dry_run: Option<bool>,
+    data: Data<'_>,
+) -> ApiResult<Custom<RawJson<String>>> {
+    let client: &Client = &connect;
+    let bytes = data
+        .open(MAX_IMPORT_MIB.mebibytes())
+        .into_bytes()
+        .await
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+    if !bytes.is_complete() {
+        let err = format!("Imports are limited to {} MiB.", MAX_IMPORT_MIB);
+        return Err(Custom(Status::PayloadTooLarge, RawJson(render_err(err))));
+    }
+    let text = decode(bytes.into_inner());
+
+    let report = import(client, &text, dry_run.unwrap_or(false))
+        .await
+        .map_err(server_err)?;
+    if report.total == 0 {
+        return Err(Custom(
+            Status::BadRequest,
+            RawJson(render_err("File holds no vCards.")),
+        ));
+    }
+    // Nothing is written unless every card is valid.
+    let status = if report.invalid > 0 {
+        Status::UnprocessableEntity
+    } else {
+        Status::Ok
+    };
+    Ok(Custom(
+        status,
+        RawJson(serde_json::to_string(&report).unwrap()),
+    ))
+}
@@ -0,0 +1,138 @@
+// Webhook subscriptions, see cosi_db::webhooks for the delivery format.
+use rocket::http::Status;
+use rocket::response::content::RawJson;
+use rocket::response::status::{Custom, NoContent};
+use rocket::serde::json::{Error as JsonError, Json};
+use rocket_db_pools::Connection;
+
+use mongodb::bson::{doc, Document};
+use mongodb::options::FindOptions;
+use mongodb::Client;
+
+use crate::cosi_db::connection::COSIMongo;
+use crate::cosi_db::controller::api::{parse_oid, ApiResult};
+use crate::cosi_db::controller::common::{parse_page_size, render_err, Located, PaginateData};
+use crate::cosi_db::errors::Missing;
+use crate::cosi_db::model::auth::{Admin, User};
+use crate::cosi_db::model::common::COSICollection;
+use crate::cosi_db::model::webhook::{Webhook, WebhookDelivery};
+
+fn server_err<E: std::fmt::Display>(err: E) -> Custom<RawJson<String>> {
+    Custom(Status::InternalServerError, RawJson(render_err(err)))
+}
+
+// Secrets are write only.
+fn render_webhook(mut data: Document) -> Document {
+    data.remove("secret");
+    data
+}
+
+#[get("/api/v1/webhooks")]
+pub async fn list_webhooks(
+    _user: User,
+    connect: Connection<COSIMongo>,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    let data: Vec<Document> = Webhook::find_raw_document(client, None, None)
+        .await
+        .map_err(server_err)?
+        .into_iter()
+        .map(render_webhook)
+        .collect();
+    Ok(RawJson(serde_json::to_string(&data).unwrap()))
+}
+
+#[post("/api/v1/webhooks", format = "json", data = "<webhook>")]
+pub async fn create_webhook(
+    admin: Admin,
+    connect: Connection<COSIMongo>,
+    webhook: Result<Json<Webhook>, JsonError<'_>>,
+) -> ApiResult<Located> {
+    let client: &Client = &connect;
+    let mut webhook = webhook
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?
+        .into_inner();
+    webhook.owner = admin.0.username;
+    webhook
+        .validate()
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+
+    let id = Webhook::insert_datum(client, &webhook, None)
+        .await
+        .map_err(server_err)?;
+    let oid = id.as_object_id().unwrap();
+    let stored = Webhook::find_raw_document(client, Some(doc! {"_id": oid}), None)
+        .await
+        .map_err(server_err)?
+        .pop()
+        .ok_or_else(|| server_err("Unable to find created webhook."))?;
+    Ok(Located::new(
+        Custom(
+            Status::Created,
+            RawJson(serde_json::to_string(&render_webhook(stored)).unwrap()),
+        ),
+        format!("/api/v1/webhooks/{}", oid.to_hex()),
+    ))
+}
+
+#[delete("/api/v1/webhooks/<oid>")]
+pub async fn delete_webhook(
+    _admin: Admin,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+) -> ApiResult<NoContent> {
+    let client: &Client = &connect;
+    let oid = parse_oid(oid)?;
+    match Webhook::delete_datum(client, &doc! {"_id": oid}).await {
+        Ok(_) => Ok(NoContent),
+        Err(err) if err.is::<Missing>() => Err(Custom(
+            Status::NotFound,
+            RawJson(render_err("No data found.")),
+        )),
+        Err(err) => Err(Custom(
+            Status::InternalServerError,
+            RawJson(render_err(err)),
+        )),
+    }
+}
+
+// Delivery attempts of one webhook, newest first.
+#[get("/api/v1/webhooks/<oid>/deliveries?<page>&<page_size>")]
+pub async fn webhook_deliveries(
+    _user: User,
+    connect: Connection<COSIMongo>,
+    oid: &str,
+    page: Option<u64>,
+    page_size: Option<i64>,
+) -> ApiResult<RawJson<String>> {
+    let client: &Client = &connect;
+    let oid = parse_oid(oid)?;
+    let page = page.unwrap_or(0);
+    let limit_size = parse_page_size(page_size)
+        .map_err(|err| Custom(Status::BadRequest, RawJson(render_err(err))))?;
+
+    let query = doc! {"webhook": oid};
+    let total_result = WebhookDelivery::get_raw_document(client)
+        .await
+        .count_documents(query.clone(), None)
+        .await
+        .map_err(server_err)?;
+    let options = FindOptions::builder()
+        .sort(doc! {"_id": -1})
+        .skip(limit_size as u64 * page)
+        .limit(limit_size)
+        .build();
+    let data = WebhookDelivery::find_raw_document(client, Some(query), Some(options))
+        .await
+        .map_err(server_err)?;
+
+    let result = PaginateData {
+        page,
+        page_size: limit_size as u64,
+        total_pages: (total_result as f64 / limit_size as f64).ceil() as u64,
+        total_result,
+        next: None,
+        data,
+    };
+    Ok(RawJson(serde_json::to_string(&result).unwrap()))
+}
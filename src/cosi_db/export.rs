@@ -0,0 +1,155 @@
+// Spreadsheet export of tables.
+// Every stored field becomes a column. References are flattened into a column per field of the
+// referenced table, such as address.city. Lists join their values with "; ", so a household
+// gets persons.first_name with the first name of every member.
+use mongodb::bson::{Bson, Document};
+use rust_xlsxwriter::Workbook;
+
+use crate::cosi_db::errors::{COSIError, COSIResult};
+use crate::cosi_db::model::common::COSIModel;
+
+// Documents resolved and rendered at once while streaming.
+pub const EXPORT_BATCH: usize = 200;
+const LIST_SEPARATOR: &str = "; ";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+}
+
+impl ExportFormat {
+    pub fn parse(format: Option<&str>) -> COSIResult<ExportFormat> {
+        match format {
+            None | Some("csv") => Ok(ExportFormat::Csv),
+            Some("xlsx") => Ok(ExportFormat::Xlsx),
+            Some(_) => Err(COSIError::msg("Export format must be csv or xlsx.")),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Xlsx => "xlsx",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Column {
+    pub name: String,
+    field: &'static str,
+    // Field of the referenced datum.
+    sub: Option<&'static str>,
+}
+
+pub fn columns<T: COSIModel>() -> Vec<Column> {
+    let references = T::references();
+    let mut result = vec![Column {
+        name: "_id".to_string(),
+        field: "_id",
+        sub: None,
+    }];
+    for field in T::fields() {
+        match references.iter().find(|r| r.field == field) {
+            Some(r) => {
+                for sub in std::iter::once("_id").chain((r.fields)()) {
+                    result.push(Column {
+                        name: format!("{}.{}", field, sub),
+                        field,
+                        sub: Some(sub),
+                    });
+                }
+            }
+            None => result.push(Column {
+                name: field.to_string(),
+                field,
+                sub: None,
+            }),
+        }
+    }
+    result
+}
+
+fn cell(value: Option<&Bson>) -> String {
+    match value {
+        None | Some(Bson::Null) => String::new(),
+        Some(Bson::String(s)) => s.clone(),
+        Some(Bson::ObjectId(oid)) => oid.to_hex(),
+        Some(Bson::Boolean(b)) => b.to_string(),
+        Some(Bson::Int32(v)) => v.to_string(),
+        Some(Bson::Int64(v)) => v.to_string(),
+        Some(Bson::Double(v)) => v.to_string(),
+        Some(Bson::DateTime(d)) => d.try_to_rfc3339_string().unwrap_or_default(),
+        Some(Bson::Array(values)) if !values.iter().any(|v| v.as_document().is_some()) => values
+            .iter()
+            .map(|v| cell(Some(v)))
+            .collect::<Vec<String>>()
+            .join(LIST_SEPARATOR),
+        // Nested structures such as household relations.
+        Some(other) => other.clone().into_relaxed_extjson().to_string(),
+    }
+}
+
+fn referenced_cell(value: Option<&Bson>, sub: &str) -> String {
+    match value {
+        Some(Bson::Document(d)) => cell(d.get(sub)),
+        Some(Bson::Array(values)) => values
+            .iter()
+            .map(|v| referenced_cell(Some(v), sub))
+            .collect::<Vec<String>>()
+            .join(LIST_SEPARATOR),
+        // Ids that could not be resolved.
+        Some(Bson::ObjectId(oid)) if sub == "_id" => oid.to_hex(),
+        _ => String::new(),
+    }
+}
+
+// Expects references to be resolved, see COSICollection::process_foreign_keys.
+pub fn row(data: &Document, columns: &[Column]) -> Vec<String> {
+    columns
+        .iter()
+        .map(|c| match c.sub {
+            None => cell(data.get(c.field)),
+            Some(sub) => referenced_cell(data.get(c.field), sub),
+        })
+        .collect()
+}
+
+// Spreadsheet programs run cells starting with these as formulas.
+const FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+// Quotes cells that would be read as formulas, as in '=1+2. Numbers such as -5 stay numbers.
+fn escape_formula(value: &str) -> String {
+    let number = value.parse::<f64>().is_ok_and(|n| n.is_finite());
+    match value.starts_with(FORMULA_PREFIXES) && !number {
+        true => format!("'{}", value),
+        false => value.to_string(),
+    }
+}
+
+pub fn render_csv(rows: &[Vec<String>]) -> COSIResult<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for r in rows {
+        writer.write_record(r.iter().map(|v| escape_formula(v)))?;
+    }
+    writer
+        .into_inner()
+        .map_err(|err| COSIError::msg(err.to_string()))
+}
+
+// Lets spreadsheet programs detect UTF-8.
+pub const CSV_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+pub fn render_xlsx(sheet: &str, rows: &[Vec<String>]) -> COSIResult<Vec<u8>> {
+    let mut workbook = Workbook::new();
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name(sheet)?;
+    for (r, values) in rows.iter().enumerate() {
+        // Written as text so values are never read as formulas.
+        for (c, value) in values.iter().enumerate() {
+            worksheet.write_string(r as u32, c as u16, value)?;
+        }
+    }
+    Ok(workbook.save_to_buffer()?)
+}
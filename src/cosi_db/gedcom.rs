@@ -0,0 +1,775 @@
+// GEDCOM 5.5.1 export and import of people and their families.
+// Families come from the relations of households, see HouseRelation: a couple and their
+// children, a single parent being the husband or wife depending on their sex. A household may
+// hold several families such as grandparents living with a couple. The address of the household
+// is the residence of its families.
+// Imports create a household per FAM at the residence of the family, or at an empty address
+// when there is none. Individuals exported by cosi-db carry their object id as REFN and _UID
+// records are kept as external_id, both being used to find people that already exist.
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, Utc};
+use mongodb::bson::{doc, from_document, oid::ObjectId, to_bson, Bson, Document};
+use mongodb::options::FindOptions;
+use mongodb::Client;
+use serde::Serialize;
+
+use crate::cosi_db::errors::{COSIError, COSIResult};
+use crate::cosi_db::model::address::Address;
+use crate::cosi_db::model::common::{COSICollection, COSIForm, OID};
+use crate::cosi_db::model::household::{
+    HouseRelation, HouseRelationStatus, Household, HouseholdImpl,
+};
+use crate::cosi_db::model::person::{Person, PersonImpl, Sex};
+
+pub const REFN_TYPE: &str = "COSI-DB";
+const LINE_END: &str = "\r\n";
+const DATE_FORMAT: &str = "%d %b %Y";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Family {
+    husband: Option<ObjectId>,
+    wife: Option<ObjectId>,
+    children: Vec<ObjectId>,
+    address: Option<ObjectId>,
+}
+
+impl Family {
+    fn has_parent(&self, id: ObjectId) -> bool {
+        self.husband == Some(id) || self.wife == Some(id)
+    }
+
+    fn members(&self) -> Vec<ObjectId> {
+        self.husband
+            .iter()
+            .chain(self.wife.iter())
+            .chain(self.children.iter())
+            .copied()
+            .collect()
+    }
+}
+
+fn families(household: &HouseholdImpl, sexes: &HashMap<ObjectId, Sex>) -> Vec<Family> {
+    let address = Some(household.address.0);
+    let mut result: Vec<Family> = vec![];
+    for r in &household.relations {
+        let (husband, wife) = match r.relation {
+            HouseRelationStatus::Husband => (r.person_a.0, r.person_b.0),
+            HouseRelationStatus::Wife => (r.person_b.0, r.person_a.0),
+            HouseRelationStatus::Child => continue,
+        };
+        if husband != wife
+            && !result
+                .iter()
+                .any(|f| f.has_parent(husband) && f.has_parent(wife))
+        {
+            result.push(Family {
+                husband: Some(husband),
+                wife: Some(wife),
+                address,
+                ..Default::default()
+            });
+        }
+    }
+    for r in &household.relations {
+        let (child, parent) = (r.person_a.0, r.person_b.0);
+        if !matches!(r.relation, HouseRelationStatus::Child) || child == parent {
+            continue;
+        }
+        let idx = match result.iter().position(|f| f.has_parent(parent)) {
+            Some(idx) => idx,
+            None => {
+                let single = match sexes.get(&parent) {
+                    Some(Sex::Female) => Family {
+                        wife: Some(parent),
+                        ..Default::default()
+                    },
+                    _ => Family {
+                        husband: Some(parent),
+                        ..Default::default()
+                    },
+                };
+                result.push(Family { address, ..single });
+                result.len() - 1
+            }
+        };
+        if !result[idx].children.contains(&child) {
+            result[idx].children.push(child);
+        }
+    }
+    result
+}
+
+// Values cannot span lines and "@" marks cross references.
+fn text(value: &str) -> String {
+    value.replace(['\r', '\n'], " ").replace('@', "@@")
+}
+
+struct Writer {
+    out: String,
+}
+
+impl Writer {
+    fn line(&mut self, level: u8, tag: &str, value: &str) {
+        self.out.push_str(&level.to_string());
+        self.out.push(' ');
+        self.out.push_str(tag);
+        if !value.is_empty() {
+            self.out.push(' ');
+            self.out.push_str(value);
+        }
+        self.out.push_str(LINE_END);
+    }
+
+    fn record(&mut self, xref: &str, tag: &str) {
+        self.line(0, &format!("@{}@", xref), tag);
+    }
+}
+
+fn write_individual(w: &mut Writer, xref: &str, id: &ObjectId, person: &Person) {
+    w.record(xref, "INDI");
+    let given = [&person.first_name, &person.middle_name]
+        .iter()
+        .filter(|n| !n.is_empty())
+        .map(|n| text(n))
+        .collect::<Vec<String>>()
+        .join(" ");
+    let surname = text(&person.last_name).replace('/', " ");
+    w.line(1, "NAME", format!("{} /{}/", given, surname).trim());
+    if !given.is_empty() {
+        w.line(2, "GIVN", &given);
+    }
+    if !surname.is_empty() {
+        w.line(2, "SURN", &surname);
+    }
+    if !person.nicks.is_empty() {
+        let nicks: Vec<String> = person.nicks.iter().map(|n| text(n)).collect();
+        w.line(2, "NICK", &nicks.join(", "));
+    }
+    let sex = match person.sex {
+        Sex::Male => "M",
+        Sex::Female => "F",
+        Sex::Undefined => "U",
+    };
+    w.line(1, "SEX", sex);
+    if let Some(dob) = person.dob {
+        w.line(1, "BIRT", "");
+        w.line(
+            2,
+            "DATE",
+            &dob.format(DATE_FORMAT).to_string().to_uppercase(),
+        );
+    }
+    w.line(1, "REFN", &id.to_hex());
+    w.line(2, "TYPE", REFN_TYPE);
+}
+
+fn write_address(w: &mut Writer, address: &Address) {
+    w.line(1, "RESI", "");
+    w.line(2, "ADDR", &text(&address.line_one));
+    for (tag, value) in [
+        ("ADR1", Some(&address.line_one)),
+        ("ADR2", Some(&address.line_two)),
+        ("ADR3", Some(&address.line_three)),
+        ("CITY", Some(&address.city)),
+        ("STAE", Some(&address.region)),
+        ("POST", address.postal_code.as_ref()),
+        ("CTRY", address.country.as_ref()),
+    ] {
+        if let Some(v) = value.filter(|v| !v.is_empty()) {
+            w.line(3, tag, &text(v));
+        }
+    }
+}
+
+// Every person and household, or the members of one household.
+pub async fn export(client: &Client, household: Option<ObjectId>) -> COSIResult<String> {
+    let households = Household::find_raw_document(
+        client,
+        household.map(|id| doc! {"_id": id}),
+        Some(FindOptions::builder().sort(doc! {"_id": 1}).build()),
+    )
+    .await?;
+    let households: Vec<HouseholdImpl> = households
+        .into_iter()
+        .map(from_document)
+        .collect::<Result<_, _>>()?;
+    let person_filter = household.map(|_| {
+        let members: Vec<ObjectId> = households
+            .iter()
+            .flat_map(|h| h.persons.iter().map(|p| p.0))
+            .collect();
+        doc! {"_id": {"$in": members}}
+    });
+    let people = Person::find_raw_document(
+        client,
+        person_filter,
+        Some(FindOptions::builder().sort(doc! {"_id": 1}).build()),
+    )
+    .await?;
+    let mut individuals: Vec<(ObjectId, Person)> = vec![];
+    for p in people {
+        individuals.push((p.get_object_id("_id")?, from_document(p)?));
+    }
+    let xrefs: HashMap<ObjectId, String> = individuals
+        .iter()
+        .enumerate()
+        .map(|(idx, (id, _))| (*id, format!("I{}", idx + 1)))
+        .collect();
+    let sexes: HashMap<ObjectId, Sex> = individuals.iter().map(|(id, p)| (*id, p.sex)).collect();
+
+    // The same couple may be related in several households.
+    let mut all: Vec<Family> = vec![];
+    for h in &households {
+        for mut f in families(h, &sexes) {
+            f.husband = f.husband.filter(|id| xrefs.contains_key(id));
+            f.wife = f.wife.filter(|id| xrefs.contains_key(id));
+            f.children.retain(|id| xrefs.contains_key(id));
+            if f.members().len() < 2 {
+                continue;
+            }
+            match all
+                .iter_mut()
+                .find(|a| a.husband == f.husband && a.wife == f.wife)
+            {
+                Some(a) => {
+                    for c in f.children {
+                        if !a.children.contains(&c) {
+                            a.children.push(c);
+                        }
+                    }
+                }
+                None => all.push(f),
+            }
+        }
+    }
+
+    let address_ids: Vec<ObjectId> = all.iter().filter_map(|f| f.address).collect();
+    let mut addresses: HashMap<ObjectId, Address> = HashMap::new();
+    for a in
+        Address::find_raw_document(client, Some(doc! {"_id": {"$in": address_ids}}), None).await?
+    {
+        addresses.insert(a.get_object_id("_id")?, from_document(a)?);
+    }
+
+    let mut w = Writer { out: String::new() };
+    w.line(0, "HEAD", "");
+    w.line(1, "SOUR", "COSI-DB");
+    w.line(2, "VERS", env!("CARGO_PKG_VERSION"));
+    w.line(2, "NAME", "COSI DB");
+    w.line(
+        1,
+        "DATE",
+        &Utc::now().format(DATE_FORMAT).to_string().to_uppercase(),
+    );
+    w.line(1, "SUBM", "@SUBM@");
+    w.line(1, "GEDC", "");
+    w.line(2, "VERS", "5.5.1");
+    w.line(2, "FORM", "LINEAGE-LINKED");
+    w.line(1, "CHAR", "UTF-8");
+    w.record("SUBM", "SUBM");
+    w.line(1, "NAME", "COSI DB");
+
+    for (id, person) in &individuals {
+        write_individual(&mut w, &xrefs[id], id, person);
+        for (idx, f) in all.iter().enumerate() {
+            if f.has_parent(*id) {
+                w.line(1, "FAMS", &format!("@F{}@", idx + 1));
+            }
+        }
+        for (idx, f) in all.iter().enumerate() {
+            if f.children.contains(id) {
+                w.line(1, "FAMC", &format!("@F{}@", idx + 1));
+            }
+        }
+    }
+    for (idx, f) in all.iter().enumerate() {
+        w.record(&format!("F{}", idx + 1), "FAM");
+        for (tag, id) in [("HUSB", f.husband), ("WIFE", f.wife)] {
+            if let Some(id) = id {
+                w.line(1, tag, &format!("@{}@", xrefs[&id]));
+            }
+        }
+        for c in &f.children {
+            w.line(1, "CHIL", &format!("@{}@", xrefs[c]));
+        }
+        if let Some(a) = f.address.and_then(|a| addresses.get(&a)) {
+            write_address(&mut w, a);
+        }
+    }
+    w.line(0, "TRLR", "");
+    Ok(w.out)
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LineError {
+    pub line: u64,
+    pub err: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct GedcomReport {
+    pub dry_run: bool,
+    pub individuals: usize,
+    pub families: usize,
+    pub persons_created: usize,
+    pub persons_existing: usize,
+    pub households_created: usize,
+    pub households_existing: usize,
+    pub relations: usize,
+    // Data that was left out, such as inexact dates.
+    pub warnings: Vec<LineError>,
+    pub errors: Vec<LineError>,
+}
+
+impl GedcomReport {
+    fn warn(&mut self, line: u64, err: impl std::fmt::Display) {
+        self.warnings.push(LineError {
+            line,
+            err: err.to_string(),
+        });
+    }
+
+    fn error(&mut self, line: u64, err: impl std::fmt::Display) {
+        self.errors.push(LineError {
+            line,
+            err: err.to_string(),
+        });
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Node {
+    line: u64,
+    xref: Option<String>,
+    tag: String,
+    value: String,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn child(&self, tag: &str) -> Option<&Node> {
+        self.children.iter().find(|c| c.tag == tag)
+    }
+
+    fn value_of(&self, tag: &str) -> Option<&str> {
+        self.child(tag)
+            .map(|c| c.value.trim())
+            .filter(|v| !v.is_empty())
+    }
+}
+
+fn parse_line(line: &str) -> COSIResult<(usize, Node)> {
+    let mut rest = line.trim_start();
+    let mut next = || {
+        let token = rest.split(' ').next().unwrap_or("");
+        rest = rest[token.len()..].strip_prefix(' ').unwrap_or("");
+        token
+    };
+    let level: usize = next()
+        .parse()
+        .map_err(|_| COSIError::msg("Line does not start with a level."))?;
+    let mut tag = next();
+    let mut xref = None;
+    if tag.len() > 2 && tag.starts_with('@') && tag.ends_with('@') {
+        xref = Some(tag.to_string());
+        tag = next();
+    }
+    if tag.is_empty() {
+        return Err(COSIError::msg("Line has no tag."));
+    }
+    let tag = tag.to_uppercase();
+    Ok((
+        level,
+        Node {
+            xref,
+            tag,
+            value: rest.replace("@@", "@"),
+            ..Default::default()
+        },
+    ))
+}
+
+// Records of the file. CONC and CONT lines are joined into the value they continue.
+fn parse(text: &str, report: &mut GedcomReport) -> Vec<Node> {
+    let mut roots: Vec<Node> = vec![];
+    let mut stack: Vec<Node> = vec![];
+    let close = |stack: &mut Vec<Node>, roots: &mut Vec<Node>, depth: usize| {
+        while stack.len() > depth {
+            let node = stack.pop().unwrap();
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(node),
+                None => roots.push(node),
+            }
+        }
+    };
+    let text = text.trim_start_matches('\u{feff}');
+    for (idx, line) in text.lines().enumerate() {
+        let number = idx as u64 + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (level, mut node) = match parse_line(line) {
+            Ok(n) => n,
+            Err(err) => {
+                report.error(number, err);
+                continue;
+            }
+        };
+        node.line = number;
+        if level > stack.len() {
+            report.error(number, format!("Level {} has no parent.", level));
+            continue;
+        }
+        close(&mut stack, &mut roots, level);
+        match (node.tag.as_str(), stack.last_mut()) {
+            ("CONT", Some(parent)) => {
+                parent.value.push('\n');
+                parent.value.push_str(&node.value);
+            }
+            ("CONC", Some(parent)) => parent.value.push_str(&node.value),
+            _ => stack.push(node),
+        }
+    }
+    close(&mut stack, &mut roots, 0);
+    roots
+}
+
+fn parse_name(node: &Node) -> Option<(String, String)> {
+    let name = node.child("NAME")?;
+    let mut parts = name.value.splitn(3, '/');
+    let mut given = parts.next().unwrap_or("").trim().to_string();
+    let mut surname = parts.next().unwrap_or("").trim().to_string();
+    if let Some(g) = name.value_of("GIVN") {
+        given = g.to_string();
+    }
+    if let Some(s) = name.value_of("SURN") {
+        surname = s.to_string();
+    }
+    if given.is_empty() && surname.is_empty() {
+        return None;
+    }
+    Some((given, surname))
+}
+
+fn parse_person(node: &Node, report: &mut GedcomReport) -> COSIResult<Option<Person>> {
+    let (given, surname) = match parse_name(node) {
+        Some(n) => n,
+        None => {
+            report.warn(node.line, "Individual has no name and was skipped.");
+            return Ok(None);
+        }
+    };
+    let mut given = given.split_whitespace();
+    let mut person = Person {
+        first_name: given.next().unwrap_or("").to_string(),
+        middle_name: given.collect::<Vec<&str>>().join(" "),
+        last_name: surname,
+        sex: match node.value_of("SEX") {
+            Some("M") => Sex::Male,
+            Some("F") => Sex::Female,
+            _ => Sex::Undefined,
+        },
+        external_id: node.value_of("_UID").map(|u| u.to_string()),
+        ..Default::default()
+    };
+    if let Some(nicks) = node.child("NAME").and_then(|n| n.value_of("NICK")) {
+        person.nicks = nicks
+            .split(',')
+            .map(|n| n.trim().to_string())
+            .filter(|n| !n.is_empty())
+            .collect();
+    }
+    if let Some(date) = node.child("BIRT").and_then(|b| b.child("DATE")) {
+        match NaiveDate::parse_from_str(date.value.trim(), DATE_FORMAT) {
+            Ok(dob) => person.dob = Some(dob),
+            Err(_) => report.warn(
+                date.line,
+                format!("Birth date {} is not exact and was left out.", date.value),
+            ),
+        }
+    }
+
+    if person.dob.is_some() && PersonImpl::from(person.clone()).sanitize_insert().is_err() {
+        report.warn(node.line, "Birth date cannot be stored and was left out.");
+        person.dob = None;
+    }
+    PersonImpl::from(person.clone()).sanitize_insert()?;
+    Ok(Some(person))
+}
+
+async fn find_existing(
+    client: &Client,
+    node: &Node,
+    person: &Person,
+) -> COSIResult<Option<ObjectId>> {
+    let refns = node.children.iter().filter(|c| c.tag == "REFN");
+    for oid in refns.filter_map(|r| ObjectId::parse_str(r.value.trim()).ok()) {
+        if !Person::find_raw_document(client, Some(doc! {"_id": oid}), None)
+            .await?
+            .is_empty()
+        {
+            return Ok(Some(oid));
+        }
+    }
+    if let Some(external_id) = &person.external_id {
+        let existing =
+            Person::find_raw_document(client, Some(doc! {"external_id": external_id}), None)
+                .await?;
+        if let Some(d) = existing.first() {
+            return Ok(Some(d.get_object_id("_id")?));
+        }
+    }
+    Ok(None)
+}
+
+fn parse_address(node: &Node) -> Option<Address> {
+    let addr = node.child("RESI").and_then(|r| r.child("ADDR"))?;
+    let mut lines = addr.value.lines().map(|l| l.trim().to_string());
+    let line = |tag: &str, fallback: Option<String>| {
+        addr.value_of(tag)
+            .map(|v| v.to_string())
+            .or(fallback)
+            .unwrap_or_default()
+    };
+    let structured = addr.child("ADR1").is_some();
+    let pick = |lines: &mut dyn Iterator<Item = String>| {
+        if structured {
+            None
+        } else {
+            lines.next()
+        }
+    };
+    let optional = |tag: &str| addr.value_of(tag).map(|v| v.to_string());
+    let address = Address {
+        line_one: line("ADR1", pick(&mut lines)),
+        line_two: line("ADR2", pick(&mut lines)),
+        line_three: line("ADR3", pick(&mut lines)),
+        city: line("CITY", None),
+        region: line("STAE", None),
+        postal_code: optional("POST"),
+        county: None,
+        country: optional("CTRY"),
+    };
+    if address.line_one.is_empty() && address.city.is_empty() {
+        return None;
+    }
+    Some(address)
+}
+
+fn empty_address() -> Address {
+    Address {
+        line_one: String::new(),
+        line_two: String::new(),
+        line_three: String::new(),
+        city: String::new(),
+        region: String::new(),
+        postal_code: None,
+        county: None,
+        country: None,
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Member {
+    // Index into the people to create.
+    New(usize),
+    Existing(ObjectId),
+}
+
+struct ImportFamily {
+    husband: Option<Member>,
+    wife: Option<Member>,
+    children: Vec<Member>,
+    address: Address,
+    house_name: String,
+}
+
+impl ImportFamily {
+    fn members(&self) -> Vec<Member> {
+        self.husband
+            .iter()
+            .chain(self.wife.iter())
+            .chain(self.children.iter())
+            .copied()
+            .collect()
+    }
+}
+
+// The couple and each child of each parent, as (person_a, person_b, relation).
+fn relations(family: &ImportFamily) -> Vec<(Member, Member, HouseRelationStatus)> {
+    let mut result = vec![];
+    if let (Some(h), Some(w)) = (family.husband, family.wife) {
+        result.push((h, w, HouseRelationStatus::Husband));
+    }
+    for c in &family.children {
+        for parent in family.husband.iter().chain(family.wife.iter()) {
+            result.push((*c, *parent, HouseRelationStatus::Child));
+        }
+    }
+    result
+}
+
+// Nothing is written on a dry run or when the file has errors.
+pub async fn import(client: &Client, text: &str, dry_run: bool) -> COSIResult<GedcomReport> {
+    let mut report = GedcomReport {
+        dry_run,
+        ..Default::default()
+    };
+    let records = parse(text, &mut report);
+
+    let mut people: Vec<Person> = vec![];
+    let mut members: HashMap<&str, Member> = HashMap::new();
+    let mut individuals: HashMap<&str, &Node> = HashMap::new();
+    for node in records.iter().filter(|n| n.tag == "INDI") {
+        report.individuals += 1;
+        let xref = match &node.xref {
+            Some(x) => x.as_str(),
+            None => {
+                report.warn(
+                    node.line,
+                    "Individual has no cross reference and was skipped.",
+                );
+                continue;
+            }
+        };
+        let person = match parse_person(node, &mut report) {
+            Ok(Some(p)) => p,
+            Ok(None) => continue,
+            Err(err) => {
+                report.error(node.line, err);
+                continue;
+            }
+        };
+        let member = match find_existing(client, node, &person).await? {
+            Some(id) => {
+                report.persons_existing += 1;
+                Member::Existing(id)
+            }
+            None => {
+                people.push(person);
+                Member::New(people.len() - 1)
+            }
+        };
+        members.insert(xref, member);
+        individuals.insert(xref, node);
+    }
+    report.persons_created = people.len();
+
+    let mut families: Vec<ImportFamily> = vec![];
+    for node in records.iter().filter(|n| n.tag == "FAM") {
+        report.families += 1;
+        let mut member = |c: &Node| match members.get(c.value.trim()) {
+            Some(m) => Some(*m),
+            None => {
+                report.warn(c.line, format!("Unknown individual {}.", c.value.trim()));
+                None
+            }
+        };
+        let husband = node.child("HUSB").and_then(&mut member);
+        let wife = node.child("WIFE").and_then(&mut member);
+        let children: Vec<Member> = node
+            .children
+            .iter()
+            .filter(|c| c.tag == "CHIL")
+            .filter_map(&mut member)
+            .collect();
+
+        // Families list their residence, otherwise that of the parents is used.
+        let parents: Vec<&Node> = ["HUSB", "WIFE"]
+            .iter()
+            .filter_map(|t| node.value_of(t))
+            .filter_map(|x| individuals.get(x).copied())
+            .collect();
+        let address = std::iter::once(node)
+            .chain(parents.iter().copied())
+            .find_map(parse_address)
+            .unwrap_or_else(empty_address);
+        let house_name = parents
+            .iter()
+            .find_map(|p| parse_name(p).map(|(_, s)| s).filter(|s| !s.is_empty()))
+            .unwrap_or_else(|| "Family".to_string());
+
+        let family = ImportFamily {
+            husband,
+            wife,
+            children,
+            address,
+            house_name,
+        };
+        if family.members().len() < 2 {
+            report.warn(
+                node.line,
+                "Family has less than two known members and was skipped.",
+            );
+            continue;
+        }
+        families.push(family);
+    }
+
+    // Families of people that all exist already are skipped when a household holds them all.
+    let mut created: Vec<ImportFamily> = vec![];
+    for family in families {
+        let existing: Vec<ObjectId> = family
+            .members()
+            .iter()
+            .filter_map(|m| match m {
+                Member::Existing(id) => Some(*id),
+                Member::New(_) => None,
+            })
+            .collect();
+        if existing.len() == family.members().len()
+            && !Household::find_raw_document(
+                client,
+                Some(doc! {"persons": {"$all": existing}}),
+                None,
+            )
+            .await?
+            .is_empty()
+        {
+            report.households_existing += 1;
+            continue;
+        }
+        report.relations += relations(&family).len();
+        created.push(family);
+    }
+    report.households_created = created.len();
+    if dry_run || !report.errors.is_empty() {
+        return Ok(report);
+    }
+
+    let mut ids: Vec<ObjectId> = vec![];
+    for p in people {
+        let id = Person::insert_datum(client, &PersonImpl::from(p), None)
+            .await?
+            .as_object_id()
+            .ok_or(COSIError::msg("Unable to insert person."))?;
+        ids.push(id);
+    }
+    let id_of = |m: Member| match m {
+        Member::New(idx) => ids[idx],
+        Member::Existing(id) => id,
+    };
+    for family in created {
+        let address = Address::find_or_insert(client, &family.address).await?;
+        let persons: Vec<ObjectId> = family.members().into_iter().map(id_of).collect();
+        let mut relations: Vec<Bson> = vec![];
+        for (a, b, relation) in self::relations(&family) {
+            relations.push(to_bson(&HouseRelation {
+                person_a: OID(id_of(a)),
+                person_b: OID(id_of(b)),
+                relation,
+            })?);
+        }
+        let data: Document = doc! {
+            "house_name": family.house_name,
+            "address": address,
+            "persons": persons,
+            "relations": relations,
+        };
+        for result in Household::insert_data(client, vec![data]).await? {
+            result?;
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,635 @@
+// iCalendar (RFC 5545) feeds of events and import of .ics files.
+// Events have no time zone and are written as floating times, shown by calendar apps in their own
+// zone. Repeating events follow cosi_db::recurrence: reoccuring and freq are the FREQ and INTERVAL
+// of the RRULE, meeting_days its BYDAY and the end of the series its UNTIL. Cancelled occurrences
+// are listed as EXDATE and moved ones as their own VEVENT with a RECURRENCE-ID.
+use std::collections::HashMap;
+
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use mongodb::bson::{doc, from_document, oid::ObjectId, Document};
+use mongodb::options::FindOptions;
+use mongodb::Client;
+use serde::Serialize;
+
+use crate::cosi_db::errors::{COSIError, COSIResult};
+use crate::cosi_db::model::common::{COSICollection, COSIForm};
+use crate::cosi_db::model::event::{
+    Days, Event, EventException, EventImpl, EventRegistration, Reoccurring,
+};
+use crate::cosi_db::recurrence::{
+    self, is_scheduled, moved_to, occurrence_end, repeats, series_end,
+};
+use crate::cosi_db::vcard::{escape, fold, parse_property, unescape, unfold, Property};
+
+// UIDs of exported events, followed by the object id of the event.
+pub const UID_PREFIX: &str = "urn:cosi-db:event:";
+const PRODID: &str = "-//COSI//cosi-db//EN";
+const DATETIME_FORMAT: &str = "%Y%m%dT%H%M%S";
+const DATE_FORMAT: &str = "%Y%m%d";
+
+const DAYS: [Days; 7] = [
+    Days::M,
+    Days::Tu,
+    Days::W,
+    Days::Th,
+    Days::F,
+    Days::Sa,
+    Days::Su,
+];
+
+fn day_code(day: Days) -> &'static str {
+    match day {
+        Days::M => "MO",
+        Days::Tu => "TU",
+        Days::W => "WE",
+        Days::Th => "TH",
+        Days::F => "FR",
+        Days::Sa => "SA",
+        Days::Su => "SU",
+    }
+}
+
+// Ordinals such as 1SU or -1FR are dropped.
+fn parse_day(code: &str) -> Option<Days> {
+    let code = code.trim_start_matches(|c: char| c.is_ascii_digit() || c == '+' || c == '-');
+    DAYS.iter()
+        .find(|d| code.eq_ignore_ascii_case(day_code(**d)))
+        .copied()
+}
+
+fn rrule(event: &Event) -> Option<String> {
+    let freq = match event.reoccuring {
+        Some(Reoccurring::Days) => "DAILY",
+        Some(Reoccurring::Weeks) => "WEEKLY",
+        Some(Reoccurring::Months) => "MONTHLY",
+        None if repeats(event) => "WEEKLY",
+        None => return None,
+    };
+    let mut rule = format!("FREQ={}", freq);
+    if let Some(interval) = event.freq.filter(|f| *f > 1) {
+        rule.push_str(&format!(";INTERVAL={}", interval));
+    }
+    if !event.meeting_days.is_empty() && !matches!(event.reoccuring, Some(Reoccurring::Months)) {
+        let days: Vec<&str> = event.meeting_days.iter().map(|d| day_code(*d)).collect();
+        rule.push_str(&format!(";BYDAY={}", days.join(",")));
+    }
+    if let Some(until) = series_end(event) {
+        rule.push_str(&format!(";UNTIL={}", until.format(DATETIME_FORMAT)));
+    }
+    Some(rule)
+}
+
+fn parse_stored(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, recurrence::DATETIME_FORMAT).ok()
+}
+
+fn time(tag: &str, time: NaiveDateTime, out: &mut String) {
+    fold(&format!("{}:{}", tag, time.format(DATETIME_FORMAT)), out);
+}
+
+fn render_event(id: &ObjectId, event: &Event, stamp: &str, out: &mut String) {
+    let uid = format!("UID:{}{}", UID_PREFIX, id.to_hex());
+    fold("BEGIN:VEVENT", out);
+    fold(&uid, out);
+    fold(&format!("DTSTAMP:{}", stamp), out);
+    time("DTSTART", event.start_datetime, out);
+    if let Some(end) = occurrence_end(event) {
+        time("DTEND", end, out);
+    }
+    let rule = rrule(event);
+    if let Some(rule) = &rule {
+        fold(&format!("RRULE:{}", rule), out);
+        for e in event.exceptions.iter().filter(|e| e.cancelled) {
+            if let Some(occurrence) = parse_stored(&e.occurrence) {
+                time("EXDATE", occurrence, out);
+            }
+        }
+    }
+    fold(&format!("SUMMARY:{}", escape(&event.name)), out);
+    fold("END:VEVENT", out);
+    if rule.is_none() {
+        return;
+    }
+
+    let moved = event.exceptions.iter().filter_map(|e| moved_to(event, e));
+    for (original, start, end) in moved.filter(|(o, _, _)| is_scheduled(event, *o)) {
+        fold("BEGIN:VEVENT", out);
+        fold(&uid, out);
+        fold(&format!("DTSTAMP:{}", stamp), out);
+        time("RECURRENCE-ID", original, out);
+        time("DTSTART", start, out);
+        if let Some(end) = end {
+            time("DTEND", end, out);
+        }
+        fold(&format!("SUMMARY:{}", escape(&event.name)), out);
+        fold("END:VEVENT", out);
+    }
+}
+
+// Calendar named after the feed.
+pub fn render(name: &str, events: &[(ObjectId, Event)]) -> String {
+    let stamp = format!("{}Z", Utc::now().format(DATETIME_FORMAT));
+    let mut out = String::new();
+    fold("BEGIN:VCALENDAR", &mut out);
+    fold("VERSION:2.0", &mut out);
+    fold(&format!("PRODID:{}", PRODID), &mut out);
+    fold("CALSCALE:GREGORIAN", &mut out);
+    fold("METHOD:PUBLISH", &mut out);
+    fold(&format!("X-WR-CALNAME:{}", escape(name)), &mut out);
+    for (id, event) in events {
+        render_event(id, event, &stamp, &mut out);
+    }
+    fold("END:VCALENDAR", &mut out);
+    out
+}
+
+// Events matching the filter, by start.
+pub async fn find_events(
+    client: &Client,
+    filter: Option<Document>,
+) -> COSIResult<Vec<(ObjectId, Event)>> {
+    let options = FindOptions::builder()
+        .sort(doc! {"start_datetime": 1, "_id": 1})
+        .build();
+    let mut result = vec![];
+    for d in Event::find_raw_document(client, filter, Some(options)).await? {
+        let id = d.get_object_id("_id")?;
+        let event: EventImpl = from_document(d)?;
+        result.push((id, Event::from(event)));
+    }
+    Ok(result)
+}
+
+// Events the group is registered to.
+pub async fn group_events(client: &Client, group: ObjectId) -> COSIResult<Vec<(ObjectId, Event)>> {
+    let ids: Vec<ObjectId> =
+        EventRegistration::find_raw_document(client, Some(doc! {"group": group}), None)
+            .await?
+            .iter()
+            .filter_map(|r| r.get_object_id("event").ok())
+            .collect();
+    find_events(client, Some(doc! {"_id": {"$in": ids}})).await
+}
+
+#[derive(Clone, Debug)]
+pub struct ICalEvent {
+    pub uid: Option<String>,
+    pub event: Event,
+    // Start as scheduled of a moved occurrence.
+    pub recurrence_id: Option<NaiveDateTime>,
+}
+
+fn param<'a>(property: &'a Property, key: &str) -> Option<&'a str> {
+    property
+        .params
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+// UTC times are moved to the zone of the server, times with a TZID are kept as written.
+fn parse_time(value: &str) -> COSIResult<NaiveDateTime> {
+    let value = value.trim();
+    if value.len() == 8 {
+        return Ok(NaiveDate::parse_from_str(value, DATE_FORMAT)?.and_hms(0, 0, 0));
+    }
+    match value.strip_suffix('Z') {
+        Some(utc) => {
+            let time = NaiveDateTime::parse_from_str(utc, DATETIME_FORMAT)?;
+            Ok(Utc
+                .from_utc_datetime(&time)
+                .with_timezone(&Local)
+                .naive_local())
+        }
+        None => Ok(NaiveDateTime::parse_from_str(value, DATETIME_FORMAT)?),
+    }
+}
+
+// DTSTART or DTEND, and whether it is a whole day.
+fn parse_datetime(property: &Property) -> COSIResult<(NaiveDateTime, bool)> {
+    let value = property.value.trim();
+    let date = param(property, "VALUE") == Some("DATE") || value.len() == 8;
+    let time = parse_time(value)
+        .map_err(|_| COSIError::msg(format!("Invalid {} {}.", property.name, value)))?;
+    Ok((time, date))
+}
+
+// Durations such as PT1H30M, P1D or P2W.
+fn parse_duration(value: &str) -> COSIResult<Duration> {
+    let err = || COSIError::msg(format!("Invalid DURATION {}.", value));
+    let rest = value.trim().trim_start_matches('+');
+    let rest = rest.strip_prefix('P').ok_or_else(err)?;
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    for c in rest.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        if c == 'T' {
+            continue;
+        }
+        let n: i64 = number.parse().map_err(|_| err())?;
+        number.clear();
+        total = total
+            + match c {
+                'W' => Duration::weeks(n),
+                'D' => Duration::days(n),
+                'H' => Duration::hours(n),
+                'M' => Duration::minutes(n),
+                'S' => Duration::seconds(n),
+                _ => return Err(err()),
+            };
+    }
+    if !number.is_empty() {
+        return Err(err());
+    }
+    Ok(total)
+}
+
+fn apply_rrule(rule: &str, event: &mut Event) -> COSIResult<()> {
+    let mut until = None;
+    let mut count: Option<i64> = None;
+    for part in rule.split(';').filter(|p| !p.is_empty()) {
+        let (key, value) = part
+            .split_once('=')
+            .ok_or_else(|| COSIError::msg(format!("Invalid RRULE {}.", rule)))?;
+        match key.to_uppercase().as_str() {
+            "FREQ" => {
+                event.reoccuring = Some(match value.to_uppercase().as_str() {
+                    "DAILY" => Reoccurring::Days,
+                    "WEEKLY" => Reoccurring::Weeks,
+                    "MONTHLY" => Reoccurring::Months,
+                    _ => {
+                        return Err(COSIError::msg(format!(
+                            "Events repeating {} cannot be stored.",
+                            value
+                        )))
+                    }
+                })
+            }
+            "INTERVAL" => {
+                event.freq =
+                    Some(value.parse().map_err(|_| {
+                        COSIError::msg(format!("Interval {} cannot be stored.", value))
+                    })?)
+            }
+            "BYDAY" => {
+                for code in value.split(',') {
+                    let day = parse_day(code)
+                        .ok_or_else(|| COSIError::msg(format!("Invalid day {}.", code)))?;
+                    event.meeting_days.push(day);
+                }
+            }
+            "UNTIL" => {
+                until = Some(
+                    parse_time(value)
+                        .map_err(|_| COSIError::msg(format!("Invalid UNTIL {}.", value)))?,
+                )
+            }
+            "COUNT" => {
+                count = Some(
+                    value
+                        .parse()
+                        .map_err(|_| COSIError::msg(format!("Invalid COUNT {}.", value)))?,
+                )
+            }
+            _ => {}
+        }
+    }
+    if event.reoccuring.is_none() {
+        return Err(COSIError::msg("RRULE has no FREQ."));
+    }
+    // Such as the first Sunday of each month.
+    if matches!(event.reoccuring, Some(Reoccurring::Months)) && !event.meeting_days.is_empty() {
+        return Err(COSIError::msg(
+            "Monthly events on days of the week cannot be stored.",
+        ));
+    }
+
+    // A count of daily or weekly occurrences ends on a known day.
+    if let Some(count) = count.filter(|c| *c > 0) {
+        let step = i64::from(event.freq.unwrap_or(1).max(1)) * (count - 1);
+        until = match event.reoccuring {
+            Some(Reoccurring::Days) if event.meeting_days.is_empty() => {
+                Some(event.start_datetime + Duration::days(step))
+            }
+            Some(Reoccurring::Weeks) if event.meeting_days.is_empty() => {
+                Some(event.start_datetime + Duration::weeks(step))
+            }
+            _ => {
+                return Err(COSIError::msg(
+                    "Only daily or weekly events without BYDAY can repeat a COUNT of times.",
+                ))
+            }
+        };
+    }
+
+    let end = event.end_datetime;
+    if end.is_some_and(|e| e.date() > event.start_datetime.date()) {
+        return Err(COSIError::msg(
+            "Repeating events lasting more than a day cannot be stored.",
+        ));
+    }
+    if let Some(until) = until {
+        event.end_datetime = Some(match end {
+            Some(end) => until.date().and_time(end.time()),
+            None => until,
+        });
+    }
+    Ok(())
+}
+
+fn parse_event(lines: &[&str]) -> COSIResult<ICalEvent> {
+    let mut uid = None;
+    let mut name = String::new();
+    let mut start = None;
+    let mut end = None;
+    let mut duration = None;
+    let mut rule = None;
+    let mut recurrence_id = None;
+    let mut exdates = vec![];
+    for line in lines {
+        let property = match parse_property(line) {
+            Some(p) => p,
+            None => continue,
+        };
+        match property.name.as_str() {
+            "UID" => uid = Some(unescape(&property.value).trim().to_string()),
+            "SUMMARY" => name = unescape(&property.value).trim().to_string(),
+            "DTSTART" => start = Some(parse_datetime(&property)?),
+            "DTEND" => end = Some(parse_datetime(&property)?),
+            "DURATION" => duration = Some(parse_duration(&property.value)?),
+            "RRULE" => rule = Some(property.value.trim().to_string()),
+            "RECURRENCE-ID" => recurrence_id = Some(parse_datetime(&property)?.0),
+            "EXDATE" => {
+                for value in property.value.split(',') {
+                    exdates.push(
+                        parse_time(value)
+                            .map_err(|_| COSIError::msg(format!("Invalid EXDATE {}.", value)))?,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+    if name.is_empty() && recurrence_id.is_none() {
+        return Err(COSIError::msg("Event has no SUMMARY."));
+    }
+    let (start, whole_day) = start.ok_or(COSIError::msg("Event has no DTSTART."))?;
+    let end = match (end, duration) {
+        (Some((end, _)), _) => Some(end),
+        (None, Some(duration)) => Some(start + duration),
+        // Whole days last one day.
+        (None, None) if whole_day => Some(start + Duration::days(1)),
+        (None, None) => None,
+    };
+    // Whole days end at the start of the next day, kept as the last second of the day before.
+    let end = end.filter(|end| *end > start).map(|end| {
+        if whole_day {
+            end - Duration::seconds(1)
+        } else {
+            end
+        }
+    });
+
+    let mut event = Event {
+        name,
+        meeting_days: vec![],
+        start_datetime: start,
+        end_datetime: end,
+        freq: None,
+        reoccuring: None,
+        exceptions: vec![],
+        capacity: None,
+        waitlist: None,
+        rooms: vec![],
+    };
+    if recurrence_id.is_some() {
+        return Ok(ICalEvent {
+            uid,
+            event,
+            recurrence_id,
+        });
+    }
+    if let Some(rule) = rule {
+        apply_rrule(&rule, &mut event)?;
+    }
+    for date in exdates {
+        event.exceptions.push(EventException {
+            occurrence: date.format(recurrence::DATETIME_FORMAT).to_string(),
+            cancelled: true,
+            start_datetime: None,
+            end_datetime: None,
+        });
+    }
+    EventImpl::from(event.clone()).sanitize_insert()?;
+    Ok(ICalEvent {
+        uid,
+        event,
+        recurrence_id: None,
+    })
+}
+
+// Moved occurrences become exceptions of the event with the same UID.
+fn merge_moved(mut events: Vec<COSIResult<ICalEvent>>) -> Vec<COSIResult<ICalEvent>> {
+    let mut masters: HashMap<String, usize> = HashMap::new();
+    for (idx, parsed) in events.iter().enumerate() {
+        if let Ok(ICalEvent {
+            uid: Some(uid),
+            recurrence_id: None,
+            ..
+        }) = parsed
+        {
+            masters.entry(uid.clone()).or_insert(idx);
+        }
+    }
+    let format = |t: NaiveDateTime| t.format(recurrence::DATETIME_FORMAT).to_string();
+    let mut merged = vec![];
+    let mut orphans = 0;
+    for idx in 0..events.len() {
+        let (master, exception) = match &events[idx] {
+            Ok(ICalEvent {
+                uid,
+                event,
+                recurrence_id: Some(original),
+            }) => (
+                uid.as_ref().and_then(|u| masters.get(u)).copied(),
+                EventException {
+                    occurrence: format(*original),
+                    cancelled: false,
+                    start_datetime: Some(format(event.start_datetime)),
+                    end_datetime: event.end_datetime.map(format),
+                },
+            ),
+            _ => continue,
+        };
+        merged.push(idx);
+        match master.map(|m| &mut events[m]) {
+            Some(Ok(master)) => master.event.exceptions.push(exception),
+            _ => orphans += 1,
+        }
+    }
+    let mut result = vec![];
+    for (idx, parsed) in events.into_iter().enumerate() {
+        if !merged.contains(&idx) {
+            result.push(parsed.and_then(|e| {
+                EventImpl::from(e.event.clone()).sanitize_insert()?;
+                Ok(e)
+            }));
+        }
+    }
+    for _ in 0..orphans {
+        result.push(Err(COSIError::msg(
+            "Moved occurrence of an event missing from the file.",
+        )));
+    }
+    result
+}
+
+// Events of an .ics file, in order. Alarms and other components inside events are skipped.
+pub fn parse(text: &str) -> Vec<COSIResult<ICalEvent>> {
+    let text = unfold(text);
+    let mut result = vec![];
+    let mut current: Option<Vec<&str>> = None;
+    let mut nested = 0;
+    for line in text.lines().map(|l| l.trim_end()) {
+        let upper = line.to_uppercase();
+        if upper == "BEGIN:VEVENT" {
+            current = Some(vec![]);
+            nested = 0;
+        } else if upper == "END:VEVENT" {
+            if let Some(lines) = current.take() {
+                result.push(parse_event(&lines));
+            }
+        } else if let Some(lines) = current.as_mut() {
+            if upper.starts_with("BEGIN:") {
+                nested += 1;
+            } else if upper.starts_with("END:") {
+                nested -= 1;
+            } else if nested == 0 {
+                lines.push(line);
+            }
+        }
+    }
+    merge_moved(result)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventStatus {
+    Created,
+    Duplicate,
+    Invalid,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct EventResult {
+    // Position in the file, starting at 1.
+    pub event: usize,
+    pub name: String,
+    pub status: EventStatus,
+    // The created event, or the existing one for duplicates.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub _id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub err: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ICalReport {
+    pub dry_run: bool,
+    pub total: usize,
+    pub created: usize,
+    pub duplicates: usize,
+    pub invalid: usize,
+    pub events: Vec<EventResult>,
+}
+
+// The exported event of the UID, or one with the same name and start.
+async fn find_duplicate(client: &Client, event: &ICalEvent) -> COSIResult<Option<ObjectId>> {
+    let uid = event.uid.as_deref().unwrap_or("");
+    if let Some(oid) = uid
+        .strip_prefix(UID_PREFIX)
+        .and_then(|oid| ObjectId::parse_str(oid).ok())
+    {
+        if !Event::find_raw_document(client, Some(doc! {"_id": oid}), None)
+            .await?
+            .is_empty()
+        {
+            return Ok(Some(oid));
+        }
+    }
+    let filter = doc! {
+        "name": &event.event.name,
+        "start_datetime": event.event.start_datetime.format(recurrence::DATETIME_FORMAT).to_string(),
+    };
+    match Event::find_raw_document(client, Some(filter), None)
+        .await?
+        .first()
+    {
+        Some(d) => Ok(Some(d.get_object_id("_id")?)),
+        None => Ok(None),
+    }
+}
+
+fn same_event(a: &Event, b: &Event) -> bool {
+    a.name == b.name && a.start_datetime == b.start_datetime
+}
+
+// Nothing is written on a dry run or when an event is invalid.
+pub async fn import(client: &Client, text: &str, dry_run: bool) -> COSIResult<ICalReport> {
+    let mut report = ICalReport {
+        dry_run,
+        ..Default::default()
+    };
+    let mut created: Vec<(usize, Event)> = vec![];
+    for (idx, parsed) in parse(text).into_iter().enumerate() {
+        let mut result = EventResult {
+            event: idx + 1,
+            name: String::new(),
+            status: EventStatus::Created,
+            _id: None,
+            err: None,
+        };
+        match parsed {
+            Err(err) => {
+                result.status = EventStatus::Invalid;
+                result.err = Some(err.to_string());
+            }
+            Ok(parsed) => {
+                result.name = parsed.event.name.clone();
+                if let Some(oid) = find_duplicate(client, &parsed).await? {
+                    result.status = EventStatus::Duplicate;
+                    result._id = Some(oid.to_hex());
+                } else if let Some((first, _)) =
+                    created.iter().find(|(_, e)| same_event(&parsed.event, e))
+                {
+                    result.status = EventStatus::Duplicate;
+                    result.err = Some(format!("Same event as event {}.", first + 1));
+                } else {
+                    created.push((report.events.len(), parsed.event));
+                }
+            }
+        }
+        report.events.push(result);
+    }
+
+    let count = |status| report.events.iter().filter(|e| e.status == status).count();
+    report.total = report.events.len();
+    report.created = count(EventStatus::Created);
+    report.duplicates = count(EventStatus::Duplicate);
+    report.invalid = count(EventStatus::Invalid);
+    if dry_run || report.invalid > 0 {
+        return Ok(report);
+    }
+
+    for (idx, event) in created {
+        let id = Event::insert_datum(client, &EventImpl::from(event), None)
+            .await?
+            .as_object_id()
+            .ok_or(COSIError::msg("Unable to insert event."))?;
+        report.events[idx]._id = Some(id.to_hex());
+    }
+    Ok(report)
+}
@@ -0,0 +1,407 @@
+// CSV import for tables.
+// Columns are mapped to model fields by name, "Birth Date" matching birth_date, unless the
+// mapping says otherwise. Cells are converted following the schema of the storage struct:
+// * Empty cells become null, "" or [] depending on the field.
+// * Enums match case-insensitively or on a unique prefix, "F" is Female.
+// * Lists are separated by ";".
+// * Formatted fields such as dates also accept MM/DD/YYYY.
+// * References take an object id, or the natural key of the referenced table when it is a single
+//   field, such as the external_id of a person.
+// Rows are validated the same way as inserts and are written only when every row is valid, in
+// one transaction unless the import is not atomic.
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDate;
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::error::ErrorKind;
+use mongodb::options::FindOneOptions;
+use mongodb::Client;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::cosi_db::changes::{self, ChangeOp};
+use crate::cosi_db::errors::{COSIError, COSIResult, Refused};
+use crate::cosi_db::model::common::{COSICollection, COSIData, COSIForm, COSISchema, Reference};
+
+// Rows returned with a dry run.
+pub const PREVIEW_ROWS: usize = 20;
+// Extra date layouts accepted next to the stored format.
+const DATE_FORMATS: [&str; 3] = ["%m/%d/%Y", "%Y/%m/%d", "%m-%d-%Y"];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RowError {
+    // Line in the file, the header being line 1.
+    pub row: u64,
+    pub err: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub table: String,
+    pub dry_run: bool,
+    pub columns: Vec<String>,
+    pub fields: Vec<String>,
+    // Column to field. Columns missing here are ignored.
+    pub mapping: BTreeMap<String, String>,
+    pub total_rows: usize,
+    pub valid_rows: usize,
+    pub errors: Vec<RowError>,
+    pub preview: Vec<Document>,
+    pub inserted: usize,
+    pub updated: usize,
+}
+
+// Exports of older systems are often Latin-1 rather than UTF-8.
+pub fn decode(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes)
+        .unwrap_or_else(|err| err.into_bytes().iter().map(|&b| b as char).collect())
+}
+
+fn field_key(column: &str) -> String {
+    column.trim().to_lowercase().replace([' ', '-'], "_")
+}
+
+// An empty field in the mapping ignores the column.
+pub fn resolve_mapping(
+    columns: &[String],
+    fields: &[String],
+    mapping: &HashMap<String, String>,
+) -> COSIResult<BTreeMap<String, String>> {
+    let mut result = BTreeMap::new();
+    for (column, field) in mapping {
+        if !columns.contains(column) {
+            return Err(COSIError::msg(format!("Unknown column {}.", column)));
+        }
+        if !field.is_empty() && !fields.contains(field) {
+            return Err(COSIError::msg(format!("Unknown field {}.", field)));
+        }
+    }
+    for column in columns {
+        let field = match mapping.get(column) {
+            Some(f) => f.clone(),
+            None => field_key(column),
+        };
+        if !fields.contains(&field) {
+            continue;
+        }
+        if result.values().any(|f| *f == field) {
+            return Err(COSIError::msg(format!("Field {} is mapped twice.", field)));
+        }
+        result.insert(column.clone(), field);
+    }
+    Ok(result)
+}
+
+pub fn normalize_date(value: &str, format: &str) -> COSIResult<String> {
+    for f in std::iter::once(format).chain(DATE_FORMATS) {
+        if let Ok(date) = NaiveDate::parse_from_str(value, f) {
+            return Ok(date.format(format).to_string());
+        }
+    }
+    Err(COSIError::msg(format!(
+        "Unrecognized date {}, expected MM/DD/YYYY or {}.",
+        value, format
+    )))
+}
+
+// Looks through the wrappers of nullable and described references.
+fn unwrap_schema(schema: &Value) -> (&Value, bool) {
+    let nullable = schema["nullable"] == true;
+    match schema["allOf"].get(0) {
+        Some(inner) => (inner, nullable),
+        None => (schema, nullable),
+    }
+}
+
+fn convert_enum(cell: &str, variants: &[Value]) -> COSIResult<Value> {
+    let names: Vec<&str> = variants.iter().filter_map(|v| v.as_str()).collect();
+    let lower = cell.to_lowercase();
+    if let Some(v) = names.iter().find(|v| v.to_lowercase() == lower) {
+        return Ok(Value::from(*v));
+    }
+    let prefixed: Vec<&&str> = names
+        .iter()
+        .filter(|v| v.to_lowercase().starts_with(&lower))
+        .collect();
+    match prefixed.as_slice() {
+        [v] => Ok(Value::from(**v)),
+        _ => Err(COSIError::msg(format!(
+            "Expected one of {}.",
+            names.join(", ")
+        ))),
+    }
+}
+
+pub fn convert_cell(cell: &str, schema: &Value) -> COSIResult<Value> {
+    let (schema, nullable) = unwrap_schema(schema);
+    let kind = schema["type"].as_str().unwrap_or("");
+    if cell.is_empty() {
+        return match kind {
+            _ if nullable => Ok(Value::Null),
+            "array" => Ok(Value::Array(vec![])),
+            "string" if schema.get("enum").is_none() => Ok(Value::from("")),
+            _ => Err(COSIError::msg("Value is required.")),
+        };
+    }
+
+    match kind {
+        "string" => match schema["enum"].as_array() {
+            Some(variants) => convert_enum(cell, variants),
+            None => Ok(Value::from(cell)),
+        },
+        "integer" => cell
+            .parse::<i64>()
+            .map(Value::from)
+            .map_err(|_| COSIError::msg("Expected a whole number.")),
+        "number" => cell
+            .parse::<f64>()
+            .map(Value::from)
+            .map_err(|_| COSIError::msg("Expected a number.")),
+        "boolean" => match cell.to_lowercase().as_str() {
+            "true" | "yes" | "y" | "1" => Ok(Value::from(true)),
+            "false" | "no" | "n" | "0" => Ok(Value::from(false)),
+            _ => Err(COSIError::msg("Expected yes or no.")),
+        },
+        "array" => cell
+            .split(';')
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(|v| convert_cell(v, &schema["items"]))
+            .collect::<COSIResult<Vec<Value>>>()
+            .map(Value::Array),
+        "object" => {
+            serde_json::from_str(cell).map_err(|_| COSIError::msg("Expected a JSON object."))
+        }
+        // Object ids and anything else are kept as text.
+        _ => Ok(Value::from(cell)),
+    }
+}
+
+async fn resolve_reference(
+    client: &Client,
+    reference: &Reference,
+    value: &str,
+    cache: &mut HashMap<(&'static str, String), ObjectId>,
+) -> COSIResult<Value> {
+    let table = (reference.table)();
+    if let Some(oid) = cache.get(&(table, value.to_string())) {
+        return Ok(Value::from(oid.to_hex()));
+    }
+
+    let query = match (ObjectId::parse_str(value), (reference.natural_key)()) {
+        (Ok(oid), _) => doc! {"_id": oid},
+        (Err(_), Some(keys)) if keys.len() == 1 => {
+            let mut query = Document::new();
+            query.insert(keys[0].clone(), value);
+            query
+        }
+        _ => {
+            return Err(COSIError::msg(format!(
+                "Expected the object id of a {}.",
+                table
+            )))
+        }
+    };
+    let options = FindOneOptions::builder()
+        .projection(doc! {"_id": 1})
+        .build();
+    let found = client
+        .database("cosi_db")
+        .collection::<Document>(table)
+        .find_one(query, options)
+        .await?;
+    let oid = found
+        .and_then(|d| d.get_object_id("_id").ok())
+        .ok_or(COSIError::msg(format!("No {} found for {}.", table, value)))?;
+    cache.insert((table, value.to_string()), oid);
+    Ok(Value::from(oid.to_hex()))
+}
+
+// Converts and validates every row. Returns the report and the documents of the valid rows.
+pub async fn prepare<T, I, F>(
+    client: &Client,
+    text: &str,
+    mapping: &HashMap<String, String>,
+) -> COSIResult<(ImportReport, Vec<Document>)>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm + COSISchema,
+    F: COSIData + Default + COSIForm,
+{
+    let schema = I::schema();
+    let properties = schema["properties"].as_object().unwrap();
+    let fields: Vec<String> = properties.keys().cloned().collect();
+    let references = T::references();
+    let formats = T::formats();
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let columns: Vec<String> = reader.headers()?.iter().map(|h| h.to_string()).collect();
+    let mapping = resolve_mapping(&columns, &fields, mapping)?;
+    let mapped: Vec<(usize, &String)> = columns
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, c)| mapping.get(c).map(|f| (idx, f)))
+        .collect();
+
+    let mut report = ImportReport {
+        table: T::get_table_name(),
+        columns: columns.clone(),
+        fields: fields.clone(),
+        mapping: mapping.clone(),
+        ..Default::default()
+    };
+    let mut rows: Vec<Document> = vec![];
+    let mut cache = HashMap::new();
+    for (idx, record) in reader.records().enumerate() {
+        let line = idx as u64 + 2;
+        let record = match record {
+            Ok(r) => r,
+            Err(err) => {
+                report.errors.push(RowError {
+                    row: line,
+                    err: err.to_string(),
+                });
+                continue;
+            }
+        };
+        let row = record.position().map(|p| p.line()).unwrap_or(line);
+        report.total_rows += 1;
+
+        let mut obj = Map::new();
+        let mut row_err: Option<String> = None;
+        for (col, field) in &mapped {
+            let cell = record.get(*col).unwrap_or("");
+            let converted = match formats.iter().find(|(f, _)| f == field) {
+                Some((_, fmt)) if !cell.is_empty() => normalize_date(cell, fmt).map(Value::from),
+                _ => convert_cell(cell, &properties[field.as_str()]),
+            };
+            let converted = match (converted, references.iter().find(|r| r.field == *field)) {
+                (Ok(Value::String(v)), Some(r)) => {
+                    resolve_reference(client, r, &v, &mut cache).await
+                }
+                (Ok(Value::Array(values)), Some(r)) => {
+                    let mut ids = vec![];
+                    for v in values {
+                        let v = v.as_str().unwrap_or("").to_string();
+                        match resolve_reference(client, r, &v, &mut cache).await {
+                            Ok(id) => ids.push(id),
+                            Err(err) => keep_first_err(&mut row_err, &columns[*col], err),
+                        }
+                    }
+                    Ok(Value::Array(ids))
+                }
+                (converted, _) => converted,
+            };
+            match converted {
+                Ok(v) => {
+                    obj.insert(field.to_string(), v);
+                }
+                Err(err) => keep_first_err(&mut row_err, &columns[*col], err),
+            }
+        }
+        // Fields without a column take their empty value when they have one.
+        for (field, field_schema) in properties {
+            if !obj.contains_key(field) {
+                if let Ok(v) = convert_cell("", field_schema) {
+                    obj.insert(field.clone(), v);
+                }
+            }
+        }
+
+        let result = match row_err {
+            Some(err) => Err(COSIError::msg(err)),
+            None => serde_json::from_value::<I>(Value::Object(obj))
+                .map_err(COSIError::from)
+                .and_then(|data| T::convert_form_insert(data)),
+        };
+        match result {
+            Ok(d) => rows.push(d),
+            Err(err) => report.errors.push(RowError {
+                row,
+                err: err.to_string(),
+            }),
+        }
+    }
+
+    report.valid_rows = rows.len();
+    report.preview = rows.iter().take(PREVIEW_ROWS).cloned().collect();
+    Ok((report, rows))
+}
+
+// Keeps the first error of a row.
+fn keep_first_err(row_err: &mut Option<String>, column: &str, err: COSIError) {
+    if row_err.is_none() {
+        *row_err = Some(format!("{}: {}", column, err));
+    }
+}
+
+// Standalone servers reject transactions with IllegalOperation, or the driver refuses to start
+// them once it knows the deployment.
+fn transactions_unsupported(err: &mongodb::error::Error) -> bool {
+    match *err.kind {
+        ErrorKind::Command(ref c) => c.code == 20,
+        ErrorKind::Transaction { ref message, .. } => message.contains("not supported"),
+        _ => false,
+    }
+}
+
+// Writes every row in one transaction, through the model so its checks and change events
+// apply. Without atomic, rows are written one by one, which leaves the rows written before a
+// failure in place.
+pub async fn commit<T, I, F>(
+    client: &Client,
+    rows: Vec<Document>,
+    atomic: bool,
+) -> COSIResult<(usize, usize)>
+where
+    T: COSICollection<'static, T, I, F> + COSIData + From<I>,
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
+{
+    let writes = if atomic {
+        let mut session = client.start_session(None).await?;
+        let probe = match session.start_transaction(None).await {
+            Ok(()) => T::get_raw_document(client)
+                .await
+                .find_one_with_session(doc! {}, None, &mut session)
+                .await
+                .map(|_| ()),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = probe {
+            let _ = session.abort_transaction().await;
+            if transactions_unsupported(&err) {
+                return Err(Refused(
+                    "The database does not support transactions, import with atomic=false."
+                        .to_string(),
+                )
+                .into());
+            }
+            return Err(err.into());
+        }
+        match T::write_data(client, rows, Some(&mut session)).await {
+            Ok(writes) => {
+                session.commit_transaction().await?;
+                writes
+            }
+            Err(err) => {
+                let _ = session.abort_transaction().await;
+                return Err(err);
+            }
+        }
+    } else {
+        T::write_data(client, rows, None).await?
+    };
+
+    let inserted = writes
+        .iter()
+        .filter(|(op, _)| *op == ChangeOp::Insert)
+        .count();
+    for (op, id) in &writes {
+        changes::publish(T::table_name(), *op, Some(id));
+    }
+    Ok((inserted, writes.len() - inserted))
+}
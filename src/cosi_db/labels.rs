@@ -0,0 +1,572 @@
+// Printable label sheets, see cosi_db::pdf.
+// Sheets follow Avery style layouts: a grid of equal labels, given by the size of the labels,
+// the margins to the first one and the pitch, the distance from one label to the next. Presets
+// cover common Avery products and custom layouts may be given in points (1/72 inch).
+use std::collections::HashMap;
+
+use mongodb::bson::{doc, from_document, oid::ObjectId, Document};
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::cosi_db::errors::{COSIError, COSIResult};
+use crate::cosi_db::model::address::Address;
+use crate::cosi_db::model::common::COSICollection;
+use crate::cosi_db::model::household::{Household, HouseholdImpl};
+use crate::cosi_db::model::person::Person;
+use crate::cosi_db::pdf::{text_width, Font, Pdf};
+
+pub const MAX_LABELS: usize = 5000;
+const PADDING: f64 = 6.0;
+const MIN_FONT_SIZE: f64 = 6.0;
+const LETTER: (f64, f64) = (612.0, 792.0);
+const A4: (f64, f64) = (595.28, 841.89);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Layout {
+    pub name: String,
+    pub page_width: f64,
+    pub page_height: f64,
+    pub columns: u32,
+    pub rows: u32,
+    pub label_width: f64,
+    pub label_height: f64,
+    // Margins to the first label.
+    pub top: f64,
+    pub left: f64,
+    // Distances from a label to the next one.
+    pub h_pitch: f64,
+    pub v_pitch: f64,
+}
+
+impl Layout {
+    pub fn validate(&self) -> COSIResult<()> {
+        let sizes = [
+            self.page_width,
+            self.page_height,
+            self.label_width,
+            self.label_height,
+        ];
+        if sizes.iter().any(|s| !s.is_finite() || *s <= 0.0) {
+            return Err(COSIError::msg("Page and label sizes must be positive."));
+        }
+        if self.columns == 0 || self.rows == 0 {
+            return Err(COSIError::msg("Layouts need at least one column and row."));
+        }
+        if self.top < 0.0 || self.left < 0.0 {
+            return Err(COSIError::msg("Margins cannot be negative."));
+        }
+        if (self.columns > 1 && self.h_pitch < self.label_width)
+            || (self.rows > 1 && self.v_pitch < self.label_height)
+        {
+            return Err(COSIError::msg("Labels cannot overlap."));
+        }
+        let right = self.left + f64::from(self.columns - 1) * self.h_pitch + self.label_width;
+        let bottom = self.top + f64::from(self.rows - 1) * self.v_pitch + self.label_height;
+        // Rounding of presets given in inches or millimetres.
+        if right > self.page_width + 1.0 || bottom > self.page_height + 1.0 {
+            return Err(COSIError::msg("Labels do not fit on the page."));
+        }
+        Ok(())
+    }
+
+    fn per_page(&self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+}
+
+fn preset(
+    name: &str,
+    page: (f64, f64),
+    grid: (u32, u32),
+    label: (f64, f64),
+    margins: (f64, f64),
+    pitch: (f64, f64),
+) -> Layout {
+    Layout {
+        name: name.to_string(),
+        page_width: page.0,
+        page_height: page.1,
+        columns: grid.0,
+        rows: grid.1,
+        label_width: label.0,
+        label_height: label.1,
+        top: margins.0,
+        left: margins.1,
+        h_pitch: pitch.0,
+        v_pitch: pitch.1,
+    }
+}
+
+pub fn presets() -> Vec<Layout> {
+    let mm = |v: f64| v * 72.0 / 25.4;
+    vec![
+        // Address labels, 1" x 2 5/8".
+        preset(
+            "avery5160",
+            LETTER,
+            (3, 10),
+            (189.0, 72.0),
+            (36.0, 13.5),
+            (198.0, 72.0),
+        ),
+        // Shipping labels, 2" x 4".
+        preset(
+            "avery5163",
+            LETTER,
+            (2, 5),
+            (288.0, 144.0),
+            (36.0, 11.25),
+            (301.5, 144.0),
+        ),
+        // Name badges, 2 1/3" x 3 3/8".
+        preset(
+            "avery5395",
+            LETTER,
+            (2, 4),
+            (243.0, 168.0),
+            (42.5, 49.5),
+            (265.5, 180.0),
+        ),
+        // Address labels on A4, 38.1 x 63.5 mm.
+        preset(
+            "averyL7160",
+            A4,
+            (3, 7),
+            (mm(63.5), mm(38.1)),
+            (mm(15.15), mm(7.25)),
+            (mm(66.04), mm(38.1)),
+        ),
+    ]
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum LabelKind {
+    // Names with nicknames, for people.
+    NameTag,
+    // Names and addresses, for people, households or addresses.
+    Mailing,
+    // Names with their allergy and care notes, for people with notes.
+    Allergy,
+}
+
+impl LabelKind {
+    pub fn default_layout(self) -> &'static str {
+        match self {
+            LabelKind::NameTag => "avery5395",
+            LabelKind::Mailing => "avery5160",
+            LabelKind::Allergy => "avery5163",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum LabelSource {
+    Person,
+    Household,
+    Address,
+}
+
+// A preset name or a custom layout.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum LayoutChoice {
+    Preset(String),
+    Custom(Layout),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct LabelRequest {
+    pub kind: LabelKind,
+    pub source: LabelSource,
+    // Every datum of the source by default.
+    #[serde(default)]
+    pub ids: Vec<String>,
+    pub layout: Option<LayoutChoice>,
+    // Labels already used on the first sheet.
+    #[serde(default)]
+    pub skip: u32,
+    // Draws the edges of the labels, to check the alignment on plain paper.
+    #[serde(default)]
+    pub outline: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Line {
+    text: String,
+    font: Font,
+    size: f64,
+    // Wrapped over the rest of the label instead of cut.
+    wrap: bool,
+}
+
+impl Line {
+    fn new(text: &str, font: Font, size: f64) -> Line {
+        Line {
+            text: text.trim().to_string(),
+            font,
+            size,
+            wrap: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Label {
+    lines: Vec<Line>,
+    centered: bool,
+}
+
+pub fn find_layout(request: &LabelRequest) -> COSIResult<Layout> {
+    let layout = match &request.layout {
+        Some(LayoutChoice::Custom(layout)) => layout.clone(),
+        choice => {
+            let name = match choice {
+                Some(LayoutChoice::Preset(name)) => name.as_str(),
+                _ => request.kind.default_layout(),
+            };
+            presets()
+                .into_iter()
+                .find(|l| l.name.eq_ignore_ascii_case(name))
+                .ok_or(COSIError::msg(format!("Unknown label layout {}.", name)))?
+        }
+    };
+    layout.validate()?;
+    if request.skip as usize >= layout.per_page() {
+        return Err(COSIError::msg(format!(
+            "At most {} labels can be skipped on this layout.",
+            layout.per_page() - 1
+        )));
+    }
+    Ok(layout)
+}
+
+fn full_name(person: &Person) -> String {
+    [&person.first_name, &person.middle_name, &person.last_name]
+        .iter()
+        .filter(|n| !n.trim().is_empty())
+        .map(|n| n.trim())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn address_lines(address: &Address) -> Vec<String> {
+    let mut lines: Vec<String> = [&address.line_one, &address.line_two, &address.line_three]
+        .iter()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect();
+    let mut city = address.city.trim().to_string();
+    if !address.region.trim().is_empty() {
+        if !city.is_empty() {
+            city.push_str(", ");
+        }
+        city.push_str(address.region.trim());
+    }
+    if let Some(postal) = address
+        .postal_code
+        .as_deref()
+        .filter(|p| !p.trim().is_empty())
+    {
+        city = format!("{} {}", city, postal.trim()).trim().to_string();
+    }
+    if !city.is_empty() {
+        lines.push(city);
+    }
+    if let Some(country) = address.country.as_deref().filter(|c| !c.trim().is_empty()) {
+        lines.push(country.trim().to_uppercase());
+    }
+    lines
+}
+
+fn mailing_label(name: &str, address: &Address) -> Label {
+    let mut lines = vec![Line::new(name, Font::Bold, 10.0)];
+    for l in address_lines(address) {
+        lines.push(Line::new(&l, Font::Regular, 9.0));
+    }
+    Label {
+        lines,
+        centered: false,
+    }
+}
+
+fn name_tag(person: &Person) -> Label {
+    // Tags show the name people go by.
+    let first = person
+        .nicks
+        .iter()
+        .find(|n| !n.trim().is_empty())
+        .unwrap_or(&person.first_name);
+    let mut lines = vec![
+        Line::new(first, Font::Bold, 28.0),
+        Line::new(&person.last_name, Font::Regular, 14.0),
+    ];
+    if !person.notes.trim().is_empty() {
+        lines.push(Line::new("ALLERGY / CARE NOTES", Font::Bold, 9.0));
+    }
+    Label {
+        lines,
+        centered: true,
+    }
+}
+
+fn allergy_label(person: &Person) -> Label {
+    Label {
+        lines: vec![
+            Line::new(&full_name(person), Font::Bold, 14.0),
+            Line::new("ALLERGY / CARE NOTES", Font::Bold, 9.0),
+            Line {
+                wrap: true,
+                ..Line::new(&person.notes, Font::Regular, 10.0)
+            },
+        ],
+        centered: false,
+    }
+}
+
+fn parse_ids(ids: &[String]) -> COSIResult<Option<Document>> {
+    if ids.is_empty() {
+        return Ok(None);
+    }
+    let ids: Vec<ObjectId> = ids
+        .iter()
+        .map(|id| {
+            ObjectId::parse_str(id)
+                .map_err(|_| COSIError::msg(format!("Invalid object id {}.", id)))
+        })
+        .collect::<COSIResult<_>>()?;
+    Ok(Some(doc! {"_id": {"$in": ids}}))
+}
+
+async fn people(client: &Client, filter: Option<Document>) -> COSIResult<Vec<(ObjectId, Person)>> {
+    let mut result: Vec<(ObjectId, Person)> = vec![];
+    for p in Person::find_raw_document(client, filter, None).await? {
+        result.push((p.get_object_id("_id")?, from_document(p)?));
+    }
+    result.sort_by(|(_, a), (_, b)| {
+        (a.last_name.to_lowercase(), a.first_name.to_lowercase())
+            .cmp(&(b.last_name.to_lowercase(), b.first_name.to_lowercase()))
+    });
+    Ok(result)
+}
+
+async fn addresses(client: &Client, ids: Vec<ObjectId>) -> COSIResult<HashMap<ObjectId, Address>> {
+    let mut result = HashMap::new();
+    for a in Address::find_raw_document(client, Some(doc! {"_id": {"$in": ids}}), None).await? {
+        result.insert(a.get_object_id("_id")?, from_document(a)?);
+    }
+    Ok(result)
+}
+
+fn postal_code(address: &Address) -> String {
+    address.postal_code.clone().unwrap_or_default()
+}
+
+async fn labels(client: &Client, request: &LabelRequest) -> COSIResult<Vec<Label>> {
+    let filter = parse_ids(&request.ids)?;
+    let result = match (request.kind, request.source) {
+        (LabelKind::NameTag, LabelSource::Person) => people(client, filter)
+            .await?
+            .iter()
+            .map(|(_, p)| name_tag(p))
+            .collect(),
+        (LabelKind::Allergy, LabelSource::Person) => people(client, filter)
+            .await?
+            .iter()
+            .filter(|(_, p)| !p.notes.trim().is_empty())
+            .map(|(_, p)| allergy_label(p))
+            .collect(),
+        (LabelKind::Mailing, LabelSource::Address) => {
+            let mut data: Vec<Address> = Address::find_raw_document(client, filter, None)
+                .await?
+                .into_iter()
+                .map(from_document)
+                .collect::<Result<_, _>>()?;
+            data.sort_by_key(postal_code);
+            data.iter()
+                .map(|a| Label {
+                    lines: address_lines(a)
+                        .iter()
+                        .map(|l| Line::new(l, Font::Regular, 9.0))
+                        .collect(),
+                    centered: false,
+                })
+                .collect()
+        }
+        (LabelKind::Mailing, LabelSource::Household) => {
+            let households: Vec<HouseholdImpl> = Household::find_raw_document(client, filter, None)
+                .await?
+                .into_iter()
+                .map(from_document)
+                .collect::<Result<_, _>>()?;
+            let found = addresses(client, households.iter().map(|h| h.address.0).collect()).await?;
+            let mut data: Vec<(&str, &Address)> = households
+                .iter()
+                .filter_map(|h| Some((h.house_name.as_str(), found.get(&h.address.0)?)))
+                .collect();
+            data.sort_by_key(|(_, a)| postal_code(a));
+            data.iter().map(|(n, a)| mailing_label(n, a)).collect()
+        }
+        // People are sent mail at the address of their first household.
+        (LabelKind::Mailing, LabelSource::Person) => {
+            let persons = people(client, filter).await?;
+            let ids: Vec<ObjectId> = persons.iter().map(|(id, _)| *id).collect();
+            let households: Vec<HouseholdImpl> =
+                Household::find_raw_document(client, Some(doc! {"persons": {"$in": &ids}}), None)
+                    .await?
+                    .into_iter()
+                    .map(from_document)
+                    .collect::<Result<_, _>>()?;
+            let found = addresses(client, households.iter().map(|h| h.address.0).collect()).await?;
+            let mut home: HashMap<ObjectId, &Address> = HashMap::new();
+            for h in &households {
+                for p in &h.persons {
+                    if let Some(a) = found.get(&h.address.0) {
+                        home.entry(p.0).or_insert(a);
+                    }
+                }
+            }
+            let mut data: Vec<(String, &Address)> = persons
+                .iter()
+                .filter_map(|(id, p)| Some((full_name(p), *home.get(id)?)))
+                .collect();
+            data.sort_by_key(|(_, a)| postal_code(a));
+            data.iter().map(|(n, a)| mailing_label(n, a)).collect()
+        }
+        (kind, source) => {
+            return Err(COSIError::msg(format!(
+                "{:?} labels are made for people, not {:?}.",
+                kind, source
+            )))
+        }
+    };
+    Ok(result)
+}
+
+// Cuts the text to the width, ending it with "...".
+fn cut(text: &str, font: Font, size: f64, width: f64) -> String {
+    if text_width(text, font, size) <= width {
+        return text.to_string();
+    }
+    let mut result: String = text.to_string();
+    while !result.is_empty() && text_width(&format!("{}...", result), font, size) > width {
+        result.pop();
+    }
+    format!("{}...", result.trim_end())
+}
+
+fn wrap(text: &str, font: Font, size: f64, width: f64) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", line, word)
+            };
+            if text_width(&candidate, font, size) <= width || line.is_empty() {
+                line = candidate;
+            } else {
+                lines.push(line);
+                line = word.to_string();
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+    lines
+        .into_iter()
+        .map(|l| cut(&l, font, size, width))
+        .collect()
+}
+
+// Lines laid out on the label as (text, font, size), shrinking long ones.
+fn fit(label: &Label, width: f64, height: f64) -> Vec<(String, Font, f64)> {
+    let mut result = vec![];
+    let mut used = 0.0;
+    for line in &label.lines {
+        if line.text.is_empty() {
+            continue;
+        }
+        if line.wrap {
+            let left = ((height - used) / (line.size * 1.2)).floor().max(0.0) as usize;
+            let mut wrapped = wrap(&line.text, line.font, line.size, width);
+            if wrapped.len() > left {
+                wrapped.truncate(left);
+                if let Some(last) = wrapped.last_mut() {
+                    *last = cut(&format!("{}...", last), line.font, line.size, width);
+                }
+            }
+            for w in wrapped {
+                used += line.size * 1.2;
+                result.push((w, line.font, line.size));
+            }
+            continue;
+        }
+        let mut size = line.size;
+        while size > MIN_FONT_SIZE && text_width(&line.text, line.font, size) > width {
+            size -= 0.5;
+        }
+        if used + size * 1.2 > height {
+            break;
+        }
+        used += size * 1.2;
+        result.push((cut(&line.text, line.font, size, width), line.font, size));
+    }
+    result
+}
+
+// Renders the labels of the request as a PDF.
+pub async fn render(client: &Client, request: &LabelRequest) -> COSIResult<Vec<u8>> {
+    let layout = find_layout(request)?;
+    let labels = labels(client, request).await?;
+    if labels.is_empty() {
+        return Err(COSIError::msg("No labels to print."));
+    }
+    if labels.len() > MAX_LABELS {
+        return Err(COSIError::msg(format!(
+            "At most {} labels are printed at once.",
+            MAX_LABELS
+        )));
+    }
+
+    let mut pdf = Pdf::new(layout.page_width, layout.page_height);
+    let per_page = layout.per_page();
+    let width = layout.label_width - 2.0 * PADDING;
+    let height = layout.label_height - 2.0 * PADDING;
+    for (i, label) in labels.iter().enumerate() {
+        // Position of the label on its page.
+        let slot = ((i + request.skip as usize) % per_page) as u32;
+        if i > 0 && slot == 0 {
+            pdf.add_page();
+        }
+        let page = pdf.page();
+        let column = slot % layout.columns;
+        let row = slot / layout.columns;
+        let x = layout.left + f64::from(column) * layout.h_pitch;
+        let y = layout.top + f64::from(row) * layout.v_pitch;
+        if request.outline {
+            page.rect(x, y, layout.label_width, layout.label_height);
+        }
+
+        let lines = fit(label, width, height);
+        let total: f64 = lines.iter().map(|(_, _, size)| size * 1.2).sum();
+        let mut baseline = if label.centered {
+            y + (layout.label_height - total) / 2.0
+        } else {
+            y + PADDING
+        };
+        for (text, font, size) in lines {
+            baseline += size;
+            let left = if label.centered {
+                x + (layout.label_width - text_width(&text, font, size)) / 2.0
+            } else {
+                x + PADDING
+            };
+            page.text(left, baseline, font, size, &text);
+            baseline += size * 0.2;
+        }
+    }
+    Ok(pdf.render())
+}
@@ -1,4 +1,19 @@
+pub mod attendance;
+pub mod backup;
+pub mod booking;
+pub mod changes;
+pub mod checkin;
 pub mod connection;
 pub mod controller;
 pub mod errors;
+pub mod export;
+pub mod gedcom;
+pub mod ical;
+pub mod import;
+pub mod labels;
 pub mod model;
+pub mod pdf;
+pub mod recurrence;
+pub mod registration;
+pub mod vcard;
+pub mod webhooks;
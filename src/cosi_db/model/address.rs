@@ -1,20 +1,20 @@
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
+use mongodb::bson::{oid::ObjectId, to_document};
 use mongodb::Client;
 use rand::{thread_rng, Rng};
 
 use lipsum::lipsum_words_from_seed;
 use std::default::Default;
 
-use rocket::form::FromForm;
-
 // cosi_db
 use super::common::{COSICollection, Generator};
-use crate::cosi_db::errors::COSIResult;
-use crate::cosi_db::model::common::COSIForm;
+use crate::cosi_db::errors::{COSIError, COSIResult};
+use cosi_db_derive::CosiModel;
 
-#[derive(Clone, Debug, Deserialize, FromForm, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, CosiModel)]
+#[cosi(natural_key = "line_one, line_two, line_three, city, region")]
 pub struct Address {
     pub line_one: String,
     pub line_two: String,
@@ -26,22 +26,6 @@ pub struct Address {
     pub country: Option<String>,
 }
 
-#[derive(Clone, Debug, Deserialize, FromForm, Serialize)]
-pub struct AddressOptional {
-    pub line_one: Option<String>,
-    pub line_two: Option<String>,
-    pub line_three: Option<String>,
-    pub city: Option<String>,
-    pub region: Option<String>,
-    pub postal_code: Option<Option<String>>,
-    pub county: Option<Option<String>>,
-    pub country: Option<Option<String>>,
-}
-
-pub type AddressImpl = Address;
-impl COSIForm for AddressImpl {}
-impl COSIForm for AddressOptional {}
-
 impl Default for Address {
     fn default() -> Self {
         Address {
@@ -57,10 +41,20 @@ impl Default for Address {
     }
 }
 
-#[async_trait]
-impl COSICollection<'_, Address, AddressImpl, AddressOptional> for Address {
-    fn get_table_name() -> String {
-        return "address".to_string();
+impl Address {
+    // Id of the stored address with the same natural key. The address is inserted if there is none.
+    pub async fn find_or_insert(client: &Client, address: &Address) -> COSIResult<ObjectId> {
+        let query = Address::natural_key_query(&to_document(address)?)?;
+        if let Some(d) = Address::find_raw_document(client, Some(query), None)
+            .await?
+            .pop()
+        {
+            return Ok(d.get_object_id("_id")?);
+        }
+        Address::insert_datum(client, &AddressImpl::from(address.clone()), None)
+            .await?
+            .as_object_id()
+            .ok_or(COSIError::msg("Unable to insert address."))
     }
 }
 
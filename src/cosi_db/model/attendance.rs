@@ -0,0 +1,70 @@
+// Attendance of event occurrences, see cosi_db::attendance.
+use serde::{Deserialize, Serialize};
+
+use crate::cosi_db::errors::{COSIError, COSIResult};
+use crate::cosi_db::model::common::{COSICollection, COSIForm, COSIModel, OID};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum AttendanceStatus {
+    #[default]
+    Present,
+    Absent,
+    Late,
+}
+
+// One entry per attendee of an occurrence, which is a person, a group or a household.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Attendance {
+    pub event: OID,
+    // Scheduled start of the occurrence, as in the exceptions of the event.
+    pub occurrence: String,
+    pub person: Option<OID>,
+    pub group: Option<OID>,
+    pub household: Option<OID>,
+    pub status: AttendanceStatus,
+    pub timestamp: String,
+}
+
+impl Attendance {
+    pub fn validate(&self) -> COSIResult<()> {
+        let keys = [&self.person, &self.group, &self.household]
+            .iter()
+            .filter(|k| k.is_some())
+            .count();
+        if keys != 1 {
+            return Err(COSIError::msg(
+                "Attendance needs exactly one of person, group or household.",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl COSIForm for Attendance {}
+
+impl COSIModel for Attendance {
+    fn table_name() -> &'static str {
+        "attendance"
+    }
+}
+
+impl COSICollection<'_, Attendance, Attendance, Attendance> for Attendance {}
+
+// Attendees of an occurrence that are not recorded by name.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Headcount {
+    pub event: OID,
+    pub occurrence: String,
+    pub count: u32,
+    pub timestamp: String,
+}
+
+impl COSIForm for Headcount {}
+
+impl COSIModel for Headcount {
+    fn table_name() -> &'static str {
+        "headcount"
+    }
+}
+
+impl COSICollection<'_, Headcount, Headcount, Headcount> for Headcount {}
@@ -2,7 +2,9 @@
 use crate::cosi_db::connection::COSIMongo;
 use crate::cosi_db::controller::auth::Credential;
 use crate::cosi_db::errors::COSIError;
-use crate::cosi_db::model::common::{COSICollection, COSIForm, OID};
+use crate::cosi_db::model::checkin::CheckIn;
+use crate::cosi_db::model::common::{COSICollection, COSIForm, COSIModel, OID};
+use crate::cosi_db::model::webhook::{Webhook, WebhookDelivery};
 
 use rocket::form::FromForm;
 use rocket::http::Status;
@@ -18,9 +20,15 @@ pub struct User {
     pub username: String,
     pub email: String,
     pub token: String,
+    // Administrators may back up and restore the database.
+    #[serde(default)]
+    pub admin: bool,
+    // Given as ?token= by calendar apps subscribing to feeds. Empty until requested.
+    #[serde(default)]
+    pub calendar_token: String,
 }
 
-#[derive(Clone, Debug, FromForm, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, FromForm, Serialize, Deserialize)]
 pub struct UserForm {
     pub username: Option<String>,
     pub email: Option<String>,
@@ -30,14 +38,31 @@ pub struct UserForm {
 impl COSIForm for User {}
 impl COSIForm for UserForm {}
 
-impl COSICollection<'_, User, User, UserForm> for User {
-    fn get_table_name() -> String {
-        return "user".to_string();
+impl User {
+    // Login data, webhook secrets and pickup codes are never shared, every other table is readable
+    // by any user.
+    pub fn can_read(&self, table: &str) -> bool {
+        ![
+            User::table_name(),
+            UserLogin::table_name(),
+            Webhook::table_name(),
+            WebhookDelivery::table_name(),
+            CheckIn::table_name(),
+        ]
+        .contains(&table)
     }
 }
 
+impl COSIModel for User {
+    fn table_name() -> &'static str {
+        "user"
+    }
+}
+
+impl COSICollection<'_, User, User, UserForm> for User {}
+
 // For security, logging items are in a separate table.
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct UserLogin {
     pub user_id: OID,
     pub password: Credential,
@@ -45,12 +70,14 @@ pub struct UserLogin {
 
 impl COSIForm for UserLogin {}
 
-impl COSICollection<'_, UserLogin, UserLogin, UserLogin> for UserLogin {
-    fn get_table_name() -> String {
-        return "userlogin".to_string();
+impl COSIModel for UserLogin {
+    fn table_name() -> &'static str {
+        "userlogin"
     }
 }
 
+impl COSICollection<'_, UserLogin, UserLogin, UserLogin> for UserLogin {}
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for User {
     type Error = COSIError;
@@ -61,7 +88,7 @@ impl<'r> FromRequest<'r> for User {
         let docs: &Vec<User> = request
             .local_cache_async(async {
                 let connect = request.guard::<&COSIMongo>().await.succeeded().unwrap();
-                let client = &*connect;
+                let client = connect;
                 let uid: Option<String> = request
                     .cookies()
                     .get_private("user_id")
@@ -75,7 +102,7 @@ impl<'r> FromRequest<'r> for User {
                     None => Vec::new(),
                     Some(ref v) => {
                         let search_doc = Some(doc! {
-                            "_id": ObjectId::parse_str(&v).unwrap(),
+                            "_id": ObjectId::parse_str(v).unwrap(),
                             "token": token
                         });
                         // TODO: Connection error handling.
@@ -85,10 +112,10 @@ impl<'r> FromRequest<'r> for User {
             })
             .await;
 
-        if docs.len() == 0 {
-            Outcome::Forward(())
+        if docs.is_empty() {
+            Outcome::Forward(Status::NotFound)
         } else if docs.len() > 1 {
-            Outcome::Failure((
+            Outcome::Error((
                 Status::InternalServerError,
                 COSIError::msg("Invalid login detected."),
             ))
@@ -97,3 +124,50 @@ impl<'r> FromRequest<'r> for User {
         }
     }
 }
+
+// User allowed to manage the whole database. Other users are refused with 403.
+pub struct Admin(pub User);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = COSIError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Admin, COSIError> {
+        match request.guard::<User>().await {
+            Outcome::Success(user) if user.admin => Outcome::Success(Admin(user)),
+            Outcome::Success(_) => Outcome::Error((
+                Status::Forbidden,
+                COSIError::msg("Administrator access required."),
+            )),
+            Outcome::Error(f) => Outcome::Error(f),
+            Outcome::Forward(f) => Outcome::Forward(f),
+        }
+    }
+}
+
+// User of a calendar feed, logged in or giving the calendar token of their feed links.
+pub struct CalendarUser(pub User);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CalendarUser {
+    type Error = COSIError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<CalendarUser, COSIError> {
+        match request.guard::<User>().await {
+            Outcome::Success(user) => return Outcome::Success(CalendarUser(user)),
+            Outcome::Error(f) => return Outcome::Error(f),
+            Outcome::Forward(_) => {}
+        }
+        let token: String = match request.query_value::<String>("token") {
+            Some(Ok(token)) if !token.is_empty() => token,
+            _ => return Outcome::Forward(Status::NotFound),
+        };
+        let connect = request.guard::<&COSIMongo>().await.succeeded().unwrap();
+        let client = connect;
+        match User::find_data(client, Some(doc! {"calendar_token": token}), None).await {
+            Ok(mut users) if users.len() == 1 => Outcome::Success(CalendarUser(users.remove(0))),
+            Ok(_) => Outcome::Forward(Status::NotFound),
+            Err(err) => Outcome::Error((Status::InternalServerError, err)),
+        }
+    }
+}
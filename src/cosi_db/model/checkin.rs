@@ -0,0 +1,28 @@
+// Check-ins of children into event occurrences, see cosi_db::checkin.
+use serde::{Deserialize, Serialize};
+
+use crate::cosi_db::model::common::{COSICollection, COSIForm, COSIModel, OID};
+
+// One entry per child. Children checked in together share the pickup code.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CheckIn {
+    pub event: OID,
+    // Scheduled start of the occurrence, as in the exceptions of the event.
+    pub occurrence: String,
+    pub household: OID,
+    pub person: OID,
+    pub code: String,
+    pub checked_in: String,
+    // Missing while the child is checked in.
+    pub checked_out: Option<String>,
+}
+
+impl COSIForm for CheckIn {}
+
+impl COSIModel for CheckIn {
+    fn table_name() -> &'static str {
+        "checkin"
+    }
+}
+
+impl COSICollection<'_, CheckIn, CheckIn, CheckIn> for CheckIn {}
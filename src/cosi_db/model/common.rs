@@ -1,32 +1,33 @@
-use mongodb::options::{FindOptions, InsertOneOptions, UpdateOptions};
+use mongodb::error::ErrorKind;
+use mongodb::options::{
+    FindOneOptions, FindOptions, InsertManyOptions, InsertOneOptions, UpdateOptions,
+};
 use rocket::async_trait;
 use rocket::data::ToByteUnit;
 use rocket::form::{DataField, FromFormField, ValueField};
+use rocket::tokio::sync::MutexGuard;
+use std::collections::HashMap;
 use std::str::FromStr;
 
-use mongodb::bson::{oid::ObjectId, to_document, Bson, Document};
-use mongodb::{Client, Collection, Cursor};
+use mongodb::bson::{doc, oid::ObjectId, to_document, Bson, Document};
+use mongodb::{Client, ClientSession, Collection, Cursor};
 
 use futures::stream::{StreamExt, TryStreamExt};
 
-use crate::cosi_db::errors::{COSIError, COSIResult};
+use crate::cosi_db::changes::{self, ChangeOp};
+use crate::cosi_db::errors::{COSIError, COSIResult, Missing};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct OID(pub ObjectId);
 
 impl OID {
-    pub fn vec_to_object_id(oid: &Vec<OID>) -> Vec<ObjectId> {
+    pub fn vec_to_object_id(oid: &[OID]) -> Vec<ObjectId> {
         oid.iter().map(|x| x.0).collect()
     }
 }
 
-impl Default for OID {
-    fn default() -> Self {
-        OID(ObjectId::default())
-    }
-}
-
 impl From<ObjectId> for OID {
     fn from(oid: ObjectId) -> Self {
         OID(oid)
@@ -42,7 +43,9 @@ impl From<OID> for ObjectId {
 #[rocket::async_trait]
 impl<'a> FromFormField<'a> for OID {
     fn from_value(field: ValueField<'a>) -> rocket::form::Result<'a, Self> {
-        Ok(ObjectId::from_str(field.value).unwrap().into()) // TODO: Error handling.
+        Ok(ObjectId::from_str(field.value)
+            .map_err(|_| rocket::form::Error::validation("Invalid object id."))?
+            .into())
     }
 
     async fn from_data(field: DataField<'a, '_>) -> rocket::form::Result<'a, Self> {
@@ -59,11 +62,140 @@ impl<'a> FromFormField<'a> for OID {
         let bytes = bytes.into_inner();
         let bytes = rocket::request::local_cache!(field.request, bytes);
         Ok(ObjectId::from_str(std::str::from_utf8(bytes)?)
-            .unwrap()
-            .into()) // TODO: Error check.
+            .map_err(|_| rocket::form::Error::validation("Invalid object id."))?
+            .into())
+    }
+}
+
+// Shorthand for the bounds COSICollection places on its type parameters.
+pub trait COSIData:
+    Clone + Sized + Serialize + DeserializeOwned + Unpin + Send + Sync + 'static
+{
+}
+
+impl<T> COSIData for T where
+    T: Clone + Sized + Serialize + DeserializeOwned + Unpin + Send + Sync + 'static
+{
+}
+
+// Field of a model holding the OID of a datum in another table.
+pub struct Reference {
+    pub field: &'static str,
+    pub table: fn() -> &'static str,
+    pub natural_key: fn() -> Option<Vec<String>>,
+    pub fields: fn() -> Vec<&'static str>,
+}
+
+// Table level metadata. Generated by #[derive(CosiModel)].
+pub trait COSIModel {
+    fn table_name() -> &'static str;
+
+    // Stored fields in declaration order.
+    fn fields() -> Vec<&'static str> {
+        vec![]
+    }
+
+    // Fields that identify a datum outside of its "_id", such as an id from a legacy system.
+    // Collections without a natural key cannot be upserted.
+    fn natural_key() -> Option<Vec<String>> {
+        None
+    }
+
+    fn references() -> Vec<Reference> {
+        vec![]
+    }
+
+    // Fields stored as formatted strings, such as dates, with their chrono format.
+    fn formats() -> Vec<(&'static str, &'static str)> {
+        vec![]
+    }
+}
+
+// OpenAPI schema of a type. Generated by #[derive(CosiModel)] and #[derive(CosiSchema)].
+pub trait COSISchema {
+    fn schema() -> Value;
+}
+
+impl COSISchema for String {
+    fn schema() -> Value {
+        json!({"type": "string"})
+    }
+}
+
+impl COSISchema for bool {
+    fn schema() -> Value {
+        json!({"type": "boolean"})
+    }
+}
+
+macro_rules! integer_schema {
+    ($($t:ty),*) => {
+        $(impl COSISchema for $t {
+            fn schema() -> Value {
+                json!({"type": "integer", "minimum": <$t>::MIN, "maximum": <$t>::MAX})
+            }
+        })*
+    };
+}
+integer_schema!(u8, u16, u32, i32, i64);
+
+impl COSISchema for u64 {
+    fn schema() -> Value {
+        json!({"type": "integer", "minimum": 0})
+    }
+}
+
+impl COSISchema for f64 {
+    fn schema() -> Value {
+        json!({"type": "number"})
+    }
+}
+
+impl COSISchema for OID {
+    fn schema() -> Value {
+        json!({"$ref": "#/components/schemas/ObjectId"})
+    }
+}
+
+impl<T: COSISchema> COSISchema for Option<T> {
+    fn schema() -> Value {
+        // Siblings of $ref are ignored, so references are wrapped.
+        let mut schema = T::schema();
+        if schema.get("$ref").is_some() {
+            schema = json!({ "allOf": [schema] });
+        }
+        schema["nullable"] = json!(true);
+        schema
+    }
+}
+
+impl<T: COSISchema> COSISchema for Vec<T> {
+    fn schema() -> Value {
+        json!({"type": "array", "items": T::schema()})
     }
 }
 
+pub fn object_schema(properties: Vec<(&str, Value)>, required: Vec<&str>) -> Value {
+    let properties: serde_json::Map<String, Value> = properties
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+    let mut schema = json!({"type": "object", "properties": properties});
+    if !required.is_empty() {
+        schema["required"] = json!(required);
+    }
+    schema
+}
+
+pub fn describe_schema(schema: Value, description: String) -> Value {
+    let mut schema = match schema.get("$ref") {
+        Some(_) => json!({ "allOf": [schema] }),
+        None => schema,
+    };
+    schema["description"] = json!(description);
+    schema
+}
+
 #[async_trait]
 pub trait Generator<T> {
     async fn generate(client: &Client, size: u32) -> COSIResult<Vec<T>>;
@@ -90,7 +222,7 @@ pub trait COSIForm {
                 }
             }
         }
-        return Ok(result);
+        Ok(result)
     }
 
     fn sanitize_query(&self) -> COSIResult<Document>
@@ -113,22 +245,47 @@ pub trait COSIForm {
 }
 
 #[async_trait]
-pub trait COSICollection<'a, T, I, F>
+pub trait COSICollection<'a, T, I, F>: COSIModel
 where
-    for<'r> T: Clone + Sized + Serialize + DeserializeOwned + Unpin + Send + Sync + From<I> + 'r, // Base class
-    for<'r> I: Clone
-        + Sized
-        + Serialize
-        + DeserializeOwned
-        + Unpin
-        + Send
-        + Sync
-        + From<T>
-        + COSIForm
-        + 'r,
-    for<'r> F: Clone + Sized + Serialize + DeserializeOwned + Unpin + Send + Sync + COSIForm + 'r,
+    T: COSIData + From<I>, // Base class
+    I: COSIData + From<T> + COSIForm,
+    F: COSIData + Default + COSIForm,
 {
-    fn get_table_name() -> String;
+    fn get_table_name() -> String {
+        Self::table_name().to_string()
+    }
+
+    // Field names of the collection, derived from the query form.
+    // Used to validate user-provided keys such as sort and projection.
+    fn get_field_names() -> Vec<String> {
+        let d = to_document(&F::default()).unwrap();
+        d.keys().map(|k| k.to_string()).collect()
+    }
+
+    fn get_natural_key() -> Option<Vec<String>> {
+        Self::natural_key()
+    }
+
+    // Builds the filter matching a datum on its natural key. Every key field must be non-null.
+    fn natural_key_query(data: &Document) -> COSIResult<Document> {
+        let keys = Self::get_natural_key().ok_or(COSIError::msg(format!(
+            "Table {} has no natural key.",
+            Self::get_table_name()
+        )))?;
+        let mut query = Document::new();
+        for k in keys {
+            match data.get(&k) {
+                None | Some(Bson::Null) => {
+                    return Err(COSIError::msg(format!("Natural key {} is required.", k)))
+                }
+                Some(v) => {
+                    query.insert(k, v.clone());
+                }
+            }
+        }
+        Ok(query)
+    }
+
     async fn get_raw_document(client: &Client) -> Collection<Document> {
         let tname = Self::get_table_name();
         return client.database("cosi_db").collection::<Document>(&tname);
@@ -159,7 +316,7 @@ where
         Ok(orm.iter().map(|v| v.clone().into()).collect())
     }
 
-    async fn to_orm(_client: &Client, imp: &Vec<I>) -> COSIResult<Vec<T>> {
+    async fn to_orm(_client: &Client, imp: &[I]) -> COSIResult<Vec<T>> {
         // This extra call allows for async side-effects.
         // Default implementation is non-bulk. Can be slow.
         Ok(imp.iter().map(|v| v.clone().into()).collect())
@@ -173,11 +330,12 @@ where
     ) -> COSIResult<Vec<T>> {
         let col = Self::get_collection(client).await;
         let cursor: Cursor<I> = col.find(filter, options).await?;
-        let results = cursor.try_collect().await?;
-        return Ok(Self::to_orm(client, &results).await?);
+        let results: Vec<I> = cursor.try_collect().await?;
+        return Self::to_orm(client, &results).await;
     }
 
-    async fn find_document(
+    // Find without resolving foreign keys.
+    async fn find_raw_document(
         client: &Client,
         filter: Option<Document>,
         options: Option<FindOptions>,
@@ -188,7 +346,15 @@ where
         while let Some(doc) = cursor.next().await {
             results.push(doc?);
         }
+        return Ok(results);
+    }
 
+    async fn find_document(
+        client: &Client,
+        filter: Option<Document>,
+        options: Option<FindOptions>,
+    ) -> COSIResult<Vec<Document>> {
+        let mut results = Self::find_raw_document(client, filter, options).await?;
         Self::process_foreign_keys(client, &mut results).await;
         return Ok(results);
     }
@@ -198,41 +364,277 @@ where
         data: &I,
         options: Option<InsertOneOptions>,
     ) -> COSIResult<Bson> {
-        let col = Self::get_collection(client).await;
-        let result = col.insert_one(data, options).await?;
+        let _lock = Self::write_lock().await;
+        let mut data = vec![to_document(data)?];
+        Self::before_write(client, &mut data).await.remove(0)?;
+        let col = Self::get_raw_document(client).await;
+        let result = col.insert_one(data.remove(0), options).await?;
+        changes::publish(
+            Self::table_name(),
+            ChangeOp::Insert,
+            Some(&result.inserted_id),
+        );
         return Ok(result.inserted_id);
     }
 
+    // Inserts all documents in one round trip. Documents without an "_id" are assigned one.
+    // Each entry reports its own result so that partial failures can be surfaced.
+    async fn insert_data(
+        client: &Client,
+        mut data: Vec<Document>,
+    ) -> COSIResult<Vec<COSIResult<Bson>>> {
+        let _lock = Self::write_lock().await;
+        let checks = Self::before_write(client, &mut data).await;
+        let mut results: Vec<COSIResult<Bson>> = vec![];
+        // Position in the results of each document written.
+        let mut positions: Vec<usize> = vec![];
+        let mut valid: Vec<Document> = vec![];
+        for (i, (mut d, check)) in data.into_iter().zip(checks).enumerate() {
+            if let Err(e) = check {
+                results.push(Err(e));
+                continue;
+            }
+            if !d.contains_key("_id") {
+                d.insert("_id", ObjectId::new());
+            }
+            results.push(Ok(d.get("_id").unwrap().clone()));
+            positions.push(i);
+            valid.push(d);
+        }
+        if valid.is_empty() {
+            return Ok(results);
+        }
+
+        let col = Self::get_raw_document(client).await;
+        let options = InsertManyOptions::builder().ordered(false).build();
+        if let Err(e) = col.insert_many(valid, options).await {
+            match *e.kind {
+                ErrorKind::BulkWrite(ref failure) => {
+                    for we in failure.write_errors.iter().flatten() {
+                        results[positions[we.index]] = Err(COSIError::msg(we.message.clone()));
+                    }
+                }
+                _ => return Err(e.into()),
+            }
+        }
+        for id in results.iter().flatten() {
+            changes::publish(Self::table_name(), ChangeOp::Insert, Some(id));
+        }
+        return Ok(results);
+    }
+
     async fn update_datum(
         client: &Client,
         query: &Document,
         data: &Document,
         options: Option<UpdateOptions>,
     ) -> COSIResult<u64> {
+        let _lock = Self::write_lock().await;
+        let mut data = data.clone();
+        Self::before_update(client, query, &mut data).await?;
         let col = Self::get_collection(client).await;
-        let result = col.update_one(query.clone(), data.clone(), options).await?;
+        let result = col.update_one(query.clone(), data, options).await?;
+        if let Some(id) = &result.upserted_id {
+            changes::publish(Self::table_name(), ChangeOp::Insert, Some(id));
+        } else if result.modified_count > 0 {
+            changes::publish(Self::table_name(), ChangeOp::Update, query.get("_id"));
+        }
         if result.matched_count == result.modified_count {
             return Ok(result.matched_count);
         } else if result.matched_count > 0 && result.modified_count == 0 {
             return Ok(0);
-        } else if let Some(_) = result.upserted_id {
+        } else if result.upserted_id.is_some() {
             return Ok(1);
         } else {
-            return Err(COSIError::msg("No data was updated."));
+            return Err(Missing("No data was updated.".to_string()).into());
         }
     }
 
-    async fn process_foreign_keys<'b>(_client: &'b Client, _raw_doc: &'b mut Vec<Document>) {}
+    // Updates the datum matching the natural key or inserts it if none exists.
+    // Returns the "_id" and whether a new datum was inserted.
+    async fn upsert_datum(client: &Client, data: Document) -> COSIResult<(Bson, bool)> {
+        let query = Self::natural_key_query(&data)?;
+        let _lock = Self::write_lock().await;
+        let mut data = vec![data];
+        Self::before_write(client, &mut data).await.remove(0)?;
+        let data = data.remove(0);
+        let col = Self::get_raw_document(client).await;
+        let options = UpdateOptions::builder().upsert(true).build();
+        let result = col
+            .update_one(query.clone(), doc! {"$set": data}, options)
+            .await?;
+        if let Some(id) = result.upserted_id {
+            changes::publish(Self::table_name(), ChangeOp::Insert, Some(&id));
+            return Ok((id, true));
+        }
+
+        let existing = col
+            .find_one(query, None)
+            .await?
+            .ok_or(COSIError::msg("Unable to find upserted data."))?;
+        let id = existing.get("_id").unwrap().clone();
+        if result.modified_count > 0 {
+            changes::publish(Self::table_name(), ChangeOp::Update, Some(&id));
+        }
+        return Ok((id, false));
+    }
+
+    // Writes the documents with the session, updating those matching a natural key and
+    // inserting the others. Nothing is written unless every document passes before_write.
+    // Returns the changes for the caller to publish once the session commits.
+    async fn write_data(
+        client: &Client,
+        mut data: Vec<Document>,
+        mut session: Option<&mut ClientSession>,
+    ) -> COSIResult<Vec<(ChangeOp, Bson)>> {
+        let _lock = Self::write_lock().await;
+        for check in Self::before_write(client, &mut data).await {
+            check?;
+        }
+        let col = Self::get_raw_document(client).await;
+        let options = FindOneOptions::builder()
+            .projection(doc! {"_id": 1})
+            .build();
+        let mut writes = vec![];
+        for mut d in data {
+            let existing = match (Self::natural_key_query(&d).ok(), session.as_deref_mut()) {
+                (None, _) => None,
+                (Some(k), Some(s)) => col.find_one_with_session(k, options.clone(), s).await?,
+                (Some(k), None) => col.find_one(k, options.clone()).await?,
+            };
+            match existing.and_then(|e| e.get("_id").cloned()) {
+                Some(id) => {
+                    let update = doc! {"$set": d};
+                    match session.as_deref_mut() {
+                        Some(s) => {
+                            col.update_one_with_session(doc! {"_id": &id}, update, None, s)
+                                .await?
+                        }
+                        None => col.update_one(doc! {"_id": &id}, update, None).await?,
+                    };
+                    writes.push((ChangeOp::Update, id));
+                }
+                None => {
+                    let id = Bson::ObjectId(ObjectId::new());
+                    d.insert("_id", id.clone());
+                    match session.as_deref_mut() {
+                        Some(s) => col.insert_one_with_session(d, None, s).await?,
+                        None => col.insert_one(d, None).await?,
+                    };
+                    writes.push((ChangeOp::Insert, id));
+                }
+            }
+        }
+        Ok(writes)
+    }
+
+    async fn delete_datum(client: &Client, query: &Document) -> COSIResult<u64> {
+        let _lock = Self::write_lock().await;
+        let col = Self::get_raw_document(client).await;
+        let deleted = col
+            .find_one_and_delete(query.clone(), None)
+            .await?
+            .ok_or(Missing("No data was deleted.".to_string()))?;
+        changes::publish(Self::table_name(), ChangeOp::Delete, query.get("_id"));
+        // The datum is gone either way, so failing clean ups do not fail the delete.
+        if let Err(err) = Self::after_delete(client, &deleted).await {
+            rocket::error!(
+                "Clean up after deleting from {} failed: {}",
+                Self::table_name(),
+                err
+            );
+        }
+        return Ok(1);
+    }
+
+    // Held by the writes of the model from their checks until the data is written, so that
+    // checks counting the data already written are not raced by other requests of the server.
+    async fn write_lock() -> Option<MutexGuard<'static, ()>> {
+        None
+    }
+
+    // Called by insert_datum, insert_data and upsert_datum before documents are written, with
+    // one result per document. Models may amend the documents or refuse some of them.
+    async fn before_write(_client: &Client, data: &mut Vec<Document>) -> Vec<COSIResult<()>> {
+        data.iter().map(|_| Ok(())).collect()
+    }
+
+    // Called by update_datum before the update is applied to the data matching the query.
+    // Models may amend the update or refuse it.
+    async fn before_update(
+        _client: &Client,
+        _query: &Document,
+        _update: &mut Document,
+    ) -> COSIResult<()> {
+        return Ok(());
+    }
+
+    // Called by delete_datum once the datum is deleted.
+    async fn after_delete(_client: &Client, _deleted: &Document) -> COSIResult<()> {
+        return Ok(());
+    }
+
+    // Replaces referenced OIDs with the documents they point to, using the reference hints
+    // of the model. Ids that cannot be found are left as is.
+    async fn process_foreign_keys<'b>(client: &'b Client, raw_doc: &'b mut Vec<Document>) {
+        for r in Self::references() {
+            let mut ids: Vec<ObjectId> = vec![];
+            for d in raw_doc.iter() {
+                match d.get(r.field) {
+                    Some(Bson::ObjectId(oid)) => ids.push(*oid),
+                    Some(Bson::Array(arr)) => {
+                        ids.extend(arr.iter().filter_map(|v| v.as_object_id()))
+                    }
+                    _ => {}
+                }
+            }
+            if ids.is_empty() {
+                continue;
+            }
+
+            let col = client
+                .database("cosi_db")
+                .collection::<Document>((r.table)());
+            let found: HashMap<ObjectId, Document> =
+                match col.find(doc! {"_id": {"$in": ids}}, None).await {
+                    Ok(cursor) => {
+                        cursor
+                            .filter_map(|d| async move { d.ok() })
+                            .filter_map(|d| async move {
+                                d.get_object_id("_id").ok().map(|oid| (oid, d.clone()))
+                            })
+                            .collect()
+                            .await
+                    }
+                    Err(_) => continue,
+                };
+            let resolve = |v: &Bson| match v {
+                Bson::ObjectId(oid) => found
+                    .get(oid)
+                    .map(|d| Bson::Document(d.clone()))
+                    .unwrap_or(v.clone()),
+                _ => v.clone(),
+            };
+            for d in raw_doc.iter_mut() {
+                let resolved = match d.get(r.field) {
+                    Some(Bson::Array(arr)) => Bson::Array(arr.iter().map(resolve).collect()),
+                    Some(v) => resolve(v),
+                    None => continue,
+                };
+                d.insert(r.field, resolved);
+            }
+        }
+    }
 
     // Used for processing formdata and input to internal representation.
     // This function technically doesn't need to be here as it is just a softwrapper
     // to into() however it allows for code-readers to understand the relationship between
     // Struct AImpl and Struct AForm.
     fn convert_form_query(form_data: F) -> COSIResult<Document> {
-        return form_data.sanitize_query();
+        form_data.sanitize_query()
     }
 
     fn convert_form_insert(form_data: I) -> COSIResult<Document> {
-        return form_data.sanitize_insert();
+        form_data.sanitize_insert()
     }
 }
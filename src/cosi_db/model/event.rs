@@ -11,22 +11,27 @@ use std::cmp;
 use names::Name;
 use rocket::form::{FromForm, FromFormField};
 use rocket::futures::TryStreamExt;
+use rocket::tokio::sync::MutexGuard;
 
 // cosi_db
+use crate::cosi_db::booking;
 use crate::cosi_db::errors::{COSIError, COSIResult};
-use crate::cosi_db::model::common::{COSICollection, COSIForm, Generator, OID};
+use crate::cosi_db::model::common::{COSICollection, Generator, OID};
 use crate::cosi_db::model::group::{Group, GroupImpl};
 use crate::cosi_db::model::household::{Household, HouseholdImpl};
 use crate::cosi_db::model::person::{Person, PersonImpl};
+use crate::cosi_db::recurrence::{meets_again, DATETIME_FORMAT};
+use crate::cosi_db::registration;
+use cosi_db_derive::{CosiModel, CosiSchema};
 
-#[derive(Copy, Clone, Debug, FromFormField, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, FromFormField, Deserialize, Serialize, CosiSchema)]
 pub enum Reoccurring {
     Days,
     Weeks,
     Months,
 }
 
-#[derive(Copy, Clone, Debug, FromFormField, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, FromFormField, Deserialize, Serialize, CosiSchema)]
 pub enum Days {
     M,
     Tu,
@@ -37,34 +42,95 @@ pub enum Days {
     Su,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+// Cancelled or moved occurrence of a repeating event, see cosi_db::recurrence.
+// Datetimes are formatted as %Y-%m-%d %H:%M:%S.
+#[derive(Clone, Debug, FromForm, Serialize, Deserialize, CosiSchema)]
+pub struct EventException {
+    // Start of the occurrence as scheduled.
+    pub occurrence: String,
+    pub cancelled: bool,
+    // New times of a moved occurrence.
+    pub start_datetime: Option<String>,
+    pub end_datetime: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, CosiModel)]
+#[cosi(
+    natural_key = "name, start_datetime",
+    validate = "EventOptional::_sanitize",
+    manual_collection
+)]
 pub struct Event {
     pub name: String,
     pub meeting_days: Vec<Days>,
+    #[cosi(format = "%Y-%m-%d %H:%M:%S")]
     pub start_datetime: NaiveDateTime,
+    #[cosi(format = "%Y-%m-%d %H:%M:%S")]
     pub end_datetime: Option<NaiveDateTime>,
     pub freq: Option<u8>,
     pub reoccuring: Option<Reoccurring>,
+    pub exceptions: Vec<EventException>,
+    // Persons the event can take, counting the members of registered households and groups.
+    // No limit when missing, see cosi_db::registration.
+    pub capacity: Option<u32>,
+    // Registrations beyond the capacity are waitlisted rather than refused.
+    pub waitlist: Option<bool>,
+    // Rooms reserved over every occurrence, see cosi_db::booking.
+    pub rooms: Vec<OID>,
 }
 
-#[derive(Clone, Debug, Deserialize, FromForm, Serialize)]
-pub struct EventImpl {
-    pub name: String,
-    pub meeting_days: Vec<Days>,
-    pub start_datetime: String,
-    pub end_datetime: Option<String>,
-    pub freq: Option<u8>,
-    pub reoccuring: Option<Reoccurring>,
-}
-
-#[derive(Clone, Debug, Deserialize, FromForm, Serialize)]
-pub struct EventOptional {
-    pub name: Option<String>,
-    pub meeting_days: Option<Vec<Days>>,
-    pub start_datetime: Option<String>,
-    pub end_datetime: Option<Option<String>>,
-    pub freq: Option<Option<u8>>,
-    pub reoccuring: Option<Reoccurring>,
+impl EventOptional {
+    fn _sanitize(form: &EventOptional) -> COSIResult<()> {
+        let parse = |value: &str| {
+            NaiveDateTime::parse_from_str(value, DATETIME_FORMAT).map_err(|_| {
+                COSIError::msg(format!(
+                    "Datetime {} should be <year>-<month>-<day> <hour>:<minute>:<second>.",
+                    value
+                ))
+            })
+        };
+        if let Some(start) = form.start_datetime.as_deref() {
+            let days = form.meeting_days.as_deref().unwrap_or_default();
+            if !meets_again(
+                form.reoccuring.flatten(),
+                form.freq.flatten(),
+                days,
+                parse(start)?.date(),
+            ) {
+                return Err(COSIError::msg(
+                    "Events repeating every freq days never reach their meeting days.",
+                ));
+            }
+        }
+        for e in form.exceptions.iter().flatten() {
+            parse(&e.occurrence)?;
+            let start = e.start_datetime.as_deref().map(parse).transpose()?;
+            let end = e.end_datetime.as_deref().map(parse).transpose()?;
+            if e.cancelled && (start.is_some() || end.is_some()) {
+                return Err(COSIError::msg("Cancelled occurrences cannot be moved."));
+            }
+            if !e.cancelled && start.is_none() {
+                return Err(COSIError::msg(
+                    "Exceptions either cancel an occurrence or give its new start_datetime.",
+                ));
+            }
+            if let (Some(start), Some(end)) = (start, end) {
+                if end < start {
+                    return Err(COSIError::msg("Occurrences cannot end before they start."));
+                }
+            }
+        }
+        let occurrences: Vec<&String> = form
+            .exceptions
+            .iter()
+            .flatten()
+            .map(|e| &e.occurrence)
+            .collect();
+        if (1..occurrences.len()).any(|i| occurrences[..i].contains(&occurrences[i])) {
+            return Err(COSIError::msg("Occurrences have one exception at most."));
+        }
+        Ok(())
+    }
 }
 
 impl Default for Event {
@@ -76,45 +142,27 @@ impl Default for Event {
             end_datetime: None,
             freq: None,
             reoccuring: None,
+            exceptions: vec![],
+            capacity: None,
+            waitlist: None,
+            rooms: vec![],
         }
     }
 }
 
-impl From<Event> for EventImpl {
-    fn from(e: Event) -> EventImpl {
-        EventImpl {
-            name: e.name.clone(),
-            meeting_days: e.meeting_days,
-            start_datetime: e.start_datetime.to_string(),
-            end_datetime: e.end_datetime.map(|x| x.to_string()),
-            freq: e.freq,
-            reoccuring: e.reoccuring,
-        }
-    }
-}
-
-impl From<EventImpl> for Event {
-    fn from(e: EventImpl) -> Event {
-        let parse_date_str =
-            |v: &str| NaiveDateTime::parse_from_str(v, "%Y-%m-%d %H:%M:%S").unwrap();
-        Event {
-            name: e.name.clone(),
-            meeting_days: e.meeting_days,
-            start_datetime: parse_date_str(&e.start_datetime),
-            end_datetime: e.end_datetime.map(|x| parse_date_str(&x)),
-            freq: e.freq,
-            reoccuring: e.reoccuring,
-        }
-    }
-}
-
-impl COSIForm for EventImpl {}
-impl COSIForm for EventOptional {}
-
+// Rooms cannot be booked by two events at once.
 #[async_trait]
 impl COSICollection<'_, Event, EventImpl, EventOptional> for Event {
-    fn get_table_name() -> String {
-        return "event".to_string();
+    async fn before_write(client: &Client, data: &mut Vec<Document>) -> Vec<COSIResult<()>> {
+        booking::check_all(client, data).await
+    }
+
+    async fn before_update(
+        client: &Client,
+        query: &Document,
+        update: &mut Document,
+    ) -> COSIResult<()> {
+        booking::check_update(client, query, update).await
     }
 }
 
@@ -143,86 +191,51 @@ impl Generator<Event> for Event {
                 end_datetime: Some(create_date(start_month, start_day + rng.gen_range(2, 17))),
                 freq: None,
                 reoccuring: None,
+                exceptions: vec![],
+                capacity: None,
+                waitlist: None,
+                rooms: vec![],
             });
         }
         return Ok(result);
     }
 }
 
-#[derive(Copy, Clone, Debug, FromFormField, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, FromFormField, Deserialize, Serialize, CosiSchema)]
 pub enum EventKeyType {
     Group,
     Household,
     Person,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, PartialEq, FromFormField, Deserialize, Serialize, CosiSchema)]
+pub enum RegistrationStatus {
+    Registered,
+    Waitlisted,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, CosiModel)]
+#[cosi(manual_collection)]
 pub struct EventRegistration {
+    #[cosi(reference)]
     pub event: Event,
+    #[cosi(format = "%Y-%m-%d %H:%M:%S")]
     pub timestamp: NaiveDateTime,
+    #[cosi(reference)]
     pub person: Option<Person>,
+    #[cosi(reference)]
     pub group: Option<Group>,
+    #[cosi(reference)]
     pub household: Option<Household>,
     pub key_type: EventKeyType,
-}
-
-#[derive(Clone, Debug, Deserialize, FromForm, Serialize)]
-pub struct EventRegistrationImpl {
-    pub event: OID,
-    pub timestamp: String,
-    pub person: Option<OID>,
-    pub group: Option<OID>,
-    pub household: Option<OID>,
-    pub key_type: EventKeyType,
-}
-
-#[derive(Clone, Debug, Deserialize, FromForm, Serialize)]
-pub struct EventRegistrationOptional {
-    pub event: Option<Option<OID>>,
-    pub timestamp: Option<String>,
-    pub person: Option<Option<OID>>,
-    pub group: Option<Option<OID>>,
-    pub household: Option<Option<OID>>,
-    pub key_type: Option<EventKeyType>,
-}
-
-impl COSIForm for EventRegistrationImpl {}
-impl COSIForm for EventRegistrationOptional {}
-
-impl From<EventRegistration> for EventRegistrationImpl {
-    fn from(er: EventRegistration) -> EventRegistrationImpl {
-        EventRegistrationImpl {
-            event: <OID as std::default::Default>::default(),
-            timestamp: er.timestamp.to_string(),
-            person: None,
-            group: None,
-            household: None,
-            key_type: er.key_type,
-        }
-    }
-}
-
-impl From<EventRegistrationImpl> for EventRegistration {
-    fn from(gr: EventRegistrationImpl) -> EventRegistration {
-        EventRegistration {
-            event: <Event as std::default::Default>::default(),
-            timestamp: NaiveDateTime::parse_from_str(&gr.timestamp, "%Y-%m-%d %H:%M:%S").unwrap(),
-            person: None,
-            group: None,
-            household: None,
-            key_type: gr.key_type,
-        }
-    }
+    // Set when the registration is written. Older registrations without it are registered.
+    pub status: Option<RegistrationStatus>,
 }
 
 #[async_trait]
 impl COSICollection<'_, EventRegistration, EventRegistrationImpl, EventRegistrationOptional>
     for EventRegistration
 {
-    fn get_table_name() -> String {
-        return "eventregistration".to_string();
-    }
-
     async fn to_impl(
         client: &Client,
         orm: Vec<EventRegistration>,
@@ -240,8 +253,8 @@ impl COSICollection<'_, EventRegistration, EventRegistrationImpl, EventRegistrat
                 .pop()
                 .unwrap();
             er_result.event = get_id(&event);
-            match &o.key_type {
-                &EventKeyType::Group => {
+            match o.key_type {
+                EventKeyType::Group => {
                     let group_impl =
                         Group::to_impl(client, vec![o.group.clone().unwrap()]).await?[0].clone();
                     let group = Group::find_document(client, Some(to_document(&group_impl)?), None)
@@ -250,7 +263,7 @@ impl COSICollection<'_, EventRegistration, EventRegistrationImpl, EventRegistrat
                         .unwrap();
                     er_result.group = Some(get_id(&group));
                 }
-                &EventKeyType::Household => {
+                EventKeyType::Household => {
                     let house_impl = Household::to_impl(client, vec![o.household.clone().unwrap()])
                         .await?[0]
                         .clone();
@@ -261,7 +274,7 @@ impl COSICollection<'_, EventRegistration, EventRegistrationImpl, EventRegistrat
                             .unwrap();
                     er_result.household = Some(get_id(&house));
                 }
-                &EventKeyType::Person => {
+                EventKeyType::Person => {
                     let person = person_raw
                         .find_one(to_document(&o.person)?, None)
                         .await?
@@ -278,7 +291,7 @@ impl COSICollection<'_, EventRegistration, EventRegistrationImpl, EventRegistrat
 
     async fn to_orm(
         client: &Client,
-        imp: &Vec<EventRegistrationImpl>,
+        imp: &[EventRegistrationImpl],
     ) -> COSIResult<Vec<EventRegistration>> {
         let mut result = vec![];
 
@@ -293,10 +306,10 @@ impl COSICollection<'_, EventRegistration, EventRegistrationImpl, EventRegistrat
                 .find_one(doc! {"_id": ObjectId::from(i.event.clone())}, None)
                 .await?
                 .ok_or(COSIError::msg("Unable to find provided event."))?;
-            let e_orm = Event::to_orm(client, &vec![event]).await?[0].clone();
+            let e_orm = Event::to_orm(client, &[event]).await?[0].clone();
             er_result.event = e_orm;
-            match &er_result.key_type {
-                &EventKeyType::Group => {
+            match er_result.key_type {
+                EventKeyType::Group => {
                     // Inefficient conversion.
                     let group = group_col
                         .find_one(
@@ -305,10 +318,10 @@ impl COSICollection<'_, EventRegistration, EventRegistrationImpl, EventRegistrat
                         )
                         .await?
                         .ok_or(COSIError::msg("Unable to find Group Event."))?;
-                    let g_orm = Group::to_orm(client, &vec![group]).await?[0].clone();
+                    let g_orm = Group::to_orm(client, &[group]).await?[0].clone();
                     er_result.group = Some(g_orm);
                 }
-                &EventKeyType::Household => {
+                EventKeyType::Household => {
                     // Inefficient conversion.
                     let household = household_col
                         .find_one(
@@ -317,10 +330,10 @@ impl COSICollection<'_, EventRegistration, EventRegistrationImpl, EventRegistrat
                         )
                         .await?
                         .ok_or(COSIError::msg("Unable to find Household Event."))?;
-                    let h_orm = Household::to_orm(client, &vec![household]).await?[0].clone();
+                    let h_orm = Household::to_orm(client, &[household]).await?[0].clone();
                     er_result.household = Some(h_orm);
                 }
-                &EventKeyType::Person => {
+                EventKeyType::Person => {
                     // Inefficient conversion.
                     let person = person_col
                         .find_one(
@@ -329,7 +342,7 @@ impl COSICollection<'_, EventRegistration, EventRegistrationImpl, EventRegistrat
                         )
                         .await?
                         .ok_or(COSIError::msg("Unable to find Person Event."))?;
-                    let p_orm = Person::to_orm(client, &vec![person]).await?[0].clone();
+                    let p_orm = Person::to_orm(client, &[person]).await?[0].clone();
                     er_result.person = Some(p_orm);
                 }
             }
@@ -339,6 +352,32 @@ impl COSICollection<'_, EventRegistration, EventRegistrationImpl, EventRegistrat
         return Ok(result);
     }
 
+    async fn before_write(client: &Client, data: &mut Vec<Document>) -> Vec<COSIResult<()>> {
+        registration::place(client, data).await
+    }
+
+    async fn write_lock() -> Option<MutexGuard<'static, ()>> {
+        Some(registration::lock().await)
+    }
+
+    async fn before_update(
+        client: &Client,
+        query: &Document,
+        update: &mut Document,
+    ) -> COSIResult<()> {
+        registration::place_update(client, query, update).await
+    }
+
+    // Cancelled registrations free seats for the waitlist.
+    async fn after_delete(client: &Client, deleted: &Document) -> COSIResult<()> {
+        let event = deleted.get_object_id("event")?;
+        let events = Event::get_raw_document(client).await;
+        if events.count_documents(doc! {"_id": event}, None).await? > 0 {
+            registration::promote(client, event, None).await?;
+        }
+        return Ok(());
+    }
+
     async fn process_foreign_keys<'b>(client: &'b Client, raw_doc: &'b mut Vec<Document>) {
         let impls: Vec<EventRegistrationImpl> = raw_doc
             .iter()
@@ -353,14 +392,14 @@ impl COSICollection<'_, EventRegistration, EventRegistrationImpl, EventRegistrat
             rd.insert("household", to_bson(&None::<Household>).unwrap());
             rd.insert("person", to_bson(&None::<Person>).unwrap());
 
-            match &o.key_type {
-                &EventKeyType::Group => {
+            match o.key_type {
+                EventKeyType::Group => {
                     rd.insert("group", to_bson(&Some(&o.group)).unwrap());
                 }
-                &EventKeyType::Household => {
+                EventKeyType::Household => {
                     rd.insert("household", to_bson(&Some(&o.household)).unwrap());
                 }
-                &EventKeyType::Person => {
+                EventKeyType::Person => {
                     rd.insert("person", to_bson(&Some(&o.person)).unwrap());
                 }
             }
@@ -440,6 +479,7 @@ impl Generator<EventRegistration> for EventRegistration {
                 group: None,
                 household: None,
                 key_type: EventKeyType::Person,
+                status: None,
             });
         }
 
@@ -451,6 +491,7 @@ impl Generator<EventRegistration> for EventRegistration {
                 group: Some(result_group.pop().unwrap()),
                 household: None,
                 key_type: EventKeyType::Group,
+                status: None,
             });
         }
 
@@ -462,6 +503,7 @@ impl Generator<EventRegistration> for EventRegistration {
                 group: None,
                 household: Some(result_household.pop().unwrap()),
                 key_type: EventKeyType::Household,
+                status: None,
             });
         }
 
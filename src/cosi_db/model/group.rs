@@ -1,6 +1,6 @@
 use async_trait::async_trait;
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::{doc, from_document, to_bson, to_document, Document};
+use mongodb::bson::{doc, from_document, to_document, Document};
 use mongodb::Client;
 use rand::{thread_rng, Rng};
 
@@ -8,38 +8,22 @@ use names::Name;
 use serde::{Deserialize, Serialize};
 
 use lipsum::lipsum_words_from_seed;
-use rocket::form::FromForm;
 use rocket::futures::TryStreamExt;
 use std::default::Default;
 
 // cosi_db
 use crate::cosi_db::errors::{COSIError, COSIResult};
-use crate::cosi_db::model::common::{COSICollection, COSIForm, Generator, OID};
+use crate::cosi_db::model::common::{COSICollection, Generator};
 use crate::cosi_db::model::person::Person;
+use cosi_db_derive::CosiModel;
 
-#[derive(Clone, Debug, Deserialize, FromForm, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, CosiModel)]
+#[cosi(natural_key = "group_name")]
 pub struct Group {
     pub group_name: String,
     pub group_desc: String,
 }
 
-#[derive(Clone, Debug, Deserialize, FromForm, Serialize)]
-pub struct GroupOptional {
-    pub group_name: Option<String>,
-    pub group_desc: Option<String>,
-}
-
-pub type GroupImpl = Group;
-impl COSIForm for GroupImpl {}
-impl COSIForm for GroupOptional {}
-
-#[async_trait]
-impl COSICollection<'_, Group, GroupImpl, GroupOptional> for Group {
-    fn get_table_name() -> String {
-        return "group".to_string();
-    }
-}
-
 #[async_trait]
 impl Generator<Group> for Group {
     async fn generate(_client: &Client, size: u32) -> COSIResult<Vec<Group>> {
@@ -69,56 +53,18 @@ impl Default for Group {
 }
 
 // Group relations
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, CosiModel)]
+#[cosi(natural_key = "person, group", manual_collection)]
 pub struct GroupRelation {
+    #[cosi(reference)]
     pub person: Person,
+    #[cosi(reference)]
     pub group: Group,
     pub role: String,
 }
 
-#[derive(Clone, Debug, Deserialize, FromForm, Serialize)]
-pub struct GroupRelationImpl {
-    pub person: OID,
-    pub group: OID,
-    pub role: String,
-}
-
-#[derive(Clone, Debug, Deserialize, FromForm, Serialize)]
-pub struct GroupRelationOptional {
-    pub person: Option<String>,
-    pub group: Option<String>,
-    pub role: Option<String>,
-}
-
-impl COSIForm for GroupRelationImpl {}
-impl COSIForm for GroupRelationOptional {}
-
-impl From<GroupRelation> for GroupRelationImpl {
-    fn from(gr: GroupRelation) -> GroupRelationImpl {
-        GroupRelationImpl {
-            person: <OID as std::default::Default>::default(),
-            group: <OID as std::default::Default>::default(),
-            role: gr.role.clone(),
-        }
-    }
-}
-
-impl From<GroupRelationImpl> for GroupRelation {
-    fn from(gr: GroupRelationImpl) -> GroupRelation {
-        GroupRelation {
-            person: <Person as std::default::Default>::default(),
-            group: <Group as std::default::Default>::default(),
-            role: gr.role.clone(),
-        }
-    }
-}
-
 #[async_trait]
 impl COSICollection<'_, GroupRelation, GroupRelationImpl, GroupRelationOptional> for GroupRelation {
-    fn get_table_name() -> String {
-        return "grouprelation".to_string();
-    }
-
     async fn to_impl(
         client: &Client,
         orm: Vec<GroupRelation>,
@@ -148,10 +94,7 @@ impl COSICollection<'_, GroupRelation, GroupRelationImpl, GroupRelationOptional>
         return Ok(results);
     }
 
-    async fn to_orm(
-        client: &Client,
-        imp: &Vec<GroupRelationImpl>,
-    ) -> COSIResult<Vec<GroupRelation>> {
+    async fn to_orm(client: &Client, imp: &[GroupRelationImpl]) -> COSIResult<Vec<GroupRelation>> {
         let mut result = vec![];
         let person_col = Person::get_collection(client).await;
         let group_col = Group::get_collection(client).await;
@@ -167,28 +110,14 @@ impl COSICollection<'_, GroupRelation, GroupRelationImpl, GroupRelationOptional>
 
             result.push(GroupRelation {
                 // TODO: Inefficient conversion.
-                person: Person::to_orm(client, &vec![person]).await?[0].clone(),
-                group: group,
+                person: Person::to_orm(client, &[person]).await?[0].clone(),
+                group: group.into(),
                 role: i.role.clone(),
             });
         }
 
         return Ok(result);
     }
-
-    async fn process_foreign_keys<'b>(client: &'b Client, raw_doc: &'b mut Vec<Document>) {
-        let impls: Vec<GroupRelationImpl> = raw_doc
-            .iter()
-            .map(|x| from_document(x.clone()).unwrap())
-            .collect();
-        // This will fetch the foreign keys for us.
-        let orms: Vec<GroupRelation> = Self::to_orm(client, &impls).await.unwrap();
-        let it = raw_doc.iter_mut().zip(orms);
-        for (rd, o) in it {
-            rd.insert("person", to_bson(&o.person).unwrap());
-            rd.insert("group", to_bson(&o.group).unwrap());
-        }
-    }
 }
 
 #[async_trait]
@@ -1,93 +1,50 @@
 use async_trait::async_trait;
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::{doc, from_document, to_bson, to_document, Document};
+use mongodb::bson::{doc, from_document, to_document, Document};
 use mongodb::Client;
 use rocket::futures::TryStreamExt;
 
 use names::Name;
 use serde::{Deserialize, Serialize};
 
-use core::convert::From;
 use rocket::form::{FromForm, FromFormField};
 
 // cosi_db
 use crate::cosi_db::errors::{COSIError, COSIResult};
 
 use crate::cosi_db::model::address::Address;
-use crate::cosi_db::model::common::{COSICollection, COSIForm, Generator, OID};
+use crate::cosi_db::model::common::{COSICollection, Generator, OID};
 use crate::cosi_db::model::person::{Person, PersonImpl};
+use cosi_db_derive::{CosiModel, CosiSchema};
 
-#[derive(Clone, Debug, FromFormField, Serialize, Deserialize)]
+#[derive(Clone, Debug, FromFormField, Serialize, Deserialize, CosiSchema)]
 pub enum HouseRelationStatus {
     Husband,
     Wife,
     Child,
 }
 
-#[derive(Clone, Debug, FromForm, Serialize, Deserialize)]
+// person_a is the husband, wife or child of person_b.
+#[derive(Clone, Debug, FromForm, Serialize, Deserialize, CosiSchema)]
 pub struct HouseRelation {
     pub person_a: OID,
     pub person_b: OID,
     pub relation: HouseRelationStatus,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, CosiModel)]
+#[cosi(natural_key = "house_name, address", manual_collection)]
 pub struct Household {
     pub house_name: String,
+    #[cosi(reference)]
     pub address: Address,
+    #[cosi(reference)]
     pub persons: Vec<Person>,
     pub relations: Vec<HouseRelation>,
 }
 
-#[derive(Clone, Debug, Deserialize, FromForm, Serialize)]
-pub struct HouseholdImpl {
-    pub house_name: String,
-    pub address: OID,
-    pub persons: Vec<OID>,
-    pub relations: Vec<HouseRelation>,
-}
-
-#[derive(Clone, Debug, Deserialize, FromForm, Serialize)]
-pub struct HouseholdOptional {
-    pub house_name: Option<String>,
-    pub address: Option<String>,
-    pub persons: Option<Vec<String>>,
-    pub relations: Option<Vec<HouseRelation>>,
-}
-
-impl COSIForm for HouseholdImpl {}
-impl COSIForm for HouseholdOptional {}
-
-// These do not fetch OID values. Rather, the COSIForm helpers which are async can interact with the
-// database. You can consider these conversions as local conversions.
-impl From<Household> for HouseholdImpl {
-    fn from(h: Household) -> HouseholdImpl {
-        HouseholdImpl {
-            house_name: h.house_name,
-            address: <OID as std::default::Default>::default(),
-            persons: vec![],
-            relations: vec![],
-        }
-    }
-}
-
-impl From<HouseholdImpl> for Household {
-    fn from(h: HouseholdImpl) -> Household {
-        Household {
-            house_name: h.house_name,
-            address: <Address as std::default::Default>::default(),
-            persons: vec![],
-            relations: vec![],
-        }
-    }
-}
-
 #[async_trait]
 impl COSICollection<'_, Household, HouseholdImpl, HouseholdOptional> for Household {
-    fn get_table_name() -> String {
-        return "household".to_string();
-    }
-
     async fn to_impl(client: &Client, mut orm: Vec<Household>) -> COSIResult<Vec<HouseholdImpl>> {
         // Slow, fetch results each and every one.
         let collection = Self::get_collection(client).await;
@@ -140,7 +97,7 @@ impl COSICollection<'_, Household, HouseholdImpl, HouseholdOptional> for Househo
         return Ok(results);
     }
 
-    async fn to_orm(client: &Client, imp: &Vec<HouseholdImpl>) -> COSIResult<Vec<Household>> {
+    async fn to_orm(client: &Client, imp: &[HouseholdImpl]) -> COSIResult<Vec<Household>> {
         let mut result = vec![];
 
         let address_col = Address::get_collection(client).await;
@@ -161,28 +118,14 @@ impl COSICollection<'_, Household, HouseholdImpl, HouseholdOptional> for Househo
 
             result.push(Household {
                 house_name: i.house_name.clone(),
-                address: address,
-                persons: persons,
+                address: address.into(),
+                persons,
                 relations: i.relations.clone(),
             })
         }
 
         return Ok(result);
     }
-
-    async fn process_foreign_keys<'b>(client: &'b Client, raw_doc: &'b mut Vec<Document>) {
-        let h_impls: Vec<HouseholdImpl> = raw_doc
-            .iter()
-            .map(|x| from_document(x.clone()).unwrap())
-            .collect();
-        // This will fetch the foreign keys for us.
-        let orms: Vec<Household> = Self::to_orm(client, &h_impls).await.unwrap();
-        let it = raw_doc.iter_mut().zip(orms);
-        for (rd, o) in it {
-            rd.insert("address", to_bson(&o.address).unwrap());
-            rd.insert("persons", to_bson(&o.persons).unwrap());
-        }
-    }
 }
 
 #[async_trait]
@@ -1,7 +1,11 @@
 pub mod address;
+pub mod attendance;
 pub mod auth;
+pub mod checkin;
 pub mod common;
 pub mod event;
 pub mod group;
 pub mod household;
 pub mod person;
+pub mod room;
+pub mod webhook;
@@ -7,25 +7,28 @@ use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 
 use names::Name;
-use rocket::form::{FromForm, FromFormField};
+use rocket::form::FromFormField;
 
 // cosi_db
-use super::common::{COSICollection, COSIForm, Generator};
+use super::common::Generator;
 use crate::cosi_db::errors::{COSIError, COSIResult};
+use cosi_db_derive::{CosiModel, CosiSchema};
 
-#[derive(Copy, Clone, Debug, FromFormField, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, FromFormField, Deserialize, Serialize, CosiSchema)]
 pub enum Sex {
     Male,
     Female,
     Undefined,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, CosiModel)]
+#[cosi(natural_key = "external_id", validate = "PersonOptional::_sanitize")]
 pub struct Person {
     pub first_name: String,
     pub middle_name: String,
     pub last_name: String,
     pub nicks: Vec<String>,
+    #[cosi(format = "%Y-%m-%d")]
     pub dob: Option<NaiveDate>,
     pub home_phone: Option<String>,
     pub work_phone: Option<String>,
@@ -33,36 +36,7 @@ pub struct Person {
     pub sex: Sex,
     pub notes: String,
     pub emergency_contact: String,
-}
-
-#[derive(Clone, Debug, Deserialize, FromForm, Serialize)]
-pub struct PersonImpl {
-    pub first_name: String,
-    pub middle_name: String,
-    pub last_name: String,
-    pub nicks: Vec<String>, // Vectors default to empty array.
-    pub dob: Option<String>,
-    pub home_phone: Option<String>,
-    pub work_phone: Option<String>,
-    pub mobile_phone: Option<String>,
-    pub sex: Sex,
-    pub notes: String,
-    pub emergency_contact: String,
-}
-
-#[derive(Clone, Debug, Deserialize, FromForm, Serialize)]
-pub struct PersonOptional {
-    pub first_name: Option<String>,
-    pub middle_name: Option<String>,
-    pub last_name: Option<String>,
-    pub nicks: Option<Vec<String>>,
-    pub dob: Option<Option<String>>,
-    pub home_phone: Option<Option<String>>,
-    pub work_phone: Option<Option<String>>,
-    pub mobile_phone: Option<Option<String>>,
-    pub sex: Option<Sex>,
-    pub notes: Option<String>,
-    pub emergency_contact: Option<String>,
+    pub external_id: Option<String>, // Id of the person in the system the data was imported from.
 }
 
 impl Default for Person {
@@ -79,44 +53,7 @@ impl Default for Person {
             sex: Sex::Undefined,
             notes: String::new(),
             emergency_contact: String::new(),
-        }
-    }
-}
-
-impl From<Person> for PersonImpl {
-    fn from(p: Person) -> PersonImpl {
-        PersonImpl {
-            first_name: p.first_name,
-            middle_name: p.middle_name,
-            last_name: p.last_name,
-            nicks: p.nicks,
-            dob: p.dob.map(|x| x.to_string()),
-            home_phone: p.home_phone.clone(),
-            work_phone: p.work_phone.clone(),
-            mobile_phone: p.mobile_phone.clone(),
-            sex: p.sex,
-            notes: String::new(),
-            emergency_contact: String::new(),
-        }
-    }
-}
-
-impl From<PersonImpl> for Person {
-    fn from(p: PersonImpl) -> Person {
-        Person {
-            first_name: p.first_name,
-            middle_name: p.middle_name,
-            last_name: p.last_name,
-            nicks: p.nicks,
-            dob: p
-                .dob
-                .map(|x| NaiveDate::parse_from_str(&x, "%Y-%m-%d").unwrap()),
-            home_phone: p.home_phone.clone(),
-            work_phone: p.work_phone.clone(),
-            mobile_phone: p.mobile_phone.clone(),
-            sex: p.sex,
-            notes: String::new(),
-            emergency_contact: String::new(),
+            external_id: None,
         }
     }
 }
@@ -146,53 +83,10 @@ impl PersonOptional {
             check(year.unwrap() > 1800, vec!["Year must be greater than 1800"])?;
 
             // Rest of the errors.
-            NaiveDate::parse_from_str(&dob, "%Y-%m-%d")?;
+            NaiveDate::parse_from_str(dob, "%Y-%m-%d")?;
         }
 
-        return Ok(());
-    }
-}
-
-impl COSIForm for PersonImpl {
-    fn sanitize_insert(&self) -> COSIResult<mongodb::bson::Document>
-    where
-        Self: Serialize,
-    {
-        // Add some common user errors.
-        // Reuse the same santize definition.
-        // TODO: into syntax is possible here.
-        PersonOptional::_sanitize(&PersonOptional {
-            first_name: Some(self.first_name.clone()),
-            middle_name: Some(self.middle_name.clone()),
-            last_name: Some(self.last_name.clone()),
-            nicks: Some(self.nicks.clone()),
-            dob: Some(self.dob.clone()),
-            home_phone: Some(self.home_phone.clone()),
-            work_phone: Some(self.work_phone.clone()),
-            mobile_phone: Some(self.mobile_phone.clone()),
-            sex: Some(self.sex),
-            notes: Some(self.notes.clone()),
-            emergency_contact: Some(self.emergency_contact.clone()),
-        })?;
-        return self.convert_to_document(true);
-    }
-}
-
-impl COSIForm for PersonOptional {
-    fn sanitize_insert(&self) -> COSIResult<mongodb::bson::Document>
-    where
-        Self: Serialize,
-    {
-        // Add some common user errors.
-        PersonOptional::_sanitize(self)?;
-        return self.convert_to_document(false);
-    }
-}
-
-#[async_trait]
-impl COSICollection<'_, Person, PersonImpl, PersonOptional> for Person {
-    fn get_table_name() -> String {
-        return "person".to_string();
+        Ok(())
     }
 }
 
@@ -251,6 +145,7 @@ impl Generator<Person> for Person {
                 sex: sexes[i as usize],
                 notes: get_name(),
                 emergency_contact: get_name(),
+                external_id: None,
             });
         }
 
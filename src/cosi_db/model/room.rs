@@ -0,0 +1,103 @@
+use async_trait::async_trait;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, from_document, Document};
+use mongodb::Client;
+use rand::{thread_rng, Rng};
+use rocket::futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+
+use names::Name;
+
+// cosi_db
+use crate::cosi_db::errors::{COSIError, COSIResult};
+use crate::cosi_db::model::address::Address;
+use crate::cosi_db::model::common::{COSICollection, Generator};
+use crate::cosi_db::model::event::Event;
+use cosi_db_derive::CosiModel;
+
+// Room of a facility, reserved by events, see cosi_db::booking.
+#[derive(Clone, Debug, Deserialize, Serialize, CosiModel)]
+#[cosi(natural_key = "facility, name", manual_collection)]
+pub struct Room {
+    // Building or campus the room is in.
+    pub facility: String,
+    pub name: String,
+    #[cosi(reference)]
+    pub address: Address,
+    // Persons the room seats.
+    pub capacity: Option<u32>,
+}
+
+#[async_trait]
+impl COSICollection<'_, Room, RoomImpl, RoomOptional> for Room {
+    async fn to_impl(client: &Client, orm: Vec<Room>) -> COSIResult<Vec<RoomImpl>> {
+        let mut results = vec![];
+        for o in orm {
+            let address = Address::find_or_insert(client, &o.address).await?;
+            results.push(RoomImpl {
+                facility: o.facility,
+                name: o.name,
+                address: address.into(),
+                capacity: o.capacity,
+            });
+        }
+        return Ok(results);
+    }
+
+    async fn to_orm(client: &Client, imp: &[RoomImpl]) -> COSIResult<Vec<Room>> {
+        let mut result = vec![];
+        let address_col = Address::get_collection(client).await;
+        for i in imp {
+            let address = address_col
+                .find_one(doc! {"_id": ObjectId::from(i.address.clone())}, None)
+                .await?
+                .ok_or(COSIError::msg("Unable to find provided address."))?;
+            result.push(Room {
+                facility: i.facility.clone(),
+                name: i.name.clone(),
+                address: address.into(),
+                capacity: i.capacity,
+            });
+        }
+        return Ok(result);
+    }
+
+    // Events no longer hold the room.
+    async fn after_delete(client: &Client, deleted: &Document) -> COSIResult<()> {
+        let id = deleted.get_object_id("_id")?;
+        Event::get_raw_document(client)
+            .await
+            .update_many(doc! {"rooms": id}, doc! {"$pull": {"rooms": id}}, None)
+            .await?;
+        return Ok(());
+    }
+}
+
+#[async_trait]
+impl Generator<Room> for Room {
+    async fn generate(client: &Client, size: u32) -> COSIResult<Vec<Room>> {
+        // Generates data dependent on the "address" table.
+        let mut result = Vec::new();
+
+        let address_col = Address::get_collection(client).await;
+        let address_agg = address_col
+            .aggregate([doc! {"$sample": {"size": size}}], None)
+            .await?;
+        let mut result_address: Vec<Document> = address_agg.try_collect().await.unwrap();
+
+        let mut generator = names::Generator::with_naming(Name::Plain);
+        let mut get_name = || generator.next().unwrap();
+
+        for _ in 0..size {
+            let address = result_address.pop().unwrap();
+            result.push(Room {
+                facility: get_name(),
+                name: format!("Room {}", thread_rng().gen_range(1, 400)),
+                address: from_document(address)?,
+                capacity: Some(thread_rng().gen_range(10, 300)),
+            });
+        }
+
+        return Ok(result);
+    }
+}
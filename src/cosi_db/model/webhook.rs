@@ -0,0 +1,89 @@
+// Outbound webhook subscriptions and their delivery log, see cosi_db::webhooks.
+use serde::{Deserialize, Serialize};
+
+use crate::cosi_db::changes::ChangeOp;
+use crate::cosi_db::errors::{COSIError, COSIResult};
+use crate::cosi_db::model::common::{COSICollection, COSIForm, COSIModel, OID};
+use crate::cosi_db::webhooks::tables;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    // Changes to deliver, such as "person.insert", "event.*" or "*".
+    pub events: Vec<String>,
+    // Key of the HMAC-SHA256 signature sent with every delivery.
+    pub secret: String,
+    // Username of the administrator who created the webhook. Only changes this user may read
+    // are delivered.
+    #[serde(default)]
+    pub owner: String,
+}
+
+impl Webhook {
+    pub fn validate(&self) -> COSIResult<()> {
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(COSIError::msg("Webhook url must be http or https."));
+        }
+        if self.secret.is_empty() {
+            return Err(COSIError::msg("Webhook secret is required."));
+        }
+        if self.events.is_empty() {
+            return Err(COSIError::msg("Webhook needs at least one event."));
+        }
+        for e in &self.events {
+            let valid = match e.split_once('.') {
+                None => e == "*",
+                Some((table, op)) => {
+                    tables().contains(&table) && (op == "*" || ChangeOp::parse(op).is_some())
+                }
+            };
+            if !valid {
+                return Err(COSIError::msg(format!("Invalid webhook event {}.", e)));
+            }
+        }
+        Ok(())
+    }
+
+    // Events are named "<table>.<op>".
+    pub fn subscribes_to(&self, table: &str, op: ChangeOp) -> bool {
+        self.events.iter().any(|e| match e.split_once('.') {
+            None => e == "*",
+            Some((t, o)) => t == table && (o == "*" || o == op.as_str()),
+        })
+    }
+}
+
+impl COSIForm for Webhook {}
+
+impl COSIModel for Webhook {
+    fn table_name() -> &'static str {
+        "webhook"
+    }
+}
+
+impl COSICollection<'_, Webhook, Webhook, Webhook> for Webhook {}
+
+// One entry per delivery attempt.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub webhook: OID,
+    // Shared by the retries of one delivery, sent as X-COSI-Delivery.
+    pub delivery: String,
+    pub event: String,
+    pub attempt: u32,
+    // HTTP status, missing when the request failed.
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub delivered: bool,
+    pub time: String,
+}
+
+impl COSIForm for WebhookDelivery {}
+
+impl COSIModel for WebhookDelivery {
+    fn table_name() -> &'static str {
+        "webhookdelivery"
+    }
+}
+
+impl COSICollection<'_, WebhookDelivery, WebhookDelivery, WebhookDelivery> for WebhookDelivery {}
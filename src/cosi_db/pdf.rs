@@ -0,0 +1,207 @@
+// Minimal PDF (ISO 32000) writer for text and outlines.
+// Text uses the standard Helvetica fonts, which viewers provide, so nothing is embedded. They are
+// WinAnsi encoded and characters outside of it are written as "?". Coordinates are in points, from
+// the top left corner of the page.
+use std::io::Write;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        }
+    }
+}
+
+// Widths of the printable ASCII characters in Helvetica, per 1000 units of the font size.
+const WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+// Helvetica-Bold runs wider, this bounds it from above.
+const BOLD_SCALE: f64 = 1.1;
+
+// Width of the text in points.
+pub fn text_width(text: &str, font: Font, size: f64) -> f64 {
+    let units: u32 = text
+        .chars()
+        .map(|c| match c {
+            ' '..='~' => u32::from(WIDTHS[c as usize - 32]),
+            _ => 556,
+        })
+        .sum();
+    let scale = match font {
+        Font::Regular => 1.0,
+        Font::Bold => BOLD_SCALE,
+    };
+    f64::from(units) * size / 1000.0 * scale
+}
+
+fn win_ansi(c: char) -> u8 {
+    match c {
+        ' '..='~' => c as u8,
+        '\u{a0}'..='\u{ff}' => c as u32 as u8,
+        '€' => 0x80,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        _ => b'?',
+    }
+}
+
+// Literal string with its delimiters escaped.
+fn literal(text: &str) -> Vec<u8> {
+    let mut result = vec![b'('];
+    for c in text.chars() {
+        let b = win_ansi(c);
+        if b == b'(' || b == b')' || b == b'\\' {
+            result.push(b'\\');
+        }
+        result.push(b);
+    }
+    result.push(b')');
+    result
+}
+
+pub struct Page {
+    height: f64,
+    content: Vec<u8>,
+}
+
+impl Page {
+    // y is the baseline of the text.
+    pub fn text(&mut self, x: f64, y: f64, font: Font, size: f64, text: &str) {
+        let _ = write!(
+            self.content,
+            "BT /{} {:.2} Tf {:.2} {:.2} Td ",
+            font.resource(),
+            size,
+            x,
+            self.height - y
+        );
+        self.content.extend(literal(text));
+        self.content.extend(b" Tj ET\n");
+    }
+
+    pub fn rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        let _ = writeln!(
+            self.content,
+            "0.5 w {:.2} {:.2} {:.2} {:.2} re S",
+            x,
+            self.height - y - height,
+            width,
+            height
+        );
+    }
+}
+
+pub struct Pdf {
+    width: f64,
+    height: f64,
+    pages: Vec<Page>,
+}
+
+impl Pdf {
+    pub fn new(width: f64, height: f64) -> Pdf {
+        Pdf {
+            width,
+            height,
+            pages: vec![],
+        }
+    }
+
+    pub fn add_page(&mut self) -> &mut Page {
+        self.pages.push(Page {
+            height: self.height,
+            content: vec![],
+        });
+        self.pages.last_mut().unwrap()
+    }
+
+    // Page being written, the first one is added when needed.
+    pub fn page(&mut self) -> &mut Page {
+        if self.pages.is_empty() {
+            return self.add_page();
+        }
+        self.pages.last_mut().unwrap()
+    }
+
+    pub fn render(&self) -> Vec<u8> {
+        // Objects 1 and 2 are the catalog and the page tree, 3 and 4 the fonts, then each page
+        // is followed by its content.
+        let mut objects: Vec<Vec<u8>> = vec![];
+        let kids: Vec<String> = (0..self.pages.len())
+            .map(|i| format!("{} 0 R", 5 + i * 2))
+            .collect();
+        objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+        objects.push(
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                kids.join(" "),
+                self.pages.len()
+            )
+            .into_bytes(),
+        );
+        for name in ["Helvetica", "Helvetica-Bold"] {
+            objects.push(
+                format!(
+                    "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+                    name
+                )
+                .into_bytes(),
+            );
+        }
+        for (i, page) in self.pages.iter().enumerate() {
+            objects.push(
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] \
+                     /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                    self.width,
+                    self.height,
+                    6 + i * 2
+                )
+                .into_bytes(),
+            );
+            let mut stream = format!("<< /Length {} >>\nstream\n", page.content.len()).into_bytes();
+            stream.extend(&page.content);
+            stream.extend(b"\nendstream");
+            objects.push(stream);
+        }
+
+        let mut out: Vec<u8> = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+        let mut offsets = vec![];
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend(format!("{} 0 obj\n", i + 1).into_bytes());
+            out.extend(object);
+            out.extend(b"\nendobj\n");
+        }
+        let xref = out.len();
+        out.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+        for offset in offsets {
+            out.extend(format!("{:010} 00000 n \n", offset).into_bytes());
+        }
+        out.extend(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+                objects.len() + 1,
+                xref
+            )
+            .into_bytes(),
+        );
+        out
+    }
+}
@@ -0,0 +1,281 @@
+// Expands events into their occurrences.
+// A repeating event, or one meeting on some days, whose end datetime is on a later day runs until
+// then, each occurrence ending at the time of day of the end datetime. Otherwise the event lasts
+// from its start to its end datetime, repeating without end when reoccuring is set.
+// reoccuring and freq tell the period and how many periods apart occurrences are, meeting_days
+// the days of the week it meets. Weeks start on Monday and monthly events repeat on the day of
+// the month they start, skipping months without it. The start is always the first occurrence.
+// Exceptions cancel or move single occurrences, those not matching an occurrence are ignored.
+use std::collections::HashMap;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use mongodb::bson::oid::ObjectId;
+use serde::Serialize;
+
+use crate::cosi_db::model::event::{Days, Event, EventException, Reoccurring};
+
+// Format of event datetimes in the database.
+pub const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+pub const MAX_OCCURRENCES: usize = 5000;
+
+// End of the series, when the event runs over several days.
+pub fn series_end(event: &Event) -> Option<NaiveDateTime> {
+    event
+        .end_datetime
+        .filter(|end| end.date() > event.start_datetime.date())
+}
+
+pub fn repeats(event: &Event) -> bool {
+    event.reoccuring.is_some() || (!event.meeting_days.is_empty() && series_end(event).is_some())
+}
+
+// End of the first occurrence.
+pub fn occurrence_end(event: &Event) -> Option<NaiveDateTime> {
+    let start = event.start_datetime;
+    let end = event.end_datetime.filter(|end| *end > start)?;
+    if end.date() == start.date() || !repeats(event) {
+        return Some(end);
+    }
+    Some(start.date().and_time(end.time())).filter(|end| *end > start)
+}
+
+fn day_index(day: Days) -> u32 {
+    match day {
+        Days::M => 0,
+        Days::Tu => 1,
+        Days::W => 2,
+        Days::Th => 3,
+        Days::F => 4,
+        Days::Sa => 5,
+        Days::Su => 6,
+    }
+}
+
+fn months(date: NaiveDate) -> i64 {
+    i64::from(date.year()) * 12 + i64::from(date.month0())
+}
+
+// Dates of the given period, in order.
+fn period_dates(event: &Event, days: &[u32], period: i64) -> Vec<NaiveDate> {
+    let start = event.start_datetime.date();
+    let interval = i64::from(event.freq.unwrap_or(1).max(1));
+    match event.reoccuring {
+        Some(Reoccurring::Days) => {
+            let date = start + Duration::days(period * interval);
+            if days.is_empty() || days.contains(&date.weekday().num_days_from_monday()) {
+                vec![date]
+            } else {
+                vec![]
+            }
+        }
+        Some(Reoccurring::Months) => {
+            let month = months(start) + period * interval;
+            NaiveDate::from_ymd_opt((month / 12) as i32, (month % 12) as u32 + 1, start.day())
+                .into_iter()
+                .collect()
+        }
+        // Meeting days without reoccuring repeat weekly.
+        Some(Reoccurring::Weeks) | None => {
+            let monday = start - Duration::days(start.weekday().num_days_from_monday().into())
+                + Duration::weeks(period * interval);
+            let own = [start.weekday().num_days_from_monday()];
+            let days = if days.is_empty() { &own[..] } else { days };
+            days.iter()
+                .map(|d| monday + Duration::days((*d).into()))
+                .collect()
+        }
+    }
+}
+
+// First date of the given period. Every date of the period is on or after it.
+fn period_start(event: &Event, period: i64) -> Option<NaiveDate> {
+    let start = event.start_datetime.date();
+    let interval = i64::from(event.freq.unwrap_or(1).max(1));
+    match event.reoccuring {
+        Some(Reoccurring::Days) => Some(start + Duration::days(period * interval)),
+        Some(Reoccurring::Months) => {
+            let month = months(start) + period * interval;
+            NaiveDate::from_ymd_opt((month / 12) as i32, (month % 12) as u32 + 1, 1)
+        }
+        Some(Reoccurring::Weeks) | None => Some(
+            start - Duration::days(start.weekday().num_days_from_monday().into())
+                + Duration::weeks(period * interval),
+        ),
+    }
+}
+
+// Whether the event meets again after its start. Events repeating every few days only reach
+// some days of the week, such as the day they start on when repeating every 7 days.
+pub fn meets_again(
+    reoccuring: Option<Reoccurring>,
+    freq: Option<u8>,
+    meeting_days: &[Days],
+    start: NaiveDate,
+) -> bool {
+    let interval = u32::from(freq.unwrap_or(1).max(1));
+    let weekday = start.weekday().num_days_from_monday();
+    match reoccuring {
+        Some(Reoccurring::Days) if !meeting_days.is_empty() => (1..=7).any(|k| {
+            let reached = (weekday + k * interval) % 7;
+            meeting_days.iter().any(|d| day_index(*d) == reached)
+        }),
+        _ => true,
+    }
+}
+
+// Periods that end before the given date.
+fn periods_before(event: &Event, date: NaiveDate) -> i64 {
+    let start = event.start_datetime.date();
+    let interval = i64::from(event.freq.unwrap_or(1).max(1));
+    let units = match event.reoccuring {
+        Some(Reoccurring::Days) => (date - start).num_days(),
+        Some(Reoccurring::Months) => months(date) - months(start),
+        Some(Reoccurring::Weeks) | None => (date - start).num_days() / 7,
+    };
+    (units / interval - 1).max(0)
+}
+
+// Scheduled (start, end) of the occurrences overlapping [from, to), by start.
+pub fn schedule(
+    event: &Event,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Vec<(NaiveDateTime, Option<NaiveDateTime>)> {
+    let start = event.start_datetime;
+    let length = occurrence_end(event).map(|end| end - start);
+    let overlaps = |s: NaiveDateTime| {
+        let end = length.map(|l| s + l);
+        if s < to && (s >= from || end.is_some_and(|e| e > from)) {
+            Some((s, end))
+        } else {
+            None
+        }
+    };
+    let mut result: Vec<_> = overlaps(start).into_iter().collect();
+    if !repeats(event) {
+        return result;
+    }
+
+    let until = series_end(event);
+    let mut days: Vec<u32> = event.meeting_days.iter().map(|d| day_index(*d)).collect();
+    days.sort_unstable();
+    days.dedup();
+    let earliest = from - length.unwrap_or_else(Duration::zero);
+    let mut period = periods_before(event, earliest.date());
+    loop {
+        // Periods may hold no date at all, such as months without the day the event starts on.
+        match period_start(event, period) {
+            Some(first) if first.and_hms(0, 0, 0) >= to => return result,
+            Some(first) if until.is_some_and(|u| first > u.date()) => return result,
+            None => return result,
+            _ => {}
+        }
+        for date in period_dates(event, &days, period) {
+            let s = date.and_time(start.time());
+            if s <= start {
+                continue;
+            }
+            if s >= to || until.is_some_and(|u| s > u) {
+                return result;
+            }
+            result.extend(overlaps(s));
+        }
+        if result.len() >= MAX_OCCURRENCES {
+            return result;
+        }
+        period += 1;
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Occurrence {
+    pub event: String,
+    pub name: String,
+    // Start as scheduled, identifying the occurrence in exceptions.
+    pub occurrence: String,
+    pub start_datetime: String,
+    pub end_datetime: Option<String>,
+    pub moved: bool,
+    pub cancelled: bool,
+}
+
+fn parse(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, DATETIME_FORMAT).ok()
+}
+
+// Whether an occurrence of the event starts at the given time.
+pub fn is_scheduled(event: &Event, time: NaiveDateTime) -> bool {
+    schedule(event, time, time + Duration::seconds(1))
+        .iter()
+        .any(|(s, _)| *s == time)
+}
+
+// New start and end of a moved occurrence. The end defaults to the length of the occurrence.
+pub fn moved_to(
+    event: &Event,
+    exception: &EventException,
+) -> Option<(NaiveDateTime, NaiveDateTime, Option<NaiveDateTime>)> {
+    if exception.cancelled {
+        return None;
+    }
+    let original = parse(&exception.occurrence)?;
+    let start = parse(exception.start_datetime.as_deref()?)?;
+    let end = match exception.end_datetime.as_deref() {
+        Some(end) => Some(parse(end)?),
+        None => occurrence_end(event).map(|end| start + (end - event.start_datetime)),
+    };
+    Some((original, start, end))
+}
+
+// Occurrences overlapping [from, to) after exceptions, by start.
+pub fn occurrences(
+    id: &ObjectId,
+    event: &Event,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    cancelled: bool,
+) -> Vec<Occurrence> {
+    let exceptions: HashMap<NaiveDateTime, &EventException> = event
+        .exceptions
+        .iter()
+        .filter_map(|e| parse(&e.occurrence).map(|o| (o, e)))
+        .collect();
+    let format = |t: NaiveDateTime| t.format(DATETIME_FORMAT).to_string();
+    let occurrence = |original, start, end: Option<NaiveDateTime>| Occurrence {
+        event: id.to_hex(),
+        name: event.name.clone(),
+        occurrence: format(original),
+        start_datetime: format(start),
+        end_datetime: end.map(format),
+        moved: false,
+        cancelled: false,
+    };
+
+    let mut result = vec![];
+    for (start, end) in schedule(event, from, to) {
+        match exceptions.get(&start) {
+            None => result.push(occurrence(start, start, end)),
+            Some(e) if e.cancelled => {
+                if cancelled {
+                    result.push(Occurrence {
+                        cancelled: true,
+                        ..occurrence(start, start, end)
+                    });
+                }
+            }
+            // Shown at their new time below.
+            Some(_) => {}
+        }
+    }
+    for (original, start, end) in event.exceptions.iter().filter_map(|e| moved_to(event, e)) {
+        let overlaps = start < to && (start >= from || end.is_some_and(|e| e > from));
+        if overlaps && is_scheduled(event, original) {
+            result.push(Occurrence {
+                moved: true,
+                ..occurrence(original, start, end)
+            });
+        }
+    }
+    result.sort_by(|a, b| a.start_datetime.cmp(&b.start_datetime));
+    result
+}
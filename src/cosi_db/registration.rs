@@ -0,0 +1,292 @@
+// Event capacities and waitlists.
+// Registrations take a seat per person: one for a person, one per member for households and
+// groups, counted when the registration is placed. Registrations beyond the capacity are refused,
+// or waitlisted when the event keeps a waitlist. Waitlisted registrations are promoted in the order
+// they were made once seats are freed, skipping those still too large to fit. The status is kept by
+// the server: updates cannot set it, and updates changing the event or the party place it again.
+// Registrations are placed and written under a lock, so that concurrent registrations on the server
+// cannot take the same seats.
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use mongodb::bson::{doc, from_bson, from_document, oid::ObjectId, to_bson, Bson, Document};
+use mongodb::options::FindOptions;
+use mongodb::Client;
+use rocket::tokio::sync::{Mutex, MutexGuard};
+use serde::Serialize;
+
+use crate::cosi_db::changes::{self, ChangeOp};
+use crate::cosi_db::errors::{COSIError, COSIResult, Refused};
+use crate::cosi_db::model::common::{COSICollection, COSIModel};
+use crate::cosi_db::model::event::{
+    Event, EventImpl, EventKeyType, EventRegistration, RegistrationStatus,
+};
+use crate::cosi_db::model::group::GroupRelation;
+use crate::cosi_db::model::household::Household;
+
+static PLACING: Mutex<()> = Mutex::const_new(());
+
+// Held by the writes of registrations and by promotions, see COSICollection::write_lock.
+pub async fn lock() -> MutexGuard<'static, ()> {
+    PLACING.lock().await
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Seats {
+    pub capacity: Option<u32>,
+    pub waitlist: bool,
+    // Persons of the registered registrations.
+    pub taken: u32,
+    // Waitlisted registrations, in the order they are promoted.
+    pub waitlisted: Vec<ObjectId>,
+}
+
+impl Seats {
+    fn fits(&self, size: u32) -> bool {
+        match self.capacity {
+            Some(c) => self.taken + size <= c,
+            None => true,
+        }
+    }
+}
+
+// Persons taking a seat with the registration.
+pub async fn party_size(client: &Client, registration: &Document) -> COSIResult<u32> {
+    let key_type: EventKeyType =
+        from_bson(registration.get("key_type").cloned().unwrap_or(Bson::Null))?;
+    let key = |field: &str| {
+        registration
+            .get_object_id(field)
+            .map_err(|_| COSIError::msg(format!("Registrations by {} need a {}.", field, field)))
+    };
+    match key_type {
+        EventKeyType::Person => Ok(1),
+        EventKeyType::Household => {
+            let household =
+                Household::find_raw_document(client, Some(doc! {"_id": key("household")?}), None)
+                    .await?
+                    .pop()
+                    .ok_or(COSIError::msg("Unable to find household."))?;
+            Ok(household.get_array("persons").map_or(0, |p| p.len() as u32))
+        }
+        EventKeyType::Group => {
+            let members = GroupRelation::get_raw_document(client)
+                .await
+                .count_documents(doc! {"group": key("group")?}, None)
+                .await?;
+            Ok(members as u32)
+        }
+    }
+}
+
+// Registrations of the event with the status, first made first.
+async fn registrations(
+    client: &Client,
+    event: ObjectId,
+    status: RegistrationStatus,
+) -> COSIResult<Vec<Document>> {
+    // Registrations made before capacities have no status and are registered.
+    let status = match status {
+        RegistrationStatus::Registered => doc! {"$ne": "Waitlisted"},
+        RegistrationStatus::Waitlisted => doc! {"$eq": "Waitlisted"},
+    };
+    let options = FindOptions::builder()
+        .sort(doc! {"timestamp": 1, "_id": 1})
+        .build();
+    EventRegistration::find_raw_document(
+        client,
+        Some(doc! {"event": event, "status": status}),
+        Some(options),
+    )
+    .await
+}
+
+pub async fn seats(client: &Client, event: ObjectId) -> COSIResult<Seats> {
+    count_seats(client, event, None).await
+}
+
+// Registrations whose party is gone take no seats, so they do not block the event.
+fn resolved(registration: &Document, size: COSIResult<u32>) -> Option<u32> {
+    match size {
+        Ok(size) => Some(size),
+        Err(err) => {
+            rocket::warn!(
+                "Skipping registration {}: {}",
+                registration.get("_id").unwrap_or(&Bson::Null),
+                err
+            );
+            None
+        }
+    }
+}
+
+// Seats of the event, leaving out the registration being placed again.
+async fn count_seats(
+    client: &Client,
+    event: ObjectId,
+    except: Option<ObjectId>,
+) -> COSIResult<Seats> {
+    let found = Event::find_raw_document(client, Some(doc! {"_id": event}), None)
+        .await?
+        .pop()
+        .ok_or(COSIError::msg("Unable to find event."))?;
+    let found: EventImpl = from_document(found)?;
+
+    let mut result = Seats {
+        capacity: found.capacity,
+        waitlist: found.waitlist.unwrap_or(false),
+        taken: 0,
+        waitlisted: vec![],
+    };
+    for r in registrations(client, event, RegistrationStatus::Registered).await? {
+        if r.get_object_id("_id").ok() == except {
+            continue;
+        }
+        result.taken += resolved(&r, party_size(client, &r).await).unwrap_or(0);
+    }
+    for r in registrations(client, event, RegistrationStatus::Waitlisted).await? {
+        result.waitlisted.push(r.get_object_id("_id")?);
+    }
+    Ok(result)
+}
+
+async fn place_one(
+    client: &Client,
+    events: &mut HashMap<ObjectId, Seats>,
+    registration: &mut Document,
+) -> COSIResult<()> {
+    let event = registration
+        .get_object_id("event")
+        .map_err(|_| COSIError::msg("Registrations need an event."))?;
+    if let Entry::Vacant(e) = events.entry(event) {
+        let except = registration.get_object_id("_id").ok();
+        e.insert(count_seats(client, event, except).await?);
+    }
+    let seats = events.get_mut(&event).unwrap();
+    let size = party_size(client, registration).await?;
+
+    let status = match seats.capacity {
+        _ if seats.fits(size) => RegistrationStatus::Registered,
+        Some(c) if size > c => {
+            return Err(Refused(format!(
+                "Registration of {} persons is over the capacity of {}.",
+                size, c
+            ))
+            .into())
+        }
+        _ if seats.waitlist => RegistrationStatus::Waitlisted,
+        _ => return Err(Refused("Event is full.".to_string()).into()),
+    };
+    if status == RegistrationStatus::Registered {
+        seats.taken += size;
+    }
+    registration.insert("status", to_bson(&status)?);
+    Ok(())
+}
+
+// Sets the status of registrations about to be written, refusing those that do not fit.
+// Registrations of the batch count the ones placed before them.
+pub async fn place(client: &Client, registrations: &mut [Document]) -> Vec<COSIResult<()>> {
+    let mut events: HashMap<ObjectId, Seats> = HashMap::new();
+    let mut results = vec![];
+    for r in registrations.iter_mut() {
+        results.push(place_one(client, &mut events, r).await);
+    }
+    results
+}
+
+// Fields naming the event and the party of a registration.
+const PLACED_FIELDS: [&str; 5] = ["event", "key_type", "person", "household", "group"];
+
+// Drops status changes from the update of a registration and places it again when the update
+// changes its event or party.
+pub async fn place_update(
+    client: &Client,
+    query: &Document,
+    update: &mut Document,
+) -> COSIResult<()> {
+    for op in ["$set", "$unset"] {
+        if let Ok(fields) = update.get_document_mut(op) {
+            fields.remove("status");
+        }
+    }
+    let current = match EventRegistration::find_raw_document(client, Some(query.clone()), None)
+        .await?
+        .pop()
+    {
+        Some(current) => current,
+        None => return Ok(()),
+    };
+
+    let mut updated = current.clone();
+    if let Ok(set) = update.get_document("$set") {
+        updated.extend(set.clone());
+    }
+    if let Ok(unset) = update.get_document("$unset") {
+        for field in unset.keys() {
+            updated.remove(field);
+        }
+    }
+    if PLACED_FIELDS
+        .iter()
+        .all(|f| current.get(f) == updated.get(f))
+    {
+        return Ok(());
+    }
+
+    place_one(client, &mut HashMap::new(), &mut updated).await?;
+    // Registrations moving to another event free their seats on the previous one.
+    if let Ok(previous) = current.get_object_id("event") {
+        if updated.get_object_id("event").ok() != Some(previous) {
+            promote(client, previous, current.get_object_id("_id").ok()).await?;
+        }
+    }
+    let status = updated.get("status").cloned().unwrap_or(Bson::Null);
+    match update.get_document_mut("$set") {
+        Ok(set) => {
+            set.insert("status", status);
+        }
+        Err(_) => {
+            update.insert("$set", doc! {"status": status});
+        }
+    }
+    Ok(())
+}
+
+// Registers the waitlisted registrations that fit and returns them, leaving out the registration
+// leaving the event. Callers hold the lock.
+pub async fn promote(
+    client: &Client,
+    event: ObjectId,
+    except: Option<ObjectId>,
+) -> COSIResult<Vec<ObjectId>> {
+    let mut seats = count_seats(client, event, except).await?;
+    let mut promoted = vec![];
+    for r in registrations(client, event, RegistrationStatus::Waitlisted).await? {
+        if r.get_object_id("_id").ok() == except {
+            continue;
+        }
+        let size = match resolved(&r, party_size(client, &r).await) {
+            Some(size) if seats.fits(size) => size,
+            _ => continue,
+        };
+        // Written past update_datum, which keeps clients from setting the status.
+        let id = r.get_object_id("_id")?;
+        EventRegistration::get_raw_document(client)
+            .await
+            .update_one(
+                doc! {"_id": id},
+                doc! {"$set": {"status": to_bson(&RegistrationStatus::Registered)?}},
+                None,
+            )
+            .await?;
+        changes::publish(
+            EventRegistration::table_name(),
+            ChangeOp::Update,
+            Some(&Bson::ObjectId(id)),
+        );
+        seats.taken += size;
+        promoted.push(id);
+    }
+    Ok(promoted)
+}
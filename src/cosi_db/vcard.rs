@@ -0,0 +1,617 @@
+// vCard (RFC 6350) export and import of people.
+// A card holds the name, nicks, phones and birthday of a person plus the address of each of
+// their households. Exports are vCard 4.0, imports also read the 3.0 cards most phones export.
+// Imported people that already exist, by the UID of an exported card or by name plus birthday
+// or phone, are reported as duplicates and left untouched.
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use mongodb::bson::{doc, from_document, oid::ObjectId, Bson, Document};
+use mongodb::options::FindOptions;
+use mongodb::Client;
+use serde::Serialize;
+
+use crate::cosi_db::errors::{COSIError, COSIResult};
+use crate::cosi_db::model::address::{Address, AddressImpl};
+use crate::cosi_db::model::common::{COSICollection, COSIForm};
+use crate::cosi_db::model::household::Household;
+use crate::cosi_db::model::person::{Person, PersonImpl};
+
+// UIDs of exported cards, followed by the object id of the person.
+pub const UID_PREFIX: &str = "urn:cosi-db:person:";
+const LINE_OCTETS: usize = 75;
+
+#[derive(Clone, Debug, Default)]
+pub struct Card {
+    pub uid: Option<String>,
+    pub person: Person,
+    pub addresses: Vec<Address>,
+}
+
+pub fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
+
+// Lines longer than 75 octets continue on the next line after a space.
+pub fn fold(line: &str, out: &mut String) {
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > LINE_OCTETS {
+            out.push_str("\r\n ");
+            octets = 1;
+        }
+        out.push(c);
+        octets += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn tel_uri(phone: &str) -> String {
+    let number: String = phone
+        .trim()
+        .chars()
+        .filter_map(|c| match c {
+            '0'..='9' | '+' | '-' | '.' | '(' | ')' => Some(c),
+            c if c.is_whitespace() => Some('-'),
+            _ => None,
+        })
+        .collect();
+    format!("tel:{}", number)
+}
+
+fn full_name(person: &Person) -> String {
+    [&person.first_name, &person.middle_name, &person.last_name]
+        .iter()
+        .filter(|n| !n.is_empty())
+        .map(|n| n.as_str())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+pub fn render(id: &ObjectId, person: &Person, addresses: &[Address]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCARD".to_string(),
+        "VERSION:4.0".to_string(),
+        format!("UID:{}{}", UID_PREFIX, id.to_hex()),
+        format!("FN:{}", escape(&full_name(person))),
+        format!(
+            "N:{};{};{};;",
+            escape(&person.last_name),
+            escape(&person.first_name),
+            escape(&person.middle_name)
+        ),
+    ];
+    if !person.nicks.is_empty() {
+        let nicks: Vec<String> = person.nicks.iter().map(|n| escape(n)).collect();
+        lines.push(format!("NICKNAME:{}", nicks.join(",")));
+    }
+    if let Some(dob) = person.dob {
+        lines.push(format!("BDAY:{}", dob.format("%Y%m%d")));
+    }
+    for (kind, phone) in [
+        ("home", &person.home_phone),
+        ("work", &person.work_phone),
+        ("cell", &person.mobile_phone),
+    ] {
+        if let Some(p) = phone.as_deref().filter(|p| !p.trim().is_empty()) {
+            lines.push(format!("TEL;VALUE=uri;TYPE={}:{}", kind, tel_uri(p)));
+        }
+    }
+    for a in addresses {
+        let street: Vec<String> = [&a.line_one, &a.line_two, &a.line_three]
+            .iter()
+            .filter(|l| !l.is_empty())
+            .map(|l| escape(l))
+            .collect();
+        lines.push(format!(
+            "ADR;TYPE=home:;;{};{};{};{};{}",
+            street.join(","),
+            escape(&a.city),
+            escape(&a.region),
+            escape(a.postal_code.as_deref().unwrap_or("")),
+            escape(a.country.as_deref().unwrap_or(""))
+        ));
+    }
+    lines.push("END:VCARD".to_string());
+
+    let mut out = String::new();
+    for l in lines {
+        fold(&l, &mut out);
+    }
+    out
+}
+
+// Cards of the given people, each with the addresses of their households.
+pub async fn render_people(client: &Client, ids: &[ObjectId]) -> COSIResult<String> {
+    let options = FindOptions::builder()
+        .sort(doc! {"last_name": 1, "first_name": 1, "_id": 1})
+        .build();
+    let people =
+        Person::find_raw_document(client, Some(doc! {"_id": {"$in": ids}}), Some(options)).await?;
+    let households =
+        Household::find_raw_document(client, Some(doc! {"persons": {"$in": ids}}), None).await?;
+    let address_ids: Vec<ObjectId> = households
+        .iter()
+        .filter_map(|h| h.get_object_id("address").ok())
+        .collect();
+    let mut addresses: HashMap<ObjectId, Address> = HashMap::new();
+    for a in
+        Address::find_raw_document(client, Some(doc! {"_id": {"$in": address_ids}}), None).await?
+    {
+        addresses.insert(a.get_object_id("_id")?, from_document(a)?);
+    }
+
+    let mut out = String::new();
+    for p in people {
+        let id = p.get_object_id("_id")?;
+        let mut own: Vec<ObjectId> = vec![];
+        for h in &households {
+            let member = h
+                .get_array("persons")
+                .map(|ps| ps.contains(&Bson::ObjectId(id)))
+                .unwrap_or(false);
+            match h.get_object_id("address") {
+                Ok(a) if member && !own.contains(&a) => own.push(a),
+                _ => {}
+            }
+        }
+        let own: Vec<Address> = own
+            .iter()
+            .filter_map(|a| addresses.get(a).cloned())
+            .collect();
+        out.push_str(&render(&id, &from_document(p)?, &own));
+    }
+    Ok(out)
+}
+
+// Continuation lines start with a space or tab.
+pub fn unfold(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .replace("\n ", "")
+        .replace("\n\t", "")
+}
+
+pub fn unescape(value: &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+// Splits on separators that are not escaped. Values stay escaped.
+fn split_unescaped(value: &str, sep: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut escaped = false;
+    for c in value.chars() {
+        if c == sep && !escaped {
+            parts.push(String::new());
+            continue;
+        }
+        escaped = c == '\\' && !escaped;
+        parts.last_mut().unwrap().push(c);
+    }
+    parts
+}
+
+fn component(parts: &[String], idx: usize) -> String {
+    parts
+        .get(idx)
+        .map(|p| {
+            split_unescaped(p, ',')
+                .iter()
+                .map(|v| unescape(v).trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect::<Vec<String>>()
+                .join(" ")
+        })
+        .unwrap_or_default()
+}
+
+// Content line, as in iCalendar.
+pub struct Property {
+    pub name: String,
+    pub types: Vec<String>,
+    // Other parameters, with upper case keys.
+    pub params: Vec<(String, String)>,
+    pub value: String,
+}
+
+pub fn parse_property(line: &str) -> Option<Property> {
+    // Parameter values may be quoted and hold ":".
+    let mut quoted = false;
+    let split = line.char_indices().find(|(_, c)| {
+        if *c == '"' {
+            quoted = !quoted;
+        }
+        *c == ':' && !quoted
+    })?;
+    let (head, value) = (&line[..split.0], &line[split.0 + 1..]);
+    let mut params = head.split(';');
+    let name = params.next()?;
+    // Drop groups such as item1.TEL.
+    let name = name.rsplit('.').next().unwrap_or(name).to_uppercase();
+
+    let mut types = vec![];
+    let mut others = vec![];
+    for p in params {
+        let (key, values) = match p.split_once('=') {
+            Some((k, v)) => (k.to_uppercase(), v),
+            // vCard 2.1 style, e.g. TEL;CELL:...
+            None => ("TYPE".to_string(), p),
+        };
+        if key == "TYPE" {
+            types.extend(
+                values
+                    .trim_matches('"')
+                    .split(',')
+                    .map(|t| t.trim().to_lowercase()),
+            );
+        } else {
+            others.push((key, values.trim_matches('"').to_string()));
+        }
+    }
+    Some(Property {
+        name,
+        types,
+        params: others,
+        value: value.to_string(),
+    })
+}
+
+fn parse_birthday(value: &str) -> COSIResult<Option<NaiveDate>> {
+    let date = value.split('T').next().unwrap_or("").trim();
+    // Dates without a year such as --0314 cannot be stored.
+    if date.starts_with("--") || date.is_empty() {
+        return Ok(None);
+    }
+    NaiveDate::parse_from_str(date, "%Y%m%d")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%Y-%m-%d"))
+        .map(Some)
+        .map_err(|_| COSIError::msg(format!("Invalid birthday {}.", value)))
+}
+
+fn parse_address(value: &str) -> Option<Address> {
+    // ;;<street>;<locality>;<region>;<postal code>;<country>, the first two being obsolete.
+    let parts = split_unescaped(value, ';');
+    let mut street: Vec<String> = vec![];
+    for idx in 0..3 {
+        if let Some(p) = parts.get(idx) {
+            for v in split_unescaped(p, ',') {
+                street.extend(
+                    unescape(&v)
+                        .lines()
+                        .map(|l| l.trim().to_string())
+                        .filter(|l| !l.is_empty()),
+                );
+            }
+        }
+    }
+    let city = component(&parts, 3);
+    if street.is_empty() && city.is_empty() {
+        return None;
+    }
+    // Extra street lines are kept on the last line.
+    let rest = if street.len() > 2 {
+        street.split_off(2).join(", ")
+    } else {
+        String::new()
+    };
+    let optional = |v: String| Some(v).filter(|v| !v.is_empty());
+    Some(Address {
+        line_one: street.first().cloned().unwrap_or_default(),
+        line_two: street.get(1).cloned().unwrap_or_default(),
+        line_three: rest,
+        city,
+        region: component(&parts, 4),
+        postal_code: optional(component(&parts, 5)),
+        county: None,
+        country: optional(component(&parts, 6)),
+    })
+}
+
+fn parse_card(lines: &[&str]) -> COSIResult<Card> {
+    let mut card = Card::default();
+    let mut formatted: Option<String> = None;
+    let mut other_phones: Vec<String> = vec![];
+    for line in lines {
+        let property = match parse_property(line) {
+            Some(p) => p,
+            None => continue,
+        };
+        let value = &property.value;
+        let person = &mut card.person;
+        match property.name.as_str() {
+            "UID" => card.uid = Some(value.trim().to_string()),
+            "FN" => formatted = Some(unescape(value).trim().to_string()),
+            "N" => {
+                let parts = split_unescaped(value, ';');
+                person.last_name = component(&parts, 0);
+                person.first_name = component(&parts, 1);
+                person.middle_name = component(&parts, 2);
+            }
+            "NICKNAME" => person.nicks.extend(
+                split_unescaped(value, ',')
+                    .iter()
+                    .map(|n| unescape(n).trim().to_string())
+                    .filter(|n| !n.is_empty()),
+            ),
+            "BDAY" => person.dob = parse_birthday(value)?,
+            "TEL" => {
+                let number = unescape(value.trim().trim_start_matches("tel:"));
+                let slot = if property.types.iter().any(|t| t == "cell") {
+                    &mut person.mobile_phone
+                } else if property.types.iter().any(|t| t == "work") {
+                    &mut person.work_phone
+                } else {
+                    &mut person.home_phone
+                };
+                match slot {
+                    None => *slot = Some(number),
+                    Some(_) => other_phones.push(number),
+                }
+            }
+            "ADR" => card.addresses.extend(parse_address(value)),
+            _ => {}
+        }
+    }
+
+    // Phones of a taken kind go to the remaining fields.
+    let person = &mut card.person;
+    for number in other_phones {
+        for slot in [
+            &mut person.home_phone,
+            &mut person.mobile_phone,
+            &mut person.work_phone,
+        ] {
+            if slot.is_none() {
+                *slot = Some(number);
+                break;
+            }
+        }
+    }
+    if person.first_name.is_empty() && person.last_name.is_empty() {
+        let formatted = formatted.unwrap_or_default();
+        let mut names = formatted.split_whitespace();
+        person.first_name = names.next().unwrap_or("").to_string();
+        person.last_name = names.collect::<Vec<&str>>().join(" ");
+    }
+    if person.first_name.is_empty() && person.last_name.is_empty() {
+        return Err(COSIError::msg("Card has no name."));
+    }
+    PersonImpl::from(card.person.clone()).sanitize_insert()?;
+    for a in &card.addresses {
+        AddressImpl::from(a.clone()).sanitize_insert()?;
+    }
+    Ok(card)
+}
+
+// Cards of a .vcf file, in order.
+pub fn parse(text: &str) -> Vec<COSIResult<Card>> {
+    let text = unfold(text);
+    let mut result = vec![];
+    let mut current: Option<Vec<&str>> = None;
+    for line in text.lines().map(|l| l.trim_end()) {
+        if line.eq_ignore_ascii_case("BEGIN:VCARD") {
+            current = Some(vec![]);
+        } else if line.eq_ignore_ascii_case("END:VCARD") {
+            if let Some(lines) = current.take() {
+                result.push(parse_card(&lines));
+            }
+        } else if let Some(lines) = current.as_mut() {
+            lines.push(line);
+        }
+    }
+    result
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CardStatus {
+    Created,
+    Duplicate,
+    Invalid,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CardResult {
+    // Position in the file, starting at 1.
+    pub card: usize,
+    pub name: String,
+    pub status: CardStatus,
+    // The created person, or the existing one for duplicates.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub _id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub err: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct VCardReport {
+    pub dry_run: bool,
+    pub total: usize,
+    pub created: usize,
+    pub duplicates: usize,
+    pub invalid: usize,
+    pub cards: Vec<CardResult>,
+}
+
+fn digits(phone: &str) -> String {
+    phone.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+fn phones(person: &Person) -> Vec<String> {
+    [&person.home_phone, &person.work_phone, &person.mobile_phone]
+        .iter()
+        .filter_map(|p| p.as_deref().map(digits))
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+// Same name and, when the card has them, the same birthday or a shared phone.
+fn same_person(a: &Person, b: &Person) -> bool {
+    if !a.first_name.eq_ignore_ascii_case(&b.first_name)
+        || !a.last_name.eq_ignore_ascii_case(&b.last_name)
+    {
+        return false;
+    }
+    let a_phones = phones(a);
+    if a.dob.is_none() && a_phones.is_empty() {
+        return true;
+    }
+    (a.dob.is_some() && a.dob == b.dob) || phones(b).iter().any(|p| a_phones.contains(p))
+}
+
+fn exact(value: &str) -> Document {
+    let mut pattern = String::from("^");
+    for c in value.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('$');
+    doc! {"$regex": pattern, "$options": "i"}
+}
+
+async fn find_duplicate(client: &Client, card: &Card) -> COSIResult<Option<ObjectId>> {
+    let uid = card
+        .uid
+        .as_deref()
+        .and_then(|u| u.strip_prefix(UID_PREFIX))
+        .and_then(|u| ObjectId::parse_str(u).ok());
+    if let Some(oid) = uid {
+        if !Person::find_raw_document(client, Some(doc! {"_id": oid}), None)
+            .await?
+            .is_empty()
+        {
+            return Ok(Some(oid));
+        }
+    }
+
+    let query = doc! {
+        "first_name": exact(&card.person.first_name),
+        "last_name": exact(&card.person.last_name),
+    };
+    for d in Person::find_raw_document(client, Some(query), None).await? {
+        let id = d.get_object_id("_id")?;
+        if same_person(&card.person, &from_document(d)?) {
+            return Ok(Some(id));
+        }
+    }
+    Ok(None)
+}
+
+// The address is shared with the household living there, a new household is made otherwise.
+async fn add_address(
+    client: &Client,
+    person: &ObjectId,
+    card: &Card,
+    address: &Address,
+) -> COSIResult<()> {
+    let address_id = Address::find_or_insert(client, address).await?;
+    match Household::find_raw_document(client, Some(doc! {"address": address_id}), None)
+        .await?
+        .pop()
+    {
+        Some(h) => {
+            Household::update_datum(
+                client,
+                &doc! {"_id": h.get_object_id("_id")?},
+                &doc! {"$addToSet": {"persons": *person}},
+                None,
+            )
+            .await?;
+        }
+        None => {
+            let house_name = match card.person.last_name.as_str() {
+                "" => full_name(&card.person),
+                last => last.to_string(),
+            };
+            let data = doc! {
+                "house_name": house_name,
+                "address": address_id,
+                "persons": [*person],
+                "relations": [],
+            };
+            for result in Household::insert_data(client, vec![data]).await? {
+                result?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Nothing is written on a dry run or when a card is invalid.
+pub async fn import(client: &Client, text: &str, dry_run: bool) -> COSIResult<VCardReport> {
+    let mut report = VCardReport {
+        dry_run,
+        ..Default::default()
+    };
+    let mut created: Vec<(usize, Card)> = vec![];
+    for (idx, parsed) in parse(text).into_iter().enumerate() {
+        let mut result = CardResult {
+            card: idx + 1,
+            name: String::new(),
+            status: CardStatus::Created,
+            _id: None,
+            err: None,
+        };
+        match parsed {
+            Err(err) => {
+                result.status = CardStatus::Invalid;
+                result.err = Some(err.to_string());
+            }
+            Ok(card) => {
+                result.name = full_name(&card.person);
+                if let Some(oid) = find_duplicate(client, &card).await? {
+                    result.status = CardStatus::Duplicate;
+                    result._id = Some(oid.to_hex());
+                } else if let Some((first, _)) = created
+                    .iter()
+                    .find(|(_, c)| same_person(&card.person, &c.person))
+                {
+                    result.status = CardStatus::Duplicate;
+                    result.err = Some(format!("Same person as card {}.", first + 1));
+                } else {
+                    created.push((report.cards.len(), card));
+                }
+            }
+        }
+        report.cards.push(result);
+    }
+
+    let count = |status| report.cards.iter().filter(|c| c.status == status).count();
+    report.total = report.cards.len();
+    report.created = count(CardStatus::Created);
+    report.duplicates = count(CardStatus::Duplicate);
+    report.invalid = count(CardStatus::Invalid);
+    if dry_run || report.invalid > 0 {
+        return Ok(report);
+    }
+
+    for (idx, card) in created {
+        let id = Person::insert_datum(client, &PersonImpl::from(card.person.clone()), None)
+            .await?
+            .as_object_id()
+            .ok_or(COSIError::msg("Unable to insert person."))?;
+        for a in &card.addresses {
+            add_address(client, &id, &card, a).await?;
+        }
+        report.cards[idx]._id = Some(id.to_hex());
+    }
+    Ok(report)
+}
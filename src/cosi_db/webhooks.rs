@@ -0,0 +1,210 @@
+// Delivers table changes to webhook subscriptions.
+// A background worker follows the change feed and POSTs matching changes as JSON, signed with
+// HMAC-SHA256 of the body using the subscription secret:
+//
+//   X-COSI-Event: person.insert
+//   X-COSI-Delivery: <id shared by retries>
+//   X-COSI-Signature: sha256=<hex digest>
+//
+// Failed deliveries are retried with exponential backoff and every attempt is logged.
+// Only changes to the model tables are delivered, and only while the owner of the webhook may read
+// them.
+use std::time::Duration;
+
+use mongodb::bson::{doc, from_document, oid::ObjectId, Bson, Document};
+use mongodb::Client;
+use ring::hmac;
+use rocket::fairing::AdHoc;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::tokio::time::sleep;
+use rocket_db_pools::Database;
+use serde_json::json;
+
+use crate::cosi_db::changes::{subscribe, ChangeEvent};
+use crate::cosi_db::connection::COSIMongo;
+use crate::cosi_db::model::address::Address;
+use crate::cosi_db::model::auth::User;
+use crate::cosi_db::model::common::{COSICollection, COSIModel, OID};
+use crate::cosi_db::model::event::{Event, EventRegistration};
+use crate::cosi_db::model::group::{Group, GroupRelation};
+use crate::cosi_db::model::household::Household;
+use crate::cosi_db::model::person::Person;
+use crate::cosi_db::model::room::Room;
+use crate::cosi_db::model::webhook::{Webhook, WebhookDelivery};
+
+pub const MAX_ATTEMPTS: u32 = 5;
+// Doubled after every failed attempt.
+pub const FIRST_RETRY: Duration = Duration::from_secs(2);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Never sent, whatever the table.
+const SECRET_FIELDS: [&str; 5] = ["token", "calendar_token", "password", "secret", "code"];
+
+// Tables webhooks may subscribe to.
+pub fn tables() -> [&'static str; 8] {
+    [
+        Person::table_name(),
+        Address::table_name(),
+        Household::table_name(),
+        Event::table_name(),
+        EventRegistration::table_name(),
+        Group::table_name(),
+        GroupRelation::table_name(),
+        Room::table_name(),
+    ]
+}
+
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = hmac::sign(&key, body);
+    let digest: String = tag.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", digest)
+}
+
+async fn payload(client: &Client, event: &str, change: &ChangeEvent) -> String {
+    // Deletes and drops have nothing left to send.
+    let data = match change.oid() {
+        Some(oid) => client
+            .database("cosi_db")
+            .collection(&change.table)
+            .find_one(doc! {"_id": oid}, None)
+            .await
+            .ok()
+            .flatten()
+            .map(|mut d: Document| {
+                for field in SECRET_FIELDS {
+                    d.remove(field);
+                }
+                Bson::Document(d).into_relaxed_extjson()
+            }),
+        None => None,
+    };
+    json!({
+        "event": event,
+        "table": change.table,
+        "_id": change._id,
+        "data": data,
+        "time": chrono::Utc::now().to_rfc3339(),
+    })
+    .to_string()
+}
+
+async fn deliver(
+    client: Client,
+    http: reqwest::Client,
+    webhook_id: ObjectId,
+    webhook: Webhook,
+    event: String,
+    body: String,
+) {
+    let delivery = ObjectId::new().to_hex();
+    let signature = sign(&webhook.secret, body.as_bytes());
+    let mut retry = FIRST_RETRY;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let result = http
+            .post(&webhook.url)
+            .header("Content-Type", "application/json")
+            .header("X-COSI-Event", &event)
+            .header("X-COSI-Delivery", &delivery)
+            .header("X-COSI-Signature", &signature)
+            .body(body.clone())
+            .send()
+            .await;
+        let (status, error) = match result {
+            Ok(r) if r.status().is_success() => (Some(r.status().as_u16()), None),
+            Ok(r) => (
+                Some(r.status().as_u16()),
+                Some(format!("Unexpected status {}.", r.status().as_u16())),
+            ),
+            Err(err) => (None, Some(err.to_string())),
+        };
+        let delivered = error.is_none();
+        let log = WebhookDelivery {
+            webhook: OID(webhook_id),
+            delivery: delivery.clone(),
+            event: event.clone(),
+            attempt,
+            status,
+            error,
+            delivered,
+            time: chrono::Utc::now().to_rfc3339(),
+        };
+        let _ = WebhookDelivery::insert_datum(&client, &log, None).await;
+        if delivered || attempt == MAX_ATTEMPTS {
+            return;
+        }
+        sleep(retry).await;
+        retry *= 2;
+    }
+}
+
+async fn owner_can_read(client: &Client, webhook: &Webhook, table: &str) -> bool {
+    let owner = User::get_collection(client)
+        .await
+        .find_one(doc! {"username": &webhook.owner}, None)
+        .await;
+    match owner {
+        Ok(Some(user)) => user.admin && user.can_read(table),
+        _ => false,
+    }
+}
+
+async fn run(client: Client) {
+    let http = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+        Ok(http) => http,
+        Err(_) => return,
+    };
+    let mut feed = subscribe();
+    loop {
+        let change = match feed.recv().await {
+            Ok(change) => change,
+            // Changes missed while lagging are not delivered.
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+        if !tables().contains(&change.table.as_str()) {
+            continue;
+        }
+
+        let hooks = match Webhook::find_raw_document(&client, None, None).await {
+            Ok(hooks) => hooks,
+            Err(_) => continue,
+        };
+        let mut matching = vec![];
+        for h in hooks {
+            let id = h.get_object_id("_id").ok();
+            if let (Some(id), Ok(hook)) = (id, from_document::<Webhook>(h)) {
+                if hook.subscribes_to(&change.table, change.op)
+                    && owner_can_read(&client, &hook, &change.table).await
+                {
+                    matching.push((id, hook));
+                }
+            }
+        }
+        if matching.is_empty() {
+            continue;
+        }
+
+        let event = format!("{}.{}", change.table, change.op.as_str());
+        let body = payload(&client, &event, &change).await;
+        for (id, hook) in matching {
+            rocket::tokio::spawn(deliver(
+                client.clone(),
+                http.clone(),
+                id,
+                hook,
+                event.clone(),
+                body.clone(),
+            ));
+        }
+    }
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Webhooks", |rocket| {
+        Box::pin(async move {
+            if let Some(db) = COSIMongo::fetch(rocket) {
+                rocket::tokio::spawn(run(Client::clone(db)));
+            }
+        })
+    })
+}
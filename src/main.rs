@@ -5,19 +5,32 @@ extern crate rocket;
 use rocket::{Build, Rocket};
 use rocket_dyn_templates::Template;
 
-pub use ::paste;
-pub use ::with_builtin_macros;
-
 // COSI
 pub mod cosi_db;
 pub mod routes;
 use crate::cosi_db::connection::COSIMongo;
 use rocket_db_pools::Database;
 
-#[launch]
 async fn rocket() -> Rocket<Build> {
-    let rocket_build = routes::register_route(rocket::build())
+    routes::register_route(rocket::build())
         .attach(Template::fairing())
-        .attach(COSIMongo::init());
-    rocket_build
+        .attach(COSIMongo::init())
+}
+
+#[rocket::main]
+async fn main() {
+    // Maintenance commands such as backups, see cosi_db::backup.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(err) = cosi_db::backup::cli(&args).await {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Err(err) = rocket().await.launch().await {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
 }
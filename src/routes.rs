@@ -1,65 +1,116 @@
 use rocket::{fs::FileServer, Build, Rocket};
 
-use super::cosi_db::controller::api::*;
+use super::cosi_db::controller::admin::*;
+use super::cosi_db::controller::attendance::*;
 use super::cosi_db::controller::auth::*;
+use super::cosi_db::controller::booking::*;
+use super::cosi_db::controller::changes::*;
 use super::cosi_db::controller::dashboard::*;
+use super::cosi_db::controller::gedcom::*;
+use super::cosi_db::controller::graphql::*;
+use super::cosi_db::controller::ical::*;
+use super::cosi_db::controller::import::*;
+use super::cosi_db::controller::kiosk::*;
+use super::cosi_db::controller::labels::*;
+use super::cosi_db::controller::occurrence::*;
+use super::cosi_db::controller::openapi::*;
+use super::cosi_db::controller::registration::*;
+use super::cosi_db::controller::vcard::*;
+use super::cosi_db::controller::webhook::*;
+use super::cosi_db::model::address::Address;
+use super::cosi_db::model::event::{Event, EventRegistration};
+use super::cosi_db::model::group::{Group, GroupRelation};
+use super::cosi_db::model::household::Household;
+use super::cosi_db::model::person::Person;
+use super::cosi_db::model::room::Room;
 
 pub fn register_route(rb: Rocket<Build>) -> Rocket<Build> {
-    rb.mount("/public", FileServer::from("public")).mount(
-        "/",
-        routes![
-            // Dashboard
-            index,
-            index_redirect,
-            // Person
-            gen_person,
-            get_person,
-            insert_person,
-            drop_person,
-            update_person,
-            person,
-            person_redirect,
-            // Address
-            gen_address,
-            get_address,
-            insert_address,
-            drop_address,
-            update_address,
-            // Household
-            gen_household,
-            get_household,
-            insert_household,
-            drop_household,
-            // Event
-            gen_event,
-            get_event,
-            insert_event,
-            drop_event,
-            update_event,
-            // Event Registration
-            gen_eventregistration,
-            get_eventregistration,
-            insert_eventregistration,
-            drop_eventregistration,
-            // Group
-            gen_group,
-            get_group,
-            insert_group,
-            drop_group,
-            update_group,
-            // Group Relation
-            gen_grouprelation,
-            get_grouprelation,
-            insert_grouprelation,
-            drop_grouprelation,
-            // Search
-            search,
-            // Auth
-            login,
-            login_logged,
-            login_submit,
-            logout,
-            gen_login
-        ],
-    )
+    rb.manage(build_schema())
+        .attach(super::cosi_db::changes::fairing())
+        .attach(super::cosi_db::webhooks::fairing())
+        .mount("/public", FileServer::from("public"))
+        .mount(
+            "/",
+            routes![
+                // Dashboard
+                index,
+                index_redirect,
+                person,
+                person_redirect,
+                calendar,
+                calendar_redirect,
+                kiosk,
+                kiosk_redirect,
+                // Search
+                search,
+                // Auth
+                login,
+                login_logged,
+                login_submit,
+                logout,
+                gen_login,
+                // Docs
+                openapi,
+                // GraphQL
+                graphql,
+                graphql_schema,
+                // Change feed
+                changes,
+                // Webhooks
+                list_webhooks,
+                create_webhook,
+                delete_webhook,
+                webhook_deliveries,
+                // Import
+                import_csv,
+                // vCard
+                person_vcard,
+                household_vcard,
+                group_vcard,
+                import_vcard,
+                // GEDCOM
+                gedcom,
+                household_gedcom,
+                import_gedcom,
+                // iCalendar
+                ical,
+                event_ical,
+                group_ical,
+                calendar_token,
+                import_ical,
+                // Occurrences
+                list_occurrences,
+                event_occurrences,
+                // Attendance
+                record_attendance,
+                event_attendance,
+                person_attendance,
+                group_attendance,
+                // Registration
+                event_seats,
+                promote_waitlist,
+                // Rooms
+                room_availability,
+                // Kiosk
+                kiosk_households,
+                kiosk_check_in,
+                kiosk_check_out,
+                kiosk_checkins,
+                // Labels
+                label_layouts,
+                labels,
+                // Backup
+                admin_backup,
+                admin_restore
+            ],
+        )
+        // Tables, see #[derive(CosiModel)].
+        .mount("/", Person::routes())
+        .mount("/", Address::routes())
+        .mount("/", Household::routes())
+        .mount("/", Event::routes())
+        .mount("/", EventRegistration::routes())
+        .mount("/", Group::routes())
+        .mount("/", GroupRelation::routes())
+        .mount("/", Room::routes())
 }
@@ -5,7 +5,8 @@ const TABLE_NAMES = [
     "Group",
     "GroupRelation",
     "Event",
-    "EventRegistration"
+    "EventRegistration",
+    "Room"
 ];
 
 let ALL_PAGEABLE_ENDPOINTS = [];
@@ -1,4 +1,7 @@
 import session from "supertest-session";
+import http from "http";
+import crypto from "crypto";
+import zlib from "zlib";
 import {jest} from "@jest/globals";
 import { ALL_PAGEABLE_ENDPOINTS, ALL_GEN_ENDPOINTS, TABLE_NAMES } from "./endpoints.js";
 
@@ -30,7 +33,8 @@ beforeAll(async ()=> {
     for (let tn of TABLE_NAMES) {
         let response = await cosiRequest.get(`/drop_${tn.toLowerCase()}`)
                                         .expect(200)
-                                        .expect("Content-Type", /json/);
+                                        .expect("Content-Type", /json/)
+                                        .expect("Deprecation", "true");
 
         let jsonData = JSON.parse(response.text);
         expectKeys(jsonData, ["dropped"]);
@@ -63,6 +67,34 @@ describe("Test Root", () => {
     })
 });
 
+describe("OpenAPI", () => {
+    test("/openapi.json covers every table", async () => {
+        const response = await cosiRequest
+                                .get("/openapi.json")
+                                .expect(200)
+                                .expect("Content-Type", /json/);
+        let spec = JSON.parse(response.text);
+        expect(spec["openapi"]).toMatch(/^3\./);
+        for (let tn of TABLE_NAMES) {
+            let name = tn.toLowerCase();
+            expect(spec["paths"][`/get_${name}`]["get"]).toBeDefined();
+            expect(spec["paths"][`/get_${name}/{oid}`]["get"]).toBeDefined();
+            expect(spec["paths"][`/insert_${name}`]["post"]).toBeDefined();
+            expect(spec["paths"][`/update_${name}`]["patch"]).toBeDefined();
+            expect(spec["paths"][`/bulk_delete_${name}`]["post"]).toBeDefined();
+        }
+
+        let person = spec["components"]["schemas"]["PersonImpl"];
+        expect(person["required"]).toContain("first_name");
+        expect(person["required"]).not.toContain("dob");
+        expect(person["properties"]["sex"]["enum"]).toEqual(["Male", "Female", "Undefined"]);
+
+        let filters = spec["paths"]["/get_person"]["get"]["parameters"].map((p) => p["name"]);
+        expect(filters).toContain("last_name");
+        expect(filters).toContain("page_size");
+    });
+});
+
 describe("CRUD", () => {
     // Check all basic GET endpoints.
     // Each get page should have max 100 datapoints.
@@ -70,8 +102,10 @@ describe("CRUD", () => {
         const maxDatapoints = 100;
         const returnKeys = [
             "page",
+            "page_size",
             "total_pages",
             "total_result",
+            "next",
             "data"
         ];
         for (let endpoint of ALL_PAGEABLE_ENDPOINTS) {
@@ -171,6 +205,193 @@ describe("CRUD", () => {
                 }
             }
         });
+
+        test("/get_person Page size", async() => {
+            const response = await cosiRequest
+                                    .get("/get_person")
+                                    .query({page: 1, page_size: 30})
+                                    .expect(200)
+                                    .expect("Content-Type", /json/);
+            let jsonData = JSON.parse(response.text);
+            expect(jsonData["page_size"]).toBe(30);
+            expect(jsonData["data"].length).toBe(30);
+            expect(jsonData["total_pages"]).toBe(Math.ceil(totalDatapointsPerTable/30));
+
+            // Sizes above the server maximum are clamped.
+            const clamped = await cosiRequest
+                                    .get("/get_person")
+                                    .query({page_size: 100000})
+                                    .expect(200);
+            expect(JSON.parse(clamped.text)["page_size"]).toBe(500);
+
+            await cosiRequest.get("/get_person").query({page_size: 0}).expect(400);
+            // Pages too far out to address are refused rather than overflowing.
+            await cosiRequest.get("/get_person").query({page: "18446744073709551615", page_size: 500}).expect(400);
+        });
+
+        test("/get_person Cursor walk", async() => {
+            let seen = new Set();
+            let cursor = null;
+            let requests = 0;
+            do {
+                let query = {page_size: 45, sort: "-last_name"};
+                if (cursor != null) {
+                    query["cursor"] = cursor;
+                }
+                const response = await cosiRequest
+                                        .get("/get_person")
+                                        .query(query)
+                                        .expect(200)
+                                        .expect("Content-Type", /json/);
+                let jsonData = JSON.parse(response.text);
+                for (let v of jsonData["data"]) {
+                    seen.add(v["_id"]["$oid"]);
+                }
+                cursor = jsonData["next"];
+                requests += 1;
+            } while (cursor != null && requests < 100);
+
+            expect(seen.size).toBe(totalDatapointsPerTable);
+        });
+
+        test("/get_person Cursor must match sort", async() => {
+            const response = await cosiRequest
+                                    .get("/get_person")
+                                    .query({page_size: 10, sort: "first_name"})
+                                    .expect(200);
+            let cursor = JSON.parse(response.text)["next"];
+            await cosiRequest
+                    .get("/get_person")
+                    .query({page_size: 10, sort: "last_name", cursor: cursor})
+                    .expect(400);
+            await cosiRequest
+                    .get("/get_person")
+                    .query({cursor: "not-a-cursor"})
+                    .expect(400);
+        });
+
+        test("/get_household/<oid> Reference expansion", async() => {
+            const list = await cosiRequest
+                                .get("/get_household")
+                                .query({page: 0, expand: false})
+                                .expect(200);
+            let raw = JSON.parse(list.text)["data"][0];
+            let oid = raw["_id"]["$oid"];
+            expect(raw["address"]["$oid"]).toBeDefined();
+
+            const expanded = await cosiRequest
+                                    .get(`/get_household/${oid}`)
+                                    .expect(200)
+                                    .expect("Content-Type", /json/);
+            let jsonData = JSON.parse(expanded.text);
+            expect(jsonData["_id"]["$oid"]).toBe(oid);
+            expect(jsonData["address"]["line_one"]).toBeDefined();
+
+            const notExpanded = await cosiRequest
+                                    .get(`/get_household/${oid}`)
+                                    .query({expand: false})
+                                    .expect(200);
+            jsonData = JSON.parse(notExpanded.text);
+            expect(jsonData["address"]).toEqual(raw["address"]);
+            expect(jsonData["persons"]).toEqual(raw["persons"]);
+        });
+
+        test("/get_eventregistration/<oid> Missing and invalid ids", async() => {
+            await cosiRequest
+                    .get("/get_eventregistration/000000000000000000000000")
+                    .expect(404)
+                    .expect("Content-Type", /json/);
+            await cosiRequest
+                    .get("/get_eventregistration/not-an-oid")
+                    .expect(400)
+                    .expect("Content-Type", /json/);
+        });
+
+        test("/get_person Sort ascending and descending", async() => {
+            const isSorted = (values, desc) => {
+                for (let i = 1; i < values.length; ++i) {
+                    if (desc ? values[i - 1] < values[i] : values[i - 1] > values[i]) {
+                        return false;
+                    }
+                }
+                return true;
+            };
+
+            for (const desc of [false, true]) {
+                const response = await cosiRequest
+                                        .get("/get_person")
+                                        .query({page: 0, sort: `${desc ? "-" : ""}last_name`})
+                                        .expect(200)
+                                        .expect("Content-Type", /json/);
+                let jsonData = JSON.parse(response.text);
+                expect(jsonData["data"].length).toBe(maxDatapoints);
+                expect(isSorted(jsonData["data"].map((v) => v["last_name"]), desc)).toBe(true);
+            }
+        });
+
+        test("/get_person Sort on unknown field", async() => {
+            const response = await cosiRequest
+                                    .get("/get_person")
+                                    .query({page: 0, sort: "last_name,-not_a_field"})
+                                    .expect(400)
+                                    .expect("Content-Type", /json/);
+            expectKeys(JSON.parse(response.text), ["err"]);
+        });
+
+        test("/get_person Field projection", async() => {
+            const response = await cosiRequest
+                                    .get("/get_person")
+                                    .query({page: 0, fields: "first_name,last_name"})
+                                    .expect(200)
+                                    .expect("Content-Type", /json/);
+            let jsonData = JSON.parse(response.text);
+            expect(jsonData["data"].length).toBe(maxDatapoints);
+            for (let v of jsonData["data"]) {
+                expectKeys(v, ["_id", "first_name", "last_name"]);
+            }
+
+            await cosiRequest
+                    .get("/get_person")
+                    .query({page: 0, fields: "first_name,password"})
+                    .expect(400);
+        });
+
+        test("/get_household Field projection keeps resolved foreign keys", async() => {
+            const response = await cosiRequest
+                                    .get("/get_household")
+                                    .query({page: 0, fields: "address"})
+                                    .expect(200)
+                                    .expect("Content-Type", /json/);
+            let jsonData = JSON.parse(response.text);
+            for (let v of jsonData["data"]) {
+                expectKeys(v, ["_id", "address"]);
+                expect(v["address"]["line_one"]).toBeDefined();
+            }
+        });
+
+        test("/get_grouprelation Filter on a reference", async() => {
+            const list = await cosiRequest
+                                .get("/get_grouprelation")
+                                .query({page_size: 1, expand: false})
+                                .expect(200);
+            let personOid = JSON.parse(list.text)["data"][0]["person"]["$oid"];
+
+            const response = await cosiRequest
+                                    .get("/get_grouprelation")
+                                    .query({person: personOid, expand: false})
+                                    .expect(200)
+                                    .expect("Content-Type", /json/);
+            let jsonData = JSON.parse(response.text);
+            expect(jsonData["total_result"]).toBeGreaterThan(0);
+            for (let v of jsonData["data"]) {
+                expect(v["person"]["$oid"]).toBe(personOid);
+            }
+
+            await cosiRequest
+                    .get("/get_grouprelation")
+                    .query({person: "not-an-oid"})
+                    .expect(res => expect(res.status).toBeLessThan(500));
+        });
     });
 
     // Insert after getters so it doesn't change the get count.
@@ -230,6 +451,334 @@ describe("CRUD", () => {
             expect(jData.data[0].middle_name).toBe("old");
         });
 
+        test("person JSON POST and UPDATE", async () => {
+            let jsonPerson = {
+                "first_name": "luigi",
+                "middle_name": "plumber",
+                "last_name": "bros",
+                "nicks": ["green", "player two"],
+                "dob": "1986-07-14",
+                "sex": "Male",
+                "notes": "",
+                "emergency_contact": ""
+            };
+            const response = await cosiRequest
+                                    .post("/insert_person")
+                                    .send(jsonPerson)
+                                    .expect(200)
+                                    .expect("Content-Type", /json/);
+            verifyData(response.text);
+            let oid = JSON.parse(response.text)["$oid"];
+
+            jsonPerson["nicks"] = ["green"];
+            const update = await cosiRequest
+                                    .post("/update_person")
+                                    .query({oid: oid})
+                                    .send(jsonPerson)
+                                    .expect(200);
+            expect(JSON.parse(update.text)).toBe(1);
+
+            const verify = await cosiRequest.get(`/get_person/${oid}`).expect(200);
+            expect(JSON.parse(verify.text)["nicks"]).toEqual(["green"]);
+        });
+
+        test("person JSON validation errors", async () => {
+            const badDate = await cosiRequest
+                                    .post("/insert_person")
+                                    .send({
+                                        "first_name": "wario",
+                                        "middle_name": "",
+                                        "last_name": "bros",
+                                        "dob": "1700-01-01",
+                                        "sex": "Male",
+                                        "notes": "",
+                                        "emergency_contact": ""
+                                    })
+                                    .expect(400)
+                                    .expect("Content-Type", /json/);
+            expectKeys(JSON.parse(badDate.text), ["err"]);
+
+            const badBody = await cosiRequest
+                                    .post("/insert_person")
+                                    .set("Content-Type", "application/json")
+                                    .send("{\"first_name\": ")
+                                    .expect(400)
+                                    .expect("Content-Type", /json/);
+            expectKeys(JSON.parse(badBody.text), ["err"]);
+        });
+
+        test("household JSON POST with nested data", async () => {
+            const people = await cosiRequest
+                                    .get("/get_person")
+                                    .query({page_size: 2, expand: false})
+                                    .expect(200);
+            let [a, b] = JSON.parse(people.text)["data"].map((v) => v["_id"]["$oid"]);
+            const addresses = await cosiRequest
+                                    .get("/get_address")
+                                    .query({page_size: 1})
+                                    .expect(200);
+            let address = JSON.parse(addresses.text)["data"][0]["_id"]["$oid"];
+
+            const response = await cosiRequest
+                                    .post("/insert_household")
+                                    .send({
+                                        "house_name": "Mushroom Household",
+                                        "address": address,
+                                        "persons": [a, b],
+                                        "relations": [{"person_a": a, "person_b": b, "relation": "Wife"}]
+                                    })
+                                    .expect(200)
+                                    .expect("Content-Type", /json/);
+            verifyData(response.text);
+
+            let oid = JSON.parse(response.text)["$oid"];
+            const verify = await cosiRequest
+                                    .get(`/get_household/${oid}`)
+                                    .query({expand: false})
+                                    .expect(200);
+            let jsonData = JSON.parse(verify.text);
+            expect(jsonData["persons"].map((v) => v["$oid"])).toEqual([a, b]);
+            expect(jsonData["relations"][0]["relation"]).toBe("Wife");
+        });
+
+        test("person PATCH partial update", async () => {
+            const response = await cosiRequest
+                                    .post("/insert_person")
+                                    .send({
+                                        "first_name": "peach",
+                                        "middle_name": "toadstool",
+                                        "last_name": "princess",
+                                        "nicks": ["peachy", "princess"],
+                                        "dob": "1985-09-13",
+                                        "sex": "Female",
+                                        "notes": "",
+                                        "emergency_contact": ""
+                                    })
+                                    .expect(200);
+            let oid = JSON.parse(response.text)["$oid"];
+
+            const patch = await cosiRequest
+                                    .patch("/update_person")
+                                    .query({oid: oid})
+                                    .send({
+                                        "middle_name": "mushroom",
+                                        "dob": null,
+                                        "$add": {"nicks": ["daisy"]}
+                                    })
+                                    .expect(200)
+                                    .expect("Content-Type", /json/);
+            expect(JSON.parse(patch.text)).toBe(1);
+
+            await cosiRequest
+                    .patch("/update_person")
+                    .query({oid: oid})
+                    .send({"$remove": {"nicks": ["princess"]}})
+                    .expect(200);
+
+            const verify = await cosiRequest.get(`/get_person/${oid}`).expect(200);
+            let jsonData = JSON.parse(verify.text);
+            expect(jsonData["first_name"]).toBe("peach");
+            expect(jsonData["middle_name"]).toBe("mushroom");
+            expect(jsonData).not.toHaveProperty("dob");
+            expect(jsonData["nicks"]).toEqual(["peachy", "daisy"]);
+        });
+
+        test("person PATCH null removes optional fields", async () => {
+            const response = await cosiRequest
+                                    .post("/insert_person")
+                                    .send({
+                                        "first_name": "daisy",
+                                        "middle_name": "sarasa",
+                                        "last_name": "princess",
+                                        "nicks": [],
+                                        "dob": "1989-04-21",
+                                        "mobile_phone": "555-0142",
+                                        "sex": "Female",
+                                        "notes": "",
+                                        "emergency_contact": ""
+                                    })
+                                    .expect(200);
+            let oid = JSON.parse(response.text)["$oid"];
+
+            await cosiRequest
+                    .patch(`/api/v1/person/${oid}`)
+                    .send({"mobile_phone": null, "dob": null})
+                    .expect(200);
+
+            const verify = await cosiRequest.get(`/api/v1/person/${oid}`).expect(200);
+            let jsonData = JSON.parse(verify.text);
+            expect(jsonData["middle_name"]).toBe("sarasa");
+            expect(jsonData).not.toHaveProperty("mobile_phone");
+            expect(jsonData).not.toHaveProperty("dob");
+        });
+
+        test("person PATCH rejects invalid patches", async () => {
+            const people = await cosiRequest.get("/get_person").query({page_size: 1}).expect(200);
+            let oid = JSON.parse(people.text)["data"][0]["_id"]["$oid"];
+
+            const invalid = [
+                {"first_name": null},
+                {"dob": "1700-01-01"},
+                {"not_a_field": "value"},
+                {"$add": {"first_name": ["a"]}},
+                {"nicks": [], "$add": {"nicks": ["a"]}},
+                {}
+            ];
+            for (const body of invalid) {
+                const response = await cosiRequest
+                                        .patch("/update_person")
+                                        .query({oid: oid})
+                                        .send(body)
+                                        .expect(400)
+                                        .expect("Content-Type", /json/);
+                expectKeys(JSON.parse(response.text), ["err"]);
+            }
+
+            await cosiRequest
+                    .patch("/update_person")
+                    .query({oid: "000000000000000000000000"})
+                    .send({"middle_name": "none"})
+                    .expect(404);
+        });
+
+        test("household PATCH array of references", async () => {
+            const households = await cosiRequest
+                                        .get("/get_household")
+                                        .query({page_size: 1, expand: false})
+                                        .expect(200);
+            let household = JSON.parse(households.text)["data"][0];
+            let oid = household["_id"]["$oid"];
+            const people = await cosiRequest
+                                    .get("/get_person")
+                                    .query({page_size: 1, sort: "-_id"})
+                                    .expect(200);
+            let personOid = JSON.parse(people.text)["data"][0]["_id"]["$oid"];
+
+            await cosiRequest
+                    .patch("/update_household")
+                    .query({oid: oid})
+                    .send({"house_name": "Patched Household", "$add": {"persons": [personOid]}})
+                    .expect(200);
+
+            const verify = await cosiRequest
+                                    .get(`/get_household/${oid}`)
+                                    .query({expand: false})
+                                    .expect(200);
+            let jsonData = JSON.parse(verify.text);
+            expect(jsonData["house_name"]).toBe("Patched Household");
+            expect(jsonData["persons"]).toContainEqual({"$oid": personOid});
+        });
+
+        test("person bulk insert, update and delete", async () => {
+            let bulkPerson = (name) => ({
+                "first_name": name,
+                "middle_name": "",
+                "last_name": "koopa",
+                "sex": "Male",
+                "notes": "",
+                "emergency_contact": ""
+            });
+            const insert = await cosiRequest
+                                    .post("/bulk_insert_person")
+                                    .send([
+                                        bulkPerson("bowser"),
+                                        {"first_name": "missing fields"},
+                                        Object.assign(bulkPerson("ludwig"), {"dob": "1700-01-01"}),
+                                        bulkPerson("larry")
+                                    ])
+                                    .expect(200)
+                                    .expect("Content-Type", /json/);
+            let jData = JSON.parse(insert.text);
+            expectKeys(jData, ["total_ok", "total_err", "results"]);
+            expect(jData.total_ok).toBe(2);
+            expect(jData.total_err).toBe(2);
+            expect(jData.results.map((r) => r.ok)).toEqual([true, false, false, true]);
+            expectKeys(jData.results[1], ["index", "ok", "err"]);
+            let [bowser, larry] = [jData.results[0], jData.results[3]].map((r) => r["_id"]["$oid"]);
+
+            const update = await cosiRequest
+                                    .post("/bulk_update_person")
+                                    .send([
+                                        {"oid": bowser, "data": Object.assign(bulkPerson("bowser"), {"middle_name": "king"})},
+                                        {"oid": "000000000000000000000000", "data": bulkPerson("nobody")},
+                                        {"oid": "not an oid", "data": bulkPerson("nobody")}
+                                    ])
+                                    .expect(200);
+            jData = JSON.parse(update.text);
+            expect(jData.results.map((r) => r.ok)).toEqual([true, false, false]);
+
+            const verify = await cosiRequest.get(`/get_person/${bowser}`).expect(200);
+            expect(JSON.parse(verify.text)["middle_name"]).toBe("king");
+
+            const remove = await cosiRequest
+                                    .post("/bulk_delete_person")
+                                    .send([bowser, larry, larry])
+                                    .expect(200);
+            jData = JSON.parse(remove.text);
+            expect(jData.total_ok).toBe(2);
+            expect(jData.total_err).toBe(1);
+            await cosiRequest.get(`/get_person/${larry}`).expect(404);
+        });
+
+        test("person upsert by natural key", async () => {
+            let legacyPerson = {
+                "first_name": "toad",
+                "middle_name": "",
+                "last_name": "kinopio",
+                "sex": "Male",
+                "notes": "",
+                "emergency_contact": "",
+                "external_id": "legacy-1001"
+            };
+            const first = await cosiRequest
+                                    .post("/upsert_person")
+                                    .send(legacyPerson)
+                                    .expect(200)
+                                    .expect("Content-Type", /json/);
+            let jData = JSON.parse(first.text);
+            expectKeys(jData, ["_id", "upserted"]);
+            expect(jData.upserted).toBe(true);
+            let oid = jData["_id"]["$oid"];
+
+            legacyPerson["notes"] = "updated";
+            const second = await cosiRequest
+                                    .post("/upsert_person")
+                                    .type("form")
+                                    .send(legacyPerson)
+                                    .expect(200);
+            jData = JSON.parse(second.text);
+            expect(jData.upserted).toBe(false);
+            expect(jData["_id"]["$oid"]).toBe(oid);
+
+            const verify = await cosiRequest
+                                    .get("/get_person")
+                                    .query({external_id: "legacy-1001"})
+                                    .expect(200);
+            jData = JSON.parse(verify.text);
+            expect(jData.total_result).toBe(1);
+            expect(jData.data[0]["notes"]).toBe("updated");
+
+            delete legacyPerson["external_id"];
+            const missingKey = await cosiRequest
+                                    .post("/upsert_person")
+                                    .send(legacyPerson)
+                                    .expect(400);
+            expectKeys(JSON.parse(missingKey.text), ["err"]);
+
+            const bulk = await cosiRequest
+                                    .post("/bulk_upsert_person")
+                                    .send([
+                                        Object.assign({}, legacyPerson, {"external_id": "legacy-1001"}),
+                                        Object.assign({}, legacyPerson, {"external_id": "legacy-1002"}),
+                                        legacyPerson
+                                    ])
+                                    .expect(200);
+            jData = JSON.parse(bulk.text);
+            expect(jData.results.map((r) => r.upserted)).toEqual([false, true, undefined]);
+            expect(jData.results[0]["_id"]["$oid"]).toBe(oid);
+            expect(jData.total_err).toBe(1);
+        });
+
         const endpointAddress = "insert_address";
         test(`/${endpointAddress} POST`, async () => {
             const response = await cosiRequest
@@ -249,3 +798,1383 @@ describe("CRUD", () => {
         });
     });
 });
+
+describe("API v1", () => {
+    let v1Person = {
+        "first_name": "peach",
+        "middle_name": "",
+        "last_name": "toadstool",
+        "sex": "Female",
+        "notes": "",
+        "emergency_contact": ""
+    };
+
+    test("/api/v1/person CRUD", async () => {
+        const create = await cosiRequest
+                                .post("/api/v1/person")
+                                .send(v1Person)
+                                .expect(201)
+                                .expect("Content-Type", /json/);
+        let jData = JSON.parse(create.text);
+        let oid = jData["_id"]["$oid"];
+        expect(jData["first_name"]).toBe("peach");
+        expect(create.headers["location"]).toBe(`/api/v1/person/${oid}`);
+
+        await cosiRequest.get(create.headers["location"]).expect(200);
+
+        const replace = await cosiRequest
+                                .put(`/api/v1/person/${oid}`)
+                                .send(Object.assign({}, v1Person, {"middle_name": "princess"}))
+                                .expect(200);
+        expect(JSON.parse(replace.text)["middle_name"]).toBe("princess");
+
+        const patch = await cosiRequest
+                                .patch(`/api/v1/person/${oid}`)
+                                .send({"notes": "castle"})
+                                .expect(200);
+        jData = JSON.parse(patch.text);
+        expect(jData["notes"]).toBe("castle");
+        expect(jData["middle_name"]).toBe("princess");
+
+        await cosiRequest.delete(`/api/v1/person/${oid}`).expect(204);
+        await cosiRequest.get(`/api/v1/person/${oid}`).expect(404);
+        await cosiRequest.delete(`/api/v1/person/${oid}`).expect(404);
+        await cosiRequest.put(`/api/v1/person/${oid}`).send(v1Person).expect(404);
+    });
+
+    test("/api/v1/person List and bulk", async () => {
+        const list = await cosiRequest
+                                .get("/api/v1/person")
+                                .query({page_size: 5, sort: "last_name"})
+                                .expect(200);
+        let jData = JSON.parse(list.text);
+        expect(jData["data"].length).toBe(5);
+        expect(list.headers["deprecation"]).toBeUndefined();
+
+        const insert = await cosiRequest
+                                .post("/api/v1/person/bulk")
+                                .send([v1Person, v1Person])
+                                .expect(200);
+        let ids = JSON.parse(insert.text).results.map((r) => r["_id"]["$oid"]);
+
+        const remove = await cosiRequest
+                                .delete("/api/v1/person/bulk")
+                                .send(ids)
+                                .expect(200);
+        expect(JSON.parse(remove.text).total_ok).toBe(2);
+    });
+
+    test("/api/v1/person PUT upserts on the natural key", async () => {
+        let keyed = Object.assign({}, v1Person, {"external_id": "v1-put-1"});
+        const first = await cosiRequest.put("/api/v1/person").send(keyed).expect(201);
+        const second = await cosiRequest.put("/api/v1/person").send(keyed).expect(200);
+        expect(second.headers["location"]).toBe(first.headers["location"]);
+        await cosiRequest.delete(first.headers["location"]).expect(204);
+    });
+
+    test("Legacy routes are deprecated", async () => {
+        const response = await cosiRequest.get("/get_person").query({page_size: 1}).expect(200);
+        expect(response.headers["deprecation"]).toBe("true");
+        expect(response.headers["link"]).toBe("</api/v1/person>; rel=\"successor-version\"");
+    });
+});
+
+describe("GraphQL", () => {
+    test("/graphql Household with related data", async () => {
+        const list = await cosiRequest
+                                .get("/get_household")
+                                .query({page_size: 1, expand: false})
+                                .expect(200);
+        let household = JSON.parse(list.text)["data"][0];
+
+        const response = await cosiRequest
+                                .post("/graphql")
+                                .send({
+                                    "query": `query ($id: ID!) {
+                                        household(id: $id) {
+                                            _id
+                                            house_name
+                                            address { city }
+                                            persons {
+                                                _id
+                                                first_name
+                                                grouprelations { role group { group_name } }
+                                                eventregistrations { event { name } }
+                                            }
+                                        }
+                                    }`,
+                                    "variables": {"id": household["_id"]["$oid"]}
+                                })
+                                .expect(200)
+                                .expect("Content-Type", /json/);
+        let jData = JSON.parse(response.text);
+        expect(jData["errors"]).toBeUndefined();
+        let result = jData["data"]["household"];
+        expect(result["house_name"]).toBe(household["house_name"]);
+        expect(result["persons"].map((p) => p["_id"])).toEqual(household["persons"].map((p) => p["$oid"]));
+    });
+
+    test("/graphql Paginated and filtered lists", async () => {
+        const response = await cosiRequest
+                                .post("/graphql")
+                                .send({"query": `{
+                                    persons(filter: {sex: "Female"}, page_size: 5, sort: "last_name") {
+                                        total_result
+                                        page_size
+                                        data { sex last_name }
+                                    }
+                                }`})
+                                .expect(200);
+        let page = JSON.parse(response.text)["data"]["persons"];
+        expect(page["page_size"]).toBe(5);
+        expect(page["data"].length).toBeLessThanOrEqual(5);
+        for (let p of page["data"]) {
+            expect(p["sex"]).toBe("Female");
+        }
+
+        const bad = await cosiRequest
+                            .post("/graphql")
+                            .send({"query": `{ person(id: "not an id") { _id } }`})
+                            .expect(200);
+        expect(JSON.parse(bad.text)["errors"][0]["message"]).toBe("Invalid object id.");
+    });
+
+    test("/graphql Refuses overly complex queries", async () => {
+        let fields = [...Array(600).keys()].map((i) => `p${i}: persons { total_result }`);
+        const response = await cosiRequest
+                                .post("/graphql")
+                                .send({"query": `{ ${fields.join(" ")} }`})
+                                .expect(200);
+        let jData = JSON.parse(response.text);
+        expect(jData["data"]).toBeFalsy();
+        expect(jData["errors"][0]["message"]).toMatch(/complex/);
+    });
+});
+
+describe("Change feed", () => {
+    test("/api/v1/changes Reports inserts", async () => {
+        const stream = cosiRequest
+                            .get("/api/v1/changes")
+                            .query({tables: "person"})
+                            .buffer(true)
+                            .parse((res, callback) => {
+                                let text = "";
+                                res.on("data", (chunk) => {
+                                    text += chunk;
+                                    if (/event: ?insert/.test(text)) {
+                                        callback(null, text);
+                                        res.destroy();
+                                    }
+                                });
+                            })
+                            .then((response) => response);
+
+        // Let the subscription register before writing.
+        await new Promise((resolve) => setTimeout(resolve, 500));
+        const create = await cosiRequest
+                                .post("/api/v1/person")
+                                .send({
+                                    "first_name": "daisy",
+                                    "middle_name": "",
+                                    "last_name": "sarasa",
+                                    "sex": "Female",
+                                    "notes": "",
+                                    "emergency_contact": ""
+                                })
+                                .expect(201);
+        let oid = JSON.parse(create.text)["_id"]["$oid"];
+
+        const response = await stream;
+        expect(response.headers["content-type"]).toMatch(/text\/event-stream/);
+        expect(response.body).toContain(`"_id":"${oid}"`);
+        expect(response.body).toContain(`"table":"person"`);
+
+        await cosiRequest.delete(`/api/v1/person/${oid}`).expect(204);
+    });
+});
+
+describe("Webhooks", () => {
+    async function waitFor(check) {
+        for (let i = 0; i < 100 && !(await check()); i++) {
+            await new Promise((resolve) => setTimeout(resolve, 100));
+        }
+    }
+
+    test("/api/v1/webhooks Signed delivery with retry", async () => {
+        // Local stand-in for the receiving service. Fails the first attempt.
+        let received = [];
+        let attempts = 0;
+        const server = http.createServer((req, res) => {
+            let body = "";
+            req.on("data", (chunk) => body += chunk);
+            req.on("end", () => {
+                attempts += 1;
+                if (attempts == 1) {
+                    res.writeHead(500);
+                } else {
+                    received.push({"headers": req.headers, "body": body});
+                    res.writeHead(200);
+                }
+                res.end();
+            });
+        });
+        await new Promise((resolve) => server.listen(0, "127.0.0.1", resolve));
+
+        const hook = await cosiRequest
+                                .post("/api/v1/webhooks")
+                                .send({
+                                    "url": `http://127.0.0.1:${server.address().port}/hook`,
+                                    "events": ["person.insert"],
+                                    "secret": "mushroom"
+                                })
+                                .expect(201);
+        let hookData = JSON.parse(hook.text);
+        let hookId = hookData["_id"]["$oid"];
+        expect(hookData["secret"]).toBeUndefined();
+
+        expect(hookData["owner"]).toBe("admin");
+
+        const invalid = [
+            {"url": "ftp://127.0.0.1", "events": ["person.insert"], "secret": "s"},
+            {"url": "http://127.0.0.1", "events": ["user.*"], "secret": "s"},
+            {"url": "http://127.0.0.1", "events": ["checkin.insert"], "secret": "s"}
+        ];
+        for (const body of invalid) {
+            await cosiRequest.post("/api/v1/webhooks").send(body).expect(400);
+        }
+
+        const create = await cosiRequest
+                                .post("/api/v1/person")
+                                .send({
+                                    "first_name": "toad",
+                                    "middle_name": "",
+                                    "last_name": "kinopio",
+                                    "sex": "Male",
+                                    "notes": "",
+                                    "emergency_contact": ""
+                                })
+                                .expect(201);
+        let oid = JSON.parse(create.text)["_id"]["$oid"];
+
+        await waitFor(() => received.length > 0);
+        expect(received.length).toBe(1);
+        let delivery = received[0];
+        let expected = "sha256=" + crypto.createHmac("sha256", "mushroom").update(delivery["body"]).digest("hex");
+        expect(delivery["headers"]["x-cosi-signature"]).toBe(expected);
+        expect(delivery["headers"]["x-cosi-event"]).toBe("person.insert");
+        let payload = JSON.parse(delivery["body"]);
+        expect(payload["_id"]).toBe(oid);
+        expect(payload["data"]["first_name"]).toBe("toad");
+
+        let log = [];
+        await waitFor(async () => {
+            const response = await cosiRequest.get(`/api/v1/webhooks/${hookId}/deliveries`).expect(200);
+            log = JSON.parse(response.text)["data"];
+            return log.length == 2;
+        });
+        expect(log.map((d) => d["attempt"])).toEqual([2, 1]);
+        expect(log[0]["delivered"]).toBe(true);
+        expect(log[1]["status"]).toBe(500);
+        expect(log[0]["delivery"]).toBe(delivery["headers"]["x-cosi-delivery"]);
+
+        await cosiRequest.delete(`/api/v1/webhooks/${hookId}`).expect(204);
+        await cosiRequest.delete(`/api/v1/person/${oid}`).expect(204);
+        server.close();
+    }, 20000);
+});
+
+describe("CSV Import", () => {
+    const people = [
+        "Individual ID,FirstName,LastName,Birth Date,Gender,HomePhone",
+        "csv-1,rosalina,comet,03/14/1990,F,555-0100",
+        "csv-2,luma,star,14/03/1990,F,"
+    ];
+    const mapping = {
+        "mapping[Individual ID]": "external_id",
+        "mapping[FirstName]": "first_name",
+        "mapping[LastName]": "last_name",
+        "mapping[Birth Date]": "dob",
+        "mapping[Gender]": "sex",
+        "mapping[HomePhone]": "home_phone"
+    };
+
+    test("/api/v1/import/person Dry run and validation", async () => {
+        const preview = await cosiRequest
+                                .post("/api/v1/import/person")
+                                .query(Object.assign({"dry_run": true}, mapping))
+                                .set("Content-Type", "text/csv")
+                                .send(people.join("\n"))
+                                .expect(200);
+        let report = JSON.parse(preview.text);
+        expect(report["total_rows"]).toBe(2);
+        expect(report["valid_rows"]).toBe(1);
+        expect(report["errors"].length).toBe(1);
+        expect(report["errors"][0]["row"]).toBe(3);
+        expect(report["errors"][0]["err"]).toMatch(/^Birth Date/);
+        expect(report["preview"][0]["dob"]).toBe("1990-03-14");
+        expect(report["preview"][0]["sex"]).toBe("Female");
+        expect(report["preview"][0]["middle_name"]).toBe("");
+
+        // Nothing is written while a row is invalid.
+        await cosiRequest
+                .post("/api/v1/import/person")
+                .query(mapping)
+                .set("Content-Type", "text/csv")
+                .send(people.join("\n"))
+                .expect(422);
+        const list = await cosiRequest.get("/api/v1/person").query({"external_id": "csv-1"}).expect(200);
+        expect(JSON.parse(list.text)["data"].length).toBe(0);
+    });
+
+    test("/api/v1/import Commit and references", async () => {
+        let fixed = people.slice();
+        fixed[2] = "csv-2,luma,star,1990-03-14,F,";
+        for (let [inserted, updated] of [[2, 0], [0, 2]]) {
+            const response = await cosiRequest
+                                    .post("/api/v1/import/person")
+                                    .query(Object.assign({"atomic": false}, mapping))
+                                    .set("Content-Type", "text/csv")
+                                    .send(fixed.join("\n"))
+                                    .expect(200);
+            let report = JSON.parse(response.text);
+            expect(report["inserted"]).toBe(inserted);
+            expect(report["updated"]).toBe(updated);
+        }
+        // Imports are atomic by default, which needs a database with transactions.
+        const atomic = await cosiRequest
+                                .post("/api/v1/import/person")
+                                .query(mapping)
+                                .set("Content-Type", "text/csv")
+                                .send(fixed.join("\n"));
+        expect([200, 409]).toContain(atomic.status);
+        if (atomic.status == 409) {
+            expect(JSON.parse(atomic.text)["err"]).toMatch(/atomic=false/);
+        }
+
+        await cosiRequest
+                .post("/api/v1/group")
+                .send({"group_name": "csv choir", "group_desc": ""})
+                .expect(201);
+        const relations = await cosiRequest
+                                .post("/api/v1/import/grouprelation")
+                                .set("Content-Type", "text/csv")
+                                .send("person,group,role\ncsv-1,csv choir,Member\ncsv-3,csv choir,Member")
+                                .expect(422);
+        let report = JSON.parse(relations.text);
+        expect(report["valid_rows"]).toBe(1);
+        expect(report["errors"][0]["err"]).toBe("person: No person found for csv-3.");
+
+        await cosiRequest
+                .post("/api/v1/import/event")
+                .set("Content-Type", "text/csv")
+                .send("name\nx")
+                .expect(404);
+    });
+});
+
+describe("Export", () => {
+    test("/api/v1/person/export CSV of a filtered list", async () => {
+        const list = await cosiRequest
+                            .get("/api/v1/person")
+                            .query({"sex": "Female", "page_size": 1})
+                            .expect(200);
+        let total = JSON.parse(list.text)["total_result"];
+
+        const response = await cosiRequest
+                                .get("/api/v1/person/export")
+                                .query({"sex": "Female", "sort": "last_name"})
+                                .expect(200)
+                                .expect("Content-Type", /text\/csv/)
+                                .expect("Content-Disposition", /person\.csv/);
+        let lines = response.text.replace(/^\uFEFF/, "").trim().split(/\r?\n/);
+        let header = lines[0].split(",");
+        expect(header.slice(0, 3)).toEqual(["_id", "first_name", "middle_name"]);
+        expect(lines.length - 1).toBe(total);
+        let sex = header.indexOf("sex");
+        for (let line of lines.slice(1)) {
+            expect(line.split(",")[sex]).toBe("Female");
+        }
+    });
+
+    test("/api/v1/person/export Quotes formulas", async () => {
+        const create = await cosiRequest
+                                .post("/api/v1/person")
+                                .send({
+                                    "first_name": "=1+2",
+                                    "middle_name": "",
+                                    "last_name": "@formula",
+                                    "sex": "Male",
+                                    "notes": "-3",
+                                    "emergency_contact": "-2+3"
+                                })
+                                .expect(201);
+        let oid = JSON.parse(create.text)["_id"]["$oid"];
+
+        const response = await cosiRequest
+                                .get("/api/v1/person/export")
+                                .query({"first_name": "=1+2"})
+                                .expect(200);
+        let lines = response.text.replace(/^\uFEFF/, "").trim().split(/\r?\n/);
+        let header = lines[0].split(",");
+        let row = lines[1].split(",");
+        expect(row[header.indexOf("first_name")]).toBe("'=1+2");
+        expect(row[header.indexOf("last_name")]).toBe("'@formula");
+        expect(row[header.indexOf("notes")]).toBe("-3");
+        expect(row[header.indexOf("emergency_contact")]).toBe("'-2+3");
+
+        await cosiRequest.delete(`/api/v1/person/${oid}`).expect(204);
+    });
+
+    test("/api/v1/household/export Flattened references", async () => {
+        const response = await cosiRequest
+                                .get("/api/v1/household/export")
+                                .expect(200);
+        let header = response.text.replace(/^\uFEFF/, "").split(/\r?\n/)[0].split(",");
+        expect(header).toContain("address.city");
+        expect(header).toContain("persons.first_name");
+        expect(header).not.toContain("address");
+
+        await cosiRequest
+                .get("/api/v1/household/export")
+                .query({"format": "xlsx"})
+                .expect(200)
+                .expect("Content-Type", /spreadsheetml/)
+                .expect("Content-Disposition", /household\.xlsx/);
+        await cosiRequest.get("/api/v1/household/export").query({"format": "pdf"}).expect(400);
+    });
+});
+
+describe("Backup", () => {
+    // Entries of a tar archive, see https://www.gnu.org/software/tar/manual/html_node/Standard.html
+    function untar(buffer) {
+        let entries = {};
+        let offset = 0;
+        while (offset + 512 <= buffer.length && buffer[offset] != 0) {
+            let name = buffer.toString("utf8", offset, offset + 100).replace(/\0.*$/s, "");
+            let size = parseInt(buffer.toString("utf8", offset + 124, offset + 136).replace(/\0.*$/s, "").trim(), 8);
+            entries[name] = buffer.toString("utf8", offset + 512, offset + 512 + size);
+            offset += 512 + Math.ceil(size / 512) * 512;
+        }
+        return entries;
+    }
+
+    function binary(res, callback) {
+        let chunks = [];
+        res.on("data", (chunk) => chunks.push(chunk));
+        res.on("end", () => callback(null, Buffer.concat(chunks)));
+    }
+
+    test("/api/v1/admin/backup Archive and restore checks", async () => {
+        const response = await cosiRequest
+                                .get("/api/v1/admin/backup")
+                                .buffer(true)
+                                .parse(binary)
+                                .expect(200)
+                                .expect("Content-Type", /gzip/)
+                                .expect("Content-Disposition", /\.tar\.gz/);
+        let archive = response.body;
+        let entries = untar(zlib.gunzipSync(archive));
+        let manifest = JSON.parse(entries["manifest.json"]);
+        expect(manifest["format"]).toBe("cosi-db-backup");
+        const list = await cosiRequest.get("/api/v1/person").query({"page_size": 1}).expect(200);
+        let total = JSON.parse(list.text)["total_result"];
+        expect(manifest["collections"]["person"]).toBe(total);
+        let people = entries["person.jsonl"].trim().split("\n").map((l) => JSON.parse(l));
+        expect(people.length).toBe(total);
+        expect(people[0]["_id"]).toHaveProperty("$oid");
+        expect(entries).toHaveProperty(["userlogin.jsonl"]);
+
+        // The live database holds more than logins.
+        await cosiRequest
+                .post("/api/v1/admin/restore")
+                .type("application/gzip")
+                .send(archive)
+                .expect(409);
+
+        const dryRun = await cosiRequest
+                            .post("/api/v1/admin/restore")
+                            .query({"dry_run": true})
+                            .type("application/gzip")
+                            .send(archive)
+                            .expect(200);
+        let report = JSON.parse(dryRun.text);
+        expect(report["database"]).toBe("cosi_db");
+        expect(report["dry_run"]).toBe(true);
+        expect(report["errors"]).toEqual([]);
+        expect(report["collections"]).toEqual(manifest["collections"]);
+
+        await cosiRequest
+                .post("/api/v1/admin/restore")
+                .query({"dry_run": true})
+                .type("application/gzip")
+                .send(Buffer.from("not an archive"))
+                .expect(400);
+
+        const tenant = await cosiRequest
+                            .post("/api/v1/admin/restore")
+                            .query({"tenant": "jest", "dry_run": true})
+                            .type("application/gzip")
+                            .send(archive)
+                            .expect(200);
+        expect(JSON.parse(tenant.text)["database"]).toBe("cosi_db_jest");
+
+        // A new tenant is empty and takes the archive once.
+        let name = `jest_${Date.now().toString(36)}`;
+        const restored = await cosiRequest
+                                .post("/api/v1/admin/restore")
+                                .query({"tenant": name})
+                                .type("application/gzip")
+                                .send(archive)
+                                .expect(200);
+        expect(JSON.parse(restored.text)["database"]).toBe(`cosi_db_${name}`);
+        await cosiRequest
+                .post("/api/v1/admin/restore")
+                .query({"tenant": name})
+                .type("application/gzip")
+                .send(archive)
+                .expect(409);
+        await cosiRequest
+                .post("/api/v1/admin/restore")
+                .query({"tenant": "Not Valid"})
+                .type("application/gzip")
+                .send(archive)
+                .expect(400);
+    });
+});
+
+describe("vCard", () => {
+    test("/api/v1/person/<oid>/vcard Export and import", async () => {
+        let suffix = Date.now().toString(36);
+        const create = await cosiRequest
+                                .post("/api/v1/person")
+                                .send({
+                                    "first_name": "daisy",
+                                    "middle_name": "",
+                                    "last_name": `sarasa${suffix}`,
+                                    "nicks": ["dee"],
+                                    "dob": "1990-03-14",
+                                    "mobile_phone": "555 0100",
+                                    "sex": "Female",
+                                    "notes": "",
+                                    "emergency_contact": ""
+                                })
+                                .expect(201);
+        let oid = JSON.parse(create.text)["_id"]["$oid"];
+
+        const exported = await cosiRequest
+                                .get(`/api/v1/person/${oid}/vcard`)
+                                .expect(200)
+                                .expect("Content-Type", /text\/vcard/)
+                                .expect("Content-Disposition", /\.vcf/);
+        let card = exported.text;
+        expect(card).toMatch(/^BEGIN:VCARD\r\nVERSION:4\.0\r\n/);
+        expect(card).toContain(`UID:urn:cosi-db:person:${oid}`);
+        expect(card).toContain("BDAY:19900314");
+        expect(card).toContain("NICKNAME:dee");
+        expect(card).toContain("TEL;VALUE=uri;TYPE=cell:tel:555-0100");
+
+        let newCard = [
+            "BEGIN:VCARD",
+            "VERSION:3.0",
+            `N:Koopa${suffix};Bowser;;;`,
+            "FN:Bowser Koopa",
+            "BDAY:1985-10-18",
+            "item1.TEL;type=HOME;type=VOICE:(555) 010-0200",
+            `ADR;TYPE=HOME:;;1 Castle Rd\\nTower 8;Darkland${suffix};MK;00001;`,
+            "END:VCARD"
+        ].join("\r\n");
+        let vcf = card + newCard + "\r\n";
+
+        const dryRun = await cosiRequest
+                            .post("/api/v1/import/vcard")
+                            .query({"dry_run": true})
+                            .type("text/vcard")
+                            .send(vcf)
+                            .expect(200);
+        let report = JSON.parse(dryRun.text);
+        expect(report["total"]).toBe(2);
+        expect(report["duplicates"]).toBe(1);
+        expect(report["cards"][0]["_id"]).toBe(oid);
+        expect(report["cards"][1]["status"]).toBe("created");
+
+        const imported = await cosiRequest
+                            .post("/api/v1/import/vcard")
+                            .type("text/vcard")
+                            .send(vcf)
+                            .expect(200);
+        report = JSON.parse(imported.text);
+        expect(report["created"]).toBe(1);
+        let created = report["cards"][1]["_id"];
+        const person = await cosiRequest.get(`/api/v1/person/${created}`).expect(200);
+        let jData = JSON.parse(person.text);
+        expect(jData["first_name"]).toBe("Bowser");
+        expect(jData["dob"]).toBe("1985-10-18");
+        expect(jData["home_phone"]).toBe("(555) 010-0200");
+
+        // The address comes with a household, which exports the new person.
+        const households = await cosiRequest
+                                .get("/api/v1/household")
+                                .query({"house_name": `Koopa${suffix}`})
+                                .expect(200);
+        let household = JSON.parse(households.text)["data"][0];
+        const householdCards = await cosiRequest
+                                    .get(`/api/v1/household/${household["_id"]["$oid"]}/vcard`)
+                                    .expect(200);
+        expect(householdCards.text).toContain(`UID:urn:cosi-db:person:${created}`);
+        expect(householdCards.text).toContain(`ADR;TYPE=home:;;1 Castle Rd,Tower 8;Darkland${suffix}`);
+
+        // Importing again only finds duplicates.
+        const again = await cosiRequest
+                            .post("/api/v1/import/vcard")
+                            .type("text/vcard")
+                            .send(vcf)
+                            .expect(200);
+        expect(JSON.parse(again.text)["duplicates"]).toBe(2);
+
+        await cosiRequest
+                .post("/api/v1/import/vcard")
+                .type("text/vcard")
+                .send("BEGIN:VCARD\r\nVERSION:4.0\r\nNOTE:nameless\r\nEND:VCARD\r\n")
+                .expect(422);
+        await cosiRequest.get("/api/v1/person/not-an-id/vcard").expect(400);
+    });
+});
+
+describe("GEDCOM", () => {
+    test("/api/v1/import/gedcom Families and export", async () => {
+        let suffix = Date.now().toString(36);
+        let file = [
+            "0 HEAD",
+            "1 GEDC",
+            "2 VERS 5.5.1",
+            "2 FORM LINEAGE-LINKED",
+            "1 CHAR UTF-8",
+            "0 @I1@ INDI",
+            `1 NAME Mario /Plumber${suffix}/`,
+            "1 SEX M",
+            "1 BIRT",
+            "2 DATE 12 SEP 1985",
+            `1 _UID mario-${suffix}`,
+            "1 FAMS @F1@",
+            "0 @I2@ INDI",
+            `1 NAME Pauline /Plumber${suffix}/`,
+            "1 SEX F",
+            "1 BIRT",
+            "2 DATE ABT 1986",
+            "1 FAMS @F1@",
+            "0 @I3@ INDI",
+            `1 NAME Baby Mario /Plumber${suffix}/`,
+            "1 FAMC @F1@",
+            "0 @F1@ FAM",
+            "1 HUSB @I1@",
+            "1 WIFE @I2@",
+            "1 CHIL @I3@",
+            "1 RESI",
+            "2 ADDR 1 Pipe Way",
+            "3 ADR1 1 Pipe Way",
+            `3 CITY Brooklyn${suffix}`,
+            "3 STAE NY",
+            "0 TRLR"
+        ].join("\r\n");
+
+        const dryRun = await cosiRequest
+                            .post("/api/v1/import/gedcom")
+                            .query({"dry_run": true})
+                            .type("application/x-gedcom")
+                            .send(file)
+                            .expect(200);
+        let report = JSON.parse(dryRun.text);
+        expect(report["individuals"]).toBe(3);
+        expect(report["persons_created"]).toBe(3);
+        expect(report["households_created"]).toBe(1);
+        // Husband plus a child relation per parent.
+        expect(report["relations"]).toBe(3);
+        expect(report["warnings"].length).toBe(1);
+
+        await cosiRequest
+                .post("/api/v1/import/gedcom")
+                .type("application/x-gedcom")
+                .send(file)
+                .expect(200);
+        const households = await cosiRequest
+                                .get("/api/v1/household")
+                                .query({"house_name": `Plumber${suffix}`})
+                                .expect(200);
+        let household = JSON.parse(households.text)["data"][0];
+        expect(household["persons"].length).toBe(3);
+        expect(household["address"]["city"]).toBe(`Brooklyn${suffix}`);
+        let oid = household["_id"]["$oid"];
+
+        const exported = await cosiRequest
+                                .get(`/api/v1/household/${oid}/gedcom`)
+                                .buffer(true)
+                                .parse((res, callback) => {
+                                    let text = "";
+                                    res.on("data", (chunk) => text += chunk);
+                                    res.on("end", () => callback(null, text));
+                                })
+                                .expect(200)
+                                .expect("Content-Type", /gedcom/);
+        let ged = exported.body;
+        expect(ged).toMatch(/^0 HEAD\r\n/);
+        expect(ged).toContain(`1 NAME Mario /Plumber${suffix}/`);
+        expect(ged).toContain("2 DATE 12 SEP 1985");
+        expect(ged).toContain("1 CHIL @I");
+        expect(ged).toContain(`3 CITY Brooklyn${suffix}`);
+        expect(ged.match(/ FAM\r\n/g).length).toBe(1);
+
+        // Exported people are recognized by their REFN.
+        const again = await cosiRequest
+                            .post("/api/v1/import/gedcom")
+                            .type("application/x-gedcom")
+                            .send(ged)
+                            .expect(200);
+        report = JSON.parse(again.text);
+        expect(report["persons_existing"]).toBe(3);
+        expect(report["households_existing"]).toBe(1);
+
+        await cosiRequest
+                .post("/api/v1/import/gedcom")
+                .type("application/x-gedcom")
+                .send("0 @I1@ INDI\r\n1 NAME A /B/\r\nnot a line\r\n")
+                .expect(422);
+    });
+});
+
+describe("iCalendar", () => {
+    test("/api/v1/event/<oid>/ical Feeds and import", async () => {
+        let suffix = Date.now().toString(36);
+        const create = await cosiRequest
+                                .post("/api/v1/event")
+                                .send({
+                                    "name": `bible study ${suffix}`,
+                                    "meeting_days": ["W"],
+                                    "start_datetime": "2024-01-03 19:00:00",
+                                    "end_datetime": "2024-06-26 20:30:00",
+                                    "freq": 1,
+                                    "reoccuring": "Weeks"
+                                })
+                                .expect(201);
+        let oid = JSON.parse(create.text)["_id"]["$oid"];
+
+        const exported = await cosiRequest
+                                .get(`/api/v1/event/${oid}/ical`)
+                                .expect(200)
+                                .expect("Content-Type", /text\/calendar/)
+                                .expect("Content-Disposition", /\.ics/);
+        let calendar = exported.text;
+        expect(calendar).toMatch(/^BEGIN:VCALENDAR\r\nVERSION:2\.0\r\n/);
+        expect(calendar).toContain(`UID:urn:cosi-db:event:${oid}`);
+        expect(calendar).toContain("DTSTART:20240103T190000");
+        expect(calendar).toContain("DTEND:20240103T203000");
+        expect(calendar).toContain("RRULE:FREQ=WEEKLY;BYDAY=WE;UNTIL=20240626T203000");
+
+        // Meeting days without a period repeat every freq weeks.
+        const fortnightly = await cosiRequest
+                                    .post("/api/v1/event")
+                                    .send({
+                                        "name": `elders ${suffix}`,
+                                        "meeting_days": ["Tu"],
+                                        "start_datetime": "2024-02-06 10:00:00",
+                                        "end_datetime": "2024-04-30 11:00:00",
+                                        "freq": 2
+                                    })
+                                    .expect(201);
+        let fortnightlyId = JSON.parse(fortnightly.text)["_id"]["$oid"];
+        const fortnightlyIcal = await cosiRequest.get(`/api/v1/event/${fortnightlyId}/ical`).expect(200);
+        expect(fortnightlyIcal.text).toContain("RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=TU;UNTIL=20240430T110000");
+        await cosiRequest.delete(`/api/v1/event/${fortnightlyId}`).expect(204);
+
+        // Calendar apps have no session and give a token.
+        let app = session("127.0.0.1:8000");
+        await app.get("/api/v1/ical").expect(404);
+        const token = await cosiRequest.post("/api/v1/ical/token").expect(200);
+        let feed = await app
+                            .get("/api/v1/ical")
+                            .query({"token": JSON.parse(token.text)["token"]})
+                            .expect(200);
+        expect(feed.text).toContain(`SUMMARY:bible study ${suffix}`);
+        await app.get("/api/v1/ical").query({"token": "wrong"}).expect(404);
+
+        let file = [
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            "BEGIN:VEVENT",
+            `UID:urn:cosi-db:event:${oid}`,
+            "DTSTART:20240103T190000",
+            `SUMMARY:bible study ${suffix}`,
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "UID:choir@example.org",
+            "DTSTART;TZID=America/New_York:20240905T183000",
+            "DURATION:PT1H30M",
+            "RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=TH;UNTIL=20241219T235959Z",
+            `SUMMARY:choir\\, ${suffix}`,
+            "BEGIN:VALARM",
+            "TRIGGER:-PT15M",
+            "END:VALARM",
+            "END:VEVENT",
+            "END:VCALENDAR"
+        ].join("\r\n");
+        const dryRun = await cosiRequest
+                            .post("/api/v1/import/ical")
+                            .query({"dry_run": true})
+                            .type("text/calendar")
+                            .send(file)
+                            .expect(200);
+        let report = JSON.parse(dryRun.text);
+        expect(report["total"]).toBe(2);
+        expect(report["duplicates"]).toBe(1);
+        expect(report["events"][0]["_id"]).toBe(oid);
+        expect(report["created"]).toBe(1);
+
+        const imported = await cosiRequest
+                                .post("/api/v1/import/ical")
+                                .type("text/calendar")
+                                .send(file)
+                                .expect(200);
+        let choirId = JSON.parse(imported.text)["events"][1]["_id"];
+        const choir = await cosiRequest.get(`/api/v1/event/${choirId}`).expect(200);
+        let event = JSON.parse(choir.text);
+        expect(event["name"]).toBe(`choir, ${suffix}`);
+        expect(event["start_datetime"]).toBe("2024-09-05 18:30:00");
+        expect(event["meeting_days"]).toEqual(["Th"]);
+        expect(event["freq"]).toBe(2);
+        expect(event["reoccuring"]).toBe("Weeks");
+        expect(event["end_datetime"]).toMatch(/^2024-12-(19|20) 20:00:00$/);
+
+        await cosiRequest
+                .post("/api/v1/import/ical")
+                .type("text/calendar")
+                .send("BEGIN:VEVENT\r\nSUMMARY:yearly\r\nDTSTART:20240101T100000\r\nRRULE:FREQ=YEARLY\r\nEND:VEVENT\r\n")
+                .expect(422);
+        await cosiRequest
+                .post("/api/v1/import/ical")
+                .type("text/calendar")
+                .send("BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n")
+                .expect(400);
+    });
+});
+
+describe("Occurrences", () => {
+    test("/api/v1/event/<oid>/occurrences Exceptions", async () => {
+        let suffix = Date.now().toString(36);
+        let event = {
+            "name": `youth group ${suffix}`,
+            "meeting_days": ["W"],
+            "start_datetime": "2024-01-03 19:00:00",
+            "end_datetime": "2024-06-26 20:30:00",
+            "freq": 1,
+            "reoccuring": "Weeks",
+            "exceptions": [
+                {"occurrence": "2024-01-10 19:00:00", "cancelled": true},
+                {"occurrence": "2024-01-17 19:00:00", "cancelled": false,
+                 "start_datetime": "2024-01-18 18:00:00"}
+            ]
+        };
+        const create = await cosiRequest.post("/api/v1/event").send(event).expect(201);
+        let oid = JSON.parse(create.text)["_id"]["$oid"];
+
+        const listed = await cosiRequest
+                                .get(`/api/v1/event/${oid}/occurrences`)
+                                .query({"from": "2024-01-01", "to": "2024-01-31"})
+                                .expect(200);
+        let data = JSON.parse(listed.text)["data"];
+        expect(data.map((o) => o["start_datetime"])).toEqual([
+            "2024-01-03 19:00:00",
+            "2024-01-18 18:00:00",
+            "2024-01-24 19:00:00",
+            "2024-01-31 19:00:00"
+        ]);
+        expect(data[1]["moved"]).toBe(true);
+        expect(data[1]["occurrence"]).toBe("2024-01-17 19:00:00");
+        expect(data[1]["end_datetime"]).toBe("2024-01-18 19:30:00");
+
+        const withCancelled = await cosiRequest
+                                .get(`/api/v1/event/${oid}/occurrences`)
+                                .query({"from": "2024-01-08", "to": "2024-01-14", "cancelled": true})
+                                .expect(200);
+        data = JSON.parse(withCancelled.text)["data"];
+        expect(data.length).toBe(1);
+        expect(data[0]["cancelled"]).toBe(true);
+
+        const exported = await cosiRequest.get(`/api/v1/event/${oid}/ical`).expect(200);
+        expect(exported.text).toContain("EXDATE:20240110T190000");
+        expect(exported.text).toContain("RECURRENCE-ID:20240117T190000");
+
+        await cosiRequest
+                .get("/api/v1/occurrences")
+                .query({"from": "2024-01-31", "to": "2024-01-01"})
+                .expect(400);
+        await cosiRequest
+                .get("/api/v1/occurrences")
+                .query({"from": "2024-01-01", "to": "2025-06-01"})
+                .expect(400);
+
+        event["name"] = `youth group ${suffix} 2`;
+        event["exceptions"] = [{"occurrence": "2024-01-10 19:00:00", "cancelled": true,
+                                "start_datetime": "2024-01-11 19:00:00"}];
+        await cosiRequest.post("/api/v1/event").send(event).expect(400);
+    });
+
+    test("/api/v1/event/<oid>/occurrences Sparse and unreachable schedules", async () => {
+        let suffix = Date.now().toString(36);
+        // Months without a 31st hold no occurrence.
+        const monthly = await cosiRequest
+                                .post("/api/v1/event")
+                                .send({
+                                    "name": `month end ${suffix}`,
+                                    "meeting_days": [],
+                                    "start_datetime": "2024-01-31 09:00:00",
+                                    "freq": 1,
+                                    "reoccuring": "Months"
+                                })
+                                .expect(201);
+        let oid = JSON.parse(monthly.text)["_id"]["$oid"];
+        const listed = await cosiRequest
+                                .get(`/api/v1/event/${oid}/occurrences`)
+                                .query({"from": "2024-02-01", "to": "2024-03-01"})
+                                .expect(200);
+        expect(JSON.parse(listed.text)["data"]).toEqual([]);
+        await cosiRequest.delete(`/api/v1/event/${oid}`).expect(204);
+
+        // Every 7 days from a Monday never falls on a Wednesday.
+        await cosiRequest
+                .post("/api/v1/event")
+                .send({
+                    "name": `never ${suffix}`,
+                    "meeting_days": ["W"],
+                    "start_datetime": "2024-01-01 09:00:00",
+                    "freq": 7,
+                    "reoccuring": "Days"
+                })
+                .expect(400);
+        await cosiRequest
+                .post("/api/v1/import/ical")
+                .type("text/calendar")
+                .send(`BEGIN:VEVENT\r\nSUMMARY:never ${suffix}\r\nDTSTART:20240101T090000\r\nRRULE:FREQ=DAILY;INTERVAL=7;BYDAY=WE\r\nEND:VEVENT\r\n`)
+                .expect(422);
+    });
+});
+
+describe("Attendance", () => {
+    test("/api/v1/event/<oid>/attendance Record and report", async () => {
+        let suffix = Date.now().toString(36);
+        const event = await cosiRequest
+                            .post("/api/v1/event")
+                            .send({
+                                "name": `sunday service ${suffix}`,
+                                "meeting_days": ["Su"],
+                                "start_datetime": "2024-03-03 10:00:00",
+                                "end_datetime": "2024-12-29 11:30:00",
+                                "freq": 1,
+                                "reoccuring": "Weeks"
+                            })
+                            .expect(201);
+        let eventId = JSON.parse(event.text)["_id"]["$oid"];
+        const person = await cosiRequest
+                            .post("/api/v1/person")
+                            .send({"first_name": "mario", "middle_name": "", "last_name": suffix,
+                                   "sex": "Male", "notes": "", "emergency_contact": ""})
+                            .expect(201);
+        let personId = JSON.parse(person.text)["_id"]["$oid"];
+        const group = await cosiRequest
+                            .post("/api/v1/group")
+                            .send({"group_name": `ushers ${suffix}`, "group_desc": ""})
+                            .expect(201);
+        let groupId = JSON.parse(group.text)["_id"]["$oid"];
+
+        const recorded = await cosiRequest
+                                .post(`/api/v1/event/${eventId}/attendance`)
+                                .send({
+                                    "occurrence": "2024-03-10 10:00:00",
+                                    "headcount": 12,
+                                    "attendees": [
+                                        {"person": personId, "status": "Present"},
+                                        {"group": groupId, "status": "Late"},
+                                        {"person": personId, "group": groupId, "status": "Present"}
+                                    ]
+                                })
+                                .expect(200);
+        let bulk = JSON.parse(recorded.text);
+        expect(bulk["total_ok"]).toBe(2);
+        expect(bulk["results"][2]["ok"]).toBe(false);
+
+        const history = await cosiRequest.get(`/api/v1/event/${eventId}/attendance`).expect(200);
+        let occurrences = JSON.parse(history.text);
+        expect(occurrences.length).toBe(1);
+        expect(occurrences[0]["occurrence"]).toBe("2024-03-10 10:00:00");
+        expect(occurrences[0]["present"]).toBe(1);
+        expect(occurrences[0]["late"]).toBe(1);
+        expect(occurrences[0]["headcount"]).toBe(12);
+        expect(occurrences[0]["attendance"]).toBe(14);
+
+        // Recording again updates the attendee.
+        await cosiRequest
+                .post(`/api/v1/event/${eventId}/attendance`)
+                .send({"occurrence": "2024-03-10 10:00:00",
+                       "attendees": [{"person": personId, "status": "Absent"}]})
+                .expect(200);
+        const personHistory = await cosiRequest
+                                    .get(`/api/v1/person/${personId}/attendance`)
+                                    .query({"from": "2024-03-01", "to": "2024-03-31"})
+                                    .expect(200);
+        let report = JSON.parse(personHistory.text);
+        expect(report["present"]).toBe(0);
+        expect(report["absent"]).toBe(1);
+        expect(report["data"][0]["name"]).toBe(`sunday service ${suffix}`);
+
+        const groupHistory = await cosiRequest.get(`/api/v1/group/${groupId}/attendance`).expect(200);
+        expect(JSON.parse(groupHistory.text)["late"]).toBe(1);
+
+        await cosiRequest
+                .post(`/api/v1/event/${eventId}/attendance`)
+                .send({"occurrence": "2024-03-11 10:00:00", "attendees": []})
+                .expect(400);
+        await cosiRequest
+                .get(`/api/v1/person/${personId}/attendance`)
+                .query({"from": "March"})
+                .expect(400);
+    });
+});
+
+describe("Kiosk", () => {
+    test("/api/v1/kiosk Check-in and check-out", async () => {
+        let suffix = Date.now().toString();
+        let phone = `555-01${suffix.slice(-5)}`;
+        const parent = await cosiRequest
+                            .post("/api/v1/person")
+                            .send({"first_name": "daisy", "middle_name": "", "last_name": `kiosk${suffix}`,
+                                   "sex": "Female", "notes": "", "emergency_contact": "",
+                                   "mobile_phone": `(${phone.slice(0, 3)}) ${phone.slice(4)}`})
+                            .expect(201);
+        let parentId = JSON.parse(parent.text)["_id"]["$oid"];
+        const child = await cosiRequest
+                            .post("/api/v1/person")
+                            .send({"first_name": "toad", "middle_name": "", "last_name": `kiosk${suffix}`,
+                                   "sex": "Male", "notes": "Peanut allergy", "emergency_contact": ""})
+                            .expect(201);
+        let childId = JSON.parse(child.text)["_id"]["$oid"];
+        const addresses = await cosiRequest.get("/get_address").query({page_size: 1}).expect(200);
+        let address = JSON.parse(addresses.text)["data"][0]["_id"]["$oid"];
+        const household = await cosiRequest
+                                .post("/insert_household")
+                                .send({
+                                    "house_name": `kiosk ${suffix}`,
+                                    "address": address,
+                                    "persons": [parentId, childId],
+                                    "relations": [{"person_a": childId, "person_b": parentId, "relation": "Child"}]
+                                })
+                                .expect(200);
+        let householdId = JSON.parse(household.text)["$oid"];
+        const event = await cosiRequest
+                            .post("/api/v1/event")
+                            .send({
+                                "name": `kids church ${suffix}`,
+                                "meeting_days": ["Su"],
+                                "start_datetime": "2024-03-03 09:00:00",
+                                "end_datetime": "2024-12-29 10:30:00",
+                                "freq": 1,
+                                "reoccuring": "Weeks"
+                            })
+                            .expect(201);
+        let occurrence = {"event": JSON.parse(event.text)["_id"]["$oid"], "occurrence": "2024-03-10 09:00:00"};
+
+        const found = await cosiRequest
+                            .get("/api/v1/kiosk/households")
+                            .query({"query": phone.replace("-", "")})
+                            .expect(200);
+        let households = JSON.parse(found.text);
+        expect(households.map((h) => h["_id"])).toContain(householdId);
+        let members = households.find((h) => h["_id"] == householdId)["members"];
+        let kid = members.find((m) => m["_id"] == childId);
+        expect(kid["child"]).toBe(true);
+        expect(kid["notes"]).toBe("Peanut allergy");
+        expect(members.find((m) => m["_id"] == parentId)["child"]).toBe(false);
+
+        // Only children of the household are checked in.
+        await cosiRequest
+                .post("/api/v1/kiosk/checkin")
+                .send(Object.assign({"household": householdId, "persons": [parentId]}, occurrence))
+                .expect(400);
+
+        const checkIn = await cosiRequest
+                                .post("/api/v1/kiosk/checkin")
+                                .send(Object.assign({"household": householdId, "persons": [childId]}, occurrence))
+                                .expect(201);
+        let labels = JSON.parse(checkIn.text);
+        expect(labels["code"]).toMatch(/^[A-Z0-9]{6}$/);
+        expect(labels["children"][0]["notes"]).toBe("Peanut allergy");
+        expect(labels["guardian"]["code"]).toBe(labels["code"]);
+        await cosiRequest
+                .post("/api/v1/kiosk/checkin")
+                .send(Object.assign({"household": householdId, "persons": [childId]}, occurrence))
+                .expect(400);
+
+        const byName = await cosiRequest
+                            .get("/api/v1/kiosk/households")
+                            .query(Object.assign({"query": `KIOSK${suffix}`}, occurrence))
+                            .expect(200);
+        members = JSON.parse(byName.text)[0]["members"];
+        expect(members.find((m) => m["_id"] == childId)["checked_in"]).toBe(true);
+
+        // No code holds a 0.
+        await cosiRequest
+                .post("/api/v1/kiosk/checkout")
+                .send(Object.assign({"code": "000000"}, occurrence))
+                .expect(403);
+        const checkOut = await cosiRequest
+                                .post("/api/v1/kiosk/checkout")
+                                .send(Object.assign({"code": labels["code"].toLowerCase()}, occurrence))
+                                .expect(200);
+        expect(JSON.parse(checkOut.text)["checked_out"]).toEqual([childId]);
+
+        const roster = await cosiRequest.get("/api/v1/kiosk/checkins").query(occurrence).expect(200);
+        let entries = JSON.parse(roster.text);
+        expect(entries.length).toBe(1);
+        expect(entries[0]["checked_out"]).toBeTruthy();
+        expect(entries[0]["code"]).toBeUndefined();
+
+        const attendance = await cosiRequest.get(`/api/v1/person/${childId}/attendance`).expect(200);
+        expect(JSON.parse(attendance.text)["present"]).toBe(1);
+
+        // Two kiosks checking the same child in at once.
+        const both = await Promise.all([1, 2].map(() => cosiRequest
+                                                        .post("/api/v1/kiosk/checkin")
+                                                        .send(Object.assign({"household": householdId, "persons": [childId]}, occurrence))));
+        expect(both.map((r) => r.status).sort()).toEqual([201, 400]);
+    });
+});
+
+describe("Labels", () => {
+    test("/api/v1/labels PDF sheets", async () => {
+        const layouts = await cosiRequest.get("/api/v1/labels/layouts").expect(200);
+        expect(JSON.parse(layouts.text).map((l) => l["name"])).toContain("avery5160");
+
+        const people = await cosiRequest
+                                .get("/get_person")
+                                .query({page_size: 3, expand: false})
+                                .expect(200);
+        let ids = JSON.parse(people.text)["data"].map((v) => v["_id"]["$oid"]);
+        const tags = await cosiRequest
+                            .post("/api/v1/labels")
+                            .send({"kind": "NameTag", "source": "Person", "ids": ids, "skip": 2, "outline": true})
+                            .buffer(true)
+                            .parse((res, done) => {
+                                let chunks = [];
+                                res.on("data", (c) => chunks.push(c));
+                                res.on("end", () => done(null, Buffer.concat(chunks)));
+                            })
+                            .expect(200)
+                            .expect("Content-Type", /application\/pdf/)
+                            .expect("Content-Disposition", /labels\.pdf/);
+        let pdf = tags.body.toString("latin1");
+        expect(pdf.startsWith("%PDF-1.4")).toBe(true);
+        expect(pdf.trim().endsWith("%%EOF")).toBe(true);
+
+        await cosiRequest
+                .post("/api/v1/labels")
+                .send({"kind": "Mailing", "source": "Household", "layout": "averyL7160"})
+                .expect(200);
+        await cosiRequest
+                .post("/api/v1/labels")
+                .send({"kind": "NameTag", "source": "Household"})
+                .expect(400);
+        await cosiRequest
+                .post("/api/v1/labels")
+                .send({"kind": "Mailing", "source": "Address", "layout": "avery0000"})
+                .expect(400);
+        let overlapping = {"name": "custom", "page_width": 612, "page_height": 792, "columns": 2, "rows": 2,
+                           "label_width": 300, "label_height": 100, "top": 0, "left": 0,
+                           "h_pitch": 200, "v_pitch": 100};
+        await cosiRequest
+                .post("/api/v1/labels")
+                .send({"kind": "Mailing", "source": "Address", "layout": overlapping})
+                .expect(400);
+    });
+});
+
+describe("Registration", () => {
+    test("/api/v1/eventregistration Capacity and waitlist", async () => {
+        let suffix = Date.now().toString(36);
+        let people = [];
+        for (const name of ["luigi", "daisy", "peach", "yoshi"]) {
+            const person = await cosiRequest
+                                .post("/api/v1/person")
+                                .send({"first_name": name, "middle_name": "", "last_name": `retreat${suffix}`,
+                                       "sex": "Female", "notes": "", "emergency_contact": ""})
+                                .expect(201);
+            people.push(JSON.parse(person.text)["_id"]["$oid"]);
+        }
+        const addresses = await cosiRequest.get("/get_address").query({page_size: 1}).expect(200);
+        let address = JSON.parse(addresses.text)["data"][0]["_id"]["$oid"];
+        const household = await cosiRequest
+                                .post("/insert_household")
+                                .send({"house_name": `retreat ${suffix}`, "address": address,
+                                       "persons": [people[1], people[2]], "relations": []})
+                                .expect(200);
+        let householdId = JSON.parse(household.text)["$oid"];
+
+        let createEvent = async (name, waitlist) => {
+            const event = await cosiRequest
+                                .post("/api/v1/event")
+                                .send({"name": `${name} ${suffix}`, "meeting_days": [],
+                                       "start_datetime": "2024-06-07 18:00:00", "capacity": 3, "waitlist": waitlist})
+                                .expect(201);
+            return JSON.parse(event.text)["_id"]["$oid"];
+        };
+        let register = (eventId, key, id, timestamp) => cosiRequest
+                                .post("/api/v1/eventregistration")
+                                .send({"event": eventId, "timestamp": timestamp, "key_type": key, [key.toLowerCase()]: id});
+
+        // The household takes a seat per member, the last person is waitlisted.
+        let retreat = await createEvent("retreat", true);
+        const first = await register(retreat, "Person", people[0], "2024-05-01 09:00:00").expect(201);
+        let firstId = JSON.parse(first.text)["_id"]["$oid"];
+        expect(JSON.parse(first.text)["status"]).toBe("Registered");
+        await register(retreat, "Household", householdId, "2024-05-02 09:00:00").expect(201);
+        const late = await register(retreat, "Person", people[3], "2024-05-03 09:00:00").expect(201);
+        let lateId = JSON.parse(late.text)["_id"]["$oid"];
+        expect(JSON.parse(late.text)["status"]).toBe("Waitlisted");
+
+        const seats = await cosiRequest.get(`/api/v1/event/${retreat}/seats`).expect(200);
+        let data = JSON.parse(seats.text);
+        expect(data["capacity"]).toBe(3);
+        expect(data["taken"]).toBe(3);
+        expect(data["waitlisted"]).toEqual([{"$oid": lateId}]);
+
+        // Cancelling frees the seat for the waitlist.
+        await cosiRequest.delete(`/api/v1/eventregistration/${firstId}`).expect(204);
+        const promoted = await cosiRequest.get(`/api/v1/eventregistration/${lateId}`).expect(200);
+        expect(JSON.parse(promoted.text)["status"]).toBe("Registered");
+        const after = await cosiRequest.get(`/api/v1/event/${retreat}/seats`).expect(200);
+        expect(JSON.parse(after.text)["waitlisted"]).toEqual([]);
+        const manual = await cosiRequest.post(`/api/v1/event/${retreat}/waitlist/promote`).expect(200);
+        expect(JSON.parse(manual.text)["promoted"]).toEqual([]);
+
+        // Without a waitlist, registrations beyond the capacity are refused.
+        let dinner = await createEvent("dinner", false);
+        await register(dinner, "Household", householdId, "2024-05-01 09:00:00").expect(201);
+        await register(dinner, "Person", people[0], "2024-05-02 09:00:00").expect(201);
+        const full = await register(dinner, "Person", people[3], "2024-05-03 09:00:00").expect(409);
+        expect(JSON.parse(full.text)["err"]).toMatch(/full/);
+        await cosiRequest
+                .post("/insert_eventregistration")
+                .send({"event": dinner, "timestamp": "2024-05-03 09:00:00", "key_type": "Person", "person": people[3]})
+                .expect(409);
+
+        // The status is kept by the server, and moving to another event places the registration again.
+        await cosiRequest.patch(`/api/v1/eventregistration/${lateId}`).send({"status": "Waitlisted"}).expect(200);
+        const kept = await cosiRequest.get(`/api/v1/eventregistration/${lateId}`).expect(200);
+        expect(JSON.parse(kept.text)["status"]).toBe("Registered");
+        await cosiRequest.patch(`/api/v1/eventregistration/${lateId}`).send({"event": dinner}).expect(409);
+
+        // Leaving for another event promotes the waitlist of the event left.
+        const again = await register(retreat, "Person", people[0], "2024-05-04 09:00:00").expect(201);
+        let againId = JSON.parse(again.text)["_id"]["$oid"];
+        expect(JSON.parse(again.text)["status"]).toBe("Waitlisted");
+        let camp = await createEvent("camp", true);
+        await cosiRequest.patch(`/api/v1/eventregistration/${lateId}`).send({"event": camp}).expect(200);
+        const moved = await cosiRequest.get(`/api/v1/eventregistration/${againId}`).expect(200);
+        expect(JSON.parse(moved.text)["status"]).toBe("Registered");
+
+        // Concurrent registrations do not overbook.
+        let supper = await createEvent("supper", false);
+        const rush = await Promise.all(people.map((p, i) => register(supper, "Person", p, `2024-05-0${i + 1} 09:00:00`)));
+        expect(rush.filter((r) => r.status == 201).length).toBe(3);
+        expect(rush.filter((r) => r.status == 409).length).toBe(1);
+
+        await cosiRequest.get("/api/v1/event/000000000000000000000000/seats").expect(404);
+    });
+});
+
+describe("Rooms", () => {
+    test("/api/v1/room Booking conflicts and availability", async () => {
+        let suffix = Date.now().toString(36);
+        const addresses = await cosiRequest.get("/get_address").query({page_size: 1}).expect(200);
+        let address = JSON.parse(addresses.text)["data"][0]["_id"]["$oid"];
+        const room = await cosiRequest
+                            .post("/api/v1/room")
+                            .send({"facility": `campus ${suffix}`, "name": "Fellowship Hall", "address": address, "capacity": 120})
+                            .expect(201);
+        let roomId = JSON.parse(room.text)["_id"]["$oid"];
+
+        let createEvent = (name, start, end, extra) => cosiRequest
+                                .post("/api/v1/event")
+                                .send({"name": `${name} ${suffix}`, "meeting_days": ["Su"], "start_datetime": start,
+                                       "end_datetime": end, "rooms": [roomId], ...extra});
+        const service = await createEvent("service", "2024-03-03 10:00:00", "2024-12-29 11:30:00",
+                                          {"freq": 1, "reoccuring": "Weeks"})
+                                .expect(201);
+        let serviceId = JSON.parse(service.text)["_id"]["$oid"];
+
+        // Overlapping the weekly occurrence, then right after it.
+        const clash = await createEvent("choir", "2024-03-10 11:00:00", "2024-03-10 12:00:00", {"meeting_days": []})
+                                .expect(409);
+        expect(JSON.parse(clash.text)["err"]).toMatch(new RegExp(`Fellowship Hall is booked by service ${suffix}`));
+        const lunch = await createEvent("lunch", "2024-03-10 12:00:00", "2024-03-10 13:00:00", {"meeting_days": []})
+                                .expect(201);
+        let lunchId = JSON.parse(lunch.text)["_id"]["$oid"];
+
+        // Cancelled occurrences free the room.
+        await cosiRequest
+                .patch(`/api/v1/event/${serviceId}`)
+                .send({"exceptions": [{"occurrence": "2024-03-17 10:00:00", "cancelled": true}]})
+                .expect(200);
+        await createEvent("baptism", "2024-03-17 10:00:00", "2024-03-17 11:00:00", {"meeting_days": []}).expect(201);
+
+        // Updates are checked as well.
+        await cosiRequest
+                .patch(`/api/v1/event/${lunchId}`)
+                .send({"start_datetime": "2024-03-24 10:30:00", "end_datetime": "2024-03-24 11:00:00"})
+                .expect(409);
+        await cosiRequest.patch(`/api/v1/event/${lunchId}`).send({"name": `brunch ${suffix}`}).expect(200);
+        const youth = await createEvent("youth", "2024-03-10 10:30:00", "2024-03-10 11:00:00",
+                                        {"meeting_days": [], "rooms": []})
+                                .expect(201);
+        await cosiRequest
+                .patch(`/api/v1/event/${JSON.parse(youth.text)["_id"]["$oid"]}`)
+                .send({"$add": {"rooms": [roomId]}})
+                .expect(409);
+        let choir = {"name": `choir ${suffix}`, "meeting_days": [], "start_datetime": "2024-03-24 10:30:00",
+                     "end_datetime": "2024-03-24 11:00:00", "exceptions": [], "rooms": [roomId]};
+        await cosiRequest.post("/insert_event").send(choir).expect(409);
+        await cosiRequest.put("/api/v1/event").send(choir).expect(409);
+        await cosiRequest.post("/upsert_event").send(choir).expect(409);
+        const moved = await cosiRequest
+                            .patch("/api/v1/event/bulk")
+                            .send([{"oid": lunchId, "data": Object.assign({}, choir, {"name": `brunch ${suffix}`})}])
+                            .expect(200);
+        expect(JSON.parse(moved.text)["results"][0]["err"]).toMatch(/Fellowship Hall is booked/);
+
+        // Events repeating without end are checked against those starting more than two years later.
+        await createEvent("jubilee", "2027-03-07 10:30:00", "2027-03-07 11:00:00", {"meeting_days": []}).expect(201);
+        const vespers = await createEvent("vespers", "2025-01-05 10:00:00", "2025-01-05 11:30:00",
+                                          {"freq": 1, "reoccuring": "Weeks"})
+                                .expect(409);
+        expect(JSON.parse(vespers.text)["err"]).toMatch(new RegExp(`booked by jubilee ${suffix}`));
+
+        const busy = await cosiRequest
+                            .get(`/api/v1/room/${roomId}/availability`)
+                            .query({"from": "2024-03-10", "to": "2024-03-10"})
+                            .expect(200);
+        let data = JSON.parse(busy.text);
+        expect(data["available"]).toBe(false);
+        expect(data["bookings"].map((o) => o["name"])).toEqual([`service ${suffix}`, `brunch ${suffix}`]);
+        const free = await cosiRequest
+                            .get(`/api/v1/room/${roomId}/availability`)
+                            .query({"from": "2024-03-11", "to": "2024-03-16"})
+                            .expect(200);
+        expect(JSON.parse(free.text)["available"]).toBe(true);
+        await cosiRequest.get("/api/v1/room/000000000000000000000000/availability").expect(404);
+
+        await createEvent("retreat", "2024-03-04 10:00:00", "2024-03-04 11:00:00",
+                          {"meeting_days": [], "rooms": ["000000000000000000000000"]})
+                .expect(409);
+
+        // Deleted rooms are no longer held.
+        await cosiRequest.delete(`/api/v1/room/${roomId}`).expect(204);
+        const released = await cosiRequest.get(`/api/v1/event/${serviceId}`).expect(200);
+        expect(JSON.parse(released.text)["rooms"]).toEqual([]);
+    });
+});